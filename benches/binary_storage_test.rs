@@ -1,109 +1,426 @@
-use binary_storage_test::{player_log::*, *};
-use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use std::io::Cursor;
+
+use binary_storage_test::{
+    player_log::{compress::ZstdSeekableReader, projection::Projection, *},
+    *,
+};
+use criterion::{
+    criterion_group, criterion_main, measurement::WallTime, BatchSize, Bencher, BenchmarkGroup,
+    BenchmarkId, Criterion, Throughput,
+};
 use flate2::Compression;
+use rand::{rngs::StdRng, SeedableRng};
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+// seeded so every run measures the same records
+fn seeded_builders(size: u64) -> Vec<PlayerLogBuilder> {
+    let mut rng = StdRng::seed_from_u64(0);
+    (0..size).map(|_| log_generator_seeded(&mut rng)).collect()
+}
+
+fn generate_logs(size: u64) -> Vec<PlayerLog> {
+    PlayerLogBuilder::build_many(&seeded_builders(size)).unwrap()
+}
+
+// cargo test starts this binary without --bench and criterion then runs every bench body once, as
+// a smoke test that only needs a hundredth of the records
+fn record_count(full: u64) -> u64 {
+    if std::env::args().any(|arg| arg == "--bench") {
+        full
+    } else {
+        full / 100
+    }
+}
+
+// times `f` alone, what it returns is dropped outside the measurement
+fn bench_batched<O>(b: &mut Bencher, mut f: impl FnMut() -> O) {
+    b.iter_batched(|| (), |()| f(), BatchSize::SmallInput);
+}
+
+// checks the format round trips once, then times serializing and deserializing on their own, the
+// serialize side per record and the deserialize side per byte of input
+fn bench_format<T: PartialEq + std::fmt::Debug>(
+    group: &mut BenchmarkGroup<WallTime>,
+    name: &str,
+    expected: &T,
+    serialize: impl Fn() -> Vec<u8>,
+    deserialize: impl Fn(&[u8]) -> T,
+) {
+    let data = serialize();
+    assert_eq!(deserialize(&data), *expected, "{name} doesn't round trip");
+
+    group.throughput(Throughput::Elements(record_count(SERIALIZATION_SIZE)));
+    group.bench_function(BenchmarkId::new("serialize", name), |b| {
+        bench_batched(b, &serialize)
+    });
+
+    group.throughput(Throughput::Bytes(data.len() as u64));
+    group.bench_function(BenchmarkId::new("deserialize", name), |b| {
+        bench_batched(b, || deserialize(&data))
+    });
+}
+
+const SERIALIZATION_SIZE: u64 = 10_000;
 
 fn criterion_benchmark(c: &mut Criterion) {
+    let builders = seeded_builders(record_count(SERIALIZATION_SIZE));
+    let logs = PlayerLogBuilder::build_many(&builders).unwrap();
+
     let mut group = c.benchmark_group("Serialization");
 
-    group.bench_with_input("serde_json", &10_000, |b, &size| {
-        b.iter_batched(
-            || {
+    bench_format(
+        &mut group,
+        "serde_json",
+        &builders,
+        || serde_json::to_vec(&builders).unwrap(),
+        |data| serde_json::from_slice::<Vec<PlayerLogBuilder>>(data).unwrap(),
+    );
+
+    bench_format(
+        &mut group,
+        "postcard",
+        &logs,
+        || postcard::to_allocvec(&logs).unwrap(),
+        |data| postcard::from_bytes::<Vec<PlayerLog>>(data).unwrap(),
+    );
+
+    bench_format(
+        &mut group,
+        "bincode",
+        &logs,
+        || bincode::serialize(&logs).unwrap(),
+        |data| bincode::deserialize::<Vec<PlayerLog>>(data).unwrap(),
+    );
+
+    bench_format(
+        &mut group,
+        "our_serialization",
+        &logs,
+        || PlayerLogSerializer::serialize_many(&logs).unwrap(),
+        |data| PlayerLogSerializer::deserialize_many(data).unwrap(),
+    );
+
+    // builders on both ends like serde_json
+    bench_format(
+        &mut group,
+        "our_serialization_builders",
+        &builders,
+        || {
+            PlayerLogSerializer::serialize_many(&PlayerLogBuilder::build_many(&builders).unwrap())
+                .unwrap()
+        },
+        |data| PlayerLogSerializer::deserialize_many_builders(data).unwrap(),
+    );
+
+    group.finish();
+}
+
+fn compressed_serialization_benchmark(c: &mut Criterion) {
+    let logs = generate_logs(record_count(SERIALIZATION_SIZE));
+
+    let mut group = c.benchmark_group("CompressedSerialization");
+
+    bench_format(
+        &mut group,
+        "zlib_default",
+        &logs,
+        || PlayerLogSerializer::serialize_many_compressed(&logs, Compression::default()).unwrap(),
+        |data| PlayerLogSerializer::deserialize_many_compressed(data).unwrap(),
+    );
+
+    bench_format(
+        &mut group,
+        "auto_compress",
+        &logs,
+        || PlayerLogSerializer::serialize_many_auto_compress(&logs).unwrap(),
+        |data| PlayerLogSerializer::deserialize_auto_compress(data).unwrap(),
+    );
+
+    for (name, codec) in [
+        ("lz4", Codec::Lz4),
+        ("zlib5", Codec::Zlib(Compression::new(5))),
+    ] {
+        let options = SerializerOptions {
+            codec,
+            ..SerializerOptions::default()
+        };
+
+        bench_format(
+            &mut group,
+            name,
+            &logs,
+            || PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap(),
+            |data| PlayerLogSerializer::deserialize_many(data).unwrap(),
+        );
+    }
+
+    bench_format(
+        &mut group,
+        "zstd_seekable",
+        &logs,
+        || PlayerLogSerializer::serialize_blocked_seekable(&logs, 1_000, Vec::new()).unwrap(),
+        |data| {
+            let mut reader = ZstdSeekableReader::new(Cursor::new(data)).unwrap();
+            (0..reader.frame_count())
+                .flat_map(|n| {
+                    PlayerLogSerializer::deserialize_seekable_block(&mut reader, n).unwrap()
+                })
+                .collect::<Vec<_>>()
+        },
+    );
+
+    group.finish();
+}
+
+fn log_generation_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("LogGeneration");
+    group.sample_size(10);
+
+    for size in [10_000u64, 100_000, 500_000].map(record_count) {
+        group.throughput(Throughput::Elements(size));
+
+        group.bench_with_input(BenchmarkId::new("sequential", size), &size, |b, &size| {
+            b.iter(|| {
                 (0..size)
-                    .into_iter()
-                    .map(|_| log_generator())
-                    .collect::<Vec<PlayerLogBuilder>>()
-            },
-            |data| {
-                let serialized = serde_json::to_string(&data).unwrap();
-                let deserialized: Vec<PlayerLogBuilder> =
-                    serde_json::from_str(&serialized).unwrap();
-                assert_eq!(data, deserialized);
-                serialized.len()
-            },
-            BatchSize::NumBatches(size),
-        )
-    });
+                    .map(|_| log_generator().build().unwrap())
+                    .collect::<Vec<_>>()
+            })
+        });
 
-    group.bench_with_input("postcard", &10_000, |b, &size| {
-        b.iter_batched(
-            || {
+        group.bench_with_input(BenchmarkId::new("parallel", size), &size, |b, &size| {
+            b.iter(|| {
                 (0..size)
-                    .into_iter()
+                    .into_par_iter()
                     .map(|_| log_generator().build().unwrap())
-                    .collect::<Vec<PlayerLog>>()
+                    .collect::<Vec<_>>()
+            })
+        });
+
+        group.bench_with_input(
+            BenchmarkId::new("seeded_sequential", size),
+            &size,
+            |b, &size| {
+                b.iter(|| {
+                    let mut rng = StdRng::seed_from_u64(0);
+                    (0..size)
+                        .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+                        .collect::<Vec<_>>()
+                })
             },
-            |data| {
-                let serialized = postcard::to_allocvec(&data).unwrap();
-                let deserialized: Vec<PlayerLog> = postcard::from_bytes(&serialized).unwrap();
-                assert_eq!(data, deserialized);
-                serialized.len()
+        );
+
+        group.bench_with_input(
+            BenchmarkId::new("seeded_parallel", size),
+            &size,
+            |b, &size| {
+                b.iter(|| {
+                    (0..size)
+                        .into_par_iter()
+                        .map_init(
+                            || {
+                                StdRng::seed_from_u64(
+                                    rayon::current_thread_index().unwrap_or(0) as u64
+                                )
+                            },
+                            |rng, _| log_generator_seeded(rng).build().unwrap(),
+                        )
+                        .collect::<Vec<_>>()
+                })
             },
-            BatchSize::NumBatches(size),
-        )
+        );
+    }
+
+    group.finish();
+}
+
+fn projection_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Projection");
+
+    let size = record_count(10_000);
+    let data = PlayerLogSerializer::serialize_many(&generate_logs(size)).unwrap();
+    group.throughput(Throughput::Elements(size));
+
+    group.bench_function("full", |b| {
+        bench_batched(b, || PlayerLogSerializer::deserialize_many(&data).unwrap())
     });
 
-    group.bench_with_input("bincode", &10_000, |b, &size| {
+    for (name, projection) in [
+        (
+            "uuid_domain_timestamp",
+            Projection::UUID | Projection::DOMAIN | Projection::TIMESTAMP,
+        ),
+        ("all", Projection::all()),
+    ] {
+        group.bench_function(name, |b| {
+            bench_batched(b, || {
+                PlayerLogSerializer::deserialize_many_projected(&data, projection).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// scanning every name without keeping the logs, the owned batch never copies them out
+fn owned_batch_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("OwnedBatch");
+
+    let size = record_count(10_000);
+    let data = PlayerLogSerializer::serialize_many(&generate_logs(size)).unwrap();
+    group.throughput(Throughput::Elements(size));
+
+    group.bench_function("full", |b| {
         b.iter_batched(
-            || {
-                (0..size)
-                    .into_iter()
-                    .map(|_| log_generator().build().unwrap())
-                    .collect::<Vec<PlayerLog>>()
-            },
+            || data.clone(),
             |data| {
-                let serialized = bincode::serialize(&data).unwrap();
-                let deserialized: Vec<PlayerLog> = bincode::deserialize(&serialized).unwrap();
-                assert_eq!(data, deserialized);
-                serialized.len()
+                PlayerLogSerializer::deserialize_many(&data)
+                    .unwrap()
+                    .iter()
+                    .map(|log| log.player_name.len())
+                    .sum::<usize>()
             },
-            BatchSize::NumBatches(size),
+            BatchSize::LargeInput,
         )
     });
 
-    group.bench_with_input("our_serialization", &10_000, |b, &size| {
+    group.bench_function("owned", |b| {
         b.iter_batched(
-            || {
-                (0..size)
-                    .into_iter()
-                    .map(|_| log_generator().build().unwrap())
-                    .collect::<Vec<PlayerLog>>()
-            },
+            || data.clone(),
             |data| {
-                let serialized = PlayerLogSerializer::serialize_many(&data).unwrap();
-                let deserialized: Vec<PlayerLog> =
-                    PlayerLogSerializer::deserialize_many(&serialized).unwrap();
-
-                assert_eq!(data, deserialized);
-                serialized.len()
+                PlayerLogSerializer::deserialize_many_owned(data)
+                    .unwrap()
+                    .iter()
+                    .map(|log| log.player_name().len())
+                    .sum::<usize>()
             },
-            BatchSize::NumBatches(size),
+            BatchSize::LargeInput,
         )
     });
 
-    group.bench_with_input("our_serialization_compressed", &10_000, |b, &size| {
-        b.iter_batched(
-            || {
-                (0..size)
-                    .into_iter()
-                    .map(|_| log_generator().build().unwrap())
-                    .collect::<Vec<PlayerLog>>()
+    group.finish();
+}
+
+// run once as is and once with --features inline_storage, the ids name the storage so both end
+// up side by side under target/criterion/Storage
+fn storage_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Storage");
+    let storage = <storage_impl::NameBytes as storage_impl::ByteStorage>::NAME;
+
+    let size = record_count(10_000);
+    let builders = seeded_builders(size);
+    let logs = PlayerLogBuilder::build_many(&builders).unwrap();
+    let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+    group.throughput(Throughput::Elements(size));
+
+    group.bench_function(BenchmarkId::new("generate", storage), |b| {
+        let mut out = PlayerLog::default();
+        b.iter(|| {
+            for builder in &builders {
+                builder.build_into(&mut out).unwrap();
+            }
+        })
+    });
+
+    group.bench_function(BenchmarkId::new("serialize", storage), |b| {
+        bench_batched(b, || PlayerLogSerializer::serialize_many(&logs).unwrap())
+    });
+
+    group.bench_function(BenchmarkId::new("deserialize", storage), |b| {
+        bench_batched(b, || PlayerLogSerializer::deserialize_many(&data).unwrap())
+    });
+
+    group.finish();
+}
+
+fn utf8_validation_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("Utf8Validation");
+
+    let size = record_count(10_000);
+    let data = PlayerLogSerializer::serialize_many(&generate_logs(size)).unwrap();
+    group.throughput(Throughput::Elements(size));
+
+    for (name, options) in [
+        ("permissive", DeserializerOptions::default()),
+        (
+            "validate_utf8",
+            DeserializerOptions {
+                validate_utf8: true,
+                ..Default::default()
             },
-            |data| {
-                let serialized =
-                    PlayerLogSerializer::serialize_many_compressed(&data, Compression::default())
-                        .unwrap();
-                let deserialized: Vec<PlayerLog> =
-                    PlayerLogSerializer::deserialize_many_compressed(&serialized).unwrap();
-
-                assert_eq!(data, deserialized);
-                serialized.len()
+        ),
+        (
+            "reject_nul",
+            DeserializerOptions {
+                validate_utf8: true,
+                reject_nul: true,
+                ..Default::default()
             },
-            BatchSize::NumBatches(size),
-        )
-    });
+        ),
+    ] {
+        group.bench_function(name, |b| {
+            bench_batched(b, || {
+                PlayerLogSerializer::deserialize_many_with_options(&data, &options).unwrap()
+            })
+        });
+    }
+
+    group.finish();
+}
+
+// the sizes are the point here, the timings only show what the codecs cost on each
+fn corpus_compression_benchmark(c: &mut Criterion) {
+    let mut group = c.benchmark_group("CorpusCompression");
+
+    let size = record_count(10_000);
+    group.throughput(Throughput::Elements(size));
+
+    for (source, config) in [
+        ("random", GeneratorConfig::default()),
+        ("corpus", GeneratorConfig::corpus()),
+    ] {
+        let mut rng = StdRng::seed_from_u64(0);
+        let builders = (0..size)
+            .map(|_| log_generator_with(&mut rng, &config))
+            .collect::<Vec<_>>();
+        let logs = PlayerLogBuilder::build_many(&builders).unwrap();
+
+        for (name, codec) in [
+            ("none", Codec::None),
+            ("lz4", Codec::Lz4),
+            ("zlib", Codec::Zlib(Compression::default())),
+        ] {
+            let options = SerializerOptions {
+                codec,
+                ..SerializerOptions::default()
+            };
+            let len = PlayerLogSerializer::serialize_many_with_options(&logs, &options)
+                .unwrap()
+                .len();
+            println!(
+                "{source}/{name}: {len} bytes, {:.1} per record",
+                len as f64 / size as f64
+            );
+
+            group.bench_function(BenchmarkId::new(source, name), |b| {
+                bench_batched(b, || {
+                    PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap()
+                })
+            });
+        }
+    }
 
     group.finish();
 }
 
-criterion_group!(benches, criterion_benchmark);
+criterion_group!(
+    benches,
+    criterion_benchmark,
+    compressed_serialization_benchmark,
+    log_generation_benchmark,
+    projection_benchmark,
+    owned_batch_benchmark,
+    storage_benchmark,
+    utf8_validation_benchmark,
+    corpus_compression_benchmark
+);
 criterion_main!(benches);
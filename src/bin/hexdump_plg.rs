@@ -0,0 +1,99 @@
+use std::fs::{self, File};
+use std::io::{BufReader, ErrorKind, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use binary_storage_test::player_log::{PlayerLog, BATCH_MAGIC};
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(
+    about = "Annotated hex dump of the records of a batch file or a framed log file, as written \
+             by FileStore"
+)]
+struct Cli {
+    path: PathBuf,
+    /// Index of the record to dump, required unless --all is given
+    #[arg(required_unless_present = "all", conflicts_with = "all")]
+    index: Option<usize>,
+    /// Dump every record
+    #[arg(long)]
+    all: bool,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let mut magic = [0; BATCH_MAGIC.len()];
+    let is_batch = File::open(&cli.path)?
+        .read_exact(&mut magic)
+        .is_ok_and(|()| magic == BATCH_MAGIC);
+
+    let dumps = match (is_batch, cli.index) {
+        (true, Some(index)) => vec![(
+            index,
+            PlayerLog::explain_record_at(&fs::read(&cli.path)?, index)?,
+        )],
+        (true, None) => PlayerLog::explain_records(&fs::read(&cli.path)?)?
+            .into_iter()
+            .enumerate()
+            .collect(),
+        (false, index) => framed_dumps(&cli.path, index)?,
+    };
+
+    for (i, (index, dump)) in dumps.iter().enumerate() {
+        if cli.all {
+            if i > 0 {
+                println!();
+            }
+            println!("[Record {index}]");
+        }
+        print!("{dump}");
+    }
+
+    Ok(())
+}
+
+// only the frame lengths are read to skip to `index`, so a corrupt record before it doesn't matter
+fn framed_dumps(path: &Path, index: Option<usize>) -> Result<Vec<(usize, String)>> {
+    let mut reader = BufReader::new(File::open(path)?);
+
+    let mut dumps = Vec::new();
+    let mut frames = 0;
+    loop {
+        let i = frames;
+        let mut len = [0; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_be_bytes(len) as usize;
+        if len > PlayerLog::MAX_ENCODED_SIZE {
+            bail!("frame {i} has length {len}, the file is corrupt or not a framed log file");
+        }
+
+        frames += 1;
+
+        if index.is_some_and(|index| index != i) {
+            reader.seek(SeekFrom::Current(len as i64))?;
+            continue;
+        }
+
+        let mut frame = vec![0; len];
+        reader
+            .read_exact(&mut frame)
+            .with_context(|| format!("frame {i} is truncated"))?;
+        dumps.push((i, PlayerLog::explain(&frame)));
+
+        if index.is_some() {
+            return Ok(dumps);
+        }
+    }
+
+    if let Some(index) = index {
+        bail!("record {index} out of range, the file has {frames} records");
+    }
+
+    Ok(dumps)
+}
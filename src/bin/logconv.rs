@@ -0,0 +1,424 @@
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use binary_storage_test::player_log::{
+    export,
+    filter::PlayerLogFilter,
+    pipeline::{BatchPipeline, RedactPolicy},
+    query::FilterExpr,
+    Codec, PlayerLog, PlayerLogSerializer, SerializerOptions, ServerVersion,
+};
+use bytesize::ByteSize;
+use clap::{Parser, Subcommand, ValueEnum};
+use flate2::Compression;
+use indicatif::{ProgressBar, ProgressStyle};
+
+#[derive(Parser)]
+#[command(about = "Convert and inspect player log files")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Convert a log file between formats
+    Convert {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long, value_enum)]
+        input_format: InputFormat,
+        #[arg(long)]
+        output: PathBuf,
+        #[arg(long, value_enum)]
+        output_format: OutputFormat,
+        /// Show a progress bar while serializing binary output
+        #[arg(long)]
+        progress: bool,
+        /// Only keep logs of a server version range like 1.12..1.20, either end can be left out
+        #[arg(long, value_name = "MIN..MAX")]
+        filter: Option<String>,
+        /// Only keep logs matching a query like 'domain = "mc.example.com" and online'
+        #[arg(long = "where", value_name = "QUERY", value_parser = parse_query)]
+        query: Option<PlayerLogFilter>,
+        /// Redact the player identity, or with network the geo info and proxy ip as well
+        #[arg(long, value_enum)]
+        redact: Option<Redact>,
+    },
+    /// Rewrite a batch file with another codec without decoding its records
+    Recompress {
+        #[arg(long)]
+        input: PathBuf,
+        #[arg(long)]
+        output: PathBuf,
+        #[arg(long, value_enum)]
+        codec: CodecArg,
+        /// Compression level for zlib, 0 to 9
+        #[arg(long, default_value_t = 6, value_parser = clap::value_parser!(u32).range(0..=9))]
+        level: u32,
+    },
+    /// Print the record count, size, format and version of a batch file
+    Info {
+        #[arg(long)]
+        input: PathBuf,
+    },
+    /// Count the records of a batch file by version, field, outcome, country and proxy
+    Stats {
+        #[arg(long)]
+        input: PathBuf,
+        /// Only count logs matching a query, see convert
+        #[arg(long = "where", value_name = "QUERY", value_parser = parse_query)]
+        query: Option<PlayerLogFilter>,
+    },
+    /// Print the batch header, optionally with an annotated hex dump of one record
+    Inspect {
+        #[arg(long)]
+        input: PathBuf,
+        /// Index of the record to dump field by field
+        #[arg(long, value_name = "RECORD_INDEX")]
+        explain: Option<usize>,
+        /// List the indices of the records matching a query, see convert
+        #[arg(long = "where", value_name = "QUERY", value_parser = parse_query)]
+        query: Option<PlayerLogFilter>,
+    },
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum InputFormat {
+    Binary,
+    Compressed,
+    Ndjson,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum CodecArg {
+    None,
+    Zlib,
+    Lz4,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum Redact {
+    Identity,
+    Network,
+}
+
+#[derive(ValueEnum, Clone, Copy)]
+enum OutputFormat {
+    Binary,
+    Compressed,
+    Ndjson,
+    Csv,
+}
+
+fn main() -> Result<()> {
+    match Cli::parse().command {
+        Command::Convert {
+            input,
+            input_format,
+            output,
+            output_format,
+            progress,
+            filter,
+            query,
+            redact,
+        } => {
+            let mut pipeline = BatchPipeline::new();
+            if let Some(range) = filter {
+                pipeline = pipeline.filter(version_filter(&range)?);
+            }
+            if let Some(query) = query {
+                pipeline = pipeline.filter(query);
+            }
+            if let Some(redact) = redact {
+                pipeline = pipeline.redact(match redact {
+                    Redact::Identity => RedactPolicy::Identity,
+                    Redact::Network => RedactPolicy::Network,
+                });
+            }
+
+            convert(
+                &input,
+                input_format,
+                &output,
+                output_format,
+                progress,
+                &mut pipeline,
+            )
+        }
+        Command::Recompress {
+            input,
+            output,
+            codec,
+            level,
+        } => {
+            let codec = match codec {
+                CodecArg::None => Codec::None,
+                CodecArg::Zlib => Codec::Zlib(Compression::new(level)),
+                CodecArg::Lz4 => Codec::Lz4,
+            };
+            recompress(&input, &output, codec)
+        }
+        Command::Info { input } => info(&input),
+        Command::Stats { input, query } => stats(&input, query.as_ref()),
+        Command::Inspect {
+            input,
+            explain,
+            query,
+        } => inspect(&input, explain, query.as_ref()),
+    }
+}
+
+fn convert(
+    input: &Path,
+    input_format: InputFormat,
+    output: &Path,
+    output_format: OutputFormat,
+    progress: bool,
+    pipeline: &mut BatchPipeline,
+) -> Result<()> {
+    let mut options = SerializerOptions::default();
+
+    let logs = match input_format {
+        InputFormat::Binary | InputFormat::Compressed => {
+            let data = fs::read(input)?;
+
+            // the batch id has to survive conversion so retried uploads are still caught
+            let header = PlayerLogSerializer::probe(&data)?;
+            options.batch_id = Some(header.batch_id);
+            options.producer = header.producer.map(str::to_string);
+
+            PlayerLogSerializer::deserialize_many(&data)?
+        }
+        InputFormat::Ndjson => export::read_ndjson(BufReader::new(File::open(input)?))?,
+    };
+
+    // a filtered or redacted batch is a different batch
+    if !pipeline.is_empty() {
+        options.batch_id = None;
+    }
+    let logs = logs
+        .into_iter()
+        .filter_map(|log| pipeline.apply(log))
+        .collect::<Vec<_>>();
+
+    match output_format {
+        OutputFormat::Binary | OutputFormat::Compressed => {
+            if matches!(output_format, OutputFormat::Compressed) {
+                options.codec = Codec::Zlib(Compression::default());
+            }
+
+            fs::write(output, serialize(&logs, &options, progress)?)?;
+        }
+        OutputFormat::Ndjson => export::write_ndjson(&logs, BufWriter::new(File::create(output)?))?,
+        OutputFormat::Csv => export::write_csv(&logs, BufWriter::new(File::create(output)?))?,
+    }
+
+    println!("converted {} logs to {}", logs.len(), output.display());
+    Ok(())
+}
+
+fn version_filter(range: &str) -> Result<PlayerLogFilter> {
+    let (min, max) = range
+        .split_once("..")
+        .context("the filter has to look like MIN..MAX")?;
+
+    match (min, max) {
+        ("", "") => Ok(PlayerLogFilter::default()),
+        (min, "") => PlayerLogFilter::by_min_version(min),
+        ("", max) => PlayerLogFilter::by_max_version(max),
+        (min, max) => PlayerLogFilter::by_version_range(min, max),
+    }
+}
+
+// clap prints the error under the query with the offending token marked
+fn parse_query(query: &str) -> Result<PlayerLogFilter, String> {
+    let expr =
+        FilterExpr::parse(query).map_err(|e| format!("{}\n{}", e.message, e.pointer(query)))?;
+
+    Ok(PlayerLogFilter {
+        expr: Some(expr),
+        ..PlayerLogFilter::default()
+    })
+}
+
+fn serialize(logs: &[PlayerLog], options: &SerializerOptions, progress: bool) -> Result<Vec<u8>> {
+    if !progress {
+        return PlayerLogSerializer::serialize_many_with_options(logs, options);
+    }
+
+    let bar = ProgressBar::new(logs.len() as u64).with_style(ProgressStyle::with_template(
+        "{bar:40} {pos}/{len} logs ({eta})",
+    )?);
+
+    let serialized =
+        PlayerLogSerializer::serialize_many_with_progress(logs, options, &|n| bar.inc(n as u64))?;
+    bar.finish();
+
+    Ok(serialized)
+}
+
+fn recompress(input: &Path, output: &Path, codec: Codec) -> Result<()> {
+    let data = fs::read(input)?;
+    let recompressed = PlayerLogSerializer::recompress(&data, None, codec)?;
+    fs::write(output, &recompressed)?;
+
+    println!(
+        "recompressed {} with {}, {} to {}",
+        output.display(),
+        codec.name(),
+        ByteSize(data.len() as u64),
+        ByteSize(recompressed.len() as u64)
+    );
+    Ok(())
+}
+
+fn info(input: &Path) -> Result<()> {
+    let stats = PlayerLogSerializer::file_stats(input)?;
+
+    println!("records: {}", stats.record_count);
+    println!("file size: {}", ByteSize(stats.file_size));
+    println!("format: batch container, codec {}", stats.codec.name());
+    println!("version: container v{}", stats.container_version);
+    if let Some(producer) = &stats.producer {
+        println!("producer: {producer}");
+    }
+
+    Ok(())
+}
+
+fn stats(input: &Path, query: Option<&PlayerLogFilter>) -> Result<()> {
+    let stats = match query {
+        Some(query) => PlayerLogSerializer::file_stats_filtered(input, query)?,
+        None => PlayerLogSerializer::file_stats(input)?,
+    };
+
+    println!("records: {}", stats.record_count);
+    if query.is_some() {
+        println!("matching records: {}", stats.matched_count);
+    }
+    for (version, count) in &stats.binary_versions {
+        println!("  binary_version {version}: {count} records");
+    }
+    for (field, count) in &stats.present_fields {
+        println!("  {}: {count} records", field.to_lowercase());
+    }
+    println!("server versions:");
+    for (id, count) in &stats.server_versions {
+        let version = ServerVersion::Known(*id);
+        println!("  {}: {count} records", version.name().unwrap_or("invalid"));
+    }
+    for (name, count) in &stats.unknown_versions {
+        println!("  {name} (unknown): {count} records");
+    }
+    println!("login outcomes:");
+    for (outcome, count) in &stats.outcomes {
+        println!("  {outcome}: {count} records");
+    }
+    if !stats.countries.is_empty() {
+        println!("countries:");
+        for (country, count) in &stats.countries {
+            println!("  {country}: {count} records");
+        }
+    }
+    if !stats.proxies.is_empty() {
+        println!("proxies:");
+        for (proxy_ip, count) in &stats.proxies {
+            println!("  {proxy_ip}: {count} records");
+        }
+    }
+
+    Ok(())
+}
+
+fn inspect(input: &Path, explain: Option<usize>, query: Option<&PlayerLogFilter>) -> Result<()> {
+    let data = fs::read(input)?;
+    let header = PlayerLogSerializer::probe(&data)?;
+
+    println!("codec: {}", header.codec.name());
+    println!(
+        "batch id: {}",
+        header
+            .batch_id
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>()
+    );
+    println!("records: {}", header.record_count);
+    if let Some(producer) = header.producer {
+        println!("producer: {producer}");
+    }
+
+    if let Some(query) = query {
+        let matching = PlayerLogSerializer::deserialize_many_filtered(&data, query)?;
+        println!("matching records: {}", matching.len());
+        for (ordinal, _) in &matching {
+            println!("  {ordinal}");
+        }
+    }
+
+    if let Some(index) = explain {
+        println!();
+        print!("{}", PlayerLog::explain_record_at(&data, index)?);
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use binary_storage_test::log_generator_seeded;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn recompress_takes_a_zlib_level_up_to_9() {
+        let args = |level: &str| {
+            Cli::try_parse_from([
+                "logconv",
+                "recompress",
+                "--input",
+                "in.plg",
+                "--output",
+                "out.plg",
+                "--codec",
+                "zlib",
+                "--level",
+                level,
+            ])
+        };
+
+        assert!(matches!(
+            args("9").unwrap().command,
+            Command::Recompress {
+                codec: CodecArg::Zlib,
+                level: 9,
+                ..
+            }
+        ));
+        assert!(args("10").is_err());
+    }
+
+    #[test]
+    fn recompress_writes_the_same_records() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let logs = (0..100)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect::<Vec<_>>();
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("in.plg");
+        let output = dir.path().join("out.plg");
+        fs::write(&input, PlayerLogSerializer::serialize_many(&logs).unwrap()).unwrap();
+
+        recompress(&input, &output, Codec::Lz4).unwrap();
+        let data = fs::read(&output).unwrap();
+        assert_eq!(
+            PlayerLogSerializer::probe(&data).unwrap().codec.id(),
+            Codec::Lz4.id()
+        );
+        assert_eq!(PlayerLogSerializer::deserialize_many(&data).unwrap(), logs);
+    }
+}
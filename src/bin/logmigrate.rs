@@ -0,0 +1,90 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use binary_storage_test::player_log::{PlayerLog, PlayerLogSerializer, SerializerOptions};
+use clap::Parser;
+
+#[derive(Parser)]
+#[command(about = "Upgrade the records of a batch file to the current binary_version")]
+struct Cli {
+    #[arg(long)]
+    input: PathBuf,
+    /// Where to write the upgraded batch, required unless --in-place or --dry-run is given
+    #[arg(long, conflicts_with = "in_place")]
+    output: Option<PathBuf>,
+    /// Report what would change without writing anything
+    #[arg(long)]
+    dry_run: bool,
+    /// Replace the input file, the new file is renamed over it so a crash can't leave it half
+    /// written
+    #[arg(long)]
+    in_place: bool,
+    /// Copy the original file here before it's overwritten
+    #[arg(long, value_name = "PATH")]
+    backup: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+
+    let output = match (&cli.output, cli.in_place) {
+        (Some(output), _) => Some(output.as_path()),
+        (None, true) => Some(cli.input.as_path()),
+        (None, false) if cli.dry_run => None,
+        (None, false) => bail!("either --output, --in-place or --dry-run is needed"),
+    };
+
+    if cli.backup.is_some() && !cli.in_place {
+        bail!("--backup only applies to --in-place");
+    }
+
+    let data = fs::read(&cli.input)?;
+    let header = PlayerLogSerializer::probe(&data)?;
+    let mut logs = PlayerLogSerializer::deserialize_many(&data)?;
+
+    let upgraded = logs
+        .iter_mut()
+        .map(PlayerLog::upgrade)
+        .filter(|u| *u)
+        .count();
+    let already_current = logs.len() - upgraded;
+
+    // same batch id, so a migrated batch that gets uploaded again is still caught as a retry
+    let options = SerializerOptions {
+        codec: header.codec,
+        batch_id: Some(header.batch_id),
+        producer: header.producer.map(str::to_string),
+        endianness: header.endianness,
+    };
+    let migrated = PlayerLogSerializer::serialize_many_with_options(&logs, &options)?;
+
+    let destination = match output {
+        Some(output) if !cli.dry_run => {
+            if let Some(backup) = &cli.backup {
+                fs::copy(&cli.input, backup)?;
+            }
+            write_atomic(output, &migrated)?;
+            output.display().to_string()
+        }
+        _ => "not written, dry run".to_string(),
+    };
+
+    println!(
+        "Upgraded {upgraded} records from v1 to v{}, {already_current} records already at v{}, \
+         output: {} bytes ({destination})",
+        PlayerLog::CURRENT_VERSION,
+        PlayerLog::CURRENT_VERSION,
+        migrated.len(),
+    );
+
+    Ok(())
+}
+
+fn write_atomic(path: &Path, data: &[u8]) -> Result<()> {
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data)?;
+    fs::rename(tmp, path)?;
+
+    Ok(())
+}
@@ -0,0 +1,326 @@
+//! Timing serialization formats against each other on the same logs, for the format comparison
+//! the binary runs and for anyone wanting to hold their own format up against the others.
+
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use bytesize::ByteSize;
+use humantime::format_duration;
+use serde_json::{json, Value};
+
+use crate::player_log::verify::{check_batches_equivalent, Equivalence};
+use crate::player_log::PlayerLog;
+
+/// A format to time, as the two halves of a round trip.
+#[derive(Debug, Clone, Copy)]
+pub struct Contender {
+    pub name: &'static str,
+    /// Appends the logs to the buffer, which is empty and reused between runs.
+    pub serialize: fn(&[PlayerLog], &mut Vec<u8>) -> Result<()>,
+    pub deserialize: fn(&[u8]) -> Result<Vec<PlayerLog>>,
+}
+
+/// The timed round trips of one [`Contender`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Timing {
+    pub name: &'static str,
+    /// Serialized bytes.
+    pub size: usize,
+    pub runs: Vec<Duration>,
+}
+
+fn median_of(sorted: &[Duration]) -> Duration {
+    let mid = sorted.len() / 2;
+    if sorted.is_empty() {
+        Duration::ZERO
+    } else if sorted.len().is_multiple_of(2) {
+        (sorted[mid - 1] + sorted[mid]) / 2
+    } else {
+        sorted[mid]
+    }
+}
+
+impl Timing {
+    pub fn median(&self) -> Duration {
+        let mut runs = self.runs.clone();
+        runs.sort_unstable();
+        median_of(&runs)
+    }
+
+    pub fn min(&self) -> Duration {
+        self.runs.iter().min().copied().unwrap_or_default()
+    }
+
+    /// Median absolute deviation, unlike the standard deviation one slow outlier barely moves it.
+    pub fn mad(&self) -> Duration {
+        let median = self.median();
+        let mut deviations = self
+            .runs
+            .iter()
+            .map(|run| run.abs_diff(median))
+            .collect::<Vec<_>>();
+        deviations.sort_unstable();
+        median_of(&deviations)
+    }
+
+    pub fn to_json(&self) -> Value {
+        let micros = |d: Duration| d.as_micros() as u64;
+        json!({
+            "name": self.name,
+            "size": self.size,
+            "median_us": micros(self.median()),
+            "min_us": micros(self.min()),
+            "mad_us": micros(self.mad()),
+            "runs_us": self.runs.iter().copied().map(micros).collect::<Vec<_>>(),
+        })
+    }
+}
+
+impl fmt::Display for Timing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}: median {}, min {}, mad {}, {}",
+            self.name,
+            format_duration(self.median()),
+            format_duration(self.min()),
+            format_duration(self.mad()),
+            ByteSize(self.size as u64)
+        )
+    }
+}
+
+/// What [`run_comparison`] measured, one [`Timing`] per contender in the order they were given.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComparisonReport {
+    pub logs: usize,
+    pub repetitions: usize,
+    pub timings: Vec<Timing>,
+}
+
+impl ComparisonReport {
+    /// One aligned row per contender.
+    pub fn render_table(&self) -> String {
+        let rows = self
+            .timings
+            .iter()
+            .map(|timing| {
+                [
+                    timing.name.to_string(),
+                    format_duration(timing.median()).to_string(),
+                    format_duration(timing.min()).to_string(),
+                    format_duration(timing.mad()).to_string(),
+                    ByteSize(timing.size as u64).to_string(),
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        let header = ["format", "median", "min", "mad", "size"].map(str::to_string);
+        let mut widths = header.clone().map(|cell| cell.len());
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.len());
+            }
+        }
+
+        let mut table = String::new();
+        for row in std::iter::once(&header).chain(&rows) {
+            let cells = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect::<Vec<_>>();
+            table.push_str(cells.join("  ").trim_end());
+            table.push('\n');
+        }
+
+        table
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "logs": self.logs,
+            "repetitions": self.repetitions,
+            "formats": self.timings.iter().map(Timing::to_json).collect::<Vec<_>>(),
+        })
+    }
+}
+
+/// Round trips `logs` through every contender, once untimed to warm up and check the logs come
+/// back unchanged, then `repetitions` times timed.
+///
+/// Fails on the first contender that errors or hands back different logs.
+pub fn run_comparison(
+    logs: &[PlayerLog],
+    contenders: &[Contender],
+    repetitions: usize,
+) -> Result<ComparisonReport> {
+    if repetitions == 0 {
+        bail!("at least one repetition is needed");
+    }
+
+    let mut buf = Vec::new();
+    let timings = contenders
+        .iter()
+        .map(|contender| {
+            time_round_trip(contender, logs, repetitions, &mut buf)
+                .with_context(|| contender.name.to_string())
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(ComparisonReport {
+        logs: logs.len(),
+        repetitions,
+        timings,
+    })
+}
+
+fn time_round_trip(
+    contender: &Contender,
+    logs: &[PlayerLog],
+    repetitions: usize,
+    buf: &mut Vec<u8>,
+) -> Result<Timing> {
+    let round_trip = |buf: &mut Vec<u8>| {
+        buf.clear();
+        (contender.serialize)(logs, buf)?;
+        (contender.deserialize)(buf)
+    };
+
+    let deserialized = round_trip(buf)?;
+    check_batches_equivalent(logs, &deserialized, Equivalence::Ordered)
+        .context("the logs changed in the round trip")?;
+    let size = buf.len();
+
+    let runs = (0..repetitions)
+        .map(|_| {
+            let instant = Instant::now();
+            let deserialized = round_trip(buf)?;
+            let elapsed = instant.elapsed();
+
+            // freeing the records isn't part of the round trip
+            drop(deserialized);
+            Ok(elapsed)
+        })
+        .collect::<Result<_>>()?;
+
+    Ok(Timing {
+        name: contender.name,
+        size,
+        runs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player_log::PlayerLogSerializer;
+
+    fn timing(runs_ms: &[u64]) -> Timing {
+        Timing {
+            name: "stub",
+            size: 0,
+            runs: runs_ms.iter().copied().map(Duration::from_millis).collect(),
+        }
+    }
+
+    const fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn statistics_of_an_odd_number_of_runs() {
+        let timing = timing(&[9, 1, 5, 3, 100]);
+        assert_eq!(timing.median(), ms(5));
+        assert_eq!(timing.min(), ms(1));
+        // deviations 4, 4, 0, 2, 95
+        assert_eq!(timing.mad(), ms(4));
+    }
+
+    #[test]
+    fn statistics_of_an_even_number_of_runs() {
+        let timing = timing(&[4, 1, 3, 2]);
+        assert_eq!(timing.median(), Duration::from_micros(2_500));
+        assert_eq!(timing.min(), ms(1));
+        // deviations 1.5, 1.5, 0.5, 0.5
+        assert_eq!(timing.mad(), ms(1));
+    }
+
+    #[test]
+    fn statistics_of_no_runs_are_zero() {
+        let timing = timing(&[]);
+        assert_eq!(timing.median(), Duration::ZERO);
+        assert_eq!(timing.min(), Duration::ZERO);
+        assert_eq!(timing.mad(), Duration::ZERO);
+    }
+
+    fn stub_serialize(logs: &[PlayerLog], out: &mut Vec<u8>) -> Result<()> {
+        out.extend(PlayerLogSerializer::serialize_many(logs)?);
+        Ok(())
+    }
+
+    fn stub_deserialize(data: &[u8]) -> Result<Vec<PlayerLog>> {
+        PlayerLogSerializer::deserialize_many(data)
+    }
+
+    // hands back one log less than it was given
+    fn lossy_deserialize(data: &[u8]) -> Result<Vec<PlayerLog>> {
+        let mut logs = PlayerLogSerializer::deserialize_many(data)?;
+        logs.pop();
+        Ok(logs)
+    }
+
+    fn logs() -> Vec<PlayerLog> {
+        (0..10)
+            .map(|_| crate::log_generator().build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn run_comparison_times_every_contender() {
+        let logs = logs();
+        let contender = Contender {
+            name: "stub",
+            serialize: stub_serialize,
+            deserialize: stub_deserialize,
+        };
+
+        let report = run_comparison(&logs, &[contender, contender], 3).unwrap();
+        assert_eq!(report.logs, 10);
+        assert_eq!(report.repetitions, 3);
+        assert_eq!(report.timings.len(), 2);
+        for timing in &report.timings {
+            assert_eq!(timing.runs.len(), 3);
+            assert_eq!(
+                timing.size,
+                PlayerLogSerializer::serialize_many(&logs).unwrap().len()
+            );
+        }
+
+        assert_eq!(report.render_table().lines().count(), 3);
+        assert_eq!(report.to_json()["formats"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn run_comparison_rejects_a_lossy_contender() {
+        let contender = Contender {
+            name: "lossy",
+            serialize: stub_serialize,
+            deserialize: lossy_deserialize,
+        };
+
+        let e = run_comparison(&logs(), &[contender], 1).unwrap_err();
+        assert!(format!("{e:#}").starts_with("lossy: "), "{e:#}");
+    }
+
+    #[test]
+    fn run_comparison_needs_a_repetition() {
+        let contender = Contender {
+            name: "stub",
+            serialize: stub_serialize,
+            deserialize: stub_deserialize,
+        };
+        assert!(run_comparison(&logs(), &[contender], 0).is_err());
+    }
+}
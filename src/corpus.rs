@@ -0,0 +1,308 @@
+//! Server names, username words and a version distribution shaped like what a public collector
+//! sees, for the [`GeneratorMode::Corpus`](crate::GeneratorMode::Corpus) generator.
+//!
+//! Uniformly random strings have far more entropy than real names and domains, so codecs look
+//! worse on them than they do on real traffic. Everything here is drawn from the rng passed in,
+//! a seeded rng always gives the same samples.
+
+use std::net::Ipv4Addr;
+
+use rand::seq::SliceRandom;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+/// Roughly by popularity, [`domain`] picks from the front far more often than from the back.
+pub static SERVER_NAMES: &[&str] = &[
+    "hypixel.net",
+    "mineplex.com",
+    "cubecraft.net",
+    "wynncraft.com",
+    "minehut.com",
+    "2b2t.org",
+    "manacube.com",
+    "pika-network.net",
+    "jartexnetwork.com",
+    "blocksmc.com",
+    "minemen.club",
+    "gommehd.net",
+    "herobrine.org",
+    "complexmc.net",
+    "purpleprison.org",
+    "mccentral.org",
+    "munchymc.com",
+    "opblocks.com",
+    "vanitymc.co",
+    "lemoncloud.net",
+    "mc-legends.com",
+    "craftyourtown.com",
+    "skyblock.net",
+    "cosmicpvp.com",
+    "hoplite.gg",
+    "minesaga.org",
+    "pvpland.net",
+    "bedwarspractice.club",
+    "universocraft.com",
+    "mush.com.br",
+    "rederaid.com",
+    "craftersland.net",
+    "pixelmonrealms.com",
+    "mcprison.com",
+    "mineberry.org",
+    "loverfella.com",
+    "cavepvp.org",
+    "viper.gg",
+    "arkhamnetwork.org",
+    "desteria.com",
+    "minecadia.com",
+    "wildprison.net",
+    "akumamc.net",
+    "mineclub.com",
+    "earthmc.net",
+    "towny.world",
+    "civmc.net",
+    "nationsglory.fr",
+    "funcraft.net",
+    "paladium-pvp.fr",
+    "epicube.fr",
+    "rinaorc.com",
+    "craftok.fr",
+    "mineland.net",
+    "vimeworld.ru",
+    "mineblaze.ru",
+    "cristalix.gg",
+    "hytale-fans.de",
+    "griefergames.net",
+    "rewinside.tv",
+    "timolia.de",
+    "playlegend.net",
+    "mcone.eu",
+    "cytooxien.de",
+    "stardix.com",
+    "mcsg.pl",
+    "skript.pl",
+    "minestar.pl",
+    "craftplay.pl",
+    "hardcore.pl",
+    "blazingpack.pl",
+    "minelc.com",
+    "chunkmc.net",
+    "blockfront.gg",
+    "nookure.net",
+    "mcbrawl.com",
+    "sovereigncraft.net",
+    "atlascraft.net",
+    "emeraldmc.net",
+    "smpearth.com",
+    "oneblockmc.com",
+    "lifestealsmc.net",
+    "anarchy.to",
+    "constantiam.net",
+    "9b9t.com",
+    "pvp.land",
+    "practice.gg",
+    "kitpvp.net",
+    "uhc.gg",
+    "mcplayhd.net",
+    "applemc.fun",
+    "kirbycraft.net",
+    "squidcraft.org",
+    "fadecloud.com",
+    "cubeville.org",
+    "nerd.nu",
+    "bteam.net",
+    "buildtheearth.net",
+    "mineverse.com",
+    "ecocitycraft.com",
+    "ultimis.net",
+    "snowcraft.net",
+    "aetherworlds.com",
+    "mythcraft.io",
+    "questmc.net",
+    "dragoncraft.net",
+    "pirate-smp.com",
+    "skywars.network",
+    "farmworld.gg",
+    "cozysmp.net",
+    "moonlightmc.net",
+    "starlegacy.net",
+    "bluefoxmc.com",
+    "redstonecity.net",
+    "mapleleafmc.ca",
+    "aussiecraft.com.au",
+    "kiwismp.nz",
+    "nordicmc.se",
+    "dutchcraft.nl",
+    "espanacraft.es",
+    "italiamc.it",
+];
+
+/// Prefixes servers put in front of their domain, weighted by how often players type them.
+pub static SUBDOMAINS: &[(&str, u32)] = &[
+    ("", 40),
+    ("mc.", 20),
+    ("play.", 25),
+    ("hub.", 5),
+    ("eu.", 4),
+    ("us.", 3),
+    ("na.", 2),
+    ("beta.", 1),
+];
+
+/// Pieces usernames are glued together from.
+pub static NAME_WORDS: &[&str] = &[
+    "Dragon", "Shadow", "Creeper", "Ender", "Steve", "Alex", "Dark", "Ninja", "Pixel", "Block",
+    "Craft", "Miner", "Gamer", "Pro", "King", "Queen", "Lord", "Wolf", "Fox", "Bear", "Tiger",
+    "Frost", "Fire", "Storm", "Night", "Sky", "Star", "Moon", "Sun", "Blaze", "Ghast", "Slime",
+    "Zombie", "Skelly", "Diamond", "Emerald", "Gold", "Iron", "Redstone", "Nether", "Void",
+    "Cookie", "Potato", "Panda", "Bunny", "Kitty", "Duck", "Noob", "Epic", "Super", "Mega",
+    "Ultra", "Toxic", "Silent", "Crazy", "Lucky", "Happy", "Sad", "Angry", "Lazy", "Swift",
+    "Hunter", "Knight", "Wizard", "Archer", "Builder", "Farmer", "Player", "Legend", "Ghost",
+    "Reaper", "Viper", "Cobra", "Hawk", "Raven", "Phoenix", "Titan", "Chaos", "Nova", "Echo",
+    "Blue", "Red", "Green", "Purple", "Pink", "Sweaty", "Cracked", "Tryhard", "Clutch",
+];
+
+/// Old pvp servers keep 1.8 popular, most of the rest is on the latest two versions. Versions
+/// missing from the list get a weight of 1.
+pub static VERSION_WEIGHTS: &[(&str, u32)] = &[
+    ("1.8", 30),
+    ("1.12", 8),
+    ("1.16", 6),
+    ("1.19", 5),
+    ("1.20", 25),
+    ("1.21", 20),
+];
+
+// cubing a uniform sample puts most of the mass at the start of the list
+fn skewed_index<R: Rng>(rng: &mut R, len: usize) -> usize {
+    let index = (rng.gen::<f64>().powi(3) * len as f64) as usize;
+    index.min(len - 1)
+}
+
+/// A popular server far more often than an obscure one, with a weighted subdomain.
+pub fn domain<R: Rng>(rng: &mut R) -> String {
+    let name = SERVER_NAMES[skewed_index(rng, SERVER_NAMES.len())];
+    let (subdomain, _) = SUBDOMAINS
+        .choose_weighted(rng, |(_, weight)| *weight)
+        .expect("weights are positive");
+
+    format!("{subdomain}{name}")
+}
+
+/// The same domain always resolves to the same address, the way real servers do.
+pub fn server_ip(domain: &str) -> Ipv4Addr {
+    let hash = Sha256::digest(domain.as_bytes());
+    Ipv4Addr::new(hash[0].max(1), hash[1], hash[2], hash[3].max(1))
+}
+
+/// Between 3 and 16 characters of `[A-Za-z0-9_]`, like every Minecraft name.
+pub fn player_name<R: Rng>(rng: &mut R) -> String {
+    let pattern = rng.gen_range(0..100);
+    let a = NAME_WORDS.choose(rng).expect("there are words");
+    let b = NAME_WORDS.choose(rng).expect("there are words");
+
+    let mut name = match pattern {
+        0..=29 => format!("{a}{b}"),
+        30..=54 => format!("{a}{}", rng.gen_range(0..1000)),
+        55..=69 => format!("{a}_{b}").to_lowercase(),
+        70..=79 => format!("xX_{a}_Xx"),
+        80..=89 => format!("{a}{}", rng.gen_range(2005..2015)),
+        _ => format!("The{a}"),
+    };
+
+    name.truncate(16);
+    name
+}
+
+/// A version from [`crate::player_log::VERSIONS`], weighted by [`VERSION_WEIGHTS`].
+pub fn server_version<R: Rng>(rng: &mut R) -> &'static str {
+    let versions = crate::player_log::VERSIONS
+        .keys()
+        .copied()
+        .collect::<Vec<_>>();
+    let weight = |version: &&str| {
+        VERSION_WEIGHTS
+            .iter()
+            .find(|(weighted, _)| weighted == version)
+            .map_or(1, |(_, weight)| *weight)
+    };
+
+    versions
+        .choose_weighted(rng, weight)
+        .expect("weights are positive")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::player_log::VERSIONS;
+
+    #[test]
+    fn names_are_valid_minecraft_names() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..10_000 {
+            let name = player_name(&mut rng);
+            assert!((3..=16).contains(&name.len()), "{name}");
+            assert!(
+                name.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_'),
+                "{name}"
+            );
+        }
+    }
+
+    #[test]
+    fn domains_come_from_the_lists_and_lean_to_the_front() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts = HashMap::<&str, usize>::new();
+        for _ in 0..10_000 {
+            let domain = domain(&mut rng);
+            let (_, name) = SUBDOMAINS
+                .iter()
+                .filter_map(|(subdomain, _)| Some((subdomain, domain.strip_prefix(subdomain)?)))
+                .find(|(_, name)| SERVER_NAMES.contains(name))
+                .unwrap_or_else(|| panic!("{domain}"));
+            let name = SERVER_NAMES.iter().find(|known| **known == name).unwrap();
+            *counts.entry(name).or_default() += 1;
+        }
+
+        let front = SERVER_NAMES[..10]
+            .iter()
+            .map(|name| counts.get(name).unwrap_or(&0))
+            .sum::<usize>();
+        let back = SERVER_NAMES[SERVER_NAMES.len() - 10..]
+            .iter()
+            .map(|name| counts.get(name).unwrap_or(&0))
+            .sum::<usize>();
+        assert!(front > back * 10, "{front} {back}");
+    }
+
+    #[test]
+    fn a_domain_always_has_the_same_ip() {
+        assert_eq!(server_ip("hypixel.net"), server_ip("hypixel.net"));
+        assert_ne!(server_ip("hypixel.net"), server_ip("mc.hypixel.net"));
+        for name in SERVER_NAMES {
+            let ip = server_ip(name).octets();
+            assert!(ip[0] != 0 && ip[3] != 0, "{name}");
+        }
+    }
+
+    #[test]
+    fn versions_follow_their_weights() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut counts = HashMap::<&str, usize>::new();
+        for _ in 0..20_000 {
+            *counts.entry(server_version(&mut rng)).or_default() += 1;
+        }
+
+        assert!(counts.keys().all(|version| VERSIONS.contains_key(version)));
+        for (version, _) in VERSION_WEIGHTS {
+            assert!(VERSIONS.contains_key(version), "{version}");
+        }
+        assert!(counts["1.8"] > counts["1.20"]);
+        assert!(counts["1.20"] > counts["1.12"]);
+        assert!(counts["1.12"] > counts.get("1.9").copied().unwrap_or(0));
+    }
+}
@@ -0,0 +1,246 @@
+use std::collections::HashSet;
+use std::f64::consts::TAU;
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+use anyhow::Result;
+use rand::rngs::StdRng;
+use rand::seq::IteratorRandom;
+use rand::{Rng, SeedableRng};
+use uuid::Uuid;
+
+use crate::generator::{rand_ip, rand_string};
+use crate::player_log::{
+    Codec, LogFlags, PlayerLogBuilder, PlayerLogSerializer, SerializerOptions, ServerContext,
+    VERSIONS,
+};
+
+// 2024-01-01T00:00:00Z, fixed so a seed always produces the same files
+const DATASET_START_MILLIS: u64 = 1_704_067_200_000;
+const HOUR_MILLIS: u64 = 60 * 60 * 1000;
+
+// share of the player pool replaced by new players at the start of every day
+const DAILY_CHURN: f64 = 0.05;
+// traffic peaks at this hour and bottoms out twelve hours later
+const PEAK_HOUR: f64 = 20.0;
+
+#[derive(Debug, Clone)]
+pub struct DatasetConfig {
+    pub days: u32,
+    pub peak_per_hour: u32,
+    pub player_pool: usize,
+    pub servers: usize,
+    pub seed: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DatasetReport {
+    pub files: usize,
+    pub records: u64,
+    pub bytes: u64,
+    pub unique_players: usize,
+    pub records_per_day: Vec<u64>,
+    pub players_per_day: Vec<usize>,
+    /// Players of each day that were also seen the day before.
+    pub returning_per_day: Vec<usize>,
+}
+
+struct Player {
+    uuid: Option<Uuid>,
+    name: String,
+    ip: Ipv4Addr,
+    auth: bool,
+}
+
+fn new_player(rng: &mut StdRng) -> Player {
+    let online = rng.gen_bool(0.7);
+    Player {
+        uuid: online.then(|| uuid::Builder::from_random_bytes(rng.gen()).into_uuid()),
+        name: rand_string(rng, 4..16),
+        ip: rand_ip(rng),
+        auth: online || rng.gen_bool(0.3),
+    }
+}
+
+fn new_server(rng: &mut StdRng) -> ServerContext {
+    let ip = rand_ip(rng);
+    let port = if rng.gen_bool(0.8) {
+        25565
+    } else {
+        rng.gen_range(1024..=u16::MAX)
+    };
+    let domain = format!("{}.net", rand_string(rng, 4..12).to_lowercase());
+    let version = VERSIONS.entries().choose(rng).unwrap().0;
+
+    ServerContext::new(ip, port, domain, version).expect("every VERSIONS entry is valid")
+}
+
+/// Writes `days` of synthetic traffic to `dir` as one batch per hour, `day-NNN/HH.plgb`.
+/// Everything is drawn from `seed`, so the same config always writes the same files.
+pub fn generate_dataset(dir: &Path, config: &DatasetConfig) -> Result<DatasetReport> {
+    let rng = &mut StdRng::seed_from_u64(config.seed);
+
+    let servers = (0..config.servers.max(1))
+        .map(|_| PlayerLogBuilder::for_server(&new_server(rng)))
+        .collect::<Vec<_>>();
+    let mut players = (0..config.player_pool.max(1))
+        .map(|_| new_player(rng))
+        .collect::<Vec<_>>();
+
+    let options = SerializerOptions {
+        codec: Codec::Lz4,
+        ..SerializerOptions::default()
+    };
+
+    let mut report = DatasetReport::default();
+    let mut seen = HashSet::new();
+    let mut yesterday = HashSet::new();
+
+    for day in 0..u64::from(config.days) {
+        if day > 0 {
+            for _ in 0..(players.len() as f64 * DAILY_CHURN).round() as usize {
+                let i = rng.gen_range(0..players.len());
+                players[i] = new_player(rng);
+            }
+        }
+
+        let mut today = HashSet::new();
+        let mut day_records = 0;
+
+        let day_dir = dir.join(format!("day-{day:03}"));
+        fs::create_dir_all(&day_dir)?;
+
+        for hour in 0..24u64 {
+            let curve = 0.45f64.mul_add((TAU * (hour as f64 - PEAK_HOUR) / 24.0).cos(), 0.55);
+            let volume =
+                (f64::from(config.peak_per_hour) * curve * rng.gen_range(0.9..1.1)).round() as u64;
+
+            let hour_start = DATASET_START_MILLIS + (day * 24 + hour) * HOUR_MILLIS;
+            let mut timestamps = (0..volume)
+                .map(|_| hour_start + rng.gen_range(0..HOUR_MILLIS))
+                .collect::<Vec<_>>();
+            timestamps.sort_unstable();
+
+            let builders = timestamps
+                .into_iter()
+                .map(|timestamp| {
+                    // squaring skews activity towards the front of the pool, like real regulars
+                    let i = (rng.gen::<f64>().powi(2) * players.len() as f64) as usize;
+                    let player = &players[i.min(players.len() - 1)];
+                    let server = &servers[rng.gen_range(0..servers.len())];
+
+                    let mut flags = LogFlags::empty();
+                    flags.set(LogFlags::IS_ONLINE, player.uuid.is_some());
+                    flags.set(LogFlags::PLAYER_AUTH, player.auth);
+
+                    today.insert(player.uuid.map_or_else(
+                        || player.name.as_bytes().to_vec(),
+                        |uuid| uuid.as_bytes().to_vec(),
+                    ));
+
+                    PlayerLogBuilder {
+                        flags,
+                        player_uuid: player.uuid,
+                        player_name: player.name.clone(),
+                        player_ip: player.ip,
+                        timestamp: Some(timestamp),
+                        session_id: rng.gen_range(1..=u32::MAX),
+                        ..server.clone()
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            let logs = PlayerLogBuilder::build_many(&builders)?;
+            let batch = PlayerLogSerializer::serialize_many_with_options(&logs, &options)?;
+            fs::write(day_dir.join(format!("{hour:02}.plgb")), &batch)?;
+
+            report.files += 1;
+            report.bytes += batch.len() as u64;
+            day_records += logs.len() as u64;
+        }
+
+        report.records += day_records;
+        report.records_per_day.push(day_records);
+        report.players_per_day.push(today.len());
+        report
+            .returning_per_day
+            .push(today.intersection(&yesterday).count());
+
+        seen.extend(today.iter().cloned());
+        yesterday = today;
+    }
+
+    report.unique_players = seen.len();
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::player_log::PlayerLog;
+
+    fn config(seed: u64) -> DatasetConfig {
+        DatasetConfig {
+            days: 2,
+            peak_per_hour: 20,
+            player_pool: 50,
+            servers: 3,
+            seed,
+        }
+    }
+
+    fn read_dataset(dir: &Path, days: u32) -> Vec<Vec<PlayerLog>> {
+        (0..days)
+            .flat_map(|day| (0..24).map(move |hour| (day, hour)))
+            .map(|(day, hour)| {
+                let path = dir.join(format!("day-{day:03}/{hour:02}.plgb"));
+                PlayerLogSerializer::deserialize_many(&fs::read(path).unwrap()).unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn a_seed_always_writes_the_same_files() {
+        let (a, b, c) = (
+            tempfile::tempdir().unwrap(),
+            tempfile::tempdir().unwrap(),
+            tempfile::tempdir().unwrap(),
+        );
+
+        let report = generate_dataset(a.path(), &config(7)).unwrap();
+        assert_eq!(generate_dataset(b.path(), &config(7)).unwrap(), report);
+        assert_eq!(read_dataset(a.path(), 2), read_dataset(b.path(), 2));
+
+        generate_dataset(c.path(), &config(8)).unwrap();
+        assert_ne!(read_dataset(a.path(), 2), read_dataset(c.path(), 2));
+    }
+
+    #[test]
+    fn the_report_matches_the_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let report = generate_dataset(dir.path(), &config(1)).unwrap();
+        let hours = read_dataset(dir.path(), 2);
+
+        assert_eq!(report.files, 48);
+        assert_eq!(
+            report.records,
+            hours.iter().map(|logs| logs.len() as u64).sum::<u64>()
+        );
+        assert_eq!(report.records_per_day.iter().sum::<u64>(), report.records);
+        assert_eq!(report.players_per_day.len(), 2);
+        assert_eq!(report.returning_per_day[0], 0);
+        assert!(report.returning_per_day[1] <= report.players_per_day[1]);
+        assert!(report.unique_players <= report.players_per_day.iter().sum::<usize>());
+
+        for (i, logs) in hours.iter().enumerate() {
+            let start = DATASET_START_MILLIS + i as u64 * HOUR_MILLIS;
+            assert!(logs
+                .windows(2)
+                .all(|pair| pair[0].timestamp <= pair[1].timestamp));
+            assert!(logs
+                .iter()
+                .all(|log| (start..start + HOUR_MILLIS).contains(&log.timestamp)));
+        }
+    }
+}
@@ -0,0 +1,275 @@
+use std::{iter, net::Ipv4Addr, ops::Range};
+
+use rand::{
+    seq::{IteratorRandom, SliceRandom},
+    Rng,
+};
+
+use crate::corpus;
+use crate::player_log::{
+    parallel, GeoInfo, LogEventType, LogFlags, LoginOutcome, PlayerLog, PlayerLogBuilder, VERSIONS,
+};
+
+const CHARSET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+
+// 2024-01-01T00:00:00Z, generated timestamps fall within the year after it
+const TIMESTAMP_START_MILLIS: u64 = 1_704_067_200_000;
+const YEAR_MILLIS: u64 = 365 * 24 * 60 * 60 * 1000;
+
+// roughly what a public server sees at login
+const CLIENT_BRANDS: &[(&str, u32)] = &[
+    ("vanilla", 55),
+    ("fabric", 20),
+    ("forge", 12),
+    ("lunarclient", 8),
+    ("quilt", 3),
+    ("badlion", 2),
+];
+const COUNTRIES: &[[u8; 2]] = &[
+    *b"US", *b"DE", *b"GB", *b"BR", *b"FR", *b"PL", *b"RU", *b"CA",
+];
+const UNKNOWN_VERSIONS: &[&str] = &["1.22", "1.22-pre3", "24w14a"];
+const CLIENT_LOCALES: &[&str] = &[
+    "en_US", "en_GB", "de_DE", "fr_FR", "es_ES", "pt_BR", "ru_RU",
+];
+
+pub fn rand_string<R: Rng>(rng: &mut R, len: Range<usize>) -> String {
+    let len = rng.gen_range(len);
+    iter::repeat_with(|| CHARSET[rng.gen_range(0..CHARSET.len())] as char)
+        .take(len)
+        .collect()
+}
+
+pub fn rand_ip<R: Rng>(rng: &mut R) -> Ipv4Addr {
+    Ipv4Addr::from([
+        rng.gen_range(1..255),
+        rng.gen_range(1..255),
+        rng.gen_range(1..255),
+        rng.gen_range(1..255),
+    ])
+}
+
+pub fn log_generator() -> PlayerLogBuilder {
+    log_generator_seeded(&mut rand::thread_rng())
+}
+
+/// `count` builders from [`log_generator`], generated in parallel with the `parallel` feature.
+pub fn generate_builders(count: usize) -> Vec<PlayerLogBuilder> {
+    // a Vec of () never allocates, it only gives the chunked loop something to split
+    parallel::map_items(&vec![(); count], |()| log_generator())
+}
+
+/// Where names, domains and versions come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum GeneratorMode {
+    /// Uniformly random strings and versions.
+    #[default]
+    Random,
+    /// Samples from [`crate::corpus`], with about the entropy of real traffic. Every server
+    /// domain resolves to the same ip.
+    Corpus,
+}
+
+#[derive(Debug, Clone)]
+pub struct GeneratorConfig {
+    /// Share of logs that came through a proxy, between 0 and 1.
+    pub proxied_fraction: f64,
+    /// Proxied logs pick one of this many proxy ips, networks only run a handful of proxies.
+    pub proxy_pool: u8,
+    pub mode: GeneratorMode,
+}
+
+impl Default for GeneratorConfig {
+    fn default() -> Self {
+        Self {
+            proxied_fraction: 0.2,
+            proxy_pool: 4,
+            mode: GeneratorMode::Random,
+        }
+    }
+}
+
+impl GeneratorConfig {
+    pub fn corpus() -> Self {
+        Self {
+            mode: GeneratorMode::Corpus,
+            ..Self::default()
+        }
+    }
+}
+
+/// Same as [`log_generator`] but draws everything, including the uuid, from `rng`, so a seeded
+/// rng gives reproducible logs.
+pub fn log_generator_seeded<R: Rng>(rng: &mut R) -> PlayerLogBuilder {
+    log_generator_with(rng, &GeneratorConfig::default())
+}
+
+pub fn log_generator_with<R: Rng>(rng: &mut R, config: &GeneratorConfig) -> PlayerLogBuilder {
+    let player_uuid = if rng.gen() {
+        Some(uuid::Builder::from_random_bytes(rng.gen()).into_uuid())
+    } else {
+        None
+    };
+
+    let mut flags = LogFlags::empty();
+    if player_uuid.is_some() {
+        flags.insert(LogFlags::IS_ONLINE);
+    } else if rng.gen_ratio(1, 10) {
+        flags.insert(LogFlags::CRACKED_CLIENT);
+    }
+
+    if rng.gen() {
+        flags.insert(LogFlags::PLAYER_AUTH);
+    }
+
+    let event_type = match rng.gen_range(0..100) {
+        0..=59 => LogEventType::Login,
+        60..=84 => LogEventType::Disconnect,
+        85..=94 => LogEventType::ServerListPing,
+        _ => LogEventType::Ping,
+    };
+    flags |= LogFlags::from_bits_retain(event_type.bits());
+
+    let mut server_ip = rand_ip(rng);
+    // a good share of players connect by typing the raw ip
+    let server_domain = if rng.gen_ratio(3, 10) {
+        server_ip.to_string()
+    } else if config.mode == GeneratorMode::Corpus {
+        let domain = corpus::domain(rng);
+        server_ip = corpus::server_ip(&domain);
+        domain
+    } else {
+        rand_string(rng, 4..255)
+    };
+
+    // older clients and proxies don't forward the client info
+    let (client_brand, client_locale) = if rng.gen_ratio(7, 10) {
+        (
+            CLIENT_BRANDS
+                .choose_weighted(rng, |(_, weight)| *weight)
+                .ok()
+                .map(|(brand, _)| brand.to_string()),
+            CLIENT_LOCALES.choose(rng).map(|locale| locale.to_string()),
+        )
+    } else {
+        (None, None)
+    };
+
+    // a few percent of logins are refused
+    let outcome = match rng.gen_range(0..1000) {
+        0..=19 => LoginOutcome::NotWhitelisted,
+        20..=29 => LoginOutcome::Banned,
+        30..=37 => LoginOutcome::InvalidSession,
+        38..=42 => LoginOutcome::Throttled,
+        _ => LoginOutcome::Success,
+    };
+
+    let geo = rng.gen_ratio(8, 10).then(|| GeoInfo {
+        country: *COUNTRIES.choose(rng).unwrap(),
+        asn: rng.gen_range(1..400_000),
+    });
+
+    // proxies usually sit next to the server on a private network
+    let proxy_ip = (config.proxy_pool > 0 && rng.gen_bool(config.proxied_fraction.clamp(0.0, 1.0)))
+        .then(|| Ipv4Addr::new(10, 0, 0, rng.gen_range(1..=config.proxy_pool.min(254))));
+
+    PlayerLogBuilder {
+        flags,
+        player_uuid,
+        player_name: match config.mode {
+            GeneratorMode::Random => rand_string(rng, 4..16),
+            GeneratorMode::Corpus => corpus::player_name(rng),
+        },
+        player_ip: rand_ip(rng),
+        server_ip,
+        // most servers run on the default port
+        server_port: if rng.gen_ratio(9, 10) {
+            PlayerLog::DEFAULT_PORT
+        } else {
+            rng.gen::<u16>()
+        },
+        server_domain,
+        // now and then a collector sees a version newer than the VERSIONS table
+        server_version: if rng.gen_ratio(1, 100) {
+            (*UNKNOWN_VERSIONS.choose(rng).unwrap()).to_string()
+        } else if config.mode == GeneratorMode::Corpus {
+            corpus::server_version(rng).to_string()
+        } else {
+            (*VERSIONS.entries().choose(rng).unwrap().0).to_string()
+        },
+        timestamp: Some(TIMESTAMP_START_MILLIS + rng.gen_range(0..YEAR_MILLIS)),
+        session_id: rng.gen(),
+        extensions: Vec::new(),
+        client_brand,
+        client_locale,
+        outcome,
+        geo,
+        proxy_ip,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::corpus::SERVER_NAMES;
+
+    fn generate(config: &GeneratorConfig, count: usize) -> Vec<PlayerLogBuilder> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_with(&mut rng, config))
+            .collect()
+    }
+
+    #[test]
+    fn the_default_config_is_the_seeded_generator() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let seeded = (0..100)
+            .map(|_| log_generator_seeded(&mut rng))
+            .collect::<Vec<_>>();
+        assert_eq!(generate(&GeneratorConfig::default(), 100), seeded);
+    }
+
+    #[test]
+    fn corpus_logs_are_reproducible_and_build() {
+        let config = GeneratorConfig::corpus();
+        let builders = generate(&config, 1000);
+        assert_eq!(builders, generate(&config, 1000));
+        PlayerLogBuilder::build_many(&builders).unwrap();
+
+        for builder in &builders {
+            if builder.server_domain.parse::<Ipv4Addr>().is_ok() {
+                assert_eq!(builder.server_domain, builder.server_ip.to_string());
+                continue;
+            }
+            assert!(
+                SERVER_NAMES
+                    .iter()
+                    .any(|name| builder.server_domain.ends_with(name)),
+                "{}",
+                builder.server_domain
+            );
+            assert_eq!(builder.server_ip, corpus::server_ip(&builder.server_domain));
+        }
+    }
+
+    #[test]
+    fn proxies_come_from_the_pool() {
+        let config = GeneratorConfig {
+            proxied_fraction: 1.0,
+            proxy_pool: 2,
+            ..GeneratorConfig::default()
+        };
+        for builder in generate(&config, 200) {
+            let proxy = builder.proxy_ip.unwrap().octets();
+            assert!(matches!(proxy, [10, 0, 0, 1 | 2]), "{proxy:?}");
+        }
+
+        let none = GeneratorConfig {
+            proxy_pool: 0,
+            ..config
+        };
+        assert!(generate(&none, 200).iter().all(|b| b.proxy_ip.is_none()));
+    }
+}
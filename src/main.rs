@@ -1,117 +1,382 @@
-use std::{env, mem::size_of_val, time::Instant};
+use std::{
+    env, fs,
+    mem::{self, size_of_val},
+    path::{Path, PathBuf},
+    process,
+    time::Instant,
+};
 
-use anyhow::Result;
+use anyhow::{bail, Context, Result};
 use binary_storage_test::{
-    log_generator,
-    player_log::{PlayerLog, PlayerLogBuilder, PlayerLogSerializer},
+    compare::{run_comparison, Contender},
+    generate_builders, log_generator_with,
+    player_log::{
+        compact::CompactBatch, conformance, fuzz, legacy::LegacyLayout, self_test, vectors, Codec,
+        LogFlags, PlayerLog, PlayerLogBuilder, PlayerLogSerializer,
+    },
+    GeneratorConfig,
 };
 use bytesize::ByteSize;
 use flate2::Compression;
 use humantime::format_duration;
-use rayon::iter::{IntoParallelIterator, ParallelIterator};
+use rand::{rngs::StdRng, SeedableRng};
 
 fn main() {
     env::set_var("RUST_BACKTRACE", "1");
 
-    let before_generation = Instant::now();
-    let logs: Vec<PlayerLog> = (0..500_000)
-        .into_par_iter()
-        .map(|_| log_generator().build().unwrap())
-        .collect();
+    let args = env::args().skip(1).collect::<Vec<String>>();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        ["migrate", input, output] => {
+            if let Err(e) = migrate(Path::new(input), Path::new(output)) {
+                eprintln!("{e:#}");
+                process::exit(1);
+            }
+        }
+        ["migrate", ..] => {
+            eprintln!("usage: binary-storage-test migrate <in> <out>");
+            process::exit(2);
+        }
+        ["conformance", corpus @ ..] if corpus.len() <= 1 => {
+            if let Err(e) = conformance(corpus.first().map(Path::new)) {
+                eprintln!("{e:#}");
+                process::exit(1);
+            }
+        }
+        ["fuzz-corpus", dir] => match fuzz::write_corpus(Path::new(dir)) {
+            Ok(count) => println!("wrote {count} corpus entries to {dir}"),
+            Err(e) => {
+                eprintln!("{e:#}");
+                process::exit(1);
+            }
+        },
+        ["emit-test-vectors", dir] => match vectors::write_vectors(Path::new(dir)) {
+            Ok(count) => println!("wrote {count} test vectors to {dir}"),
+            Err(e) => {
+                eprintln!("{e:#}");
+                process::exit(1);
+            }
+        },
+        ["conformance", ..] => {
+            eprintln!("usage: binary-storage-test conformance [corpus dir]");
+            process::exit(2);
+        }
+        ["doctor"] => {
+            if !doctor() {
+                process::exit(1);
+            }
+        }
+        args => {
+            let args = match compare_args(args) {
+                Ok(args) => args,
+                Err(e) => {
+                    eprintln!("{e:#}");
+                    eprintln!("usage: binary-storage-test [--repetitions <n>] [--json <path>]");
+                    process::exit(2);
+                }
+            };
 
-    println!(
-        "! generated {} logs in {}, {}",
-        logs.len(),
-        format_duration(before_generation.elapsed()),
-        ByteSize(size_of_val(&*logs) as u64)
-    );
+            if let Err(e) = compare_formats(&args) {
+                eprintln!("{e:#}");
+                process::exit(1);
+            }
+        }
+    }
+}
 
-    {
-        // we let serde_json use builders to be more fair so it doesn't have to use the byte arrays
-        let log_builders = logs
-            .iter()
-            .map(PlayerLogBuilder::from_log)
-            .collect::<Result<Vec<PlayerLogBuilder>>>()
-            .unwrap();
-
-        let instant = Instant::now();
-
-        let serialized = serde_json::to_string(&log_builders).unwrap();
-        let deserialized: Vec<PlayerLogBuilder> = serde_json::from_str(&serialized).unwrap();
-
-        println!(
-            "serde_json: {}µs, {}",
-            format_duration(instant.elapsed()),
-            ByteSize(serialized.bytes().len() as u64)
+// runs the reference decoder against ours, failing on the first run that finds a divergence
+fn conformance(corpus: Option<&Path>) -> Result<()> {
+    let mut divergences = conformance::run(0, 1000)
+        .into_iter()
+        .map(|(name, divergence)| (name, divergence.to_string()))
+        .collect::<Vec<_>>();
+
+    if let Some(corpus) = corpus {
+        divergences.extend(
+            conformance::run_corpus(corpus)?
+                .into_iter()
+                .map(|(path, divergence)| (path.display().to_string(), divergence.to_string())),
         );
+    }
 
-        assert_eq!(log_builders, deserialized);
+    for (name, divergence) in &divergences {
+        eprintln!("{name}: {divergence}");
     }
 
-    {
-        let instant = Instant::now();
+    if !divergences.is_empty() {
+        bail!("{} divergences between the decoders", divergences.len());
+    }
 
-        let serialized = postcard::to_allocvec(&logs).unwrap();
-        let deserialized: Vec<PlayerLog> = postcard::from_bytes(&serialized).unwrap();
+    println!("decoders agree");
 
-        println!(
-            "postcard: {}, {}",
-            format_duration(instant.elapsed()),
-            ByteSize(serialized.len() as u64)
-        );
+    conformance::check_determinism()?;
+    println!("output is deterministic");
+
+    let committed = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-vectors");
+    vectors::check_vectors(&committed).context(
+        "the committed test vectors are stale, regenerate them with emit-test-vectors if the change is intended",
+    )?;
+    println!("test vectors match");
+    Ok(())
+}
 
-        assert_eq!(logs, deserialized);
+// prints every self test, false if any failed
+fn doctor() -> bool {
+    let results = self_test::run();
+    for result in &results {
+        println!("{result}");
     }
 
-    {
-        let instant = Instant::now();
+    let failed = results.iter().filter(|result| !result.passed()).count();
+    if failed > 0 {
+        eprintln!("{failed} of {} checks failed", results.len());
+    }
 
-        let serialized = bincode::serialize(&logs).unwrap();
-        let deserialized: Vec<PlayerLog> = bincode::deserialize(&serialized).unwrap();
+    failed == 0
+}
 
-        println!(
-            "bincode: {}µs, {}",
-            format_duration(instant.elapsed()),
-            ByteSize(serialized.len() as u64)
-        );
+// migrates a single legacy file, or every file under a directory into the same layout under `output`
+fn migrate(input: &Path, output: &Path) -> Result<()> {
+    let mut files = Vec::new();
+    collect_files(input, &mut files)?;
 
-        assert_eq!(logs, deserialized);
+    let mut failed = 0;
+    for file in &files {
+        let relative = file.strip_prefix(input)?;
+        let target = if relative.as_os_str().is_empty() {
+            output.to_path_buf()
+        } else {
+            output.join(relative)
+        };
+
+        match migrate_file(file, &target) {
+            Ok(status) => println!("{}: {status}", file.display()),
+            Err(e) => {
+                failed += 1;
+                eprintln!("{}: {e:#}", file.display());
+            }
+        }
     }
 
-    {
-        let instant = Instant::now();
+    if failed > 0 {
+        bail!("{failed} of {} files failed to migrate", files.len());
+    }
 
-        let serialized = PlayerLogSerializer::serialize_many(&logs).unwrap();
-        let _deserialized: Vec<PlayerLog> =
-            PlayerLogSerializer::deserialize_many(&serialized).unwrap();
+    Ok(())
+}
 
-        println!(
-            "our_serialization: {}µs, {}",
-            format_duration(instant.elapsed()),
-            ByteSize(serialized.len() as u64)
-        );
+fn collect_files(path: &Path, files: &mut Vec<PathBuf>) -> Result<()> {
+    if path.is_dir() {
+        let mut entries = fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        entries.sort();
 
-        // will be out of order
-        // assert_eq!(logs, deserialized);
+        for entry in entries {
+            collect_files(&entry, files)?;
+        }
+    } else {
+        files.push(path.to_path_buf());
     }
 
-    {
-        let instant = Instant::now();
+    Ok(())
+}
 
-        let serialized =
-            PlayerLogSerializer::serialize_many_compressed(&logs, Compression::new(5)).unwrap();
-        let _deserialized: Vec<PlayerLog> =
-            PlayerLogSerializer::deserialize_many_compressed(&serialized).unwrap();
+fn migrate_file(input: &Path, output: &Path) -> Result<&'static str> {
+    let data = fs::read(input)?;
 
-        println!(
-            "our_serialization compressed: {}µs, {}",
-            format_duration(instant.elapsed()),
-            ByteSize(serialized.len() as u64)
-        );
+    if let Some(parent) = output.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if PlayerLogSerializer::probe(&data).is_ok() {
+        fs::write(output, &data)?;
+        return Ok("already migrated, copied");
+    }
+
+    // keep compressed archives compressed
+    let (codec, status) = match PlayerLogSerializer::detect_legacy(&data)? {
+        LegacyLayout::Uncompressed => (Codec::None, "migrated"),
+        LegacyLayout::Compressed => (Codec::Zlib(Compression::default()), "migrated (zlib)"),
+    };
+
+    let migrated = PlayerLogSerializer::migrate_legacy(&data, codec)
+        .with_context(|| format!("migrating {}", input.display()))?;
+    fs::write(output, migrated)?;
+
+    Ok(status)
+}
+
+const DEFAULT_REPETITIONS: usize = 5;
+
+struct CompareArgs {
+    repetitions: usize,
+    json: Option<PathBuf>,
+}
+
+fn compare_args(args: &[&str]) -> Result<CompareArgs> {
+    let mut compare = CompareArgs {
+        repetitions: DEFAULT_REPETITIONS,
+        json: None,
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let value = args
+            .next()
+            .with_context(|| format!("{arg} needs a value"))?;
+        match *arg {
+            "--repetitions" => {
+                compare.repetitions = value
+                    .parse()
+                    .with_context(|| format!("invalid repetition count {value}"))?;
+                if compare.repetitions == 0 {
+                    bail!("at least one repetition is needed");
+                }
+            }
+            "--json" => compare.json = Some(PathBuf::from(value)),
+            _ => bail!("unknown argument {arg}"),
+        }
+    }
+
+    Ok(compare)
+}
+
+// serde_json goes through builders both ways so it doesn't have to deal with the byte arrays,
+// "our_serialization builders" does the same conversions for a fair comparison
+fn contenders() -> Vec<Contender> {
+    vec![
+        Contender {
+            name: "serde_json",
+            serialize: |logs, buf| {
+                let builders = logs
+                    .iter()
+                    .map(PlayerLogBuilder::from_log)
+                    .collect::<Result<Vec<_>>>()?;
+                Ok(serde_json::to_writer(buf, &builders)?)
+            },
+            deserialize: |data| {
+                PlayerLogBuilder::build_many(&serde_json::from_slice::<Vec<PlayerLogBuilder>>(
+                    data,
+                )?)
+            },
+        },
+        Contender {
+            name: "postcard",
+            serialize: |logs, buf| {
+                *buf = postcard::to_extend(logs, mem::take(buf))?;
+                Ok(())
+            },
+            deserialize: |data| Ok(postcard::from_bytes(data)?),
+        },
+        Contender {
+            name: "bincode",
+            serialize: |logs, buf| Ok(bincode::serialize_into(buf, logs)?),
+            deserialize: |data| Ok(bincode::deserialize(data)?),
+        },
+        // the serializer hands back its own buffer, there's nothing to write into
+        Contender {
+            name: "our_serialization",
+            serialize: |logs, buf| {
+                *buf = PlayerLogSerializer::serialize_many(logs)?;
+                Ok(())
+            },
+            deserialize: PlayerLogSerializer::deserialize_many,
+        },
+        Contender {
+            name: "our_serialization builders",
+            serialize: |logs, buf| {
+                let builders = logs
+                    .iter()
+                    .map(PlayerLogBuilder::from_log)
+                    .collect::<Result<Vec<_>>>()?;
+                *buf =
+                    PlayerLogSerializer::serialize_many(&PlayerLogBuilder::build_many(&builders)?)?;
+                Ok(())
+            },
+            deserialize: |data| {
+                PlayerLogBuilder::build_many(&PlayerLogSerializer::deserialize_many_builders(data)?)
+            },
+        },
+        Contender {
+            name: "our_serialization compressed",
+            serialize: |logs, buf| {
+                *buf = PlayerLogSerializer::serialize_many_compressed(logs, Compression::new(5))?;
+                Ok(())
+            },
+            deserialize: PlayerLogSerializer::deserialize_many_compressed,
+        },
+    ]
+}
+
+fn compare_formats(args: &CompareArgs) -> Result<()> {
+    let before_generation = Instant::now();
+    let builders = generate_builders(500_000);
+    let logs = PlayerLogBuilder::build_many(&builders)?;
+
+    println!(
+        "! generated {} logs in {}, {}, timing {} round trips each",
+        logs.len(),
+        format_duration(before_generation.elapsed()),
+        ByteSize(size_of_val(&*logs) as u64),
+        args.repetitions
+    );
+
+    let report = run_comparison(&logs, &contenders(), args.repetitions)?;
+    print!("{}", report.render_table());
+
+    let default_ports = logs
+        .iter()
+        .filter(|log| log.binary_version >= 2 && log.server_port == PlayerLog::DEFAULT_PORT)
+        .count();
+    println!(
+        "  default port omitted on {default_ports} of {} logs, saving {}",
+        logs.len(),
+        ByteSize(default_ports as u64 * 2)
+    );
+
+    // the domain and its length byte are left out
+    let (ip_domains, ip_domain_bytes) = logs
+        .iter()
+        .filter(|log| LogFlags::from_bits_retain(log.flags).contains(LogFlags::DOMAIN_IS_IP))
+        .fold((0, 0), |(count, bytes), log| {
+            (count + 1, bytes + 1 + log.server_domain.len() as u64)
+        });
+    println!(
+        "  ip domain omitted on {ip_domains} of {} logs, saving {}",
+        logs.len(),
+        ByteSize(ip_domain_bytes)
+    );
+
+    // corpus names and domains repeat like real ones do, which is where interning pays off
+    let mut rng = StdRng::seed_from_u64(0);
+    let corpus_builders = (0..logs.len())
+        .map(|_| log_generator_with(&mut rng, &GeneratorConfig::corpus()))
+        .collect::<Vec<_>>();
+    let corpus_logs = PlayerLogBuilder::build_many(&corpus_builders)?;
+    let expanded = corpus_logs
+        .iter()
+        .map(PlayerLog::memory_size)
+        .sum::<usize>();
+    let compact = CompactBatch::from_logs(corpus_logs);
+    println!(
+        "  {} corpus logs take {} as logs, {} as a compact batch of {} distinct strings",
+        compact.len(),
+        ByteSize(expanded as u64),
+        ByteSize(compact.memory_usage() as u64),
+        compact.distinct_strings()
+    );
 
-        // will be out of order
-        // assert_eq!(logs, deserialized);
+    if let Some(path) = &args.json {
+        fs::write(path, serde_json::to_string_pretty(&report.to_json())?)
+            .with_context(|| format!("writing {}", path.display()))?;
+        println!("wrote timings to {}", path.display());
     }
 
     println!("all tests successful!");
+    Ok(())
 }
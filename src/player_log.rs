@@ -1,18 +1,88 @@
-use std::io::{Read, Write};
-use std::net::Ipv4Addr;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::marker::PhantomData;
+use std::mem;
+use std::net::{Ipv4Addr, SocketAddrV4};
+use std::path::Path;
 
 use anyhow::Result;
 use anyhow::{bail, Context};
 use bitflags::bitflags;
-use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt, WriteBytesExt};
 use flate2::read::ZlibDecoder;
 use flate2::write::ZlibEncoder;
 use flate2::Compression;
+use lz4_flex::frame::{FrameDecoder, FrameEncoder};
 use phf::phf_map;
-use rayon::iter::{ParallelBridge, ParallelIterator};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
+use dictionary::DictionaryId;
+use storage_impl::{ByteStorage, DomainBytes, NameBytes};
+use versions::{version_supports, Feature};
+
+#[cfg(feature = "avro")]
+pub mod avro;
+pub mod backfill;
+pub mod batch_io;
+pub mod clock;
+pub mod compact;
+pub mod compress;
+pub mod conformance;
+pub mod consistency;
+pub mod dictionary;
+pub mod diff;
+pub mod dual;
+pub mod enrich;
+pub mod envelope;
+pub mod explain;
+pub mod export;
+pub mod fanout;
+pub mod file;
+pub mod filter;
+pub mod fingerprint;
+pub mod fuzz;
+pub mod geo;
+pub mod graph;
+pub mod guards;
+pub mod identity;
+pub mod legacy;
+pub mod net;
+pub mod ordinal;
+pub mod owned;
+pub(crate) mod parallel;
+#[cfg(feature = "parquet")]
+pub mod parquet;
+pub mod patch;
+pub mod pipeline;
+#[cfg(feature = "polars")]
+pub mod polars;
+pub mod policy;
+pub mod privacy;
+pub mod projection;
+pub mod query;
+pub mod seen;
+pub mod segment;
+pub mod self_test;
+pub mod serial;
+pub mod snapshot;
+pub mod sniff;
+pub mod spill;
+#[cfg(feature = "sqlite")]
+pub mod sqlite;
+pub mod stats;
+pub mod storage;
+pub mod storage_impl;
+pub mod stream;
+pub mod tombstone;
+pub mod vectors;
+pub mod verify;
+pub mod versions;
+pub mod visit;
+
 pub static VERSIONS: phf::Map<&'static str, u8> = phf_map! {
     "1.8" => 1,
     "1.9" => 2,
@@ -30,241 +100,3445 @@ pub static VERSIONS: phf::Map<&'static str, u8> = phf_map! {
     "1.21" => 14,
 };
 
+/// Known versions are written as their [`VERSIONS`] id. Anything newer is kept as the version
+/// string, so collectors running old code don't drop records the day a new version ships.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone)]
+pub enum ServerVersion {
+    Known(u8),
+    /// Only written by binary_version 2 and up.
+    Unknown(Box<str>),
+}
+
+impl Default for ServerVersion {
+    fn default() -> Self {
+        Self::Known(0)
+    }
+}
+
+impl ServerVersion {
+    /// Written in place of the id, followed by the length prefixed version string.
+    pub const UNKNOWN_ID: u8 = u8::MAX;
+    pub const MAX_UNKNOWN_LEN: usize = 32;
+
+    pub fn from_name(name: &str) -> Result<Self> {
+        if let Some(id) = VERSIONS.get(name) {
+            return Ok(Self::Known(*id));
+        }
+
+        if name.is_empty() || name.len() > Self::MAX_UNKNOWN_LEN {
+            bail!("invalid server version {name:?}");
+        }
+        Ok(Self::Unknown(name.into()))
+    }
+
+    /// `None` for a known id that isn't in [`VERSIONS`].
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Known(id) => VERSIONS
+                .entries()
+                .find(|(_, n)| *n == id)
+                .map(|(name, _)| *name),
+            Self::Unknown(name) => Some(name),
+        }
+    }
+
+    pub const fn id(&self) -> Option<u8> {
+        match self {
+            Self::Known(id) => Some(*id),
+            Self::Unknown(_) => None,
+        }
+    }
+}
+
 bitflags! {
     #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, Clone)]
     #[serde(transparent)]
-    pub struct LogFlags: u8 {
+    pub struct LogFlags: u16 {
         const PLAYER_AUTH = 1;
         const IS_ONLINE = 1 << 1; // (has uuid)
+        const SERVER_WHITELIST = 1 << 3;
+        const CRACKED_CLIENT = 1 << 4; // can't be online, cracked clients never authenticate with mojang
+        const REDACTED = 1 << 5; // the uuid and player ip were dropped and the name hashed
+        // bits 6 and 7 hold the LogEventType
+
+        // the high byte is only written by binary_version 2 and up
+        const NONDEFAULT_PORT = 1 << 8; // the port is only written when it isn't 25565
+        const DOMAIN_IS_IP = 1 << 9; // the domain is the dotted server ip and isn't written
+        const HAS_CLIENT_INFO = 1 << 10; // the client brand and locale follow the session id
+        const LOGIN_REFUSED = 1 << 11; // the login outcome follows the session id
+        const HAS_GEO = 1 << 12; // the player ip's country and asn follow the outcome
+        const VIA_PROXY = 1 << 13; // the proxy ip follows the geo info
+    }
+}
+
+impl LogFlags {
+    /// The [`LogEventType`] bits, they aren't flags so `from_bits` rejects them.
+    pub const EVENT_TYPE_BITS: u16 = 0b11 << 6;
+}
+
+/// Checks every bit outside the event type is a known flag, the event type bits are kept as
+/// they are since every value of them is a valid [`LogEventType`].
+pub fn validate_flags(bits: u16) -> Result<LogFlags> {
+    let flags = LogFlags::from_bits(bits & !LogFlags::EVENT_TYPE_BITS)
+        .with_context(|| format!("invalid flags {bits:#06x}"))?;
+
+    Ok(flags | LogFlags::from_bits_retain(bits & LogFlags::EVENT_TYPE_BITS))
+}
+
+/// What the log was recorded for, stored in bits 6 and 7 of the flags.
+#[derive(
+    Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default,
+)]
+pub enum LogEventType {
+    #[default]
+    Login,
+    Disconnect,
+    Ping,
+    ServerListPing,
+}
+
+impl LogEventType {
+    pub const fn from_flags(flags: u16) -> Self {
+        match (flags & LogFlags::EVENT_TYPE_BITS) >> 6 {
+            0 => Self::Login,
+            1 => Self::Disconnect,
+            2 => Self::Ping,
+            _ => Self::ServerListPing,
+        }
     }
+
+    pub const fn bits(self) -> u16 {
+        (self as u16) << 6
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Login => "login",
+            Self::Disconnect => "disconnect",
+            Self::Ping => "ping",
+            Self::ServerListPing => "server list ping",
+        }
+    }
+}
+
+bitflags! {
+    /// Which of the optional fields a record actually carries.
+    #[derive(Debug, PartialEq, Eq, Clone, Copy, Default)]
+    pub struct FieldPresenceMask: u8 {
+        const UUID = 1;
+        const TIMESTAMP = 1 << 1;
+        const SESSION_ID = 1 << 2;
+        const EXTENSIONS = 1 << 3;
+        const CLIENT_INFO = 1 << 4;
+        const GEO = 1 << 5;
+        const PROXY_IP = 1 << 6;
+    }
+}
+
+impl FieldPresenceMask {
+    /// Every field a record of `binary_version` can carry.
+    pub const fn all_for_version(binary_version: u8) -> Self {
+        match binary_version {
+            0 => Self::empty(),
+            1 => Self::UUID,
+            _ => Self::all(),
+        }
+    }
+}
+
+/// How a login attempt ended, refused logins are only recorded by binary_version 2.
+#[derive(
+    Debug, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy, Default,
+)]
+pub enum LoginOutcome {
+    #[default]
+    Success,
+    Banned,
+    NotWhitelisted,
+    Throttled,
+    InvalidSession,
+    /// Anything this version doesn't know yet, kept as the raw byte.
+    Other(u8),
+}
+
+impl LoginOutcome {
+    pub const fn from_byte(byte: u8) -> Self {
+        match byte {
+            0 => Self::Success,
+            1 => Self::Banned,
+            2 => Self::NotWhitelisted,
+            3 => Self::Throttled,
+            4 => Self::InvalidSession,
+            other => Self::Other(other),
+        }
+    }
+
+    pub const fn to_byte(self) -> u8 {
+        match self {
+            Self::Success => 0,
+            Self::Banned => 1,
+            Self::NotWhitelisted => 2,
+            Self::Throttled => 3,
+            Self::InvalidSession => 4,
+            Self::Other(other) => other,
+        }
+    }
+}
+
+impl fmt::Display for LoginOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Success => f.write_str("success"),
+            Self::Banned => f.write_str("banned"),
+            Self::NotWhitelisted => f.write_str("not whitelisted"),
+            Self::Throttled => f.write_str("throttled"),
+            Self::InvalidSession => f.write_str("invalid session"),
+            Self::Other(other) => write!(f, "other ({other})"),
+        }
+    }
+}
+
+/// Where the player ip is, filled in by an [`enrich::Enricher`] or set directly.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Hash, Clone, Copy)]
+pub struct GeoInfo {
+    /// ISO 3166-1 alpha-2, uppercase.
+    pub country: [u8; 2],
+    pub asn: u32,
+}
+
+impl GeoInfo {
+    pub fn country_code(&self) -> &str {
+        std::str::from_utf8(&self.country).unwrap_or("??")
+    }
+}
+
+/// Opaque tagged data attached to a v2 record.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
+pub struct Extension {
+    pub kind: u8,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
 pub struct PlayerLogBuilder {
     pub flags: LogFlags,
     pub player_uuid: Option<Uuid>, // 128 bits (16 bytes)
-    pub player_name: String,       // max 16 bytes
+    pub player_name: String,       // max 16 characters and 16 bytes, see BuildOptions
     pub player_ip: Ipv4Addr,
     pub server_ip: Ipv4Addr,
     pub server_port: u16, // max 16 bits (1-65535)
     pub server_domain: String,
     pub server_version: String,
+    /// Milliseconds since the unix epoch, setting any of these makes the log binary_version 2.
+    #[serde(default)]
+    pub timestamp: Option<u64>,
+    #[serde(default)]
+    pub session_id: u32,
+    #[serde(default)]
+    pub extensions: Vec<Extension>,
+    /// "vanilla", "fabric", "forge", ... as sent by the client at login.
+    #[serde(default)]
+    pub client_brand: Option<String>,
+    #[serde(default)]
+    pub client_locale: Option<String>,
+    #[serde(default)]
+    pub outcome: LoginOutcome,
+    #[serde(default)]
+    pub geo: Option<GeoInfo>,
+    /// The proxy the player connected through, `player_ip` is then the address it forwarded.
+    #[serde(default)]
+    pub proxy_ip: Option<Ipv4Addr>,
 }
 
 impl PlayerLogBuilder {
+    /// Sets the server ip and port together.
+    pub const fn with_server_socket_addr(mut self, addr: SocketAddrV4) -> Self {
+        self.server_ip = *addr.ip();
+        self.server_port = addr.port();
+        self
+    }
+
+    pub const fn with_event_type(mut self, event_type: LogEventType) -> Self {
+        self.flags = LogFlags::from_bits_retain(
+            self.flags.bits() & !LogFlags::EVENT_TYPE_BITS | event_type.bits(),
+        );
+        self
+    }
+
     pub fn build(&self) -> Result<PlayerLog> {
-        if self.player_name.len() > 16 {
-            bail!("Player name too long");
+        self.build_with_options(&BuildOptions::default())
+    }
+
+    pub fn build_with_options(&self, options: &BuildOptions) -> Result<PlayerLog> {
+        let mut log = PlayerLog::default();
+        self.build_into_with_options(&mut log, options)?;
+
+        Ok(log)
+    }
+
+    /// Builds into an existing log, reusing the capacity of its name and domain buffers.
+    pub fn build_into(&self, out: &mut PlayerLog) -> Result<()> {
+        self.build_into_with_options(out, &BuildOptions::default())
+    }
+
+    pub fn build_into_with_options(
+        &self,
+        out: &mut PlayerLog,
+        options: &BuildOptions,
+    ) -> Result<()> {
+        self.build_into_with_version(out, options, None)
+    }
+
+    // `server_version` is used in place of looking up `self.server_version` when it's set
+    fn build_into_with_version(
+        &self,
+        out: &mut PlayerLog,
+        options: &BuildOptions,
+        server_version: Option<&ServerVersion>,
+    ) -> Result<()> {
+        validate_flags(self.flags.bits())?;
+        let name_chars = self.player_name.chars().count();
+        if name_chars > PlayerLog::MAX_NAME_CHARS {
+            return Err(BuildError::NameTooManyChars(name_chars).into());
+        }
+        let max_name_len = if options.allow_long_name_bytes {
+            PlayerLog::MAX_LONG_NAME_LEN
+        } else {
+            PlayerLog::MAX_NAME_LEN
+        };
+        if self.player_name.len() > max_name_len {
+            return Err(BuildError::NameTooManyBytes(self.player_name.len()).into());
         }
 
-        let player_uuid = self.player_uuid.map(|uuid| {
-            let mut uuid_array = [0; 16];
-            uuid_array.copy_from_slice(uuid.as_bytes());
-            uuid_array
-        });
+        let server_version = match server_version {
+            Some(server_version) => server_version.clone(),
+            None => ServerVersion::from_name(&self.server_version)?,
+        };
 
-        let player_name_bytes = self.player_name.as_bytes().to_vec();
+        let mut flags = self.flags.clone();
+        let player_uuid = self
+            .player_uuid
+            .filter(|uuid| !(options.normalize_nil_uuid && uuid.is_nil()));
+        if self.player_uuid.is_some() && player_uuid.is_none() {
+            flags.remove(LogFlags::IS_ONLINE);
+        }
 
-        let player_ip = self.player_ip.octets();
-        let server_ip = self.server_ip.octets();
+        if flags.contains(LogFlags::CRACKED_CLIENT | LogFlags::IS_ONLINE) {
+            bail!("Cracked clients can't be online");
+        }
 
-        let mut server_domain_bytes = self.server_domain.as_bytes().to_vec();
-        server_domain_bytes.truncate(255);
+        if self.extensions.len() > PlayerLog::MAX_EXTENSIONS {
+            bail!("Too many extensions");
+        }
+        if self
+            .extensions
+            .iter()
+            .any(|e| e.data.len() > PlayerLog::MAX_EXTENSION_LEN)
+        {
+            bail!("Extension data too long");
+        }
+        if let Some(extension) = self
+            .extensions
+            .iter()
+            .find(|e| tombstone::is_reserved(e.kind))
+        {
+            bail!(
+                "Extension kind {:#04x} is reserved for tombstones and gaps",
+                extension.kind
+            );
+        }
 
-        let server_version = *VERSIONS
-            .get(&self.server_version)
-            .context("invalid server version")?;
+        let client_brand = self.client_brand.as_deref().unwrap_or_default();
+        let client_locale = self.client_locale.as_deref().unwrap_or_default();
+        // an empty string would come back as None
+        if self.client_brand.as_ref().is_some_and(String::is_empty)
+            || self.client_locale.as_ref().is_some_and(String::is_empty)
+        {
+            bail!("Client brand and locale can't be empty");
+        }
+        if client_brand.len() > PlayerLog::MAX_CLIENT_BRAND_LEN {
+            bail!("Client brand too long");
+        }
+        if client_locale.len() > PlayerLog::MAX_CLIENT_LOCALE_LEN {
+            bail!("Client locale too long");
+        }
+        let has_client_info = self.client_brand.is_some() || self.client_locale.is_some();
 
-        Ok(PlayerLog {
-            binary_version: 1,
-            flags: self.flags.bits(),
-            player_uuid,
-            player_name: player_name_bytes,
-            player_ip,
+        // Other(1) would be read back as Banned
+        if LoginOutcome::from_byte(self.outcome.to_byte()) != self.outcome {
+            bail!("{:?} has a known outcome's byte", self.outcome);
+        }
+        let refused = self.outcome != LoginOutcome::Success;
+
+        if let Some(geo) = &self.geo {
+            if !geo.country.iter().all(u8::is_ascii_uppercase) {
+                bail!(
+                    "Country code {:?} isn't two uppercase letters",
+                    geo.country_code()
+                );
+            }
+        }
+
+        // the lowest version that can hold everything that was set
+        out.binary_version = if self.timestamp.is_some()
+            || self.session_id != 0
+            || !self.extensions.is_empty()
+            || has_client_info
+            || refused
+            || self.geo.is_some()
+            || self.proxy_ip.is_some()
+            || matches!(server_version, ServerVersion::Unknown(_))
+            || self.player_name.len() > PlayerLog::MAX_NAME_LEN
+        {
+            2
+        } else {
+            1
+        };
+        flags.set(LogFlags::HAS_CLIENT_INFO, has_client_info);
+        flags.set(LogFlags::LOGIN_REFUSED, refused);
+        flags.set(LogFlags::HAS_GEO, self.geo.is_some());
+        flags.set(LogFlags::VIA_PROXY, self.proxy_ip.is_some());
+        flags.set(
+            LogFlags::NONDEFAULT_PORT,
+            out.binary_version >= 2 && self.server_port != PlayerLog::DEFAULT_PORT,
+        );
+        // the parser only accepts the canonical dotted form, so "01.2.3.4" or "1.2.3.4." don't match
+        flags.set(
+            LogFlags::DOMAIN_IS_IP,
+            out.binary_version >= 2
+                && self.server_domain.parse::<Ipv4Addr>().ok() == Some(self.server_ip),
+        );
+        out.flags = flags.bits();
+        out.player_uuid = player_uuid.map(Uuid::into_bytes);
+
+        out.player_name.clear();
+        out.player_name
+            .extend_from_slice(self.player_name.as_bytes());
+
+        out.player_ip = self.player_ip.octets();
+        out.server_ip = self.server_ip.octets();
+        out.server_port = self.server_port;
+
+        let server_domain = self.server_domain.as_bytes();
+        out.server_domain.clear();
+        out.server_domain.extend_from_slice(
+            &server_domain[..server_domain.len().min(PlayerLog::MAX_DOMAIN_LEN)],
+        );
+
+        out.server_version = server_version;
+
+        out.timestamp = self.timestamp.unwrap_or_default();
+        out.session_id = self.session_id;
+        out.outcome = self.outcome;
+        out.geo = self.geo;
+        out.proxy_ip = self.proxy_ip.map(|ip| ip.octets());
+        out.extensions.clone_from(&self.extensions);
+
+        out.client_brand.clear();
+        out.client_brand.extend_from_slice(client_brand.as_bytes());
+        out.client_locale.clear();
+        out.client_locale
+            .extend_from_slice(client_locale.as_bytes());
+
+        Ok(())
+    }
+
+    /// Checks that are too strict for [`PlayerLogBuilder::build`], which has to accept anything
+    /// older clients sent.
+    pub fn validate_strict(&self) -> Result<()> {
+        // build derives the flag from the field, so a lone flag would be silently dropped
+        if self.flags.contains(LogFlags::VIA_PROXY) && self.proxy_ip.is_none() {
+            bail!("VIA_PROXY is set without a proxy ip");
+        }
+
+        if let Some(locale) = &self.client_locale {
+            // xx_XX, like en_US
+            let shaped = matches!(
+                locale.as_bytes(),
+                [a, b, b'_', c, d] if a.is_ascii_lowercase()
+                    && b.is_ascii_lowercase()
+                    && c.is_ascii_uppercase()
+                    && d.is_ascii_uppercase()
+            );
+            if !shaped {
+                bail!("client locale {locale:?} isn't shaped like en_US");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Builds every builder in parallel, the error names the first builder that failed.
+    pub fn build_many(builders: &[Self]) -> Result<Vec<PlayerLog>> {
+        parallel::map_items(builders, Self::build)
+            .into_iter()
+            .enumerate()
+            .map(|(i, log)| log.with_context(|| format!("builder {i} failed")))
+            .collect()
+    }
+
+    pub fn from_log(log: &PlayerLog) -> Result<Self> {
+        Self::try_from(log.clone())
+    }
+
+    /// A builder with the server fields of `server` set and everything else empty.
+    pub fn for_server(server: &ServerContext) -> Self {
+        let mut builder = PlayerLogBuilderPartial::new().builder;
+        builder.server_ip = server.server_ip;
+        builder.server_port = server.server_port;
+        builder.server_domain.clone_from(&server.server_domain);
+        builder.server_version = server.server_version.name().unwrap_or_default().to_string();
+
+        builder
+    }
+}
+
+/// The server fields every record of one collector shares, with the version checked once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerContext {
+    pub server_ip: Ipv4Addr,
+    pub server_port: u16,
+    pub server_domain: String,
+    pub server_version: ServerVersion,
+}
+
+impl ServerContext {
+    pub fn new(
+        server_ip: Ipv4Addr,
+        server_port: u16,
+        server_domain: impl Into<String>,
+        server_version: &str,
+    ) -> Result<Self> {
+        Ok(Self {
             server_ip,
-            server_port: self.server_port,
-            server_domain: server_domain_bytes,
-            server_version,
+            server_port,
+            server_domain: server_domain.into(),
+            server_version: ServerVersion::from_name(server_version)?,
         })
     }
 
-    pub fn from_log(log: &PlayerLog) -> Result<Self> {
-        let flags = LogFlags::from_bits(log.flags).context("invalid flags")?;
+    /// Builds a log of a player on this server without looking up the version again, the same
+    /// log [`PlayerLogBuilder::for_server`] with these fields set builds.
+    pub fn log(
+        &self,
+        name: &str,
+        uuid: Option<Uuid>,
+        player_ip: Ipv4Addr,
+        flags: LogFlags,
+    ) -> Result<PlayerLog> {
+        let builder = PlayerLogBuilder {
+            flags,
+            player_uuid: uuid,
+            player_name: name.to_string(),
+            player_ip,
+            server_ip: self.server_ip,
+            server_port: self.server_port,
+            server_domain: self.server_domain.clone(),
+            ..PlayerLogBuilderPartial::new().builder
+        };
+
+        let mut log = PlayerLog::default();
+        builder.build_into_with_version(
+            &mut log,
+            &BuildOptions::default(),
+            Some(&self.server_version),
+        )?;
 
-        let player_uuid = log.player_uuid.map(Uuid::from_bytes);
+        Ok(log)
+    }
+}
 
-        let player_name =
-            String::from_utf8(log.player_name.clone()).context("invalid player name")?;
+/// Moves the byte buffers of the log into the strings instead of copying them.
+impl TryFrom<PlayerLog> for PlayerLogBuilder {
+    type Error = anyhow::Error;
 
-        let player_ip = Ipv4Addr::from(log.player_ip);
-        let server_ip = Ipv4Addr::from(log.server_ip);
+    fn try_from(log: PlayerLog) -> Result<Self> {
+        let mut flags = validate_flags(log.flags)?;
+        // derived from the fields they describe again when building
+        flags.remove(
+            LogFlags::NONDEFAULT_PORT
+                | LogFlags::DOMAIN_IS_IP
+                | LogFlags::HAS_CLIENT_INFO
+                | LogFlags::LOGIN_REFUSED
+                | LogFlags::HAS_GEO
+                | LogFlags::VIA_PROXY,
+        );
 
-        let server_domain =
-            String::from_utf8(log.server_domain.clone()).context("invalid server domain")?;
+        // the same as build does by default, a nil uuid means offline
+        let player_uuid = log
+            .player_uuid
+            .map(Uuid::from_bytes)
+            .filter(|uuid| !uuid.is_nil());
+        if log.player_uuid.is_some() && player_uuid.is_none() {
+            flags.remove(LogFlags::IS_ONLINE);
+        }
 
-        let server_version = VERSIONS
-            .entries()
-            .find(|(_, n)| **n == log.server_version)
+        let server_version = log
+            .server_version
+            .name()
             .context("invalid server version")?
-            .0
             .to_string();
 
         Ok(Self {
             flags,
             player_uuid,
-            player_name,
-            player_ip,
-            server_ip,
+            player_name: String::from_utf8(log.player_name.into_vec())
+                .context("invalid player name")?,
+            player_ip: Ipv4Addr::from(log.player_ip),
+            server_ip: Ipv4Addr::from(log.server_ip),
             server_port: log.server_port,
-            server_domain,
+            server_domain: String::from_utf8(log.server_domain.into_vec())
+                .context("invalid server domain")?,
             server_version,
+            // 0 is how a record stores an unknown timestamp
+            timestamp: (log.timestamp != 0).then_some(log.timestamp),
+            session_id: log.session_id,
+            extensions: log.extensions,
+            client_brand: client_info(log.client_brand).context("invalid client brand")?,
+            client_locale: client_info(log.client_locale).context("invalid client locale")?,
+            outcome: log.outcome,
+            geo: log.geo,
+            proxy_ip: log.proxy_ip.map(Ipv4Addr::from),
         })
     }
 }
 
-#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone)]
-pub struct PlayerLog {
-    pub binary_version: u8,
-    pub flags: u8,
-    pub player_uuid: Option<[u8; 16]>, // 128 bits (16 bytes)
-    pub player_name: Vec<u8>,          // max 16 bytes
-    pub player_ip: [u8; 4],
-    pub server_ip: [u8; 4],
-    pub server_port: u16, // max 16 bits (1-65535)
-    pub server_domain: Vec<u8>,
-    pub server_version: u8,
+fn check_text(field: &str, bytes: &[u8], reject_nul: bool) -> Result<()> {
+    std::str::from_utf8(bytes).with_context(|| format!("{field} isn't valid utf8"))?;
+    if reject_nul && bytes.contains(&0) {
+        bail!("{field} contains a NUL byte");
+    }
+
+    Ok(())
 }
 
-impl PlayerLog {
-    pub fn serialize<W: WriteBytesExt>(&self, writer: &mut W) -> Result<()> {
-        writer.write_u8(self.binary_version)?;
-        writer.write_u8(self.flags)?;
+// an empty field is an absent one
+fn client_info(field: Vec<u8>) -> Result<Option<String>> {
+    if field.is_empty() {
+        return Ok(None);
+    }
 
-        if LogFlags::from_bits_retain(self.flags).contains(LogFlags::IS_ONLINE) {
-            let uuid = self.player_uuid.as_ref().context("missing player uuid")?;
-            writer.write_all(uuid)?;
+    Ok(Some(String::from_utf8(field)?))
+}
+
+#[derive(Debug, Clone)]
+pub struct BuildOptions {
+    /// Builds a nil player uuid as no uuid, dropping IS_ONLINE. Some upstreams send the nil uuid
+    /// for offline players.
+    pub normalize_nil_uuid: bool,
+    /// Accepts names of up to [`PlayerLog::MAX_LONG_NAME_LEN`] bytes, as long as they're still
+    /// at most 16 characters. Such a log is always binary_version 2, since version 1 readers
+    /// stop at 16 bytes.
+    pub allow_long_name_bytes: bool,
+}
+
+impl Default for BuildOptions {
+    fn default() -> Self {
+        Self {
+            normalize_nil_uuid: true,
+            allow_long_name_bytes: false,
         }
+    }
+}
 
-        writer.write_u8(self.player_name.len() as u8)?;
-        writer.write_all(&self.player_name)?;
+/// The build errors callers tell apart, everything else is a plain message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildError {
+    /// More characters than Minecraft allows, no option changes this.
+    NameTooManyChars(usize),
+    /// Few enough characters but too many bytes, see [`BuildOptions::allow_long_name_bytes`].
+    NameTooManyBytes(usize),
+}
 
-        writer.write_all(&self.player_ip)?;
-        writer.write_all(&self.server_ip)?;
-        writer.write_u16::<BigEndian>(self.server_port)?;
+impl fmt::Display for BuildError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NameTooManyChars(chars) => write!(
+                f,
+                "Player name too long: {chars} characters, at most {} are allowed",
+                PlayerLog::MAX_NAME_CHARS
+            ),
+            Self::NameTooManyBytes(len) => write!(f, "Player name too long: {len} bytes"),
+        }
+    }
+}
 
-        writer.write_u8(self.server_domain.len() as u8)?;
-        writer.write_all(&self.server_domain)?;
+impl std::error::Error for BuildError {}
 
-        writer.write_u8(self.server_version)?;
+pub struct NeedsPlayerIp;
+pub struct NeedsServerVersion;
+pub struct Ready;
 
-        Ok(())
-    }
+/// A [`PlayerLogBuilder`] that can only be built once the player ip and server version are set.
+#[derive(Debug, Clone)]
+pub struct PlayerLogBuilderPartial<Stage> {
+    builder: PlayerLogBuilder,
+    stage: PhantomData<Stage>,
+}
 
-    pub fn deserialize<R: ReadBytesExt>(reader: &mut R) -> Result<Self> {
-        let binary_version = reader.read_u8()?;
-        if binary_version != 1 {
-            bail!("invalid binary version");
+impl PlayerLogBuilderPartial<NeedsPlayerIp> {
+    pub const fn new() -> Self {
+        Self {
+            builder: PlayerLogBuilder {
+                flags: LogFlags::empty(),
+                player_uuid: None,
+                player_name: String::new(),
+                player_ip: Ipv4Addr::UNSPECIFIED,
+                server_ip: Ipv4Addr::UNSPECIFIED,
+                server_port: 25565,
+                server_domain: String::new(),
+                server_version: String::new(),
+                timestamp: None,
+                session_id: 0,
+                extensions: Vec::new(),
+                client_brand: None,
+                client_locale: None,
+                outcome: LoginOutcome::Success,
+                geo: None,
+                proxy_ip: None,
+            },
+            stage: PhantomData,
         }
+    }
 
-        let flags = reader.read_u8()?;
-        let parsed_flags = LogFlags::from_bits(flags).context("invalid flags")?;
+    pub fn with_player_ip(mut self, ip: Ipv4Addr) -> PlayerLogBuilderPartial<NeedsServerVersion> {
+        self.builder.player_ip = ip;
+        self.into_stage()
+    }
+}
 
-        let player_uuid = if parsed_flags.contains(LogFlags::IS_ONLINE) {
-            let mut uuid = [0; 16];
-            reader.read_exact(&mut uuid)?;
-            Some(uuid)
-        } else {
-            None
-        };
+impl Default for PlayerLogBuilderPartial<NeedsPlayerIp> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
 
-        let name_len = reader.read_u8()?;
-        let mut player_name = vec![0; name_len as usize];
-        reader.read_exact(&mut player_name)?;
+impl PlayerLogBuilderPartial<NeedsServerVersion> {
+    pub fn with_server_version(mut self, ver: &str) -> Result<PlayerLogBuilderPartial<Ready>> {
+        ServerVersion::from_name(ver)?;
 
-        let mut player_ip = [0; 4];
-        reader.read_exact(&mut player_ip)?;
+        self.builder.server_version = ver.to_string();
+        Ok(self.into_stage())
+    }
+}
 
-        let mut server_ip = [0; 4];
-        reader.read_exact(&mut server_ip)?;
+impl PlayerLogBuilderPartial<Ready> {
+    pub fn build(&self) -> Result<PlayerLog> {
+        self.builder.build()
+    }
 
-        let server_port = reader.read_u16::<BigEndian>()?;
+    pub fn into_builder(self) -> PlayerLogBuilder {
+        self.builder
+    }
+}
 
-        let domain_len = reader.read_u8()?;
-        let mut server_domain = vec![0; domain_len as usize];
-        reader.read_exact(&mut server_domain)?;
+impl<Stage> PlayerLogBuilderPartial<Stage> {
+    fn into_stage<Next>(self) -> PlayerLogBuilderPartial<Next> {
+        PlayerLogBuilderPartial {
+            builder: self.builder,
+            stage: PhantomData,
+        }
+    }
 
-        let server_version = reader.read_u8()?;
+    pub const fn with_flags(mut self, flags: LogFlags) -> Self {
+        self.builder.flags = flags;
+        self
+    }
 
-        Ok(Self {
-            binary_version,
-            flags,
-            player_uuid,
-            player_name,
-            player_ip,
-            server_ip,
-            server_port,
-            server_domain,
-            server_version,
-        })
+    pub fn with_event_type(mut self, event_type: LogEventType) -> Self {
+        self.builder = self.builder.with_event_type(event_type);
+        self
     }
-}
 
-pub struct PlayerLogSerializer;
+    /// Also marks the log as online, the uuid is only written for online logs.
+    pub fn with_player_uuid(mut self, uuid: Uuid) -> Self {
+        self.builder.player_uuid = Some(uuid);
+        self.builder.flags |= LogFlags::IS_ONLINE;
+        self
+    }
 
-impl PlayerLogSerializer {
-    pub fn serialize_many(logs: &[PlayerLog]) -> Result<Vec<u8>> {
-        let mut writer = Vec::with_capacity(logs.len() * 128);
-        Self::serialization_helper(logs, &mut writer)?;
+    pub fn with_player_name(mut self, name: &str) -> Self {
+        self.builder.player_name = name.to_string();
+        self
+    }
 
-        Ok(writer)
+    pub const fn with_server_ip(mut self, ip: Ipv4Addr) -> Self {
+        self.builder.server_ip = ip;
+        self
     }
 
-    pub fn serialize_many_compressed(logs: &[PlayerLog], level: Compression) -> Result<Vec<u8>> {
-        let mut e = ZlibEncoder::new(Vec::with_capacity(logs.len() * 128), level);
+    pub const fn with_server_port(mut self, port: u16) -> Self {
+        self.builder.server_port = port;
+        self
+    }
 
-        Self::serialization_helper(logs, &mut e)?;
+    pub fn with_server_domain(mut self, domain: &str) -> Self {
+        self.builder.server_domain = domain.to_string();
+        self
+    }
 
-        e.finish().map_err(Into::into)
+    pub const fn with_timestamp(mut self, timestamp: u64) -> Self {
+        self.builder.timestamp = Some(timestamp);
+        self
     }
 
-    fn serialization_helper<W: Write>(logs: &[PlayerLog], writer: &mut W) -> anyhow::Result<()> {
-        writer.write_u64::<BigEndian>(logs.len() as u64)?;
+    pub const fn with_session_id(mut self, session_id: u32) -> Self {
+        self.builder.session_id = session_id;
+        self
+    }
 
-        // I hate this
-        let log_buffers = logs
-            .chunks((logs.len() / 10).max(1))
-            .par_bridge()
-            .map(|c| -> Result<Vec<u8>> {
-                let mut buf = Vec::with_capacity(c.len() * 128);
+    pub fn with_extension(mut self, kind: u8, data: &[u8]) -> Self {
+        self.builder.extensions.push(Extension {
+            kind,
+            data: data.to_vec(),
+        });
+        self
+    }
 
-                c.iter().try_for_each(|log| log.serialize(&mut buf))?;
-                Ok(buf)
-            })
-            .collect::<Result<Vec<_>>>()?;
+    pub const fn with_outcome(mut self, outcome: LoginOutcome) -> Self {
+        self.builder.outcome = outcome;
+        self
+    }
 
-        log_buffers
-            .iter()
-            .try_for_each(|buf| writer.write_all(buf))?;
+    pub const fn with_geo(mut self, geo: GeoInfo) -> Self {
+        self.builder.geo = Some(geo);
+        self
+    }
 
-        Ok(())
+    pub const fn with_proxy_ip(mut self, ip: Ipv4Addr) -> Self {
+        self.builder.proxy_ip = Some(ip);
+        self
     }
 
-    pub fn deserialize_many(data: &[u8]) -> Result<Vec<PlayerLog>> {
-        let mut reader = std::io::Cursor::new(data);
-        Self::deserialize_helper(&mut reader)
+    pub fn with_client_brand(mut self, brand: &str) -> Self {
+        self.builder.client_brand = Some(brand.to_string());
+        self
     }
 
-    pub fn deserialize_many_compressed(data: &[u8]) -> Result<Vec<PlayerLog>> {
-        let mut reader = ZlibDecoder::new(data);
-        Self::deserialize_helper(&mut reader)
+    pub fn with_client_locale(mut self, locale: &str) -> Self {
+        self.builder.client_locale = Some(locale.to_string());
+        self
     }
+}
+
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Default)]
+pub struct PlayerLog {
+    pub binary_version: u8,
+    pub flags: u16,
+    pub player_uuid: Option<[u8; 16]>, // 128 bits (16 bytes)
+    pub player_name: NameBytes,        // max 16 bytes, 32 under binary_version 2
+    pub player_ip: [u8; 4],
+    pub server_ip: [u8; 4],
+    pub server_port: u16, // max 16 bits (1-65535)
+    pub server_domain: DomainBytes,
+    pub server_version: ServerVersion,
+    // binary_version 2 and up
+    pub timestamp: u64, // millis since the unix epoch, 0 if unknown
+    pub session_id: u32,
+    pub outcome: LoginOutcome, // only written with LOGIN_REFUSED, Success otherwise
+    pub geo: Option<GeoInfo>,  // only written with HAS_GEO
+    pub proxy_ip: Option<[u8; 4]>, // only written with VIA_PROXY
+    pub extensions: Vec<Extension>,
+    // only written with HAS_CLIENT_INFO, empty if absent
+    pub client_brand: Vec<u8>,  // max 64 bytes
+    pub client_locale: Vec<u8>, // max 16 bytes
+}
+
+impl PlayerLog {
+    /// Name bytes a binary_version 1 record can hold, and what the builder allows by default.
+    pub const MAX_NAME_LEN: usize = 16;
+    /// Name bytes a binary_version 2 record can hold, for names with multi-byte characters.
+    pub const MAX_LONG_NAME_LEN: usize = 32;
+    pub const MAX_NAME_CHARS: usize = 16;
+    pub const MAX_DOMAIN_LEN: usize = u8::MAX as usize;
+    pub const MAX_EXTENSIONS: usize = 8;
+    pub const MAX_EXTENSION_LEN: usize = 64;
+    pub const MAX_CLIENT_BRAND_LEN: usize = 64;
+    pub const MAX_CLIENT_LOCALE_LEN: usize = 16;
+    pub const CURRENT_VERSION: u8 = 2;
+    pub const DEFAULT_PORT: u16 = 25565;
+    const UUID_LEN: usize = 16;
 
-    fn deserialize_helper<R: Read>(reader: &mut R) -> Result<Vec<PlayerLog>> {
-        let len = reader.read_u64::<BigEndian>()?;
-        let logs = (0..len)
-            .map(|_| PlayerLog::deserialize(reader))
-            .collect::<Result<Vec<PlayerLog>>>()?;
+    // binary version + flags + name length + player ip + server ip + port + domain length + server version
+    pub const MIN_ENCODED_SIZE: usize = 1 + 1 + 1 + 4 + 4 + 2 + 1 + 1;
+    // high flags byte + timestamp + session id + extension count
+    const V2_FIELDS_SIZE: usize = 1 + 8 + 4 + 1;
+    // kind + data length
+    const EXTENSION_HEADER_SIZE: usize = 1 + 1;
+    // brand length + locale length
+    const CLIENT_INFO_HEADER_SIZE: usize = 1 + 1;
+    // country + asn
+    const GEO_SIZE: usize = 2 + 4;
+    const PROXY_IP_SIZE: usize = 4;
+    pub const MAX_ENCODED_SIZE: usize = Self::MIN_ENCODED_SIZE
+        + Self::UUID_LEN
+        + Self::MAX_LONG_NAME_LEN
+        + Self::MAX_DOMAIN_LEN
+        + Self::V2_FIELDS_SIZE
+        + Self::MAX_EXTENSIONS * (Self::EXTENSION_HEADER_SIZE + Self::MAX_EXTENSION_LEN)
+        + Self::CLIENT_INFO_HEADER_SIZE
+        + Self::MAX_CLIENT_BRAND_LEN
+        + Self::MAX_CLIENT_LOCALE_LEN
+        + Self::GEO_SIZE
+        + Self::PROXY_IP_SIZE
+        + 1 // login outcome
+        + 1 + ServerVersion::MAX_UNKNOWN_LEN; // unknown server version name
+    /// Size of a batch header without a producer name.
+    pub const HEADER_SIZE: usize = BatchHeader::MIN_SIZE;
 
-        Ok(logs)
+    /// Upper bound on the size of an uncompressed batch of `records` records, a dictionary batch
+    /// included.
+    pub const fn max_batch_size(records: usize) -> usize {
+        // a dictionary batch puts a 2 byte domain index in front of every record
+        BatchHeader::MAX_SIZE + records * (2 + Self::MAX_ENCODED_SIZE)
+    }
+
+    pub const fn max_name_len(binary_version: u8) -> usize {
+        if binary_version >= 2 {
+            Self::MAX_LONG_NAME_LEN
+        } else {
+            Self::MAX_NAME_LEN
+        }
+    }
+
+    // can't be const, the inline storage derefs to its bytes at runtime
+    #[allow(clippy::missing_const_for_fn)]
+    pub fn serialized_size(&self) -> usize {
+        let uuid_len = if self.is_online() { Self::UUID_LEN } else { 0 };
+        let mut size =
+            Self::MIN_ENCODED_SIZE + uuid_len + self.player_name.len() + self.server_domain.len();
+
+        if self.binary_version >= 2 {
+            size += Self::V2_FIELDS_SIZE;
+            if !self.writes_port() {
+                size -= 2;
+            }
+            if !self.writes_domain() {
+                size -= 1 + self.server_domain.len();
+            }
+            if let ServerVersion::Unknown(name) = &self.server_version {
+                size += 1 + name.len();
+            }
+            if self.is_refused() {
+                size += 1;
+            }
+            if self.has_geo() {
+                size += Self::GEO_SIZE;
+            }
+            if self.is_proxied() {
+                size += Self::PROXY_IP_SIZE;
+            }
+            if self.has_client_info() {
+                size += Self::CLIENT_INFO_HEADER_SIZE
+                    + self.client_brand.len()
+                    + self.client_locale.len();
+            }
+
+            let extensions = self.extensions.as_slice();
+            let mut i = 0;
+            while i < extensions.len() {
+                size += Self::EXTENSION_HEADER_SIZE + extensions[i].data.len();
+                i += 1;
+            }
+        }
+
+        size
+    }
+
+    /// Bytes the log takes up in memory, the struct and the contents of its heap buffers.
+    pub fn memory_size(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.player_name.len()
+            + self.server_domain.len()
+            + self.client_brand.len()
+            + self.client_locale.len()
+            + self
+                .extensions
+                .iter()
+                .map(|extension| mem::size_of_val(extension) + extension.data.len())
+                .sum::<usize>()
+    }
+
+    pub fn present_fields(&self) -> FieldPresenceMask {
+        let mut fields = FieldPresenceMask::empty();
+        fields.set(FieldPresenceMask::UUID, self.player_uuid.is_some());
+        fields.set(FieldPresenceMask::TIMESTAMP, self.binary_version >= 2);
+        fields.set(
+            FieldPresenceMask::SESSION_ID,
+            self.binary_version >= 2 && self.session_id != 0,
+        );
+        fields.set(FieldPresenceMask::EXTENSIONS, !self.extensions.is_empty());
+        fields.set(FieldPresenceMask::GEO, self.geo.is_some());
+        fields.set(FieldPresenceMask::PROXY_IP, self.proxy_ip.is_some());
+        fields.set(
+            FieldPresenceMask::CLIENT_INFO,
+            !self.client_brand.is_empty() || !self.client_locale.is_empty(),
+        );
+        fields
+    }
+
+    /// The player uuid, `None` for the nil uuid even if a record was written with it.
+    pub fn identity_uuid(&self) -> Option<[u8; 16]> {
+        self.player_uuid.filter(|uuid| *uuid != [0; 16])
+    }
+
+    const fn is_online(&self) -> bool {
+        LogFlags::from_bits_retain(self.flags).contains(LogFlags::IS_ONLINE)
+    }
+
+    const fn writes_port(&self) -> bool {
+        self.binary_version < 2
+            || LogFlags::from_bits_retain(self.flags).contains(LogFlags::NONDEFAULT_PORT)
+    }
+
+    const fn writes_domain(&self) -> bool {
+        self.binary_version < 2
+            || !LogFlags::from_bits_retain(self.flags).contains(LogFlags::DOMAIN_IS_IP)
+    }
+
+    const fn is_refused(&self) -> bool {
+        self.binary_version >= 2
+            && LogFlags::from_bits_retain(self.flags).contains(LogFlags::LOGIN_REFUSED)
+    }
+
+    const fn has_geo(&self) -> bool {
+        self.binary_version >= 2
+            && LogFlags::from_bits_retain(self.flags).contains(LogFlags::HAS_GEO)
+    }
+
+    const fn is_proxied(&self) -> bool {
+        self.binary_version >= 2
+            && LogFlags::from_bits_retain(self.flags).contains(LogFlags::VIA_PROXY)
+    }
+
+    const fn has_client_info(&self) -> bool {
+        self.binary_version >= 2
+            && LogFlags::from_bits_retain(self.flags).contains(LogFlags::HAS_CLIENT_INFO)
+    }
+
+    pub const fn event_type(&self) -> LogEventType {
+        LogEventType::from_flags(self.flags)
+    }
+
+    pub const fn is_authenticated_session(&self) -> bool {
+        let flags = LogFlags::from_bits_retain(self.flags);
+        flags.contains(LogFlags::IS_ONLINE.union(LogFlags::PLAYER_AUTH))
+            && !flags.contains(LogFlags::CRACKED_CLIENT)
+    }
+
+    pub fn is_suspicious(&self) -> bool {
+        let flags = LogFlags::from_bits_retain(self.flags);
+
+        (flags.contains(LogFlags::IS_ONLINE) && !flags.contains(LogFlags::PLAYER_AUTH))
+            || self
+                .player_name
+                .iter()
+                .any(|b| !(b.is_ascii_alphanumeric() || *b == b'_'))
+            || self.server_port < 1024
+            || (self.player_ip == [0, 0, 0, 0] && !flags.contains(LogFlags::REDACTED))
+            || self.player_ip == [255, 255, 255, 255]
+            || (self.player_uuid.is_some() && !flags.contains(LogFlags::IS_ONLINE))
+    }
+
+    /// A copy without the uuid and player ip, the name is replaced by the hex of the first 4
+    /// bytes of its sha256 so the same player still gets the same name.
+    pub fn clone_redacted(&self) -> Self {
+        let digest = Sha256::digest(&*self.player_name);
+        let name = digest[..4]
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+
+        let mut flags = LogFlags::from_bits_retain(self.flags);
+        flags.remove(LogFlags::IS_ONLINE);
+        flags.insert(LogFlags::REDACTED);
+
+        Self {
+            flags: flags.bits(),
+            player_uuid: None,
+            player_name: NameBytes::from_slice(name.as_bytes()),
+            player_ip: [0; 4],
+            ..self.clone()
+        }
+    }
+
+    /// Rewrites an older record as [`PlayerLog::CURRENT_VERSION`], deriving the flags the newer
+    /// layout relies on. Returns `false` if it was already current.
+    pub fn upgrade(&mut self) -> bool {
+        if self.binary_version >= Self::CURRENT_VERSION {
+            return false;
+        }
+
+        // the timestamp and session id stay 0, they weren't recorded
+        let mut flags = LogFlags::from_bits_retain(self.flags);
+        flags.set(
+            LogFlags::NONDEFAULT_PORT,
+            self.server_port != Self::DEFAULT_PORT,
+        );
+        flags.set(
+            LogFlags::DOMAIN_IS_IP,
+            *self.server_domain == *Ipv4Addr::from(self.server_ip).to_string().as_bytes(),
+        );
+
+        self.flags = flags.bits();
+        self.binary_version = Self::CURRENT_VERSION;
+        true
+    }
+
+    pub fn serialize<W: WriteBytesExt>(&self, writer: &mut W) -> Result<()> {
+        self.serialize_with_domain::<BigEndian, _>(writer, &self.server_domain)
+    }
+
+    /// [`PlayerLog::serialize`] writing the multi-byte integers in `endianness`.
+    pub fn serialize_with_endianness<W: WriteBytesExt>(
+        &self,
+        writer: &mut W,
+        endianness: Endianness,
+    ) -> Result<()> {
+        match endianness {
+            Endianness::Big => {
+                self.serialize_with_domain::<BigEndian, _>(writer, &self.server_domain)
+            }
+            Endianness::Little => {
+                self.serialize_with_domain::<LittleEndian, _>(writer, &self.server_domain)
+            }
+        }
+    }
+
+    // writes `server_domain` in place of the log's own, so a dictionary batch can leave out the
+    // domains it has an index for
+    fn serialize_with_domain<B: ByteOrder, W: WriteBytesExt>(
+        &self,
+        writer: &mut W,
+        server_domain: &[u8],
+    ) -> Result<()> {
+        self.validate_with_domain(server_domain)?;
+
+        writer.write_u8(self.binary_version)?;
+        if self.binary_version >= 2 {
+            writer.write_u16::<B>(self.flags)?;
+        } else {
+            // validated to fit
+            writer.write_u8(self.flags as u8)?;
+        }
+
+        if self.is_online() {
+            let uuid = self.player_uuid.as_ref().context("missing player uuid")?;
+            writer.write_all(uuid)?;
+        }
+
+        writer.write_u8(self.player_name.len() as u8)?;
+        writer.write_all(&self.player_name)?;
+
+        writer.write_all(&self.player_ip)?;
+        writer.write_all(&self.server_ip)?;
+        if self.writes_port() {
+            writer.write_u16::<B>(self.server_port)?;
+        }
+
+        if self.writes_domain() {
+            writer.write_u8(server_domain.len() as u8)?;
+            writer.write_all(server_domain)?;
+        }
+
+        match &self.server_version {
+            ServerVersion::Known(id) => writer.write_u8(*id)?,
+            ServerVersion::Unknown(name) => {
+                writer.write_u8(ServerVersion::UNKNOWN_ID)?;
+                writer.write_u8(name.len() as u8)?;
+                writer.write_all(name.as_bytes())?;
+            }
+        }
+
+        if self.binary_version >= 2 {
+            writer.write_u64::<B>(self.timestamp)?;
+            writer.write_u32::<B>(self.session_id)?;
+
+            if self.is_refused() {
+                writer.write_u8(self.outcome.to_byte())?;
+            }
+            if self.has_geo() {
+                let geo = self.geo.as_ref().context("missing geo info")?;
+                writer.write_all(&geo.country)?;
+                writer.write_u32::<B>(geo.asn)?;
+            }
+            if self.is_proxied() {
+                let proxy_ip = self.proxy_ip.as_ref().context("missing proxy ip")?;
+                writer.write_all(proxy_ip)?;
+            }
+            if self.has_client_info() {
+                writer.write_u8(self.client_brand.len() as u8)?;
+                writer.write_all(&self.client_brand)?;
+                writer.write_u8(self.client_locale.len() as u8)?;
+                writer.write_all(&self.client_locale)?;
+            }
+
+            writer.write_u8(self.extensions.len() as u8)?;
+            for extension in &self.extensions {
+                writer.write_u8(extension.kind)?;
+                writer.write_u8(extension.data.len() as u8)?;
+                writer.write_all(&extension.data)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails for the same reasons [`PlayerLog::serialize`] would, without writing anything.
+    pub fn validate(&self) -> Result<()> {
+        self.validate_with_domain(&self.server_domain)
+    }
+
+    fn validate_with_domain(&self, server_domain: &[u8]) -> Result<()> {
+        if self.player_name.len() > Self::max_name_len(self.binary_version) {
+            bail!("Player name too long");
+        }
+        if server_domain.len() > Self::MAX_DOMAIN_LEN {
+            bail!("Server domain too long");
+        }
+        if !(1..=Self::CURRENT_VERSION).contains(&self.binary_version) {
+            bail!("invalid binary version");
+        }
+
+        let unsupported = self
+            .present_fields()
+            .difference(FieldPresenceMask::all_for_version(self.binary_version));
+        if !unsupported.is_empty() {
+            bail!(
+                "{unsupported:?} can't be written as binary_version {}",
+                self.binary_version
+            );
+        }
+        if self.is_online() && !version_supports(self.binary_version, Feature::OnlineModeUuid) {
+            bail!("binary_version {} can't hold a uuid", self.binary_version);
+        }
+        if matches!(self.server_version, ServerVersion::Unknown(_))
+            && !version_supports(self.binary_version, Feature::SnapshotVersion)
+        {
+            bail!("Unknown server versions need binary_version 2");
+        }
+        if self.binary_version < 2 && (self.timestamp != 0 || self.session_id != 0) {
+            bail!("timestamp and session id need binary_version 2");
+        }
+        if self.extensions.len() > Self::MAX_EXTENSIONS {
+            bail!("Too many extensions");
+        }
+        if !self.writes_port() && self.server_port != Self::DEFAULT_PORT {
+            bail!("Non-default port without the NONDEFAULT_PORT flag");
+        }
+        if !self.writes_domain()
+            && *self.server_domain != *Ipv4Addr::from(self.server_ip).to_string().as_bytes()
+        {
+            bail!("DOMAIN_IS_IP set but the domain isn't the server ip");
+        }
+        if !self.has_client_info()
+            && self
+                .present_fields()
+                .contains(FieldPresenceMask::CLIENT_INFO)
+        {
+            bail!("Client brand or locale without the HAS_CLIENT_INFO flag");
+        }
+        if self.client_brand.len() > Self::MAX_CLIENT_BRAND_LEN {
+            bail!("Client brand too long");
+        }
+        if self.client_locale.len() > Self::MAX_CLIENT_LOCALE_LEN {
+            bail!("Client locale too long");
+        }
+        if !self.is_refused() && self.outcome != LoginOutcome::Success {
+            bail!("Refused login without the LOGIN_REFUSED flag");
+        }
+        if !self.has_geo() && self.geo.is_some() {
+            bail!("Geo info without the HAS_GEO flag");
+        }
+        if !self.is_proxied() && self.proxy_ip.is_some() {
+            bail!("Proxy ip without the VIA_PROXY flag");
+        }
+
+        if self.binary_version < 2 && self.flags > u16::from(u8::MAX) {
+            bail!("flags above the low byte need binary_version 2");
+        }
+        if self.is_online() && self.player_uuid.is_none() {
+            bail!("missing player uuid");
+        }
+        match &self.server_version {
+            ServerVersion::Known(ServerVersion::UNKNOWN_ID) if self.binary_version >= 2 => {
+                bail!("Known server version with the unknown version id")
+            }
+            ServerVersion::Unknown(name) if name.len() > ServerVersion::MAX_UNKNOWN_LEN => {
+                bail!("Unknown server version too long")
+            }
+            _ => {}
+        }
+        if self.has_geo() && self.geo.is_none() {
+            bail!("missing geo info");
+        }
+        if self.is_proxied() && self.proxy_ip.is_none() {
+            bail!("missing proxy ip");
+        }
+        if self
+            .extensions
+            .iter()
+            .any(|extension| extension.data.len() > Self::MAX_EXTENSION_LEN)
+        {
+            bail!("Extension data too long");
+        }
+
+        Ok(())
+    }
+
+    pub fn deserialize<R: ReadBytesExt>(reader: &mut R) -> Result<Self> {
+        Self::deserialize_as::<BigEndian, _>(reader)
+    }
+
+    /// [`PlayerLog::deserialize`] for records written with `endianness`. A record read with the
+    /// wrong one usually fails on its flags or lengths, but not always, so the caller has to know.
+    pub fn deserialize_with_endianness<R: ReadBytesExt>(
+        reader: &mut R,
+        endianness: Endianness,
+    ) -> Result<Self> {
+        match endianness {
+            Endianness::Big => Self::deserialize_as::<BigEndian, _>(reader),
+            Endianness::Little => Self::deserialize_as::<LittleEndian, _>(reader),
+        }
+    }
+
+    fn deserialize_as<B: ByteOrder, R: ReadBytesExt>(reader: &mut R) -> Result<Self> {
+        let binary_version = reader.read_u8()?;
+        if !(1..=Self::CURRENT_VERSION).contains(&binary_version) {
+            bail!("invalid binary version");
+        }
+
+        let flags = if binary_version >= 2 {
+            reader.read_u16::<B>()?
+        } else {
+            u16::from(reader.read_u8()?)
+        };
+        let parsed_flags = validate_flags(flags)?;
+
+        let player_uuid = if parsed_flags.contains(LogFlags::IS_ONLINE) {
+            let mut uuid = [0; Self::UUID_LEN];
+            reader.read_exact(&mut uuid)?;
+            Some(uuid)
+        } else {
+            None
+        };
+
+        let name_len = reader.read_u8()? as usize;
+        if name_len > Self::max_name_len(binary_version) {
+            bail!("invalid player name: {name_len} bytes");
+        }
+        let player_name = NameBytes::read_from(reader, name_len)?;
+
+        let mut player_ip = [0; 4];
+        reader.read_exact(&mut player_ip)?;
+
+        let mut server_ip = [0; 4];
+        reader.read_exact(&mut server_ip)?;
+
+        let server_port = if binary_version < 2 || parsed_flags.contains(LogFlags::NONDEFAULT_PORT)
+        {
+            reader.read_u16::<B>()?
+        } else {
+            Self::DEFAULT_PORT
+        };
+
+        let server_domain = if binary_version >= 2 && parsed_flags.contains(LogFlags::DOMAIN_IS_IP)
+        {
+            DomainBytes::from_vec(Ipv4Addr::from(server_ip).to_string().into_bytes())
+        } else {
+            let domain_len = reader.read_u8()?;
+            DomainBytes::read_from(reader, domain_len as usize)?
+        };
+
+        let server_version = match reader.read_u8()? {
+            ServerVersion::UNKNOWN_ID if binary_version >= 2 => {
+                let name = read_bounded(reader, ServerVersion::MAX_UNKNOWN_LEN)
+                    .context("invalid unknown server version")?;
+                ServerVersion::Unknown(
+                    String::from_utf8(name)
+                        .context("invalid unknown server version")?
+                        .into(),
+                )
+            }
+            id => ServerVersion::Known(id),
+        };
+
+        let mut log = Self {
+            binary_version,
+            flags,
+            player_uuid,
+            player_name,
+            player_ip,
+            server_ip,
+            server_port,
+            server_domain,
+            server_version,
+            ..Self::default()
+        };
+
+        if binary_version >= 2 {
+            log.timestamp = reader.read_u64::<B>()?;
+            log.session_id = reader.read_u32::<B>()?;
+
+            if parsed_flags.contains(LogFlags::LOGIN_REFUSED) {
+                log.outcome = LoginOutcome::from_byte(reader.read_u8()?);
+            }
+            if parsed_flags.contains(LogFlags::HAS_GEO) {
+                let mut country = [0; 2];
+                reader.read_exact(&mut country)?;
+                let asn = reader.read_u32::<B>()?;
+                log.geo = Some(GeoInfo { country, asn });
+            }
+            if parsed_flags.contains(LogFlags::VIA_PROXY) {
+                let mut proxy_ip = [0; 4];
+                reader.read_exact(&mut proxy_ip)?;
+                log.proxy_ip = Some(proxy_ip);
+            }
+            if parsed_flags.contains(LogFlags::HAS_CLIENT_INFO) {
+                log.client_brand = read_bounded(reader, Self::MAX_CLIENT_BRAND_LEN)
+                    .context("invalid client brand")?;
+                log.client_locale = read_bounded(reader, Self::MAX_CLIENT_LOCALE_LEN)
+                    .context("invalid client locale")?;
+            }
+
+            let extension_count = reader.read_u8()? as usize;
+            if extension_count > Self::MAX_EXTENSIONS {
+                bail!("Too many extensions");
+            }
+
+            for _ in 0..extension_count {
+                let kind = reader.read_u8()?;
+                let len = reader.read_u8()? as usize;
+                if len > Self::MAX_EXTENSION_LEN {
+                    bail!("Extension data too long");
+                }
+
+                let mut data = vec![0; len];
+                reader.read_exact(&mut data)?;
+                log.extensions.push(Extension { kind, data });
+            }
+        }
+
+        Ok(log)
+    }
+}
+
+/// The server the player joined.
+///
+/// ```
+/// use std::net::SocketAddrV4;
+///
+/// use binary_storage_test::player_log::PlayerLogBuilderPartial;
+///
+/// let log = PlayerLogBuilderPartial::new()
+///     .with_player_ip([10, 0, 0, 7].into())
+///     .with_server_version("1.20")?
+///     .with_server_ip([192, 168, 1, 5].into())
+///     .with_server_port(25566)
+///     .build()?;
+///
+/// assert_eq!(SocketAddrV4::from(&log), "192.168.1.5:25566".parse()?);
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+impl From<&PlayerLog> for SocketAddrV4 {
+    fn from(log: &PlayerLog) -> Self {
+        Self::new(log.server_ip.into(), log.server_port)
+    }
+}
+
+/// The player's address, the one the proxy forwarded for logs with a `proxy_ip`.
+///
+/// ```
+/// use std::net::Ipv4Addr;
+///
+/// use binary_storage_test::player_log::PlayerLogBuilderPartial;
+///
+/// let log = PlayerLogBuilderPartial::new()
+///     .with_player_ip([10, 0, 0, 7].into())
+///     .with_server_version("1.20")?
+///     .build()?;
+///
+/// assert_eq!(Ipv4Addr::from(&log), Ipv4Addr::new(10, 0, 0, 7));
+/// # Ok::<(), anyhow::Error>(())
+/// ```
+impl From<&PlayerLog> for Ipv4Addr {
+    fn from(log: &PlayerLog) -> Self {
+        log.player_ip.into()
+    }
+}
+
+fn read_bounded<R: ReadBytesExt>(reader: &mut R, max_len: usize) -> Result<Vec<u8>> {
+    let len = reader.read_u8()? as usize;
+    if len > max_len {
+        bail!("{len} bytes, at most {max_len} are allowed");
+    }
+
+    let mut field = vec![0; len];
+    reader.read_exact(&mut field)?;
+    Ok(field)
+}
+
+pub type BatchId = [u8; 16];
+
+pub const BATCH_MAGIC: [u8; 4] = *b"PLGB";
+pub const CONTAINER_VERSION: u8 = 1;
+
+/// Byte order of the multi-byte record fields. Batches are big endian unless written for a tool
+/// that expects otherwise, a little endian batch says so in its header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Endianness {
+    #[default]
+    Big,
+    Little,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Codec {
+    #[default]
+    None,
+    Zlib(Compression),
+    Lz4,
+}
+
+impl Codec {
+    pub const fn id(self) -> u8 {
+        match self {
+            Self::None => 0,
+            Self::Zlib(_) => 1,
+            Self::Lz4 => 2,
+        }
+    }
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Zlib(_) => "zlib",
+            Self::Lz4 => "lz4",
+        }
+    }
+
+    // the level isn't stored, it's only needed when encoding
+    pub fn from_id(id: u8) -> Result<Self> {
+        match id {
+            0 => Ok(Self::None),
+            1 => Ok(Self::Zlib(Compression::default())),
+            2 => Ok(Self::Lz4),
+            _ => bail!("unknown codec id {id}"),
+        }
+    }
+
+    fn encode_into(self, payload: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::None => out.extend_from_slice(payload),
+            Self::Zlib(level) => {
+                let mut e = ZlibEncoder::new(out, level);
+                e.write_all(payload)?;
+                e.finish()?;
+            }
+            Self::Lz4 => {
+                let mut e = FrameEncoder::new(out);
+                e.write_all(payload)?;
+                e.finish()?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn decoder<'a>(self, payload: &'a [u8]) -> Box<dyn Read + 'a> {
+        match self {
+            Self::None => Box::new(payload),
+            Self::Zlib(_) => Box::new(ZlibDecoder::new(payload)),
+            Self::Lz4 => Box::new(FrameDecoder::new(payload)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SerializerOptions {
+    pub codec: Codec,
+    /// Idempotency key written into the header, defaults to a hash of the uncompressed payload.
+    pub batch_id: Option<BatchId>,
+    /// Free form name of whatever wrote the batch, at most 255 bytes.
+    pub producer: Option<String>,
+    /// Every reader of a batch follows the byte order in its header. Only
+    /// [`PlayerLog::explain`], which sees a bare record, assumes big endian.
+    pub endianness: Endianness,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct DeserializerOptions {
+    /// Runs [`PlayerLog::validate_consistency`] on every record, failing on the first
+    /// inconsistent one.
+    pub validate: bool,
+    /// Fails on the first player name or server domain that isn't utf8, instead of leaving it to
+    /// [`PlayerLogBuilder::from_log`]. Decoding takes about 12% longer, see the `Utf8Validation`
+    /// bench group.
+    pub validate_utf8: bool,
+    /// Along with `validate_utf8`, rejects NUL bytes. They're valid utf8 but never part of a real
+    /// name or domain.
+    pub reject_nul: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BatchHeader<'a> {
+    pub codec: Codec,
+    pub batch_id: BatchId,
+    pub record_count: u64,
+    pub producer: Option<&'a str>,
+    /// The domain dictionary the records were written against, see [`dictionary`].
+    pub dictionary: Option<DictionaryId>,
+    pub endianness: Endianness,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ProbeResult<'a> {
+    /// At least this many more bytes are needed before the header can be parsed.
+    NeedMoreData(usize),
+    Complete {
+        header: BatchHeader<'a>,
+        /// Total size of the header, the payload starts right after it.
+        header_len: usize,
+    },
+}
+
+impl<'a> BatchHeader<'a> {
+    // magic + container version + length of the rest of the header
+    pub const PREFIX_SIZE: usize = 4 + 1 + 2;
+    // codec + batch id + record count + producer length
+    const MIN_BODY_SIZE: usize = 1 + 16 + 8 + 1;
+    pub const MIN_SIZE: usize = Self::PREFIX_SIZE + Self::MIN_BODY_SIZE;
+    /// A header with the longest producer name and a domain dictionary.
+    pub const MAX_SIZE: usize = Self::MIN_SIZE + u8::MAX as usize + Self::DICTIONARY_SIZE;
+
+    // length + entry count + hash, only written for dictionary batches
+    const DICTIONARY_SIZE: usize = 1 + 2 + 16;
+    // set on the codec id of a little endian batch, so readers that predate it reject the codec
+    // instead of misreading the records
+    const LITTLE_ENDIAN_CODEC: u8 = 0x80;
+
+    pub fn encoded_len(&self) -> usize {
+        Self::MIN_SIZE
+            + self.producer.map_or(0, str::len)
+            + self.dictionary.map_or(0, |_| Self::DICTIONARY_SIZE)
+    }
+
+    fn check_no_dictionary(&self) -> Result<()> {
+        if let Some(dictionary) = self.dictionary {
+            bail!("batch needs domain dictionary {dictionary}");
+        }
+        Ok(())
+    }
+
+    /// The record count as the u32 every ordinal fits in, records past `u32::MAX` couldn't be
+    /// addressed.
+    fn ordinal_count(&self) -> Result<u32> {
+        u32::try_from(self.record_count)
+            .with_context(|| format!("{} records don't fit u32 ordinals", self.record_count))
+    }
+
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        let producer = self.producer.unwrap_or_default().as_bytes();
+        if producer.len() > u8::MAX as usize {
+            bail!("producer name too long");
+        }
+
+        writer.write_all(&BATCH_MAGIC)?;
+        writer.write_u8(CONTAINER_VERSION)?;
+        writer.write_u16::<BigEndian>((self.encoded_len() - Self::PREFIX_SIZE) as u16)?;
+
+        let codec = match self.endianness {
+            Endianness::Big => self.codec.id(),
+            Endianness::Little => self.codec.id() | Self::LITTLE_ENDIAN_CODEC,
+        };
+        writer.write_u8(codec)?;
+        writer.write_all(&self.batch_id)?;
+        writer.write_u64::<BigEndian>(self.record_count)?;
+        writer.write_u8(producer.len() as u8)?;
+        writer.write_all(producer)?;
+
+        if let Some(dictionary) = self.dictionary {
+            writer.write_u8((Self::DICTIONARY_SIZE - 1) as u8)?;
+            writer.write_u16::<BigEndian>(dictionary.len)?;
+            writer.write_all(&dictionary.hash)?;
+        }
+
+        Ok(())
+    }
+
+    fn parse(prefix: &'a [u8]) -> Result<ProbeResult<'a>> {
+        let magic_len = prefix.len().min(BATCH_MAGIC.len());
+        if prefix[..magic_len] != BATCH_MAGIC[..magic_len] {
+            bail!("invalid batch magic");
+        }
+
+        if prefix.len() < Self::PREFIX_SIZE {
+            return Ok(ProbeResult::NeedMoreData(Self::PREFIX_SIZE - prefix.len()));
+        }
+
+        let container_version = prefix[4];
+        if container_version != CONTAINER_VERSION {
+            bail!("unsupported container version {container_version}");
+        }
+
+        let body_len = u16::from_be_bytes([prefix[5], prefix[6]]) as usize;
+        if body_len < Self::MIN_BODY_SIZE {
+            bail!("batch header too short");
+        }
+
+        let header_len = Self::PREFIX_SIZE + body_len;
+        if prefix.len() < header_len {
+            return Ok(ProbeResult::NeedMoreData(header_len - prefix.len()));
+        }
+
+        let mut body = &prefix[Self::PREFIX_SIZE..header_len];
+
+        let codec = body.read_u8()?;
+        let endianness = if codec & Self::LITTLE_ENDIAN_CODEC == 0 {
+            Endianness::Big
+        } else {
+            Endianness::Little
+        };
+        let codec = Codec::from_id(codec & !Self::LITTLE_ENDIAN_CODEC)?;
+
+        let mut batch_id = [0; 16];
+        body.read_exact(&mut batch_id)?;
+
+        let record_count = body.read_u64::<BigEndian>()?;
+
+        let producer_len = body.read_u8()? as usize;
+        if body.len() < producer_len {
+            bail!("producer overruns the batch header");
+        }
+        let producer = match producer_len {
+            0 => None,
+            len => Some(std::str::from_utf8(&body[..len]).context("invalid producer")?),
+        };
+        body = &body[producer_len..];
+
+        let dictionary = match body.first() {
+            Some(&len)
+                if len as usize == Self::DICTIONARY_SIZE - 1 && body.len() > len as usize =>
+            {
+                body = &body[1..];
+                let len = body.read_u16::<BigEndian>()?;
+                let mut hash = [0; 16];
+                body.read_exact(&mut hash)?;
+                Some(DictionaryId { len, hash })
+            }
+            _ => None,
+        };
+
+        // anything left in the body was written by a newer version and can be skipped
+
+        Ok(ProbeResult::Complete {
+            header: Self {
+                codec,
+                batch_id,
+                record_count,
+                producer,
+                dictionary,
+                endianness,
+            },
+            header_len,
+        })
+    }
+}
+
+/// Every record [`PlayerLogSerializer::serialize_many_strict`] found invalid, by index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BatchValidationError {
+    /// The first [`BatchValidationError::MAX_OFFENDERS`] invalid records in batch order, with
+    /// why they're invalid.
+    pub offenders: Vec<(usize, String)>,
+    /// How many records are invalid, including the ones past the limit.
+    pub invalid: usize,
+}
+
+impl BatchValidationError {
+    pub const MAX_OFFENDERS: usize = 1000;
+}
+
+impl fmt::Display for BatchValidationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} invalid records", self.invalid)?;
+        for (index, reason) in &self.offenders {
+            write!(f, "\n  record {index}: {reason}")?;
+        }
+        if self.invalid > self.offenders.len() {
+            write!(f, "\n  and {} more", self.invalid - self.offenders.len())?;
+        }
+
+        Ok(())
+    }
+}
+
+impl std::error::Error for BatchValidationError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileStats {
+    pub file_size: u64,
+    pub record_count: u64,
+    /// Records the filter kept, the counts below only cover these.
+    pub matched_count: u64,
+    pub codec: Codec,
+    pub container_version: u8,
+    pub producer: Option<String>,
+    /// Number of records per record `binary_version`.
+    pub binary_versions: BTreeMap<u8, u64>,
+    /// Number of records carrying each optional field.
+    pub present_fields: BTreeMap<&'static str, u64>,
+    pub outcomes: BTreeMap<LoginOutcome, u64>,
+    /// Number of records per known server version id.
+    pub server_versions: BTreeMap<u8, u64>,
+    /// Number of records per version string the [`VERSIONS`] table doesn't know yet.
+    pub unknown_versions: BTreeMap<String, u64>,
+    /// Number of records per country, for records that have geo info.
+    pub countries: BTreeMap<String, u64>,
+    /// Number of records per proxy, for records that came through one.
+    pub proxies: BTreeMap<Ipv4Addr, u64>,
+}
+
+// reads the payload to its end after the last record, so a compressed stream cut off or corrupted
+// after it fails and so does anything left over after it
+fn check_payload_end<R: Read>(reader: &mut R) -> Result<()> {
+    let trailing =
+        io::copy(reader, &mut io::sink()).context("corrupt payload after the last record")?;
+    if trailing > 0 {
+        bail!("{trailing} bytes after the last record");
+    }
+    Ok(())
+}
+
+fn default_batch_id(record_count: u64, payload: &[u8]) -> BatchId {
+    batch_id_from_hasher(
+        Sha256::new()
+            .chain_update(record_count.to_be_bytes())
+            .chain_update(payload),
+    )
+}
+
+// for callers that hash the record count and the payload as they go
+fn batch_id_from_hasher(hasher: Sha256) -> BatchId {
+    let digest = hasher.finalize();
+
+    let mut batch_id = [0; 16];
+    batch_id.copy_from_slice(&digest[..16]);
+    batch_id
+}
+
+// how many records serialize_many_auto_compress test compresses before picking a codec
+const AUTO_COMPRESS_SAMPLE: usize = 1000;
+
+pub struct PlayerLogSerializer;
+
+impl PlayerLogSerializer {
+    pub fn serialize_many(logs: &[PlayerLog]) -> Result<Vec<u8>> {
+        Self::serialize_many_with_options(logs, &SerializerOptions::default())
+    }
+
+    pub fn serialize_many_compressed(logs: &[PlayerLog], level: Compression) -> Result<Vec<u8>> {
+        let options = SerializerOptions {
+            codec: Codec::Zlib(level),
+            ..SerializerOptions::default()
+        };
+
+        Self::serialize_many_with_options(logs, &options)
+    }
+
+    pub fn serialize_many_with_options(
+        logs: &[PlayerLog],
+        options: &SerializerOptions,
+    ) -> Result<Vec<u8>> {
+        let payload = Self::serialize_payload(logs, options.endianness, &|_| {})?;
+        Self::write_container(&payload, logs.len() as u64, options, None)
+    }
+
+    /// [`PlayerLogSerializer::serialize_many_with_options`] validating every record before any of
+    /// them is encoded, so a bad batch fails with a [`BatchValidationError`] naming each invalid
+    /// record instead of whichever one a chunk hit first.
+    pub fn serialize_many_strict(
+        logs: &[PlayerLog],
+        options: &SerializerOptions,
+    ) -> Result<Vec<u8>> {
+        let errors = parallel::map_items(logs, |log| log.validate().err());
+
+        let mut invalid = 0;
+        let mut offenders = Vec::new();
+        for (index, error) in errors.into_iter().enumerate() {
+            let Some(error) = error else {
+                continue;
+            };
+
+            invalid += 1;
+            if offenders.len() < BatchValidationError::MAX_OFFENDERS {
+                offenders.push((index, format!("{error:#}")));
+            }
+        }
+
+        if invalid > 0 {
+            return Err(BatchValidationError { offenders, invalid }.into());
+        }
+
+        Self::serialize_many_with_options(logs, options)
+    }
+
+    /// Same as [`PlayerLogSerializer::serialize_many_with_options`], calling `progress` with the
+    /// number of records serialized every time a chunk finishes.
+    pub fn serialize_many_with_progress(
+        logs: &[PlayerLog],
+        options: &SerializerOptions,
+        progress: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<u8>> {
+        let payload = Self::serialize_payload(logs, options.endianness, progress)?;
+        Self::write_container(&payload, logs.len() as u64, options, None)
+    }
+
+    /// Packs `logs` greedily into as few batches as possible, each at most `max_bytes` long and
+    /// readable on its own. No logs make no batches.
+    pub fn serialize_bounded(
+        logs: &[PlayerLog],
+        max_bytes: usize,
+        codec: Codec,
+    ) -> Result<Vec<Vec<u8>>> {
+        let options = SerializerOptions {
+            codec,
+            ..SerializerOptions::default()
+        };
+
+        let mut batches = Vec::new();
+        let mut start = 0;
+        while start < logs.len() {
+            // the uncompressed size is exact without a codec and a conservative estimate with one
+            let mut size = PlayerLog::HEADER_SIZE;
+            let mut end = start;
+            while end < logs.len() && size + logs[end].serialized_size() <= max_bytes {
+                size += logs[end].serialized_size();
+                end += 1;
+            }
+
+            if end == start {
+                bail!("record {start} does not fit in {max_bytes} bytes by itself");
+            }
+
+            // incompressible payloads can still grow, so shrink the batch by how much it overshot
+            // until it fits
+            loop {
+                let batch = Self::serialize_many_with_options(&logs[start..end], &options)?;
+                if batch.len() <= max_bytes {
+                    batches.push(batch);
+                    break;
+                }
+
+                if end - start == 1 {
+                    bail!(
+                        "record {start} does not fit in {max_bytes} bytes once compressed with {}",
+                        codec.name()
+                    );
+                }
+                let count = end - start;
+                end = start + (count * max_bytes / batch.len()).clamp(1, count - 1);
+            }
+
+            start = end;
+        }
+
+        Ok(batches)
+    }
+
+    // wraps already serialized records, the payload bytes are written as is
+    fn write_container(
+        payload: &[u8],
+        record_count: u64,
+        options: &SerializerOptions,
+        dictionary: Option<DictionaryId>,
+    ) -> Result<Vec<u8>> {
+        let header = BatchHeader {
+            codec: options.codec,
+            batch_id: options
+                .batch_id
+                .unwrap_or_else(|| default_batch_id(record_count, payload)),
+            record_count,
+            producer: options.producer.as_deref(),
+            dictionary,
+            endianness: options.endianness,
+        };
+
+        let mut writer = Vec::with_capacity(header.encoded_len() + payload.len());
+        header.write(&mut writer)?;
+        options.codec.encode_into(payload, &mut writer)?;
+
+        Ok(writer)
+    }
+
+    /// Rewrites `data` with its payload compressed as `to`, without decoding a single record. The
+    /// batch id, producer, dictionary and endianness are kept, and so is the payload byte for byte.
+    /// Header bytes this build doesn't know about are dropped.
+    ///
+    /// `from_hint` is the codec the caller expects the batch to have, a batch written with another
+    /// one is an error instead of being recompressed.
+    pub fn recompress(data: &[u8], from_hint: Option<Codec>, to: Codec) -> Result<Vec<u8>> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        if let Some(hint) = from_hint.filter(|hint| hint.id() != header.codec.id()) {
+            bail!(
+                "batch is compressed with {}, not {}",
+                header.codec.name(),
+                hint.name()
+            );
+        }
+
+        let mut payload = Vec::new();
+        header
+            .codec
+            .decoder(&data[header_len..])
+            .read_to_end(&mut payload)
+            .context("corrupt payload")?;
+
+        let options = SerializerOptions {
+            codec: to,
+            batch_id: Some(header.batch_id),
+            producer: header.producer.map(str::to_owned),
+            endianness: header.endianness,
+        };
+
+        Self::write_container(&payload, header.record_count, &options, header.dictionary)
+    }
+
+    /// Picks whichever of identity, lz4 and zlib-5 compresses a sample of the batch best. The choice
+    /// is recorded in the header, so any of the deserialize functions can read it back.
+    pub fn serialize_many_auto_compress(logs: &[PlayerLog]) -> Result<Vec<u8>> {
+        let options = SerializerOptions {
+            codec: Self::select_codec(logs)?,
+            ..SerializerOptions::default()
+        };
+
+        Self::serialize_many_with_options(logs, &options)
+    }
+
+    pub fn select_codec(logs: &[PlayerLog]) -> Result<Codec> {
+        let sample = Self::serialize_payload(
+            &logs[..logs.len().min(AUTO_COMPRESS_SAMPLE)],
+            Endianness::Big,
+            &|_| {},
+        )?;
+
+        let mut best = (Codec::None, sample.len());
+        let mut buf = Vec::with_capacity(sample.len());
+        for codec in [Codec::Lz4, Codec::Zlib(Compression::new(5))] {
+            buf.clear();
+            codec.encode_into(&sample, &mut buf)?;
+
+            if buf.len() < best.1 {
+                best = (codec, buf.len());
+            }
+        }
+
+        Ok(best.0)
+    }
+
+    fn serialize_payload(
+        logs: &[PlayerLog],
+        endianness: Endianness,
+        progress: &(dyn Fn(usize) + Sync),
+    ) -> Result<Vec<u8>> {
+        // an empty batch is just its header, the codec still frames the empty payload
+        if logs.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        // chunks come back in order, so the output (and the default batch id) is stable
+        let log_buffers = parallel::for_each_chunk(logs, (logs.len() / 10).max(1), |c| {
+            let mut buf = Vec::with_capacity(c.iter().map(PlayerLog::serialized_size).sum());
+
+            c.iter()
+                .try_for_each(|log| log.serialize_with_endianness(&mut buf, endianness))?;
+            progress(c.len());
+            Ok(buf)
+        })
+        .into_iter()
+        .collect::<Result<Vec<_>>>()?;
+
+        Ok(log_buffers.concat())
+    }
+
+    /// Splits logs into `(clean, suspicious)` using [`PlayerLog::is_suspicious`].
+    pub fn filter_suspicious(logs: &[PlayerLog]) -> (Vec<&PlayerLog>, Vec<&PlayerLog>) {
+        let (suspicious, clean) = logs.iter().partition(|log| log.is_suspicious());
+        (clean, suspicious)
+    }
+
+    pub fn probe(data: &[u8]) -> Result<BatchHeader<'_>> {
+        Self::probe_with_len(data).map(|(header, _)| header)
+    }
+
+    /// Parses as much of the header as `prefix` holds without touching the payload, so a router
+    /// can peek at the first few bytes of an upload.
+    pub fn probe_prefix(prefix: &[u8]) -> Result<ProbeResult<'_>> {
+        BatchHeader::parse(prefix)
+    }
+
+    fn probe_with_len(data: &[u8]) -> Result<(BatchHeader<'_>, usize)> {
+        let parsed = BatchHeader::parse(data).map_err(|e| {
+            // only worth a look once the magic is wrong, and then it can take its time
+            if data.starts_with(&BATCH_MAGIC) {
+                return e;
+            }
+            sniff::WrongFormat::from_detected(sniff::sniff(data)).map_or(e, Into::into)
+        })?;
+
+        match parsed {
+            ProbeResult::Complete { header, header_len } => Ok((header, header_len)),
+            ProbeResult::NeedMoreData(_) => bail!("truncated batch header"),
+        }
+    }
+
+    pub fn deserialize_many(data: &[u8]) -> Result<Vec<PlayerLog>> {
+        Self::deserialize_many_with_options(data, &DeserializerOptions::default())
+    }
+
+    pub fn deserialize_many_with_options(
+        data: &[u8],
+        options: &DeserializerOptions,
+    ) -> Result<Vec<PlayerLog>> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        let mut reader = header.codec.decoder(&data[header_len..]);
+        let logs = Self::deserialize_helper(
+            &mut reader,
+            header.record_count,
+            header.endianness,
+            options,
+            false,
+        )?;
+
+        check_payload_end(&mut reader)?;
+
+        Ok(logs)
+    }
+
+    /// [`PlayerLogSerializer::deserialize_many`] converting every record to a builder as it's
+    /// read, the error names the first record that isn't valid utf8.
+    pub fn deserialize_many_builders(data: &[u8]) -> Result<Vec<PlayerLogBuilder>> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        let mut reader = header.codec.decoder(&data[header_len..]);
+        let mut builders = Vec::new();
+        for i in 0..header.record_count {
+            let log = PlayerLog::deserialize_with_endianness(&mut reader, header.endianness)
+                .with_context(|| format!("record {i}"))?;
+            if !log.is_marker() {
+                builders
+                    .push(PlayerLogBuilder::try_from(log).with_context(|| format!("record {i}"))?);
+            }
+        }
+
+        check_payload_end(&mut reader)?;
+
+        Ok(builders)
+    }
+
+    // the codec is read from the header, this is kept so call sites can stay symmetric
+    pub fn deserialize_many_compressed(data: &[u8]) -> Result<Vec<PlayerLog>> {
+        Self::deserialize_many(data)
+    }
+
+    // the codec id in the header already drives decoding
+    pub fn deserialize_auto_compress(data: &[u8]) -> Result<Vec<PlayerLog>> {
+        Self::deserialize_many(data)
+    }
+
+    pub fn file_stats(path: &Path) -> Result<FileStats> {
+        let data = fs::read(path)?;
+        let header = Self::probe(&data)?;
+
+        let mut stats = visit::StatsVisitor::default();
+        Self::visit(&data, &mut stats)?;
+
+        Ok(FileStats {
+            file_size: data.len() as u64,
+            record_count: header.record_count,
+            matched_count: header.record_count,
+            codec: header.codec,
+            container_version: CONTAINER_VERSION,
+            producer: header.producer.map(str::to_string),
+            binary_versions: stats.binary_versions,
+            present_fields: stats.present_fields,
+            outcomes: stats.outcomes,
+            server_versions: stats.server_versions,
+            unknown_versions: stats.unknown_versions,
+            countries: stats.countries,
+            proxies: stats.proxies,
+        })
+    }
+
+    /// [`PlayerLogSerializer::file_stats`] of only the records `filter` keeps.
+    pub fn file_stats_filtered(path: &Path, filter: &filter::PlayerLogFilter) -> Result<FileStats> {
+        let data = fs::read(path)?;
+        let header = Self::probe(&data)?;
+
+        let mut stats = visit::StatsVisitor::default();
+        let mut matched_count = 0;
+        for log in Self::deserialize_many(&data)? {
+            if filter.matches(&log) {
+                matched_count += 1;
+                log.visit(&mut stats);
+            }
+        }
+
+        Ok(FileStats {
+            file_size: data.len() as u64,
+            record_count: header.record_count,
+            matched_count,
+            codec: header.codec,
+            container_version: CONTAINER_VERSION,
+            producer: header.producer.map(str::to_string),
+            binary_versions: stats.binary_versions,
+            present_fields: stats.present_fields,
+            outcomes: stats.outcomes,
+            server_versions: stats.server_versions,
+            unknown_versions: stats.unknown_versions,
+            countries: stats.countries,
+            proxies: stats.proxies,
+        })
+    }
+
+    // tombstones and gaps are only kept, and never validated, with `keep_markers`
+    fn deserialize_helper<R: Read>(
+        reader: &mut R,
+        len: u64,
+        endianness: Endianness,
+        options: &DeserializerOptions,
+        keep_markers: bool,
+    ) -> Result<Vec<PlayerLog>> {
+        let mut logs = Vec::new();
+        for i in 0..len {
+            let log = PlayerLog::deserialize_with_endianness(reader, endianness)?;
+            if log.is_marker() {
+                if keep_markers {
+                    logs.push(log);
+                }
+                continue;
+            }
+
+            if options.validate_utf8 {
+                check_text("player name", &log.player_name, options.reject_nul)
+                    .and_then(|()| {
+                        check_text("server domain", &log.server_domain, options.reject_nul)
+                    })
+                    .with_context(|| format!("record {i}"))?;
+            }
+            if options.validate {
+                if let Err(errors) = log.validate_consistency() {
+                    let errors = errors.iter().map(ToString::to_string).collect::<Vec<_>>();
+                    bail!("record {i} is inconsistent: {}", errors.join(", "));
+                }
+            }
+
+            logs.push(log);
+        }
+
+        Ok(logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::slice;
+
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::storage_impl::ByteStorage;
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn batch(logs: &[PlayerLog], codec: Codec) -> Vec<u8> {
+        let options = SerializerOptions {
+            codec,
+            ..SerializerOptions::default()
+        };
+        PlayerLogSerializer::serialize_many_with_options(logs, &options).unwrap()
+    }
+
+    fn codecs() -> [Codec; 3] {
+        [Codec::None, Codec::Zlib(Compression::default()), Codec::Lz4]
+    }
+
+    // a v1 record without a uuid, so the name length is the third byte
+    fn offline_log(name: &[u8]) -> PlayerLog {
+        PlayerLog {
+            binary_version: 1,
+            player_name: NameBytes::from_slice(name),
+            player_ip: [10, 0, 0, 1],
+            server_ip: [192, 168, 1, 254],
+            server_port: PlayerLog::DEFAULT_PORT,
+            server_domain: DomainBytes::from_slice(b"mc.example.com"),
+            server_version: ServerVersion::Known(1),
+            ..PlayerLog::default()
+        }
+    }
+
+    fn deserialize(data: &[u8]) -> Result<Vec<PlayerLog>> {
+        PlayerLogSerializer::deserialize_many_with_options(data, &DeserializerOptions::default())
+    }
+
+    #[test]
+    fn round_trips_with_every_option() {
+        let logs = logs(200);
+        let options = DeserializerOptions {
+            validate: true,
+            validate_utf8: true,
+            reject_nul: true,
+        };
+
+        for codec in codecs() {
+            let data = batch(&logs, codec);
+            assert_eq!(
+                PlayerLogSerializer::deserialize_many_with_options(&data, &options).unwrap(),
+                logs,
+                "{}",
+                codec.name()
+            );
+        }
+    }
+
+    #[test]
+    fn little_endian_batches_read_everywhere() {
+        use super::ordinal::OffsetIndex;
+        use super::patch::RecordPatch;
+        use super::projection::Projection;
+
+        let logs = logs(50);
+        let written = |endianness| {
+            let options = SerializerOptions {
+                codec: Codec::Lz4,
+                endianness,
+                ..SerializerOptions::default()
+            };
+            PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap()
+        };
+        let big = written(Endianness::Big);
+        let data = written(Endianness::Little);
+        let indexed: Vec<_> = (0..).zip(logs.iter().cloned()).collect();
+
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_indexed(&data).unwrap(),
+            indexed
+        );
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_filtered(&data, &Default::default()).unwrap(),
+            indexed
+        );
+        assert_eq!(PlayerLogSerializer::fetch(&data, 49).unwrap(), logs[49]);
+        assert_eq!(
+            OffsetIndex::build(&data).unwrap().fetch(7).unwrap(),
+            logs[7]
+        );
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_owned(data.clone())
+                .unwrap()
+                .into_logs(),
+            logs
+        );
+
+        let projected =
+            PlayerLogSerializer::deserialize_many_projected(&data, Projection::all()).unwrap();
+        assert!(projected.iter().zip(&logs).all(|(partial, log)| {
+            partial.timestamp == Some(log.timestamp)
+                && partial.session_id == Some(log.session_id)
+                && partial.geo == log.geo
+        }));
+
+        let patch = RecordPatch {
+            player_name: Some(b"patched".to_vec()),
+            ..RecordPatch::default()
+        };
+        let patched = PlayerLogSerializer::patch(&data, 3, patch).unwrap();
+        assert_eq!(
+            PlayerLogSerializer::probe(&patched).unwrap().endianness,
+            Endianness::Little
+        );
+        assert_eq!(
+            deserialize(&patched).unwrap()[3].player_name,
+            NameBytes::from_slice(b"patched")
+        );
+
+        // the same fields come out, only the hex differs
+        let descriptions = |data| {
+            PlayerLog::explain_records(data)
+                .unwrap()
+                .iter()
+                .flat_map(|dump| dump.lines().map(|line| line.get(55..).map(str::to_owned)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(descriptions(&data), descriptions(&big));
+    }
+
+    #[test]
+    fn filtered_stats_only_count_the_matching_records() {
+        let logs = logs(300);
+        let filter = filter::PlayerLogFilter {
+            expr: Some(query::FilterExpr::parse("online and not refused").unwrap()),
+            ..filter::PlayerLogFilter::default()
+        };
+        let matching: Vec<_> = logs
+            .iter()
+            .filter(|log| filter.matches(log))
+            .cloned()
+            .collect();
+        assert!(!matching.is_empty() && matching.len() < logs.len());
+
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, logs: &[PlayerLog]| {
+            let path = dir.path().join(name);
+            fs::write(&path, PlayerLogSerializer::serialize_many(logs).unwrap()).unwrap();
+            path
+        };
+        let all = write("all.plgb", &logs);
+        let only = write("matching.plgb", &matching);
+
+        let filtered = PlayerLogSerializer::file_stats_filtered(&all, &filter).unwrap();
+        assert_eq!(filtered.record_count, 300);
+        assert_eq!(filtered.matched_count, matching.len() as u64);
+        let expected = PlayerLogSerializer::file_stats(&only).unwrap();
+        assert_eq!(
+            FileStats {
+                file_size: expected.file_size,
+                record_count: expected.record_count,
+                ..filtered
+            },
+            expected
+        );
+
+        // decoded logs visit the same as the records they came from
+        assert_eq!(
+            PlayerLogSerializer::file_stats_filtered(&all, &filter::PlayerLogFilter::default())
+                .unwrap(),
+            PlayerLogSerializer::file_stats(&all).unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_inputs() {
+        assert!(deserialize(&[]).is_err());
+        for codec in codecs() {
+            assert_eq!(deserialize(&batch(&[], codec)).unwrap(), []);
+        }
+    }
+
+    #[test]
+    fn every_truncation_fails() {
+        let logs = logs(20);
+        for codec in codecs() {
+            let data = batch(&logs, codec);
+            // lz4_flex reads a frame missing just its end mark as complete, every record is there
+            let end_mark = match codec {
+                Codec::Lz4 => data.len() - 4,
+                _ => data.len(),
+            };
+            assert_eq!(deserialize(&data[..end_mark]).unwrap(), logs);
+
+            for len in 0..end_mark {
+                assert!(
+                    deserialize(&data[..len]).is_err(),
+                    "{} cut to {len} of {} bytes",
+                    codec.name(),
+                    data.len()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn bytes_after_the_last_record_fail() {
+        let logs = logs(3);
+        let mut payload = Vec::new();
+        for log in &logs {
+            log.serialize(&mut payload).unwrap();
+        }
+        payload.extend_from_slice(&[0; 3]);
+
+        for codec in codecs() {
+            let options = SerializerOptions {
+                codec,
+                ..SerializerOptions::default()
+            };
+            let data =
+                PlayerLogSerializer::write_container(&payload, logs.len() as u64, &options, None)
+                    .unwrap();
+            let e = deserialize(&data).unwrap_err();
+            assert_eq!(e.to_string(), "3 bytes after the last record");
+        }
+    }
+
+    #[test]
+    fn garbage_fails_without_panicking() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let valid = batch(&logs(5), Codec::None);
+        let (_, header_len) = PlayerLogSerializer::probe_with_len(&valid).unwrap();
+
+        for len in [0, 1, 7, 64, 1024] {
+            let mut garbage = vec![0; len];
+            rng.fill(garbage.as_mut_slice());
+            assert!(deserialize(&garbage).is_err());
+
+            // a valid header in front only gets the garbage as far as the records
+            let mut data = valid[..header_len].to_vec();
+            data.extend_from_slice(&garbage);
+            let _ = deserialize(&data);
+        }
+
+        let mut bad_magic = valid;
+        bad_magic[0] ^= 0xFF;
+        assert!(deserialize(&bad_magic).is_err());
+    }
+
+    #[test]
+    fn oversized_record_count_fails_without_allocating() {
+        let mut payload = Vec::new();
+        offline_log(b"Notch").serialize(&mut payload).unwrap();
+
+        for codec in codecs() {
+            let options = SerializerOptions {
+                codec,
+                ..SerializerOptions::default()
+            };
+            let data =
+                PlayerLogSerializer::write_container(&payload, u64::MAX, &options, None).unwrap();
+            assert!(deserialize(&data).is_err());
+        }
+    }
+
+    #[test]
+    fn oversized_fields_fail() {
+        let mut record = Vec::new();
+        offline_log(b"Notch").serialize(&mut record).unwrap();
+        assert_eq!(record[2], 5);
+
+        // the length claims more than a name can hold, with enough bytes after it to read
+        let mut long_name = record.clone();
+        long_name[2] = PlayerLog::MAX_LONG_NAME_LEN as u8 + 1;
+        long_name.splice(3..3, [b'x'; PlayerLog::MAX_LONG_NAME_LEN + 1 - 5]);
+
+        let data = PlayerLogSerializer::write_container(
+            &long_name,
+            1,
+            &SerializerOptions::default(),
+            None,
+        )
+        .unwrap();
+        assert!(deserialize(&data).is_err());
+    }
+
+    #[test]
+    fn max_batch_size_covers_a_dictionary_batch() {
+        let logs = logs(10);
+        let dict = dictionary::DomainDictionary::build([b"unused.example.com"]).unwrap();
+        let data = PlayerLogSerializer::serialize_many_with_dict(&logs, &dict).unwrap();
+        let header = PlayerLogSerializer::probe(&data).unwrap();
+        assert!(header.dictionary.is_some());
+
+        assert!(header.encoded_len() <= BatchHeader::MAX_SIZE);
+        assert!(data.len() <= PlayerLog::max_batch_size(logs.len()));
+    }
+
+    #[test]
+    fn text_options_reject_what_they_say() {
+        let utf8 = DeserializerOptions {
+            validate_utf8: true,
+            ..DeserializerOptions::default()
+        };
+        let nul = DeserializerOptions {
+            reject_nul: true,
+            ..utf8
+        };
+
+        let invalid = batch(&[offline_log(b"No\xFFch")], Codec::None);
+        assert!(deserialize(&invalid).is_ok());
+        assert!(PlayerLogSerializer::deserialize_many_with_options(&invalid, &utf8).is_err());
+
+        let with_nul = batch(&[offline_log(b"No\0ch")], Codec::None);
+        assert!(PlayerLogSerializer::deserialize_many_with_options(&with_nul, &utf8).is_ok());
+        assert!(PlayerLogSerializer::deserialize_many_with_options(&with_nul, &nul).is_err());
+    }
+
+    #[test]
+    fn bounded_batches_split_and_read_alone() {
+        let logs = logs(300);
+        let whole = batch(&logs, Codec::None).len();
+
+        for codec in codecs() {
+            let max_bytes = whole / 5;
+            let batches = PlayerLogSerializer::serialize_bounded(&logs, max_bytes, codec).unwrap();
+            assert!(batches.len() > 1, "{}", codec.name());
+
+            let mut read = Vec::new();
+            for batch in &batches {
+                assert!(batch.len() <= max_bytes, "{}", codec.name());
+                let chunk = PlayerLogSerializer::deserialize_many(batch).unwrap();
+                assert!(!chunk.is_empty());
+                read.extend(chunk);
+            }
+            assert_eq!(read, logs, "{}", codec.name());
+        }
+
+        let batches = PlayerLogSerializer::serialize_bounded(&logs, whole, Codec::None).unwrap();
+        assert_eq!(batches, [batch(&logs, Codec::None)]);
+        assert!(PlayerLogSerializer::serialize_bounded(&[], 0, Codec::None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn a_record_bigger_than_the_bound_fails() {
+        let logs = logs(20);
+        // every record fits in a batch of its own until the first one bigger than the first
+        let sizes = logs
+            .iter()
+            .map(PlayerLog::serialized_size)
+            .collect::<Vec<_>>();
+        let max_bytes = PlayerLog::HEADER_SIZE + sizes[0];
+        let too_big = sizes.iter().position(|size| *size > sizes[0]).unwrap();
+
+        let e = PlayerLogSerializer::serialize_bounded(&logs, max_bytes, Codec::None).unwrap_err();
+        assert!(
+            e.to_string()
+                .contains(&format!("record {too_big} does not fit")),
+            "{e}"
+        );
+        assert!(
+            PlayerLogSerializer::serialize_bounded(&logs, PlayerLog::HEADER_SIZE, Codec::Lz4)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn the_smallest_record_is_min_encoded_size() {
+        let log = PlayerLog {
+            binary_version: 1,
+            ..PlayerLog::default()
+        };
+        let mut data = Vec::new();
+        log.serialize(&mut data).unwrap();
+
+        assert_eq!(data.len(), PlayerLog::MIN_ENCODED_SIZE);
+        assert_eq!(log.serialized_size(), PlayerLog::MIN_ENCODED_SIZE);
+        assert_eq!(
+            deserialize(&batch(slice::from_ref(&log), Codec::None)).unwrap(),
+            [log]
+        );
+    }
+
+    // inline names only have room for MAX_NAME_LEN bytes
+    #[cfg(not(feature = "inline_storage"))]
+    #[test]
+    fn the_largest_record_is_max_encoded_size() {
+        let log = PlayerLog {
+            binary_version: 2,
+            flags: (LogFlags::IS_ONLINE
+                | LogFlags::PLAYER_AUTH
+                | LogFlags::NONDEFAULT_PORT
+                | LogFlags::HAS_CLIENT_INFO
+                | LogFlags::LOGIN_REFUSED
+                | LogFlags::HAS_GEO
+                | LogFlags::VIA_PROXY)
+                .bits(),
+            player_uuid: Some([0xAB; 16]),
+            player_name: "é".repeat(PlayerLog::MAX_NAME_CHARS).into_bytes(),
+            player_ip: [10, 0, 0, 1],
+            server_ip: [192, 168, 1, 254],
+            server_port: 8080,
+            server_domain: vec![b'd'; PlayerLog::MAX_DOMAIN_LEN],
+            server_version: ServerVersion::Unknown(
+                "v".repeat(ServerVersion::MAX_UNKNOWN_LEN).into(),
+            ),
+            timestamp: 1_700_000_000_000,
+            session_id: 0xDEAD_BEEF,
+            outcome: LoginOutcome::Banned,
+            geo: Some(GeoInfo {
+                country: *b"NL",
+                asn: 1136,
+            }),
+            proxy_ip: Some([172, 16, 0, 1]),
+            extensions: (0..PlayerLog::MAX_EXTENSIONS as u8)
+                .map(|kind| Extension {
+                    kind,
+                    data: vec![kind; PlayerLog::MAX_EXTENSION_LEN],
+                })
+                .collect(),
+            client_brand: vec![b'b'; PlayerLog::MAX_CLIENT_BRAND_LEN],
+            client_locale: vec![b'l'; PlayerLog::MAX_CLIENT_LOCALE_LEN],
+        };
+        assert_eq!(log.player_name.len(), PlayerLog::MAX_LONG_NAME_LEN);
+
+        let mut data = Vec::new();
+        log.serialize(&mut data).unwrap();
+
+        assert_eq!(data.len(), PlayerLog::MAX_ENCODED_SIZE);
+        assert_eq!(log.serialized_size(), PlayerLog::MAX_ENCODED_SIZE);
+        assert_eq!(
+            deserialize(&batch(slice::from_ref(&log), Codec::None)).unwrap(),
+            [log]
+        );
+    }
+
+    #[test]
+    fn build_into_forgets_the_previous_shape() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut rich = log_generator_seeded(&mut rng);
+        rich.flags |= LogFlags::IS_ONLINE;
+        rich.player_uuid = Some(Uuid::from_bytes([0xAB; 16]));
+        rich.server_domain = "d".repeat(PlayerLog::MAX_DOMAIN_LEN);
+        rich.timestamp = Some(1_700_000_000_000);
+        rich.extensions = vec![Extension {
+            kind: 1,
+            data: vec![0xE1; PlayerLog::MAX_EXTENSION_LEN],
+        }];
+        rich.client_brand = Some("fabric".to_string());
+        rich.geo = Some(GeoInfo {
+            country: *b"NL",
+            asn: 1136,
+        });
+        rich.proxy_ip = Some([172, 16, 0, 1].into());
+
+        let mut bare = rich.clone();
+        bare.flags.remove(LogFlags::IS_ONLINE);
+        bare.player_uuid = None;
+        bare.server_domain = "mc.example.com".to_string();
+        bare.timestamp = None;
+        bare.session_id = 0;
+        bare.extensions.clear();
+        bare.client_brand = None;
+        bare.client_locale = None;
+        bare.outcome = LoginOutcome::Success;
+        bare.geo = None;
+        bare.proxy_ip = None;
+
+        let mut out = PlayerLog::default();
+        for builder in [&rich, &bare, &rich, &bare] {
+            builder.build_into(&mut out).unwrap();
+            assert_eq!(out, builder.build().unwrap());
+        }
+        assert_eq!(out.binary_version, 1);
+
+        // and across whatever the generator comes up with
+        for _ in 0..200 {
+            let builder = log_generator_seeded(&mut rng);
+            builder.build_into(&mut out).unwrap();
+            assert_eq!(out, builder.build().unwrap());
+        }
+    }
+
+    #[test]
+    fn an_unknown_timestamp_stays_unknown_through_a_builder() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.timestamp = None;
+        builder.session_id = 7;
+        let log = builder.build().unwrap();
+        assert_eq!((log.binary_version, log.timestamp), (2, 0));
+
+        let rebuilt = PlayerLogBuilder::from_log(&log).unwrap();
+        assert_eq!(rebuilt.timestamp, None);
+        assert_eq!(rebuilt, builder);
+        assert_eq!(rebuilt.build().unwrap(), log);
+
+        builder.timestamp = Some(1_700_000_000_000);
+        let log = builder.build().unwrap();
+        assert_eq!(PlayerLogBuilder::from_log(&log).unwrap(), builder);
+    }
+
+    #[test]
+    fn cracked_clients_are_never_authenticated() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.flags = LogFlags::IS_ONLINE | LogFlags::PLAYER_AUTH | LogFlags::SERVER_WHITELIST;
+        builder.player_uuid = Some(Uuid::from_bytes([0xAB; 16]));
+        let online = builder.build().unwrap();
+        assert!(online.is_authenticated_session());
+
+        builder.flags |= LogFlags::CRACKED_CLIENT;
+        let e = builder.build().unwrap_err();
+        assert!(e.to_string().contains("Cracked clients"), "{e}");
+
+        builder.flags = LogFlags::CRACKED_CLIENT | LogFlags::PLAYER_AUTH;
+        builder.player_uuid = None;
+        let cracked = builder.build().unwrap();
+        assert!(!cracked.is_authenticated_session());
+
+        let logs = [online, cracked];
+        let read = deserialize(&batch(&logs, Codec::None)).unwrap();
+        assert_eq!(read, logs);
+        assert!(LogFlags::from_bits_retain(read[0].flags).contains(LogFlags::SERVER_WHITELIST));
+        assert!(LogFlags::from_bits_retain(read[1].flags).contains(LogFlags::CRACKED_CLIENT));
+    }
+
+    #[test]
+    fn the_default_port_is_only_written_under_v1() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.timestamp = Some(1_700_000_000_000);
+        builder.server_port = PlayerLog::DEFAULT_PORT;
+        let default = builder.build().unwrap();
+        builder.server_port = 8080;
+        let other = builder.build().unwrap();
+
+        let flags = |log: &PlayerLog| LogFlags::from_bits_retain(log.flags);
+        assert!(!flags(&default).contains(LogFlags::NONDEFAULT_PORT));
+        assert!(flags(&other).contains(LogFlags::NONDEFAULT_PORT));
+        assert_eq!(default.serialized_size() + 2, other.serialized_size());
+        let mut data = Vec::new();
+        default.serialize(&mut data).unwrap();
+        assert!(PlayerLog::explain(&data).contains("server_port: 25565 (default)"));
+
+        let logs = [default.clone(), other];
+        assert_eq!(deserialize(&batch(&logs, Codec::Lz4)).unwrap(), logs);
+
+        // a v1 record always writes it, and upgrading works out the flag
+        let mut v1 = offline_log(b"Notch");
+        v1.server_port = 8080;
+        let v1_size = v1.serialized_size();
+        assert!(v1.upgrade());
+        assert!(flags(&v1).contains(LogFlags::NONDEFAULT_PORT));
+        assert_eq!(v1.serialized_size(), v1_size + PlayerLog::V2_FIELDS_SIZE);
+
+        let mut wrong = default;
+        wrong.server_port = 8080;
+        let e = wrong.serialize(&mut Vec::new()).unwrap_err();
+        assert!(e.to_string().contains("NONDEFAULT_PORT"), "{e}");
+    }
+
+    #[test]
+    fn domains_that_are_the_server_ip_arent_written() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.timestamp = Some(1_700_000_000_000);
+        builder.server_ip = [203, 0, 113, 7].into();
+
+        let flags = |log: &PlayerLog| LogFlags::from_bits_retain(log.flags);
+        builder.server_domain = "203.0.113.7".to_string();
+        let ip = builder.build().unwrap();
+        assert!(flags(&ip).contains(LogFlags::DOMAIN_IS_IP));
+
+        // only the canonical form of the same ip counts
+        for domain in [
+            "203.0.113.8",
+            "203.0.113.07",
+            "203.0.113.7.",
+            "mc.example.com",
+        ] {
+            builder.server_domain = domain.to_string();
+            let log = builder.build().unwrap();
+            assert!(!flags(&log).contains(LogFlags::DOMAIN_IS_IP), "{domain}");
+            assert_eq!(
+                log.serialized_size(),
+                ip.serialized_size() + 1 + domain.len(),
+                "{domain}"
+            );
+        }
+
+        let read = deserialize(&batch(slice::from_ref(&ip), Codec::None)).unwrap();
+        assert_eq!(read, slice::from_ref(&ip));
+        assert_eq!(&*read[0].server_domain, b"203.0.113.7");
+
+        let mut v1 = offline_log(b"Notch");
+        v1.server_domain = DomainBytes::from_slice(b"192.168.1.254");
+        assert!(v1.upgrade());
+        assert!(flags(&v1).contains(LogFlags::DOMAIN_IS_IP));
+
+        let mut wrong = ip;
+        wrong.server_domain = DomainBytes::from_slice(b"mc.example.com");
+        let e = wrong.serialize(&mut Vec::new()).unwrap_err();
+        assert!(e.to_string().contains("DOMAIN_IS_IP"), "{e}");
+    }
+
+    #[test]
+    fn client_info_round_trips_and_is_bounded() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.client_brand = Some("fabric".to_string());
+        builder.client_locale = Some("en_US".to_string());
+        let both = builder.build().unwrap();
+        builder.client_locale = None;
+        let brand_only = builder.build().unwrap();
+
+        for log in [&both, &brand_only] {
+            assert_eq!(log.binary_version, 2);
+            assert!(LogFlags::from_bits_retain(log.flags).contains(LogFlags::HAS_CLIENT_INFO));
+        }
+        assert_eq!(both.serialized_size(), brand_only.serialized_size() + 5);
+
+        let logs = [both, brand_only];
+        let read = deserialize(&batch(&logs, Codec::None)).unwrap();
+        assert_eq!(read, logs);
+        assert_eq!(read[1].client_locale, b"");
+        let rebuilt = PlayerLogBuilder::from_log(&read[1]).unwrap();
+        assert_eq!(rebuilt.client_brand.as_deref(), Some("fabric"));
+        assert_eq!(rebuilt.client_locale, None);
+
+        builder.client_brand = Some(String::new());
+        assert!(builder.build().is_err());
+        builder.client_brand = Some("b".repeat(PlayerLog::MAX_CLIENT_BRAND_LEN + 1));
+        assert!(builder.build().is_err());
+        builder.client_brand = None;
+        builder.client_locale = Some("l".repeat(PlayerLog::MAX_CLIENT_LOCALE_LEN + 1));
+        assert!(builder.build().is_err());
+
+        builder.client_locale = Some("en-us".to_string());
+        assert!(builder.build().is_ok());
+        assert!(builder.validate_strict().is_err());
+    }
+
+    #[test]
+    fn refused_logins_keep_their_outcome() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.timestamp = Some(1_700_000_000_000);
+        let success = builder.build().unwrap();
+        assert!(!LogFlags::from_bits_retain(success.flags).contains(LogFlags::LOGIN_REFUSED));
+
+        let outcomes = [
+            LoginOutcome::Banned,
+            LoginOutcome::NotWhitelisted,
+            LoginOutcome::Throttled,
+            LoginOutcome::InvalidSession,
+            LoginOutcome::Other(200),
+        ];
+        let mut logs = vec![success.clone()];
+        for outcome in outcomes {
+            assert_eq!(LoginOutcome::from_byte(outcome.to_byte()), outcome);
+            builder.outcome = outcome;
+            let refused = builder.build().unwrap();
+            assert!(LogFlags::from_bits_retain(refused.flags).contains(LogFlags::LOGIN_REFUSED));
+            assert_eq!(refused.serialized_size(), success.serialized_size() + 1);
+            logs.push(refused);
+        }
+        assert_eq!(deserialize(&batch(&logs, Codec::Lz4)).unwrap(), logs);
+
+        // Other(1) would come back as Banned
+        builder.outcome = LoginOutcome::Other(1);
+        let e = builder.build().unwrap_err();
+        assert!(e.to_string().contains("known outcome"), "{e}");
+
+        let mut unflagged = logs.pop().unwrap();
+        unflagged.flags &= !LogFlags::LOGIN_REFUSED.bits();
+        assert!(unflagged.serialize(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn event_types_live_beside_the_flags() {
+        let event_types = [
+            LogEventType::Login,
+            LogEventType::Disconnect,
+            LogEventType::Ping,
+            LogEventType::ServerListPing,
+        ];
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut logs = Vec::new();
+        for event_type in event_types {
+            let builder = log_generator_seeded(&mut rng).with_event_type(event_type);
+            let log = builder.build().unwrap();
+            assert_eq!(log.event_type(), event_type);
+            // switching it leaves the real flags alone
+            let other = builder.with_event_type(LogEventType::Ping).build().unwrap();
+            assert_eq!(
+                log.flags & !LogFlags::EVENT_TYPE_BITS,
+                other.flags & !LogFlags::EVENT_TYPE_BITS
+            );
+            logs.push(log);
+        }
+
+        // v1 records have room for it in their single flags byte
+        let mut v1 = offline_log(b"Notch");
+        v1.flags = LogEventType::ServerListPing.bits();
+        logs.push(v1);
+
+        let read = deserialize(&batch(&logs, Codec::None)).unwrap();
+        assert_eq!(read, logs);
+        assert_eq!(read[4].event_type(), LogEventType::ServerListPing);
+
+        for bits in [0b11 << 6, LogFlags::all().bits() | 0b11 << 6] {
+            assert_eq!(validate_flags(bits).unwrap().bits(), bits);
+        }
+        assert!(validate_flags(1 << 2).is_err());
+        assert!(validate_flags(1 << 15).is_err());
+    }
+
+    #[test]
+    fn proxied_logs_keep_the_proxy_ip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        let direct = builder.build().unwrap();
+        builder.proxy_ip = Some([172, 16, 0, 1].into());
+        let proxied = builder.build().unwrap();
+
+        assert!(!LogFlags::from_bits_retain(direct.flags).contains(LogFlags::VIA_PROXY));
+        assert!(LogFlags::from_bits_retain(proxied.flags).contains(LogFlags::VIA_PROXY));
+        assert_eq!(proxied.binary_version, 2);
+
+        let logs = [direct, proxied];
+        let read = deserialize(&batch(&logs, Codec::Zlib(Compression::default()))).unwrap();
+        assert_eq!(read, logs);
+        assert_eq!(read[1].proxy_ip, Some([172, 16, 0, 1]));
+
+        // the flag without the ip is only caught by the strict checks
+        builder.proxy_ip = None;
+        builder.flags |= LogFlags::VIA_PROXY;
+        assert!(builder.build().is_ok());
+        assert!(builder.validate_strict().is_err());
+
+        let mut missing = logs[1].clone();
+        missing.proxy_ip = None;
+        let e = missing.serialize(&mut Vec::new()).unwrap_err();
+        assert!(e.to_string().contains("missing proxy ip"), "{e}");
+    }
+
+    #[test]
+    fn unknown_server_versions_are_kept_by_name() {
+        assert_eq!(
+            ServerVersion::from_name("1.12").unwrap(),
+            ServerVersion::Known(5)
+        );
+        let snapshot = ServerVersion::from_name("24w14a").unwrap();
+        assert_eq!(snapshot, ServerVersion::Unknown("24w14a".into()));
+        assert_eq!((snapshot.name(), snapshot.id()), (Some("24w14a"), None));
+        assert!(ServerVersion::from_name("").is_err());
+        assert!(ServerVersion::from_name(&"v".repeat(ServerVersion::MAX_UNKNOWN_LEN + 1)).is_err());
+        assert_eq!(ServerVersion::Known(200).name(), None);
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.server_version = "24w14a".to_string();
+        let log = builder.build().unwrap();
+        assert_eq!(log.binary_version, 2);
+        assert_eq!(log.server_version, snapshot);
+
+        let read = deserialize(&batch(slice::from_ref(&log), Codec::None)).unwrap();
+        assert_eq!(read, slice::from_ref(&log));
+        assert_eq!(
+            PlayerLogBuilder::from_log(&read[0]).unwrap().server_version,
+            "24w14a"
+        );
+
+        // v1 has no room for the name, and v2 reserves the id that introduces it
+        let mut v1 = offline_log(b"Notch");
+        v1.server_version = snapshot;
+        assert!(v1.serialize(&mut Vec::new()).is_err());
+        let mut reserved = log;
+        reserved.server_version = ServerVersion::Known(ServerVersion::UNKNOWN_ID);
+        assert!(reserved.serialize(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn nil_uuids_build_as_offline() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.flags = LogFlags::IS_ONLINE | LogFlags::CRACKED_CLIENT;
+        builder.player_uuid = Some(Uuid::nil());
+
+        // dropping the uuid drops IS_ONLINE, so a cracked client is fine
+        let offline = builder.build().unwrap();
+        assert_eq!(offline.player_uuid, None);
+        assert!(!offline.is_online());
+        assert!(LogFlags::from_bits_retain(offline.flags).contains(LogFlags::CRACKED_CLIENT));
+
+        let keep = BuildOptions {
+            normalize_nil_uuid: false,
+            ..BuildOptions::default()
+        };
+        let e = builder.build_with_options(&keep).unwrap_err();
+        assert!(e.to_string().contains("Cracked clients"), "{e}");
+
+        builder.flags = LogFlags::IS_ONLINE;
+        let nil = builder.build_with_options(&keep).unwrap();
+        assert_eq!(nil.player_uuid, Some([0; 16]));
+        assert!(nil.is_online());
+        assert_eq!(nil.identity_uuid(), None);
+    }
+
+    #[test]
+    fn nil_uuid_records_read_as_written_and_convert_as_offline() {
+        let mut online = offline_log(b"Notch");
+        online.flags = LogFlags::IS_ONLINE.bits();
+        online.player_uuid = Some([0; 16]);
+
+        let read = deserialize(&batch(slice::from_ref(&online), Codec::None)).unwrap();
+        assert_eq!(read, [online]);
+        assert_eq!(read[0].identity_uuid(), None);
+
+        let builder = PlayerLogBuilder::from_log(&read[0]).unwrap();
+        assert_eq!(builder.player_uuid, None);
+        assert!(!builder.flags.contains(LogFlags::IS_ONLINE));
+        assert_eq!(builder.build().unwrap(), offline_log(b"Notch"));
+
+        let mut real = read[0].clone();
+        real.player_uuid = Some([7; 16]);
+        assert_eq!(real.identity_uuid(), Some([7; 16]));
+        let builder = PlayerLogBuilder::from_log(&real).unwrap();
+        assert_eq!(builder.player_uuid, Some(Uuid::from_bytes([7; 16])));
+        assert!(builder.flags.contains(LogFlags::IS_ONLINE));
+    }
+
+    #[test]
+    fn builders_decode_like_converted_logs() {
+        let logs = logs(200);
+        for codec in codecs() {
+            let data = batch(&logs, codec);
+            let builders = PlayerLogSerializer::deserialize_many_builders(&data).unwrap();
+            let converted = deserialize(&data)
+                .unwrap()
+                .iter()
+                .map(PlayerLogBuilder::from_log)
+                .collect::<Result<Vec<_>>>()
+                .unwrap();
+
+            assert_eq!(builders, converted, "{codec:?}");
+            assert_eq!(PlayerLogBuilder::build_many(&builders).unwrap(), logs);
+        }
+    }
+
+    #[test]
+    fn builders_name_the_record_that_isnt_utf8() {
+        let mut bad_domain = offline_log(b"Alex");
+        bad_domain.server_domain = DomainBytes::from_slice(b"mc.\xffxample.com");
+        let logs = [offline_log(b"Notch"), offline_log(b"\xffotch"), bad_domain];
+
+        let e =
+            PlayerLogSerializer::deserialize_many_builders(&batch(&logs, Codec::Lz4)).unwrap_err();
+        assert!(
+            format!("{e:#}").starts_with("record 1: invalid player name"),
+            "{e:#}"
+        );
+
+        let e = PlayerLogSerializer::deserialize_many_builders(&batch(
+            &[logs[0].clone(), logs[2].clone()],
+            Codec::None,
+        ))
+        .unwrap_err();
+        assert!(
+            format!("{e:#}").starts_with("record 1: invalid server domain"),
+            "{e:#}"
+        );
+    }
+
+    #[test]
+    fn text_errors_name_the_record_and_field() {
+        let strict = DeserializerOptions {
+            validate_utf8: true,
+            reject_nul: true,
+            ..DeserializerOptions::default()
+        };
+        let read = |logs: &[PlayerLog], codec, options: &DeserializerOptions| {
+            PlayerLogSerializer::deserialize_many_with_options(&batch(logs, codec), options)
+                .map_err(|e| format!("{e:#}"))
+        };
+
+        let mut bad_domain = offline_log(b"Alex");
+        bad_domain.server_domain = DomainBytes::from_slice(b"mc.\xC3xample.com");
+        let mut nul_domain = offline_log(b"Alex");
+        nul_domain.server_domain = DomainBytes::from_slice(b"mc.example.com\0");
+
+        for codec in codecs() {
+            let good = logs(20);
+            assert_eq!(read(&good, codec, &strict).unwrap(), good);
+
+            let logs = [
+                offline_log(b"Notch"),
+                offline_log(b"Steve"),
+                bad_domain.clone(),
+            ];
+            let e = read(&logs, codec, &strict).unwrap_err();
+            assert!(
+                e.starts_with("record 2: server domain isn't valid utf8"),
+                "{e}"
+            );
+
+            let logs = [offline_log(b"Notch"), nul_domain.clone()];
+            let e = read(&logs, codec, &strict).unwrap_err();
+            assert!(
+                e.starts_with("record 1: server domain contains a NUL byte"),
+                "{e}"
+            );
+
+            let logs = [offline_log(b"\0otch"), bad_domain.clone()];
+            let e = read(&logs, codec, &strict).unwrap_err();
+            assert!(
+                e.starts_with("record 0: player name contains a NUL byte"),
+                "{e}"
+            );
+        }
+
+        // reject_nul only applies along with validate_utf8
+        let nul_only = DeserializerOptions {
+            reject_nul: true,
+            ..DeserializerOptions::default()
+        };
+        let logs = [offline_log(b"\0otch"), bad_domain];
+        assert_eq!(read(&logs, Codec::None, &nul_only).unwrap(), logs);
+    }
+
+    #[test]
+    fn names_are_limited_in_characters_and_bytes() {
+        let long = BuildOptions {
+            allow_long_name_bytes: true,
+            ..BuildOptions::default()
+        };
+        // everything else about this log fits version 1
+        let mut builder = PlayerLogBuilder::from_log(&offline_log(b"Notch")).unwrap();
+        let build_error = |builder: &PlayerLogBuilder, options: &BuildOptions| {
+            let e = builder.build_with_options(options).unwrap_err();
+            (*e.downcast_ref::<BuildError>().unwrap(), e.to_string())
+        };
+
+        builder.player_name = "x".repeat(17);
+        for options in [&BuildOptions::default(), &long] {
+            let (e, message) = build_error(&builder, options);
+            assert_eq!(e, BuildError::NameTooManyChars(17));
+            assert_eq!(
+                message,
+                "Player name too long: 17 characters, at most 16 are allowed"
+            );
+        }
+
+        // 16 characters in 20 bytes
+        builder.player_name = "§a§l§o§nNotch_Xx".to_string();
+        let (e, message) = build_error(&builder, &BuildOptions::default());
+        assert_eq!(e, BuildError::NameTooManyBytes(20));
+        assert_eq!(message, "Player name too long: 20 bytes");
+        let log = builder.build_with_options(&long).unwrap();
+        assert_eq!(log.binary_version, 2);
+        for codec in codecs() {
+            let read = deserialize(&batch(slice::from_ref(&log), codec)).unwrap();
+            assert_eq!(read, slice::from_ref(&log));
+        }
+
+        builder.player_name = "€".repeat(11);
+        assert_eq!(
+            build_error(&builder, &long).0,
+            BuildError::NameTooManyBytes(33)
+        );
+
+        // the option alone doesn't make a log version 2
+        builder.player_name = "Notch".to_string();
+        assert_eq!(
+            builder.build_with_options(&long).unwrap(),
+            offline_log(b"Notch")
+        );
+    }
+
+    #[test]
+    fn the_wire_name_limit_follows_the_version() {
+        let mut v1 = offline_log(&[b'a'; 17]);
+        assert!(v1.serialize(&mut Vec::new()).is_err());
+        v1.player_name = NameBytes::from_slice(&[b'a'; 16]);
+        let mut data = Vec::new();
+        v1.serialize(&mut data).unwrap();
+
+        // a name length byte past the limit fails before any name is read
+        data[2] = 17;
+        let e = PlayerLog::deserialize(&mut &data[..]).unwrap_err();
+        assert_eq!(e.to_string(), "invalid player name: 17 bytes");
+
+        let mut v2 = offline_log(&[b'a'; 32]);
+        v2.binary_version = 2;
+        v2.timestamp = 1;
+        let read = deserialize(&batch(slice::from_ref(&v2), Codec::None)).unwrap();
+        assert_eq!(read, [v2.clone()]);
+
+        v2.player_name = NameBytes::from_slice(&[b'a'; 33]);
+        assert!(v2.serialize(&mut Vec::new()).is_err());
+    }
+
+    #[test]
+    fn server_contexts_build_what_a_full_builder_does() {
+        let mut rng = StdRng::seed_from_u64(0);
+        for version in ["1.20", "24w14a"] {
+            let server = ServerContext::new(
+                Ipv4Addr::new(192, 168, 1, 254),
+                25566,
+                "play.example.com",
+                version,
+            )
+            .unwrap();
+
+            for _ in 0..50 {
+                let generated = log_generator_seeded(&mut rng);
+                let log = server.log(
+                    &generated.player_name,
+                    generated.player_uuid,
+                    generated.player_ip,
+                    generated.flags.clone(),
+                );
+
+                let builder = PlayerLogBuilder {
+                    flags: generated.flags.clone(),
+                    player_uuid: generated.player_uuid,
+                    player_name: generated.player_name.clone(),
+                    player_ip: generated.player_ip,
+                    ..PlayerLogBuilder::for_server(&server)
+                };
+                let by_hand = PlayerLogBuilder {
+                    server_ip: server.server_ip,
+                    server_port: 25566,
+                    server_domain: "play.example.com".to_string(),
+                    server_version: version.to_string(),
+                    ..builder.clone()
+                };
+
+                match (log, builder.build(), by_hand.build()) {
+                    (Ok(log), Ok(built), Ok(by_hand)) => {
+                        assert_eq!(log, built);
+                        assert_eq!(log, by_hand);
+                        assert_eq!(log.server_version, server.server_version);
+                    }
+                    (Err(_), Err(_), Err(_)) => {}
+                    (log, built, by_hand) => {
+                        panic!("{:?} {:?} {:?}", log.err(), built.err(), by_hand.err())
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn server_contexts_check_the_version_once() {
+        let ip = Ipv4Addr::new(192, 168, 1, 254);
+        let e = ServerContext::new(ip, 25565, "mc.example.com", "").unwrap_err();
+        assert_eq!(e.to_string(), "invalid server version \"\"");
+        let long = "x".repeat(ServerVersion::MAX_UNKNOWN_LEN + 1);
+        assert!(ServerContext::new(ip, 25565, "mc.example.com", &long).is_err());
+
+        let server = ServerContext::new(ip, 25565, "mc.example.com", "1.8").unwrap();
+        let builder = PlayerLogBuilder::for_server(&server);
+        assert_eq!(builder.server_version, "1.8");
+        assert_eq!(builder.player_name, "");
+        assert_eq!(builder.timestamp, None);
+
+        let log = server
+            .log("Notch", None, Ipv4Addr::new(10, 0, 0, 1), LogFlags::empty())
+            .unwrap();
+        assert_eq!(log.player_name, NameBytes::from_slice(b"Notch"));
+        let e = server
+            .log(&"x".repeat(17), None, ip, LogFlags::empty())
+            .unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<BuildError>(),
+            Some(&BuildError::NameTooManyChars(17))
+        );
+    }
+
+    #[test]
+    fn strict_batches_name_every_invalid_record() {
+        let mut logs = logs(50);
+        let options = SerializerOptions {
+            codec: Codec::Lz4,
+            ..SerializerOptions::default()
+        };
+        assert_eq!(
+            PlayerLogSerializer::serialize_many_strict(&logs, &options).unwrap(),
+            batch(&logs, Codec::Lz4)
+        );
+
+        logs[3] = offline_log(b"Notch");
+        logs[3].flags = LogFlags::IS_ONLINE.bits();
+        logs[40] = offline_log(b"Notch");
+        logs[40].flags = 0x100;
+        for log in [&logs[3], &logs[40]] {
+            assert!(log.validate().is_err());
+            assert!(log.serialize(&mut Vec::new()).is_err());
+        }
+
+        let e = PlayerLogSerializer::serialize_many_strict(&logs, &options).unwrap_err();
+        let e = e.downcast_ref::<BatchValidationError>().unwrap();
+        assert_eq!(e.invalid, 2);
+        assert_eq!(
+            e.to_string(),
+            "2 invalid records\n  record 3: missing player uuid\n  record 40: flags above the low \
+             byte need binary_version 2"
+        );
+    }
+
+    #[test]
+    fn strict_errors_stop_listing_at_the_limit() {
+        let mut invalid = offline_log(b"Notch");
+        invalid.flags = LogFlags::IS_ONLINE.bits();
+        let logs = vec![invalid; BatchValidationError::MAX_OFFENDERS + 5];
+
+        let e = PlayerLogSerializer::serialize_many_strict(&logs, &SerializerOptions::default())
+            .unwrap_err();
+        let e = e.downcast_ref::<BatchValidationError>().unwrap();
+        assert_eq!(e.invalid, logs.len());
+        assert_eq!(e.offenders.len(), BatchValidationError::MAX_OFFENDERS);
+        assert_eq!(e.offenders[999].0, 999);
+        assert!(e.to_string().ends_with("\n  and 5 more"));
+    }
+
+    #[test]
+    fn recompressed_batches_match_serializing_with_the_new_codec() {
+        let logs = logs(300);
+        for from in codecs() {
+            for to in codecs() {
+                let options = SerializerOptions {
+                    codec: from,
+                    batch_id: Some([7; 16]),
+                    producer: Some("collector-1".to_string()),
+                    endianness: Endianness::Little,
+                };
+                let data =
+                    PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap();
+
+                let recompressed = PlayerLogSerializer::recompress(&data, Some(from), to).unwrap();
+                let expected = SerializerOptions {
+                    codec: to,
+                    ..options
+                };
+                assert_eq!(
+                    recompressed,
+                    PlayerLogSerializer::serialize_many_with_options(&logs, &expected).unwrap(),
+                    "{} to {}",
+                    from.name(),
+                    to.name()
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn recompressing_keeps_the_dictionary() {
+        let logs = logs(200);
+        let dict =
+            dictionary::DomainDictionary::build(logs.iter().map(|log| log.server_domain.to_vec()))
+                .unwrap();
+        let data = PlayerLogSerializer::serialize_many_with_dict(&logs, &dict).unwrap();
+
+        let recompressed = PlayerLogSerializer::recompress(&data, None, Codec::Lz4).unwrap();
+        let header = PlayerLogSerializer::probe(&recompressed).unwrap();
+        assert_eq!(header.codec.id(), Codec::Lz4.id());
+        assert_eq!(header.dictionary, Some(dict.id()));
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_with_dict(&recompressed, &dict).unwrap(),
+            logs
+        );
+    }
+
+    #[test]
+    fn recompressing_checks_the_hint_and_the_payload() {
+        let logs = logs(50);
+        let data = batch(&logs, Codec::Lz4);
+        let err =
+            PlayerLogSerializer::recompress(&data, Some(Codec::None), Codec::Lz4).unwrap_err();
+        assert_eq!(err.to_string(), "batch is compressed with lz4, not none");
+
+        let mut zlib = batch(&logs, Codec::Zlib(Compression::default()));
+        let end = zlib.len() - 10;
+        zlib.truncate(end);
+        let err = PlayerLogSerializer::recompress(&zlib, None, Codec::None).unwrap_err();
+        assert_eq!(err.to_string(), "corrupt payload");
     }
 }
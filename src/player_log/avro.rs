@@ -0,0 +1,300 @@
+use std::io::{Read, Write};
+
+use anyhow::{bail, Context, Result};
+use apache_avro::types::Value;
+use apache_avro::{Reader, Schema, Writer};
+use serde_json::json;
+
+use super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+use super::{Extension, GeoInfo, LoginOutcome, PlayerLog, ServerVersion};
+
+// the same columns as the sqlite table, ips stay 4 raw bytes
+fn schema() -> Result<Schema> {
+    let schema = json!({
+        "type": "record",
+        "name": "PlayerLog",
+        "fields": [
+            { "name": "binary_version", "type": "int" },
+            { "name": "flags", "type": "int" },
+            { "name": "player_uuid", "type": ["null", { "type": "fixed", "name": "Uuid", "size": 16 }] },
+            { "name": "player_name", "type": "string" },
+            { "name": "player_ip", "type": { "type": "fixed", "name": "Ipv4", "size": 4 } },
+            { "name": "server_ip", "type": "Ipv4" },
+            { "name": "server_port", "type": "int" },
+            { "name": "server_domain", "type": "string" },
+            { "name": "server_version", "type": "int" },
+            { "name": "server_version_name", "type": ["null", "string"] },
+            { "name": "timestamp", "type": "long" },
+            { "name": "session_id", "type": "long" },
+            { "name": "outcome", "type": "int" },
+            { "name": "country", "type": ["null", "string"] },
+            { "name": "asn", "type": ["null", "long"] },
+            { "name": "proxy_ip", "type": ["null", "Ipv4"] },
+            { "name": "client_brand", "type": "string" },
+            { "name": "client_locale", "type": "string" },
+            {
+                "name": "extensions",
+                "type": {
+                    "type": "array",
+                    "items": {
+                        "type": "record",
+                        "name": "Extension",
+                        "fields": [
+                            { "name": "kind", "type": "int" },
+                            { "name": "data", "type": "bytes" },
+                        ],
+                    },
+                },
+            },
+        ],
+    });
+
+    Ok(Schema::parse(&schema)?)
+}
+
+fn text(bytes: &[u8]) -> Value {
+    Value::String(String::from_utf8_lossy(bytes).into_owned())
+}
+
+fn optional(value: Option<Value>) -> Value {
+    // the null branch always comes first in the schema
+    value.map_or_else(
+        || Value::Union(0, Box::new(Value::Null)),
+        |value| Value::Union(1, Box::new(value)),
+    )
+}
+
+/// Writes an Avro object container file with the schema embedded. Names and domains that aren't
+/// utf8 are converted lossily.
+pub fn write_avro(logs: &[PlayerLog], writer: impl Write) -> Result<()> {
+    let schema = schema()?;
+    let mut writer = Writer::new(&schema, writer);
+
+    for log in logs {
+        let extensions = log
+            .extensions
+            .iter()
+            .map(|extension| {
+                Value::Record(vec![
+                    ("kind".to_string(), Value::Int(extension.kind.into())),
+                    ("data".to_string(), Value::Bytes(extension.data.clone())),
+                ])
+            })
+            .collect();
+
+        let fields = vec![
+            ("binary_version", Value::Int(log.binary_version.into())),
+            ("flags", Value::Int(log.flags.into())),
+            (
+                "player_uuid",
+                optional(log.player_uuid.map(|uuid| Value::Fixed(16, uuid.to_vec()))),
+            ),
+            ("player_name", text(&log.player_name)),
+            ("player_ip", Value::Fixed(4, log.player_ip.to_vec())),
+            ("server_ip", Value::Fixed(4, log.server_ip.to_vec())),
+            ("server_port", Value::Int(log.server_port.into())),
+            ("server_domain", text(&log.server_domain)),
+            (
+                "server_version",
+                Value::Int(
+                    log.server_version
+                        .id()
+                        .unwrap_or(ServerVersion::UNKNOWN_ID)
+                        .into(),
+                ),
+            ),
+            (
+                "server_version_name",
+                optional(
+                    log.server_version
+                        .name()
+                        .filter(|_| log.server_version.id().is_none())
+                        .map(|name| Value::String(name.to_string())),
+                ),
+            ),
+            ("timestamp", Value::Long(log.timestamp as i64)),
+            ("session_id", Value::Long(log.session_id.into())),
+            ("outcome", Value::Int(log.outcome.to_byte().into())),
+            ("country", optional(log.geo.map(|geo| text(&geo.country)))),
+            (
+                "asn",
+                optional(log.geo.map(|geo| Value::Long(geo.asn.into()))),
+            ),
+            (
+                "proxy_ip",
+                optional(log.proxy_ip.map(|ip| Value::Fixed(4, ip.to_vec()))),
+            ),
+            ("client_brand", text(&log.client_brand)),
+            ("client_locale", text(&log.client_locale)),
+            ("extensions", Value::Array(extensions)),
+        ];
+
+        writer.append(Value::Record(
+            fields
+                .into_iter()
+                .map(|(name, value)| (name.to_string(), value))
+                .collect(),
+        ))?;
+    }
+
+    // unlike flush this writes the header when there are no logs, so the file still reads back
+    writer.into_inner()?.flush()?;
+    Ok(())
+}
+
+/// Reads back a file written by [`write_avro`], or any file whose schema resolves to it.
+pub fn read_avro(reader: impl Read) -> Result<Vec<PlayerLog>> {
+    let schema = schema()?;
+
+    Reader::with_schema(&schema, reader)?
+        .enumerate()
+        .map(|(i, value)| {
+            value
+                .map_err(anyhow::Error::from)
+                .and_then(|value| from_value(&value))
+                .with_context(|| format!("record {i}"))
+        })
+        .collect()
+}
+
+fn from_value(value: &Value) -> Result<PlayerLog> {
+    let Value::Record(fields) = value else {
+        bail!("not a record");
+    };
+    let field = |name: &str| -> Result<&Value> {
+        fields
+            .iter()
+            .find(|(field, _)| field == name)
+            .map(|(_, value)| value)
+            .with_context(|| format!("missing {name}"))
+    };
+
+    let geo = match (
+        optional_field(field("country")?)?,
+        optional_field(field("asn")?)?,
+    ) {
+        (Some(country), Some(asn)) => Some(GeoInfo {
+            country: string(country)?
+                .as_bytes()
+                .try_into()
+                .context("invalid country")?,
+            asn: int(asn, "asn")?,
+        }),
+        (None, None) => None,
+        _ => bail!("country and asn have to be set together"),
+    };
+
+    let server_version = match optional_field(field("server_version_name")?)? {
+        Some(name) => ServerVersion::Unknown(string(name)?.into()),
+        None => ServerVersion::Known(int(field("server_version")?, "server_version")?),
+    };
+
+    let Value::Array(extensions) = field("extensions")? else {
+        bail!("extensions isn't an array");
+    };
+    let extensions = extensions
+        .iter()
+        .map(|extension| -> Result<Extension> {
+            let Value::Record(fields) = extension else {
+                bail!("extension isn't a record");
+            };
+            match fields.as_slice() {
+                [(_, kind), (_, Value::Bytes(data))] => Ok(Extension {
+                    kind: int(kind, "extension kind")?,
+                    data: data.clone(),
+                }),
+                _ => bail!("invalid extension"),
+            }
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(PlayerLog {
+        binary_version: int(field("binary_version")?, "binary_version")?,
+        flags: int(field("flags")?, "flags")?,
+        player_uuid: optional_field(field("player_uuid")?)?
+            .map(fixed)
+            .transpose()?,
+        player_name: NameBytes::from_slice(string(field("player_name")?)?.as_bytes()),
+        player_ip: fixed(field("player_ip")?)?,
+        server_ip: fixed(field("server_ip")?)?,
+        server_port: int(field("server_port")?, "server_port")?,
+        server_domain: DomainBytes::from_slice(string(field("server_domain")?)?.as_bytes()),
+        server_version,
+        timestamp: int(field("timestamp")?, "timestamp")?,
+        session_id: int(field("session_id")?, "session_id")?,
+        outcome: LoginOutcome::from_byte(int(field("outcome")?, "outcome")?),
+        geo,
+        proxy_ip: optional_field(field("proxy_ip")?)?.map(fixed).transpose()?,
+        extensions,
+        client_brand: string(field("client_brand")?)?.as_bytes().to_vec(),
+        client_locale: string(field("client_locale")?)?.as_bytes().to_vec(),
+    })
+}
+
+fn optional_field(value: &Value) -> Result<Option<&Value>> {
+    match value {
+        Value::Union(_, value) if **value == Value::Null => Ok(None),
+        Value::Union(_, value) => Ok(Some(value)),
+        _ => bail!("expected a union, got {value:?}"),
+    }
+}
+
+fn string(value: &Value) -> Result<&str> {
+    match value {
+        Value::String(string) => Ok(string),
+        _ => bail!("expected a string, got {value:?}"),
+    }
+}
+
+fn fixed<const N: usize>(value: &Value) -> Result<[u8; N]> {
+    match value {
+        Value::Fixed(_, bytes) => Ok(bytes.as_slice().try_into()?),
+        _ => bail!("expected {N} fixed bytes, got {value:?}"),
+    }
+}
+
+// ints and longs both have to fit the field's type
+fn int<T: TryFrom<i64>>(value: &Value, name: &str) -> Result<T> {
+    let int = match value {
+        Value::Int(int) => i64::from(*int),
+        Value::Long(long) => *long,
+        _ => bail!("{name} isn't an int, got {value:?}"),
+    };
+
+    T::try_from(int)
+        .ok()
+        .with_context(|| format!("{name} is out of range: {int}"))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    #[test]
+    fn logs_round_trip() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut logs = (0..200)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect::<Vec<_>>();
+        logs[0].server_version = ServerVersion::Unknown("24w14potato".into());
+        logs[1].extensions.push(Extension {
+            kind: 7,
+            data: vec![0, 255],
+        });
+
+        let mut file = Vec::new();
+        write_avro(&logs, &mut file).unwrap();
+        assert_eq!(read_avro(file.as_slice()).unwrap(), logs);
+    }
+
+    #[test]
+    fn empty_files_read_back_empty() {
+        let mut file = Vec::new();
+        write_avro(&[], &mut file).unwrap();
+        assert!(!file.is_empty());
+        assert!(read_avro(file.as_slice()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,283 @@
+//! Filling in the binary_version 2 fields of old records from data kept outside the batch.
+
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, Context, Result};
+use serde::Deserialize;
+
+use super::fingerprint::xxhash;
+use super::{GeoInfo, LogFlags, PlayerLog, PlayerLogSerializer, SerializerOptions};
+
+/// Where [`PlayerLogSerializer::backfill`] gets the fields a record doesn't have from.
+pub trait BackfillProvider {
+    /// The time of the login, only asked for records without a timestamp, and with the record as
+    /// the batch stores it.
+    fn timestamp_for(&self, log: &PlayerLog) -> Option<u64>;
+    /// Where the player ip is, only asked for records without geo info.
+    fn geo_for(&self, ip: Ipv4Addr) -> Option<GeoInfo>;
+}
+
+/// A [`BackfillProvider`] answering from two csv tables, one of timestamps with a `fingerprint`
+/// and `timestamp` column, and one of geo info with an `ip`, `country` and `asn` column.
+///
+/// The fingerprint is the [`xxhash`] in decimal of a log as the old batch stores it, which
+/// leaves out the binary_version 2 fields a backfill writes.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CsvBackfillProvider {
+    pub timestamps: HashMap<u64, u64>,
+    pub geo: HashMap<Ipv4Addr, GeoInfo>,
+}
+
+#[derive(Deserialize)]
+struct TimestampRow {
+    fingerprint: u64,
+    timestamp: u64,
+}
+
+#[derive(Deserialize)]
+struct GeoRow {
+    ip: Ipv4Addr,
+    country: String,
+    asn: u32,
+}
+
+impl CsvBackfillProvider {
+    /// Reads both tables, a later row for the same fingerprint or ip replaces an earlier one.
+    pub fn from_csv<T: Read, G: Read>(timestamps: T, geo: G) -> Result<Self> {
+        let mut provider = Self::default();
+
+        for (i, row) in csv::Reader::from_reader(timestamps)
+            .deserialize::<TimestampRow>()
+            .enumerate()
+        {
+            let row = row.with_context(|| format!("timestamp row {}", i + 1))?;
+            provider.timestamps.insert(row.fingerprint, row.timestamp);
+        }
+
+        for (i, row) in csv::Reader::from_reader(geo)
+            .deserialize::<GeoRow>()
+            .enumerate()
+        {
+            let row = row.with_context(|| format!("geo row {}", i + 1))?;
+            let country = match row.country.as_bytes() {
+                &[a, b] if a.is_ascii_uppercase() && b.is_ascii_uppercase() => [a, b],
+                _ => bail!(
+                    "geo row {}: country {:?} isn't two uppercase letters",
+                    i + 1,
+                    row.country
+                ),
+            };
+            provider.geo.insert(
+                row.ip,
+                GeoInfo {
+                    country,
+                    asn: row.asn,
+                },
+            );
+        }
+
+        Ok(provider)
+    }
+}
+
+impl BackfillProvider for CsvBackfillProvider {
+    fn timestamp_for(&self, log: &PlayerLog) -> Option<u64> {
+        self.timestamps.get(&xxhash(log)).copied()
+    }
+
+    fn geo_for(&self, ip: Ipv4Addr) -> Option<GeoInfo> {
+        self.geo.get(&ip).copied()
+    }
+}
+
+impl PlayerLog {
+    /// Upgrades the log to [`PlayerLog::CURRENT_VERSION`] and fills in the timestamp and geo info
+    /// from `provider` if the log has none. A field the provider doesn't know stays absent.
+    pub fn backfill(&mut self, provider: &dyn BackfillProvider) {
+        // asked before the upgrade, which can set flags the fingerprint covers
+        let timestamp = match self.timestamp {
+            0 => provider.timestamp_for(self),
+            _ => None,
+        };
+        self.upgrade();
+
+        if let Some(timestamp) = timestamp {
+            self.timestamp = timestamp;
+        }
+
+        if self.geo.is_none() {
+            if let Some(geo) = provider.geo_for(Ipv4Addr::from(self.player_ip)) {
+                self.geo = Some(geo);
+                self.flags |= LogFlags::HAS_GEO.bits();
+            }
+        }
+    }
+}
+
+impl PlayerLogSerializer {
+    /// A copy of `data` with every record [backfilled](PlayerLog::backfill), in the same order and
+    /// written with the same codec, producer and endianness.
+    ///
+    /// The batch id is the default hash of the new payload, the old one named different records.
+    pub fn backfill(data: &[u8], provider: &dyn BackfillProvider) -> Result<Vec<u8>> {
+        let header = Self::probe(data)?;
+        let mut logs = Self::deserialize_many(data)?;
+        for log in &mut logs {
+            log.backfill(provider);
+        }
+
+        let options = SerializerOptions {
+            codec: header.codec,
+            batch_id: None,
+            producer: header.producer.map(str::to_owned),
+            endianness: header.endianness,
+        };
+
+        Self::serialize_many_with_options(&logs, &options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+    use super::super::{Codec, Endianness, ServerVersion};
+    use super::*;
+
+    fn v1_log(name: &[u8], server_port: u16) -> PlayerLog {
+        PlayerLog {
+            binary_version: 1,
+            player_name: NameBytes::from_slice(name),
+            player_ip: [10, 0, 0, 1],
+            server_ip: [192, 168, 1, 254],
+            server_port,
+            server_domain: DomainBytes::from_slice(b"mc.example.com"),
+            server_version: ServerVersion::Known(1),
+            ..PlayerLog::default()
+        }
+    }
+
+    fn geo(country: &[u8; 2], asn: u32) -> GeoInfo {
+        GeoInfo {
+            country: *country,
+            asn,
+        }
+    }
+
+    #[test]
+    fn csv_tables_load_and_later_rows_win() {
+        let timestamps = "fingerprint,timestamp\n1,100\n2,200\n1,300\n";
+        let geo_rows = "ip,country,asn\n10.0.0.1,DE,3320\n10.0.0.2,US,7922\n10.0.0.1,FR,3215\n";
+        let provider =
+            CsvBackfillProvider::from_csv(timestamps.as_bytes(), geo_rows.as_bytes()).unwrap();
+
+        assert_eq!(provider.timestamps, HashMap::from([(1, 300), (2, 200)]));
+        assert_eq!(
+            provider.geo,
+            HashMap::from([
+                (Ipv4Addr::new(10, 0, 0, 1), geo(b"FR", 3215)),
+                (Ipv4Addr::new(10, 0, 0, 2), geo(b"US", 7922)),
+            ])
+        );
+    }
+
+    #[test]
+    fn bad_csv_rows_are_named() {
+        let geo_rows = "ip,country,asn\n10.0.0.1,DE,3320\n10.0.0.2,us,7922\n";
+        let err =
+            CsvBackfillProvider::from_csv(&b"fingerprint,timestamp\n"[..], geo_rows.as_bytes())
+                .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "geo row 2: country \"us\" isn't two uppercase letters"
+        );
+
+        let timestamps = "fingerprint,timestamp\n1,100\nnope,200\n";
+        let err = CsvBackfillProvider::from_csv(timestamps.as_bytes(), &b"ip,country,asn\n"[..])
+            .unwrap_err();
+        assert_eq!(err.to_string(), "timestamp row 2");
+    }
+
+    #[test]
+    fn old_records_are_upgraded_and_filled_in() {
+        // a nondefault port gains a flag in the upgrade, the fingerprint is still the stored one
+        for port in [PlayerLog::DEFAULT_PORT, 25570] {
+            let mut log = v1_log(b"Notch", port);
+            let provider = CsvBackfillProvider {
+                timestamps: HashMap::from([(xxhash(&log), 1_700_000_000_000)]),
+                geo: HashMap::from([(Ipv4Addr::new(10, 0, 0, 1), geo(b"SE", 2119))]),
+            };
+
+            log.backfill(&provider);
+            assert_eq!(log.binary_version, PlayerLog::CURRENT_VERSION);
+            assert_eq!(log.timestamp, 1_700_000_000_000, "port {port}");
+            assert_eq!(log.geo, Some(geo(b"SE", 2119)));
+            assert!(LogFlags::from_bits_retain(log.flags).contains(LogFlags::HAS_GEO));
+            log.validate_consistency().unwrap();
+        }
+    }
+
+    #[test]
+    fn known_fields_are_kept_and_unknown_ones_stay_absent() {
+        let provider = CsvBackfillProvider {
+            timestamps: HashMap::new(),
+            geo: HashMap::from([(Ipv4Addr::new(10, 0, 0, 1), geo(b"SE", 2119))]),
+        };
+
+        let mut log = v1_log(b"Notch", PlayerLog::DEFAULT_PORT);
+        log.player_ip = [10, 0, 0, 9];
+        log.backfill(&provider);
+        assert_eq!(log.timestamp, 0);
+        assert_eq!(log.geo, None);
+
+        let mut log = v1_log(b"Notch", PlayerLog::DEFAULT_PORT);
+        log.upgrade();
+        log.timestamp = 5;
+        log.geo = Some(geo(b"NO", 1));
+        log.flags |= LogFlags::HAS_GEO.bits();
+        let before = log.clone();
+        log.backfill(&CsvBackfillProvider {
+            timestamps: HashMap::from([(xxhash(&log), 10)]),
+            ..provider
+        });
+        assert_eq!(log, before);
+    }
+
+    #[test]
+    fn batches_keep_their_header_but_not_their_id() {
+        let logs = vec![
+            v1_log(b"Notch", PlayerLog::DEFAULT_PORT),
+            v1_log(b"jeb_", 25570),
+        ];
+        let options = SerializerOptions {
+            codec: Codec::Lz4,
+            producer: Some("collector-1".to_string()),
+            endianness: Endianness::Little,
+            ..SerializerOptions::default()
+        };
+        let data = PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap();
+        let provider = CsvBackfillProvider {
+            timestamps: logs.iter().map(|log| (xxhash(log), 42)).collect(),
+            geo: HashMap::new(),
+        };
+
+        let backfilled = PlayerLogSerializer::backfill(&data, &provider).unwrap();
+        let old = PlayerLogSerializer::probe(&data).unwrap();
+        let new = PlayerLogSerializer::probe(&backfilled).unwrap();
+        assert_eq!(new.codec, old.codec);
+        assert_eq!(new.producer, Some("collector-1"));
+        assert_eq!(new.endianness, Endianness::Little);
+        assert_ne!(new.batch_id, old.batch_id);
+
+        let mut expected = logs;
+        for log in &mut expected {
+            log.backfill(&provider);
+        }
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&backfilled).unwrap(),
+            expected
+        );
+        assert!(expected.iter().all(|log| log.timestamp == 42));
+    }
+}
@@ -0,0 +1,683 @@
+//! Batches as byte streams, for handing them to code that takes a reader or a writer.
+
+use std::io::{self, ErrorKind, Read, Write};
+
+use anyhow::{bail, Context, Result};
+use flate2::write::{ZlibDecoder, ZlibEncoder};
+use lz4_flex::frame::FrameEncoder;
+use sha2::{Digest, Sha256};
+
+use super::{
+    batch_id_from_hasher, BatchHeader, Codec, Endianness, PlayerLog, PlayerLogSerializer,
+    ProbeResult, SerializerOptions,
+};
+
+// records serialized per refill, the scratch buffer stays around this many records long
+const CHUNK_LOGS: usize = 256;
+
+enum Encoder {
+    None,
+    Zlib(ZlibEncoder<Vec<u8>>),
+    Lz4(FrameEncoder<Vec<u8>>),
+}
+
+impl Encoder {
+    fn new(codec: Codec) -> Self {
+        match codec {
+            Codec::None => Self::None,
+            Codec::Zlib(level) => Self::Zlib(ZlibEncoder::new(Vec::new(), level)),
+            Codec::Lz4 => Self::Lz4(FrameEncoder::new(Vec::new())),
+        }
+    }
+
+    // compresses `records` and appends whatever the encoder let out so far to `out`
+    fn encode(&mut self, records: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::None => out.extend_from_slice(records),
+            Self::Zlib(e) => {
+                e.write_all(records)?;
+                out.append(e.get_mut());
+            }
+            Self::Lz4(e) => {
+                e.write_all(records)?;
+                out.append(e.get_mut());
+            }
+        }
+
+        Ok(())
+    }
+
+    fn finish(self, out: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::None => {}
+            Self::Zlib(e) => out.append(&mut e.finish()?),
+            Self::Lz4(e) => out.append(&mut e.finish()?),
+        }
+
+        Ok(())
+    }
+}
+
+/// Produces the bytes [`PlayerLogSerializer::serialize_many_with_options`] would for `logs`,
+/// serializing and compressing a chunk of records at a time as they're read.
+///
+/// Without an explicit batch id the records are serialized once up front to hash them into the
+/// default one, so the output is the same byte for byte. The one exception is lz4, which can
+/// split a large payload into different blocks when it's fed a chunk at a time.
+pub struct BatchReader<'a> {
+    logs: &'a [PlayerLog],
+    endianness: Endianness,
+    encoder: Option<Encoder>,
+    total_len: Option<u64>,
+    scratch: Vec<u8>,
+    // encoded bytes not handed out yet, starting at `pos`
+    out: Vec<u8>,
+    pos: usize,
+}
+
+impl<'a> BatchReader<'a> {
+    pub fn new(logs: &'a [PlayerLog], codec: Codec) -> Result<Self> {
+        let options = SerializerOptions {
+            codec,
+            ..SerializerOptions::default()
+        };
+
+        Self::with_options(logs, &options)
+    }
+
+    pub fn with_options(logs: &'a [PlayerLog], options: &SerializerOptions) -> Result<Self> {
+        let mut scratch = Vec::with_capacity(CHUNK_LOGS * PlayerLog::MAX_ENCODED_SIZE);
+
+        let batch_id = match options.batch_id {
+            Some(batch_id) => batch_id,
+            None => {
+                let mut hasher = Sha256::new().chain_update((logs.len() as u64).to_be_bytes());
+                for log in logs {
+                    scratch.clear();
+                    log.serialize_with_endianness(&mut scratch, options.endianness)?;
+                    hasher.update(&scratch);
+                }
+
+                batch_id_from_hasher(hasher)
+            }
+        };
+
+        let header = BatchHeader {
+            codec: options.codec,
+            batch_id,
+            record_count: logs.len() as u64,
+            producer: options.producer.as_deref(),
+            dictionary: None,
+            endianness: options.endianness,
+        };
+
+        let mut out = Vec::with_capacity(header.encoded_len());
+        header.write(&mut out)?;
+
+        Ok(Self {
+            logs,
+            endianness: options.endianness,
+            encoder: Some(Encoder::new(options.codec)),
+            total_len: (options.codec == Codec::None)
+                .then(|| PlayerLogSerializer::serialized_size_many(logs, options) as u64),
+            scratch,
+            out,
+            pos: 0,
+        })
+    }
+
+    /// Length of the whole batch, only known up front for [`Codec::None`].
+    pub const fn total_len(&self) -> Option<u64> {
+        self.total_len
+    }
+
+    // encodes the next chunk of records, finishing the encoder after the last one
+    fn refill(&mut self) -> Result<()> {
+        self.out.clear();
+        self.pos = 0;
+
+        if self.logs.is_empty() {
+            return match self.encoder.take() {
+                Some(encoder) => encoder.finish(&mut self.out),
+                None => Ok(()),
+            };
+        }
+
+        let Some(encoder) = &mut self.encoder else {
+            return Ok(());
+        };
+
+        let (chunk, rest) = self.logs.split_at(self.logs.len().min(CHUNK_LOGS));
+        self.logs = rest;
+
+        self.scratch.clear();
+        for log in chunk {
+            log.serialize_with_endianness(&mut self.scratch, self.endianness)?;
+        }
+        encoder.encode(&self.scratch, &mut self.out)
+    }
+}
+
+impl Read for BatchReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        // a compressed chunk can come out empty while the encoder holds on to it
+        while self.pos == self.out.len() {
+            if self.encoder.is_none() {
+                return Ok(0);
+            }
+
+            self.refill()
+                .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{e:#}")))?;
+        }
+
+        let len = buf.len().min(self.out.len() - self.pos);
+        buf[..len].copy_from_slice(&self.out[self.pos..self.pos + len]);
+        self.pos += len;
+
+        Ok(len)
+    }
+}
+
+impl PlayerLogSerializer {
+    /// Length of the uncompressed batch [`PlayerLogSerializer::serialize_many_with_options`]
+    /// writes for `logs`, whatever codec `options` names.
+    pub fn serialized_size_many(logs: &[PlayerLog], options: &SerializerOptions) -> usize {
+        BatchHeader::MIN_SIZE
+            + options.producer.as_deref().map_or(0, str::len)
+            + logs.iter().map(PlayerLog::serialized_size).sum::<usize>()
+    }
+}
+
+const LZ4_MAGIC: u32 = 0x184D_2204;
+
+/// Decodes an lz4 frame as its bytes come in, a block at a time. Only independent blocks are
+/// supported, which is all [`FrameEncoder`] writes by default, and checksums are skipped over
+/// without being checked.
+#[derive(Default)]
+struct Lz4Blocks {
+    input: Vec<u8>,
+    // max block size, block checksums, content checksum, once the frame header is in
+    frame: Option<(usize, bool, bool)>,
+    ended: bool,
+}
+
+impl Lz4Blocks {
+    fn push(&mut self, input: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        self.input.extend_from_slice(input);
+
+        let mut pos = 0;
+        let result = self.decode(&mut pos, out);
+        self.input.drain(..pos);
+        result
+    }
+
+    // decodes everything complete in `input` from `pos` on, leaving `pos` after it
+    fn decode(&mut self, pos: &mut usize, out: &mut Vec<u8>) -> Result<()> {
+        let (max_block, block_checksums, content_checksum) = match self.frame {
+            Some(frame) => frame,
+            None => {
+                let Some(&[m0, m1, m2, m3, flg, bd]) = self.input.first_chunk::<6>() else {
+                    return Ok(());
+                };
+                if u32::from_le_bytes([m0, m1, m2, m3]) != LZ4_MAGIC {
+                    bail!("invalid lz4 frame magic");
+                }
+                if flg >> 6 != 1 {
+                    bail!("unsupported lz4 frame version");
+                }
+                if flg & 0x20 == 0 {
+                    bail!("linked lz4 blocks can't be decoded incrementally");
+                }
+
+                // magic, FLG, BD, the optional content size and dictionary id, header checksum
+                let header_len = 6
+                    + if flg & 0x08 != 0 { 8 } else { 0 }
+                    + if flg & 0x01 != 0 { 4 } else { 0 }
+                    + 1;
+                if self.input.len() < header_len {
+                    return Ok(());
+                }
+
+                let max_block = match (bd >> 4) & 0x07 {
+                    4 => 64 << 10,
+                    5 => 256 << 10,
+                    6 => 1 << 20,
+                    7 => 4 << 20,
+                    size => bail!("invalid lz4 block size {size}"),
+                };
+                let frame = (max_block, flg & 0x10 != 0, flg & 0x04 != 0);
+                self.frame = Some(frame);
+                *pos = header_len;
+                frame
+            }
+        };
+
+        while !self.ended {
+            let rest = &self.input[*pos..];
+            let Some(&size) = rest.first_chunk::<4>() else {
+                return Ok(());
+            };
+            let size = u32::from_le_bytes(size);
+
+            if size == 0 {
+                let end_len = 4 + if content_checksum { 4 } else { 0 };
+                if rest.len() < end_len {
+                    return Ok(());
+                }
+
+                *pos += end_len;
+                self.ended = true;
+                break;
+            }
+
+            let len = (size & 0x7FFF_FFFF) as usize;
+            if len > max_block {
+                bail!("lz4 block of {len} bytes, at most {max_block} are allowed");
+            }
+            let block_len = 4 + len + if block_checksums { 4 } else { 0 };
+            if rest.len() < block_len {
+                return Ok(());
+            }
+
+            let block = &rest[4..4 + len];
+            if size & 0x8000_0000 != 0 {
+                out.extend_from_slice(block);
+            } else {
+                let start = out.len();
+                out.resize(start + max_block, 0);
+                let decoded = lz4_flex::block::decompress_into(block, &mut out[start..])
+                    .context("corrupt lz4 block")?;
+                out.truncate(start + decoded);
+            }
+
+            *pos += block_len;
+        }
+
+        if *pos < self.input.len() {
+            bail!("{} bytes after the lz4 frame", self.input.len() - *pos);
+        }
+
+        Ok(())
+    }
+}
+
+enum Decoder {
+    None,
+    Zlib(ZlibDecoder<Vec<u8>>),
+    Lz4(Lz4Blocks),
+}
+
+impl Decoder {
+    fn new(codec: Codec) -> Self {
+        match codec {
+            Codec::None => Self::None,
+            Codec::Zlib(_) => Self::Zlib(ZlibDecoder::new(Vec::new())),
+            Codec::Lz4 => Self::Lz4(Lz4Blocks::default()),
+        }
+    }
+
+    // decompresses `payload` and appends whatever came out to `out`
+    fn decode(&mut self, payload: &[u8], out: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::None => out.extend_from_slice(payload),
+            Self::Zlib(d) => {
+                // flushing hands out everything decoded so far, not just whole output blocks
+                d.write_all(payload)
+                    .and_then(|()| d.flush())
+                    .context("corrupt zlib payload")?;
+                out.append(d.get_mut());
+            }
+            Self::Lz4(d) => d.push(payload, out)?,
+        }
+
+        Ok(())
+    }
+
+    fn finish(self, out: &mut Vec<u8>) -> Result<()> {
+        match self {
+            Self::None => {}
+            Self::Zlib(d) => out.append(&mut d.finish().context("truncated zlib payload")?),
+            Self::Lz4(d) => {
+                if !d.ended {
+                    bail!("truncated lz4 payload");
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// what the sink needs from the header once it's parsed
+struct SinkState {
+    decoder: Decoder,
+    remaining: u64,
+    endianness: Endianness,
+}
+
+/// Takes a batch through [`Write`] in pieces of any size and decodes each record as soon as all
+/// of its bytes are in, so the batch never has to be buffered whole.
+///
+/// [`BatchSink::completed_records`] hands out what's been decoded so far, and
+/// [`BatchSink::finish`] checks that the batch ended where its header says. Dictionary batches
+/// aren't supported.
+#[derive(Default)]
+pub struct BatchSink {
+    // header bytes until the header is complete
+    header: Vec<u8>,
+    state: Option<SinkState>,
+    // decompressed bytes of a record that isn't complete yet
+    records: Vec<u8>,
+    completed: Vec<PlayerLog>,
+}
+
+impl BatchSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the records decoded since the last call.
+    pub fn completed_records(&mut self) -> Vec<PlayerLog> {
+        std::mem::take(&mut self.completed)
+    }
+
+    /// Fails unless the header and every record it counts came in, with nothing after them.
+    /// Records still in [`BatchSink::completed_records`] are dropped.
+    pub fn finish(mut self) -> Result<()> {
+        let Some(state) = self.state.take() else {
+            bail!("truncated batch header");
+        };
+
+        state.decoder.finish(&mut self.records)?;
+        if state.remaining > 0 {
+            bail!("batch ended {} records short", state.remaining);
+        }
+        if !self.records.is_empty() {
+            bail!("{} bytes after the last record", self.records.len());
+        }
+
+        Ok(())
+    }
+
+    fn push(&mut self, mut data: &[u8]) -> Result<()> {
+        if self.state.is_none() {
+            // the header is parsed again from the start whenever more of it comes in
+            let have = self.header.len();
+            self.header.extend_from_slice(data);
+            let header_len = match PlayerLogSerializer::probe_prefix(&self.header)? {
+                ProbeResult::NeedMoreData(_) => return Ok(()),
+                ProbeResult::Complete { header, header_len } => {
+                    header.check_no_dictionary()?;
+                    self.state = Some(SinkState {
+                        decoder: Decoder::new(header.codec),
+                        remaining: header.record_count,
+                        endianness: header.endianness,
+                    });
+                    header_len
+                }
+            };
+
+            data = &data[header_len - have..];
+            self.header = Vec::new();
+        }
+
+        let Some(state) = &mut self.state else {
+            return Ok(());
+        };
+        state.decoder.decode(data, &mut self.records)?;
+
+        let mut records = self.records.as_slice();
+        while state.remaining > 0 && !records.is_empty() {
+            let mut record = records;
+            match PlayerLog::deserialize_with_endianness(&mut record, state.endianness) {
+                Ok(log) => {
+                    if !log.is_marker() {
+                        self.completed.push(log);
+                    }
+                    state.remaining -= 1;
+                    records = record;
+                }
+                Err(e) if is_eof(&e) => break,
+                Err(e) => return Err(e),
+            }
+        }
+
+        let used = self.records.len() - records.len();
+        self.records.drain(..used);
+
+        Ok(())
+    }
+}
+
+// the record runs past what has come in so far
+fn is_eof(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<io::Error>()
+        .is_some_and(|e| e.kind() == ErrorKind::UnexpectedEof)
+}
+
+impl Write for BatchSink {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.push(buf)
+            .map_err(|e| io::Error::new(ErrorKind::InvalidData, format!("{e:#}")))?;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::storage_impl::{ByteStorage, NameBytes};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn with_producer(codec: Codec) -> SerializerOptions {
+        SerializerOptions {
+            codec,
+            producer: Some("collector-1".to_string()),
+            ..SerializerOptions::default()
+        }
+    }
+
+    // reads `reader` through a buffer of `len` bytes
+    fn read_in(mut reader: impl Read, len: usize) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut buf = vec![0; len];
+        loop {
+            match reader.read(&mut buf).unwrap() {
+                0 => return out,
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[test]
+    fn readers_write_the_same_batch_as_the_serializer() {
+        let logs = logs(600);
+        for codec in [Codec::None, Codec::Zlib(Compression::default())] {
+            for endianness in [Endianness::Big, Endianness::Little] {
+                let options = SerializerOptions {
+                    endianness,
+                    ..with_producer(codec)
+                };
+                let expected =
+                    PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap();
+
+                for len in [1, 7, 4096] {
+                    let reader = BatchReader::with_options(&logs, &options).unwrap();
+                    assert_eq!(read_in(reader, len), expected, "{codec:?} {len}");
+                }
+            }
+        }
+
+        // lz4 may split its blocks differently, but decodes the same
+        let reader = BatchReader::new(&logs, Codec::Lz4).unwrap();
+        let data = read_in(reader, 4096);
+        assert_eq!(PlayerLogSerializer::deserialize_many(&data).unwrap(), logs);
+    }
+
+    #[test]
+    fn only_plain_batches_know_their_length() {
+        let logs = logs(300);
+        let options = with_producer(Codec::None);
+        let reader = BatchReader::with_options(&logs, &options).unwrap();
+        let total_len = reader.total_len().unwrap();
+        assert_eq!(read_in(reader, 4096).len() as u64, total_len);
+        assert_eq!(
+            PlayerLogSerializer::serialized_size_many(&logs, &with_producer(Codec::Lz4)) as u64,
+            total_len
+        );
+
+        let reader = BatchReader::with_options(&logs, &with_producer(Codec::Lz4)).unwrap();
+        assert_eq!(reader.total_len(), None);
+    }
+
+    #[test]
+    fn empty_batches_and_explicit_ids() {
+        for codec in [Codec::None, Codec::Zlib(Compression::fast()), Codec::Lz4] {
+            let data = read_in(BatchReader::new(&[], codec).unwrap(), 16);
+            assert!(PlayerLogSerializer::deserialize_many(&data)
+                .unwrap()
+                .is_empty());
+        }
+
+        let logs = logs(3);
+        let options = SerializerOptions {
+            batch_id: Some([7; 16]),
+            ..with_producer(Codec::None)
+        };
+        let data = read_in(BatchReader::with_options(&logs, &options).unwrap(), 16);
+        assert_eq!(
+            data,
+            PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap()
+        );
+        assert_eq!(PlayerLogSerializer::probe(&data).unwrap().batch_id, [7; 16]);
+    }
+
+    #[test]
+    fn an_invalid_record_fails_the_read() {
+        let mut logs = logs(3);
+        logs[2].player_name = NameBytes::from_slice(&[b'x'; 40]);
+        assert!(BatchReader::new(&logs, Codec::None).is_err());
+
+        // with an explicit id nothing is serialized until it's read
+        let options = SerializerOptions {
+            batch_id: Some([7; 16]),
+            ..with_producer(Codec::None)
+        };
+        let mut reader = BatchReader::with_options(&logs, &options).unwrap();
+        let e = io::copy(&mut reader, &mut io::sink()).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(e.to_string().contains("Player name too long"), "{e}");
+    }
+
+    // writes `data` to a new sink `len` bytes at a time
+    fn sink_in(data: &[u8], len: usize) -> (BatchSink, Vec<PlayerLog>) {
+        let mut sink = BatchSink::new();
+        let mut logs = Vec::new();
+        for chunk in data.chunks(len) {
+            sink.write_all(chunk).unwrap();
+            logs.extend(sink.completed_records());
+        }
+
+        (sink, logs)
+    }
+
+    #[test]
+    fn sinks_decode_batches_written_in_any_pieces() {
+        let logs = logs(600);
+        for codec in [Codec::None, Codec::Zlib(Compression::default()), Codec::Lz4] {
+            for endianness in [Endianness::Big, Endianness::Little] {
+                let options = SerializerOptions {
+                    endianness,
+                    ..with_producer(codec)
+                };
+
+                // a byte at a time is slow in debug builds, a few records cover it
+                for (count, len) in [(20, 1), (600, 61), (600, 4096), (600, usize::MAX)] {
+                    let logs = &logs[..count];
+                    let data =
+                        PlayerLogSerializer::serialize_many_with_options(logs, &options).unwrap();
+                    let (sink, read) = sink_in(&data, len);
+                    sink.finish().unwrap();
+                    assert_eq!(read, logs, "{codec:?} {len}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn records_come_out_before_the_batch_ends() {
+        let logs = logs(10);
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+
+        let mut sink = BatchSink::new();
+        sink.write_all(&data[..data.len() / 2]).unwrap();
+        let first = sink.completed_records();
+        assert!(!first.is_empty() && first.len() < logs.len());
+        assert!(sink.completed_records().is_empty());
+
+        sink.write_all(&data[data.len() / 2..]).unwrap();
+        let rest = sink.completed_records();
+        assert_eq!([first, rest].concat(), logs);
+        sink.finish().unwrap();
+    }
+
+    #[test]
+    fn finishing_checks_where_the_batch_ends() {
+        let logs = logs(10);
+        let finish = |data: &[u8]| sink_in(data, 7).0.finish().map_err(|e| e.to_string());
+
+        let plain = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        assert_eq!(finish(&plain[..10]).unwrap_err(), "truncated batch header");
+        let header_len = PlayerLogSerializer::probe_with_len(&plain).unwrap().1;
+        let last = logs[9].serialized_size();
+        assert_eq!(
+            finish(&plain[..plain.len() - last]).unwrap_err(),
+            "batch ended 1 records short"
+        );
+        assert_eq!(
+            finish(&[&plain[..], b"xyz"].concat()).unwrap_err(),
+            "3 bytes after the last record"
+        );
+        assert!(finish(&plain[..header_len]).is_err());
+
+        for (codec, message) in [
+            (
+                Codec::Zlib(Compression::default()),
+                "truncated zlib payload",
+            ),
+            (Codec::Lz4, "truncated lz4 payload"),
+        ] {
+            let data =
+                PlayerLogSerializer::serialize_many_with_options(&logs, &with_producer(codec))
+                    .unwrap();
+            assert_eq!(finish(&data[..data.len() - 3]).unwrap_err(), message);
+        }
+    }
+
+    #[test]
+    fn a_corrupt_record_fails_the_write() {
+        let logs = logs(3);
+        let mut data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let header_len = PlayerLogSerializer::probe_with_len(&data).unwrap().1;
+        data[header_len] = 0xEE;
+
+        let mut sink = BatchSink::new();
+        let e = sink.write_all(&data).unwrap_err();
+        assert_eq!(e.kind(), ErrorKind::InvalidData);
+        assert!(sink.completed_records().is_empty());
+    }
+}
@@ -0,0 +1,243 @@
+//! Timestamps of a batch that go backwards, from collectors whose clocks are off.
+//!
+//! Only records with a timestamp take part, a timestamp of 0 is unknown and stays where it is.
+
+use std::fmt;
+
+use super::PlayerLog;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BatchTimeReport {
+    pub min: Option<u64>,
+    pub max: Option<u64>,
+    /// Records with a timestamp.
+    pub timed: usize,
+    /// Timed records earlier than the timed record before them.
+    pub out_of_order: usize,
+    /// In millis, the furthest one of those steps back.
+    pub largest_regression: u64,
+}
+
+impl BatchTimeReport {
+    pub fn analyze(logs: &[PlayerLog]) -> Self {
+        let mut report = Self::default();
+        let mut previous = None;
+
+        for timestamp in timestamps(logs) {
+            report.timed += 1;
+            report.min = Some(report.min.map_or(timestamp, |min| min.min(timestamp)));
+            report.max = Some(report.max.map_or(timestamp, |max| max.max(timestamp)));
+
+            if let Some(previous) = previous.filter(|&previous| previous > timestamp) {
+                report.out_of_order += 1;
+                report.largest_regression = report.largest_regression.max(previous - timestamp);
+            }
+            previous = Some(timestamp);
+        }
+
+        report
+    }
+
+    pub const fn is_monotonic(&self) -> bool {
+        self.out_of_order == 0
+    }
+}
+
+fn timestamps(logs: &[PlayerLog]) -> impl Iterator<Item = u64> + '_ {
+    logs.iter()
+        .map(|log| log.timestamp)
+        .filter(|&timestamp| timestamp != 0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strategy {
+    /// Raises every timestamp to the latest one before it, records keep their order.
+    ClampToPrevious,
+    /// Reorders the timed records by timestamp, equal ones keep their order and records without
+    /// a timestamp keep their place.
+    SortStable,
+}
+
+/// Makes the timestamps of `logs` never go backwards, returns how many records were changed or
+/// moved.
+pub fn repair_monotonic(logs: &mut [PlayerLog], strategy: Strategy) -> usize {
+    match strategy {
+        Strategy::ClampToPrevious => {
+            let mut latest = 0;
+            let mut clamped = 0;
+            for log in logs.iter_mut().filter(|log| log.timestamp != 0) {
+                if log.timestamp < latest {
+                    log.timestamp = latest;
+                    clamped += 1;
+                }
+                latest = log.timestamp;
+            }
+
+            clamped
+        }
+        Strategy::SortStable => {
+            let slots = logs
+                .iter()
+                .enumerate()
+                .filter(|(_, log)| log.timestamp != 0)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+
+            let mut order = slots.clone();
+            order.sort_by_key(|&i| logs[i].timestamp);
+
+            let moved = slots.iter().zip(&order).filter(|(a, b)| a != b).count();
+            let sorted = order.iter().map(|&i| logs[i].clone()).collect::<Vec<_>>();
+            for (slot, log) in slots.into_iter().zip(sorted) {
+                logs[slot] = log;
+            }
+
+            moved
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkewAction {
+    /// Writes the record anyway and counts it.
+    Warn,
+    /// Fails the append with [`ClockSkewExceeded`].
+    Reject,
+}
+
+/// How far a timestamp may go back behind the latest one a writer has seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkewGuard {
+    pub max_skew_millis: u64,
+    pub action: SkewAction,
+}
+
+impl ClockSkewGuard {
+    /// `Err` if `timestamp` is further than the allowed skew behind `latest`, for either action.
+    pub const fn check(&self, latest: u64, timestamp: u64) -> Result<(), ClockSkewExceeded> {
+        if timestamp != 0 && latest > timestamp && latest - timestamp > self.max_skew_millis {
+            return Err(ClockSkewExceeded {
+                latest,
+                timestamp,
+                max_skew_millis: self.max_skew_millis,
+            });
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockSkewExceeded {
+    pub latest: u64,
+    pub timestamp: u64,
+    pub max_skew_millis: u64,
+}
+
+impl fmt::Display for ClockSkewExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "timestamp {} is {}ms behind the latest one {}, at most {}ms are allowed",
+            self.timestamp,
+            self.latest - self.timestamp,
+            self.latest,
+            self.max_skew_millis
+        )
+    }
+}
+
+impl std::error::Error for ClockSkewExceeded {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn logs(timestamps: &[u64]) -> Vec<PlayerLog> {
+        timestamps
+            .iter()
+            .enumerate()
+            .map(|(i, &timestamp)| PlayerLog {
+                timestamp,
+                session_id: i as u32,
+                ..PlayerLog::default()
+            })
+            .collect()
+    }
+
+    fn timestamps_of(logs: &[PlayerLog]) -> Vec<u64> {
+        logs.iter().map(|log| log.timestamp).collect()
+    }
+
+    fn sessions(logs: &[PlayerLog]) -> Vec<u32> {
+        logs.iter().map(|log| log.session_id).collect()
+    }
+
+    #[test]
+    fn reports_skip_unknown_timestamps() {
+        let report = BatchTimeReport::analyze(&logs(&[10, 0, 30, 20, 0, 5, 40]));
+        assert_eq!(
+            report,
+            BatchTimeReport {
+                min: Some(5),
+                max: Some(40),
+                timed: 5,
+                out_of_order: 2,
+                largest_regression: 15,
+            }
+        );
+        assert!(!report.is_monotonic());
+
+        assert!(BatchTimeReport::analyze(&logs(&[1, 0, 1, 2])).is_monotonic());
+        assert_eq!(
+            BatchTimeReport::analyze(&logs(&[0, 0])),
+            BatchTimeReport::default()
+        );
+    }
+
+    #[test]
+    fn clamping_keeps_the_order() {
+        let mut logs = logs(&[10, 0, 30, 20, 0, 5, 40]);
+        assert_eq!(repair_monotonic(&mut logs, Strategy::ClampToPrevious), 2);
+        assert_eq!(timestamps_of(&logs), [10, 0, 30, 30, 0, 30, 40]);
+        assert_eq!(sessions(&logs), [0, 1, 2, 3, 4, 5, 6]);
+        assert!(BatchTimeReport::analyze(&logs).is_monotonic());
+        assert_eq!(repair_monotonic(&mut logs, Strategy::ClampToPrevious), 0);
+    }
+
+    #[test]
+    fn sorting_leaves_untimed_records_in_place() {
+        let mut logs = logs(&[10, 0, 30, 20, 0, 10, 40]);
+        assert_eq!(repair_monotonic(&mut logs, Strategy::SortStable), 2);
+        assert_eq!(timestamps_of(&logs), [10, 0, 10, 20, 0, 30, 40]);
+        // the two records at 10 keep their order
+        assert_eq!(sessions(&logs), [0, 1, 5, 3, 4, 2, 6]);
+        assert_eq!(repair_monotonic(&mut logs, Strategy::SortStable), 0);
+    }
+
+    #[test]
+    fn the_guard_allows_its_skew_and_no_more() {
+        let guard = ClockSkewGuard {
+            max_skew_millis: 1000,
+            action: SkewAction::Reject,
+        };
+        assert!(guard.check(5000, 4000).is_ok());
+        assert!(guard.check(5000, 9000).is_ok());
+        assert!(guard.check(5000, 0).is_ok());
+        assert!(guard.check(0, 1).is_ok());
+
+        let e = guard.check(5000, 3999).unwrap_err();
+        assert_eq!(
+            e,
+            ClockSkewExceeded {
+                latest: 5000,
+                timestamp: 3999,
+                max_skew_millis: 1000,
+            }
+        );
+        assert_eq!(
+            e.to_string(),
+            "timestamp 3999 is 1001ms behind the latest one 5000, at most 1000ms are allowed"
+        );
+    }
+}
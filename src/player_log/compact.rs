@@ -0,0 +1,533 @@
+//! Holding a batch in memory with every distinct string in it stored once.
+
+use std::collections::HashMap;
+use std::mem::{size_of, size_of_val};
+use std::ops::Range;
+
+use anyhow::{Context, Result};
+
+use super::storage_impl::ByteStorage;
+use super::visit::ServerVersionRef;
+use super::{
+    Extension, GeoInfo, LoginOutcome, PlayerLog, PlayerLogSerializer, SerializerOptions,
+    ServerVersion,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum CompactVersion {
+    Known(u8),
+    // the id of the name in the pool
+    Unknown(u32),
+}
+
+/// A record of a [`CompactBatch`], the strings are ids into its pool.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct CompactRecord {
+    binary_version: u8,
+    flags: u16,
+    player_uuid: Option<[u8; 16]>,
+    player_name: u32,
+    player_ip: [u8; 4],
+    server_ip: [u8; 4],
+    server_port: u16,
+    server_domain: u32,
+    server_version: CompactVersion,
+    timestamp: u64,
+    session_id: u32,
+    outcome: LoginOutcome,
+    geo: Option<GeoInfo>,
+    proxy_ip: Option<[u8; 4]>,
+    // almost always empty, a boxed slice is 8 bytes smaller than a Vec
+    extensions: Box<[Extension]>,
+    client_brand: u32,
+    client_locale: u32,
+}
+
+/// The logs of a batch sharing their player names, server domains and other strings.
+///
+/// A few thousand servers and a few hundred thousand players make up most of any large batch, so
+/// most of what the logs would allocate is never stored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CompactBatch {
+    // every distinct string back to back, `spans` says where each one is
+    pool: Vec<u8>,
+    spans: Vec<Range<u32>>,
+    records: Vec<CompactRecord>,
+}
+
+/// One record of a [`CompactBatch`].
+#[derive(Debug, Clone, Copy)]
+pub struct CompactLog<'a> {
+    batch: &'a CompactBatch,
+    record: &'a CompactRecord,
+}
+
+fn refill<S: ByteStorage>(storage: &mut S, bytes: &[u8]) {
+    storage.clear();
+    storage.extend_from_slice(bytes);
+}
+
+impl<'a> CompactLog<'a> {
+    pub const fn binary_version(&self) -> u8 {
+        self.record.binary_version
+    }
+
+    pub const fn flags(&self) -> u16 {
+        self.record.flags
+    }
+
+    pub const fn player_uuid(&self) -> Option<[u8; 16]> {
+        self.record.player_uuid
+    }
+
+    pub fn player_name(&self) -> &'a [u8] {
+        self.batch.string(self.record.player_name)
+    }
+
+    pub const fn player_ip(&self) -> [u8; 4] {
+        self.record.player_ip
+    }
+
+    pub const fn server_ip(&self) -> [u8; 4] {
+        self.record.server_ip
+    }
+
+    pub const fn server_port(&self) -> u16 {
+        self.record.server_port
+    }
+
+    pub fn server_domain(&self) -> &'a [u8] {
+        self.batch.string(self.record.server_domain)
+    }
+
+    pub fn server_version(&self) -> ServerVersionRef<'a> {
+        match self.record.server_version {
+            CompactVersion::Known(id) => ServerVersionRef::Known(id),
+            CompactVersion::Unknown(name) => ServerVersionRef::Unknown(
+                std::str::from_utf8(self.batch.string(name)).expect("interned from a str"),
+            ),
+        }
+    }
+
+    pub const fn timestamp(&self) -> u64 {
+        self.record.timestamp
+    }
+
+    pub const fn session_id(&self) -> u32 {
+        self.record.session_id
+    }
+
+    pub const fn outcome(&self) -> LoginOutcome {
+        self.record.outcome
+    }
+
+    pub const fn geo(&self) -> Option<GeoInfo> {
+        self.record.geo
+    }
+
+    pub const fn proxy_ip(&self) -> Option<[u8; 4]> {
+        self.record.proxy_ip
+    }
+
+    pub fn extensions(&self) -> &'a [Extension] {
+        &self.record.extensions
+    }
+
+    pub fn client_brand(&self) -> &'a [u8] {
+        self.batch.string(self.record.client_brand)
+    }
+
+    pub fn client_locale(&self) -> &'a [u8] {
+        self.batch.string(self.record.client_locale)
+    }
+
+    pub fn to_log(&self) -> PlayerLog {
+        let mut log = PlayerLog::default();
+        self.to_log_into(&mut log);
+        log
+    }
+
+    /// [`CompactLog::to_log`] into `out`, reusing its buffers.
+    pub fn to_log_into(&self, out: &mut PlayerLog) {
+        out.binary_version = self.binary_version();
+        out.flags = self.flags();
+        out.player_uuid = self.player_uuid();
+        refill(&mut out.player_name, self.player_name());
+        out.player_ip = self.player_ip();
+        out.server_ip = self.server_ip();
+        out.server_port = self.server_port();
+        refill(&mut out.server_domain, self.server_domain());
+        out.server_version = match self.server_version() {
+            ServerVersionRef::Known(id) => ServerVersion::Known(id),
+            ServerVersionRef::Unknown(name) => ServerVersion::Unknown(name.into()),
+        };
+        out.timestamp = self.timestamp();
+        out.session_id = self.session_id();
+        out.outcome = self.outcome();
+        out.geo = self.geo();
+        out.proxy_ip = self.proxy_ip();
+        out.extensions.clear();
+        out.extensions.extend_from_slice(self.extensions());
+        out.client_brand.clear();
+        out.client_brand.extend_from_slice(self.client_brand());
+        out.client_locale.clear();
+        out.client_locale.extend_from_slice(self.client_locale());
+    }
+}
+
+// compares field by field, so nothing is copied out of the pool
+impl PartialEq<PlayerLog> for CompactLog<'_> {
+    fn eq(&self, other: &PlayerLog) -> bool {
+        let PlayerLog {
+            binary_version,
+            flags,
+            player_uuid,
+            player_name,
+            player_ip,
+            server_ip,
+            server_port,
+            server_domain,
+            server_version,
+            timestamp,
+            session_id,
+            outcome,
+            geo,
+            proxy_ip,
+            extensions,
+            client_brand,
+            client_locale,
+        } = other;
+
+        let same_version = match (self.server_version(), server_version) {
+            (ServerVersionRef::Known(a), ServerVersion::Known(b)) => a == *b,
+            (ServerVersionRef::Unknown(a), ServerVersion::Unknown(b)) => a == &**b,
+            _ => false,
+        };
+
+        self.binary_version() == *binary_version
+            && self.flags() == *flags
+            && self.player_uuid() == *player_uuid
+            && self.player_name() == &**player_name
+            && self.player_ip() == *player_ip
+            && self.server_ip() == *server_ip
+            && self.server_port() == *server_port
+            && self.server_domain() == &**server_domain
+            && same_version
+            && self.timestamp() == *timestamp
+            && self.session_id() == *session_id
+            && self.outcome() == *outcome
+            && self.geo() == *geo
+            && self.proxy_ip() == *proxy_ip
+            && self.extensions() == extensions.as_slice()
+            && self.client_brand() == client_brand.as_slice()
+            && self.client_locale() == client_locale.as_slice()
+    }
+}
+
+impl PartialEq<[PlayerLog]> for CompactBatch {
+    fn eq(&self, other: &[PlayerLog]) -> bool {
+        self.len() == other.len() && self.iter().zip(other).all(|(log, other)| log == *other)
+    }
+}
+
+impl PartialEq<Vec<PlayerLog>> for CompactBatch {
+    fn eq(&self, other: &Vec<PlayerLog>) -> bool {
+        *self == **other
+    }
+}
+
+// hands out pool ids, keyed by the bytes of the logs still to be moved in so only strings the
+// pool doesn't have yet are copied
+struct Interner<'a> {
+    ids: HashMap<&'a [u8], u32>,
+    pool: Vec<u8>,
+    spans: Vec<Range<u32>>,
+}
+
+impl<'a> Interner<'a> {
+    fn intern(&mut self, bytes: &'a [u8]) -> u32 {
+        *self.ids.entry(bytes).or_insert_with(|| {
+            let start = self.pool.len() as u32;
+            self.pool.extend_from_slice(bytes);
+            let end = u32::try_from(self.pool.len()).expect("pool under 4 GiB");
+            self.spans.push(start..end);
+            self.spans.len() as u32 - 1
+        })
+    }
+}
+
+impl CompactBatch {
+    /// Moves the logs in, keeping one copy of every distinct string.
+    ///
+    /// # Panics
+    /// If the distinct strings add up to 4 GiB or more.
+    pub fn from_logs(logs: Vec<PlayerLog>) -> Self {
+        let mut interner = Interner {
+            ids: HashMap::new(),
+            pool: Vec::new(),
+            spans: Vec::new(),
+        };
+
+        let strings = logs
+            .iter()
+            .map(|log| {
+                let version = match &log.server_version {
+                    ServerVersion::Known(id) => CompactVersion::Known(*id),
+                    ServerVersion::Unknown(name) => {
+                        CompactVersion::Unknown(interner.intern(name.as_bytes()))
+                    }
+                };
+                (
+                    interner.intern(&log.player_name),
+                    interner.intern(&log.server_domain),
+                    version,
+                    interner.intern(&log.client_brand),
+                    interner.intern(&log.client_locale),
+                )
+            })
+            .collect::<Vec<_>>();
+        let Interner {
+            mut pool,
+            mut spans,
+            ..
+        } = interner;
+
+        let records = logs
+            .into_iter()
+            .zip(strings)
+            .map(|(log, strings)| {
+                // destructured so a new field can't be forgotten
+                let PlayerLog {
+                    binary_version,
+                    flags,
+                    player_uuid,
+                    player_name: _,
+                    player_ip,
+                    server_ip,
+                    server_port,
+                    server_domain: _,
+                    server_version: _,
+                    timestamp,
+                    session_id,
+                    outcome,
+                    geo,
+                    proxy_ip,
+                    extensions,
+                    client_brand: _,
+                    client_locale: _,
+                } = log;
+                let (player_name, server_domain, server_version, client_brand, client_locale) =
+                    strings;
+
+                CompactRecord {
+                    binary_version,
+                    flags,
+                    player_uuid,
+                    player_name,
+                    player_ip,
+                    server_ip,
+                    server_port,
+                    server_domain,
+                    server_version,
+                    timestamp,
+                    session_id,
+                    outcome,
+                    geo,
+                    proxy_ip,
+                    extensions: extensions.into_boxed_slice(),
+                    client_brand,
+                    client_locale,
+                }
+            })
+            .collect();
+
+        pool.shrink_to_fit();
+        spans.shrink_to_fit();
+        Self {
+            pool,
+            spans,
+            records,
+        }
+    }
+
+    fn string(&self, id: u32) -> &[u8] {
+        let span = &self.spans[id as usize];
+        &self.pool[span.start as usize..span.end as usize]
+    }
+
+    pub const fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// How many distinct strings the pool holds, one used in several fields counts once.
+    pub const fn distinct_strings(&self) -> usize {
+        self.spans.len()
+    }
+
+    pub fn get(&self, index: usize) -> Option<CompactLog<'_>> {
+        self.records.get(index).map(|record| CompactLog {
+            batch: self,
+            record,
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = CompactLog<'_>> + '_ {
+        self.records.iter().map(|record| CompactLog {
+            batch: self,
+            record,
+        })
+    }
+
+    /// Copies every string out into independent logs.
+    pub fn to_logs(&self) -> Vec<PlayerLog> {
+        self.iter().map(|log| log.to_log()).collect()
+    }
+
+    /// Bytes the batch takes up in memory, counted like [`PlayerLog::memory_size`] so the two
+    /// compare.
+    pub fn memory_usage(&self) -> usize {
+        let extensions = self
+            .records
+            .iter()
+            .flat_map(|record| record.extensions.iter())
+            .map(|extension| size_of_val(extension) + extension.data.len())
+            .sum::<usize>();
+
+        size_of::<Self>()
+            + self.pool.len()
+            + self.spans.len() * size_of::<Range<u32>>()
+            + self.records.len() * size_of::<CompactRecord>()
+            + extensions
+    }
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::serialize_many_with_options`] of the logs of `batch`, each one
+    /// written through the same reused [`PlayerLog`] instead of expanding the whole batch.
+    pub fn serialize_compact(batch: &CompactBatch, options: &SerializerOptions) -> Result<Vec<u8>> {
+        let mut payload = Vec::new();
+        let mut scratch = PlayerLog::default();
+        for (i, log) in batch.iter().enumerate() {
+            log.to_log_into(&mut scratch);
+            scratch
+                .serialize_with_endianness(&mut payload, options.endianness)
+                .with_context(|| format!("record {i}"))?;
+        }
+
+        Self::write_container(&payload, batch.len() as u64, options, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::storage_impl::{DomainBytes, NameBytes};
+    use super::super::{Codec, Endianness};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn compact_batches_hold_the_same_logs() {
+        let logs = logs(500);
+        let batch = CompactBatch::from_logs(logs.clone());
+
+        assert_eq!(batch.len(), 500);
+        assert_eq!(batch, logs);
+        assert_eq!(batch.to_logs(), logs);
+        assert_eq!(batch.get(7).unwrap().to_log(), logs[7]);
+        assert!(batch.get(500).is_none());
+        assert!(CompactBatch::from_logs(Vec::new()).is_empty());
+
+        let mut changed = logs.clone();
+        changed[3].session_id ^= 1;
+        assert!(batch != changed);
+        assert!(batch != logs[..499]);
+    }
+
+    #[test]
+    fn every_distinct_string_is_stored_once() {
+        let mut log = logs(1).remove(0);
+        log.client_brand = b"vanilla".to_vec();
+        log.client_locale = b"en_us".to_vec();
+        let mut other = log.clone();
+        // the name doubles as the domain of the other log
+        other.server_domain = DomainBytes::from_slice(&log.player_name);
+        other.client_brand.clear();
+
+        let batch = CompactBatch::from_logs(vec![log.clone(), log.clone(), other.clone()]);
+        let unknown = usize::from(matches!(log.server_version, ServerVersion::Unknown(_)));
+        // name, domain, brand, locale, the empty brand and the version name if any
+        assert_eq!(batch.distinct_strings(), 5 + unknown);
+        assert_eq!(batch, vec![log.clone(), log, other]);
+    }
+
+    #[test]
+    fn repeated_strings_take_less_memory_than_the_logs() {
+        let logs = logs(2_000);
+        let expanded = logs.iter().map(PlayerLog::memory_size).sum::<usize>();
+        let batch = CompactBatch::from_logs(logs);
+        assert!(
+            batch.memory_usage() < expanded,
+            "{} vs {expanded}",
+            batch.memory_usage()
+        );
+    }
+
+    #[test]
+    fn reused_logs_are_fully_overwritten() {
+        let logs = logs(2);
+        let mut first = logs[0].clone();
+        first.player_name = NameBytes::from_slice(b"a_much_longer_nm");
+        first.server_version = ServerVersion::Unknown("24w14potato".into());
+        first.extensions = vec![Extension {
+            kind: 1,
+            data: vec![1, 2, 3],
+        }];
+        first.client_brand = b"fabric".to_vec();
+        first.client_locale = b"de_de".to_vec();
+        let mut second = logs[1].clone();
+        second.extensions.clear();
+        second.client_brand.clear();
+        second.client_locale.clear();
+
+        let batch = CompactBatch::from_logs(vec![first.clone(), second.clone()]);
+        let mut scratch = PlayerLog::default();
+        batch.get(0).unwrap().to_log_into(&mut scratch);
+        assert_eq!(scratch, first);
+        batch.get(1).unwrap().to_log_into(&mut scratch);
+        assert_eq!(scratch, second);
+    }
+
+    #[test]
+    fn compact_batches_serialize_like_their_logs() {
+        let logs = logs(500);
+        let batch = CompactBatch::from_logs(logs.clone());
+        for codec in [Codec::None, Codec::Zlib(Compression::default()), Codec::Lz4] {
+            for endianness in [Endianness::Big, Endianness::Little] {
+                let options = SerializerOptions {
+                    codec,
+                    endianness,
+                    ..SerializerOptions::default()
+                };
+                assert_eq!(
+                    PlayerLogSerializer::serialize_compact(&batch, &options).unwrap(),
+                    PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap(),
+                    "{codec:?} {endianness:?}"
+                );
+            }
+        }
+    }
+}
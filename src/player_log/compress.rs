@@ -0,0 +1,285 @@
+use std::io::{Read, Seek, SeekFrom, Write};
+
+use anyhow::{bail, Context, Result};
+use byteorder::{LittleEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{PlayerLog, PlayerLogSerializer};
+
+// https://github.com/facebook/zstd/blob/dev/contrib/seekable_format/zstd_seekable_compression_format.md
+const SKIPPABLE_MAGIC: u32 = 0x184D_2A5E;
+const SEEKABLE_MAGIC: u32 = 0x8F92_EAB1;
+// skippable magic + frame size
+const SKIPPABLE_HEADER_SIZE: u64 = 4 + 4;
+// frame count + descriptor + seekable magic
+const SEEK_TABLE_FOOTER_SIZE: u64 = 4 + 1 + 4;
+// compressed size + decompressed size, the optional checksum isn't written
+const SEEK_ENTRY_SIZE: u64 = 4 + 4;
+const CHECKSUM_FLAG: u8 = 1 << 7;
+// bits 2 to 6 of the descriptor are reserved and have to be zero
+const RESERVED_BITS: u8 = 0b0111_1100;
+
+/// The most a single frame may decompress to, [`ZstdSeekableReader`] rejects a seek table that
+/// claims more so a corrupt entry can't make it allocate gigabytes.
+pub const MAX_FRAME_LEN: usize = 64 << 20;
+
+/// Writes independently compressed zstd frames followed by a seek table, in the zstd seekable
+/// format so the stock `zstd` tools can still decompress the whole file.
+pub struct ZstdSeekableWriter<W: Write> {
+    writer: W,
+    level: i32,
+    // compressed and decompressed size of every frame
+    frames: Vec<(u32, u32)>,
+}
+
+impl<W: Write> ZstdSeekableWriter<W> {
+    pub const fn new(writer: W) -> Self {
+        Self::with_level(writer, zstd::DEFAULT_COMPRESSION_LEVEL)
+    }
+
+    pub const fn with_level(writer: W, level: i32) -> Self {
+        Self {
+            writer,
+            level,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Compresses `data` into a frame of its own, readable back on its own. Fails if `data` is
+    /// over [`MAX_FRAME_LEN`].
+    pub fn write_frame(&mut self, data: &[u8]) -> Result<()> {
+        if data.len() > MAX_FRAME_LEN {
+            bail!("frame of {} bytes is over {MAX_FRAME_LEN}", data.len());
+        }
+        let decompressed_len = data.len() as u32;
+        let compressed = zstd::bulk::compress(data, self.level)?;
+
+        self.writer.write_all(&compressed)?;
+        self.frames
+            .push((compressed.len() as u32, decompressed_len));
+
+        Ok(())
+    }
+
+    /// Writes the seek table and hands back the writer.
+    pub fn finish(mut self) -> Result<W> {
+        let table_len = self.frames.len() as u64 * SEEK_ENTRY_SIZE + SEEK_TABLE_FOOTER_SIZE;
+
+        self.writer.write_u32::<LittleEndian>(SKIPPABLE_MAGIC)?;
+        self.writer.write_u32::<LittleEndian>(table_len as u32)?;
+        for &(compressed_len, decompressed_len) in &self.frames {
+            self.writer.write_u32::<LittleEndian>(compressed_len)?;
+            self.writer.write_u32::<LittleEndian>(decompressed_len)?;
+        }
+        self.writer
+            .write_u32::<LittleEndian>(self.frames.len() as u32)?;
+        self.writer.write_u8(0)?;
+        self.writer.write_u32::<LittleEndian>(SEEKABLE_MAGIC)?;
+
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Reads single frames of a [`ZstdSeekableWriter`] file, or any other zstd seekable file.
+pub struct ZstdSeekableReader<R: Read + Seek> {
+    reader: R,
+    // byte offset, compressed size and decompressed size of every frame
+    frames: Vec<(u64, u32, u32)>,
+    // where the frames end and the seek table starts
+    table_start: u64,
+}
+
+impl<R: Read + Seek> ZstdSeekableReader<R> {
+    /// Reads the seek table from the end of `reader`.
+    pub fn new(mut reader: R) -> Result<Self> {
+        let end = reader.seek(SeekFrom::End(0))?;
+        if end < SKIPPABLE_HEADER_SIZE + SEEK_TABLE_FOOTER_SIZE {
+            bail!("too short for a seek table");
+        }
+
+        reader.seek(SeekFrom::Start(end - SEEK_TABLE_FOOTER_SIZE))?;
+        let frame_count = reader.read_u32::<LittleEndian>()? as u64;
+        let descriptor = reader.read_u8()?;
+        if reader.read_u32::<LittleEndian>()? != SEEKABLE_MAGIC {
+            bail!("invalid seekable magic");
+        }
+        if descriptor & RESERVED_BITS != 0 {
+            bail!("reserved seek table descriptor bits set");
+        }
+
+        let has_checksums = descriptor & CHECKSUM_FLAG != 0;
+        let entry_size = SEEK_ENTRY_SIZE + if has_checksums { 4 } else { 0 };
+        let table_len = frame_count * entry_size + SEEK_TABLE_FOOTER_SIZE;
+        let table_start = end
+            .checked_sub(SKIPPABLE_HEADER_SIZE + table_len)
+            .context("seek table overruns the file")?;
+
+        reader.seek(SeekFrom::Start(table_start))?;
+        if reader.read_u32::<LittleEndian>()? != SKIPPABLE_MAGIC {
+            bail!("seek table isn't in a skippable frame");
+        }
+        if u64::from(reader.read_u32::<LittleEndian>()?) != table_len {
+            bail!("seek table frame size doesn't match its frame count");
+        }
+
+        let mut offset = 0;
+        let mut frames = Vec::with_capacity(frame_count as usize);
+        for _ in 0..frame_count {
+            let compressed_len = reader.read_u32::<LittleEndian>()?;
+            let decompressed_len = reader.read_u32::<LittleEndian>()?;
+            if has_checksums {
+                reader.read_u32::<LittleEndian>()?;
+            }
+
+            if decompressed_len as usize > MAX_FRAME_LEN {
+                bail!(
+                    "frame {} claims {decompressed_len} decompressed bytes, over {MAX_FRAME_LEN}",
+                    frames.len()
+                );
+            }
+
+            frames.push((offset, compressed_len, decompressed_len));
+            offset += u64::from(compressed_len);
+        }
+
+        if offset != table_start {
+            bail!("frame sizes don't add up to the start of the seek table");
+        }
+
+        Ok(Self {
+            reader,
+            frames,
+            table_start,
+        })
+    }
+
+    pub const fn frame_count(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Decompresses frame `n` without touching any other frame.
+    pub fn read_frame(&mut self, n: usize) -> Result<Vec<u8>> {
+        let &(offset, compressed_len, decompressed_len) = self
+            .frames
+            .get(n)
+            .with_context(|| format!("frame {n} out of range, there are {}", self.frames.len()))?;
+
+        // checked by new already, but the sizes are about to be allocated
+        if offset + u64::from(compressed_len) > self.table_start {
+            bail!("frame {n} runs past the start of the seek table");
+        }
+        if decompressed_len as usize > MAX_FRAME_LEN {
+            bail!("frame {n} claims {decompressed_len} decompressed bytes, over {MAX_FRAME_LEN}");
+        }
+
+        self.reader.seek(SeekFrom::Start(offset))?;
+        let mut compressed = vec![0; compressed_len as usize];
+        self.reader.read_exact(&mut compressed)?;
+
+        let data = zstd::bulk::decompress(&compressed, decompressed_len as usize)
+            .with_context(|| format!("corrupt frame {n}"))?;
+        if data.len() != decompressed_len as usize {
+            bail!(
+                "frame {n} decompressed to {} bytes, expected {decompressed_len}",
+                data.len()
+            );
+        }
+
+        Ok(data)
+    }
+}
+
+impl PlayerLogSerializer {
+    /// Writes `logs` as one zstd seekable frame per `records_per_block` records, so any block can
+    /// be read with [`PlayerLogSerializer::deserialize_seekable_block`] without decoding the rest.
+    pub fn serialize_blocked_seekable<W: Write>(
+        logs: &[PlayerLog],
+        records_per_block: usize,
+        writer: W,
+    ) -> Result<W> {
+        if records_per_block == 0 {
+            bail!("records_per_block must be at least 1");
+        }
+
+        let mut writer = ZstdSeekableWriter::new(writer);
+        let mut raw = Vec::new();
+        for block in logs.chunks(records_per_block) {
+            raw.clear();
+            block.iter().try_for_each(|log| log.serialize(&mut raw))?;
+            writer.write_frame(&raw)?;
+        }
+
+        writer.finish()
+    }
+
+    pub fn deserialize_seekable_block<R: Read + Seek>(
+        reader: &mut ZstdSeekableReader<R>,
+        n: usize,
+    ) -> Result<Vec<PlayerLog>> {
+        let raw = reader.read_frame(n)?;
+
+        let mut records = raw.as_slice();
+        let mut logs = Vec::new();
+        while !records.is_empty() {
+            logs.push(PlayerLog::deserialize(&mut records)?);
+        }
+
+        Ok(logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn seekable(frames: &[&[u8]]) -> Vec<u8> {
+        let mut writer = ZstdSeekableWriter::new(Vec::new());
+        for frame in frames {
+            writer.write_frame(frame).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    // the compressed and decompressed size of the last frame
+    fn last_entry(file: &mut [u8]) -> &mut [u8] {
+        let end = file.len() - SEEK_TABLE_FOOTER_SIZE as usize;
+        &mut file[end - SEEK_ENTRY_SIZE as usize..end]
+    }
+
+    #[test]
+    fn frames_read_back_on_their_own() {
+        let file = seekable(&[b"first frame", b"", b"third"]);
+        let mut reader = ZstdSeekableReader::new(Cursor::new(file)).unwrap();
+
+        assert_eq!(reader.frame_count(), 3);
+        assert_eq!(reader.read_frame(2).unwrap(), b"third");
+        assert_eq!(reader.read_frame(0).unwrap(), b"first frame");
+        assert_eq!(reader.read_frame(1).unwrap(), b"");
+        assert!(reader.read_frame(3).is_err());
+    }
+
+    #[test]
+    fn an_oversized_decompressed_length_is_rejected() {
+        let mut file = seekable(&[b"frame"]);
+        last_entry(&mut file)[4..].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        let e = ZstdSeekableReader::new(Cursor::new(file)).err().unwrap();
+        assert!(e.to_string().contains("decompressed bytes"), "{e}");
+    }
+
+    #[test]
+    fn a_compressed_length_past_the_frames_is_rejected() {
+        let mut file = seekable(&[b"frame"]);
+        last_entry(&mut file)[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+
+        assert!(ZstdSeekableReader::new(Cursor::new(file)).is_err());
+    }
+
+    #[test]
+    fn frames_over_the_limit_are_not_written() {
+        let mut writer = ZstdSeekableWriter::new(Vec::new());
+        assert!(writer.write_frame(&vec![0; MAX_FRAME_LEN + 1]).is_err());
+    }
+}
@@ -0,0 +1,1054 @@
+//! A second, deliberately plain decoder for the record and batch formats.
+//!
+//! It is written straight from the wire layout without sharing any code with
+//! [`PlayerLog::deserialize`] or [`PlayerLogSerializer`], so running both over the same inputs
+//! catches drift between the spec and the implementation. Every record both agree on is also
+//! run through the no_std encoder in [`crate::wire`].
+//!
+//! [`check_determinism`] pins what the serializer writes for a seeded batch to [`GOLDEN_HASH`].
+
+use std::fmt;
+use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::slice;
+
+use anyhow::{bail, Result};
+use flate2::read::ZlibDecoder;
+use flate2::Compression;
+use lz4_flex::frame::FrameDecoder;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+
+use super::dictionary::DomainDictionary;
+use super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+use super::{
+    BuildOptions, Codec, Extension, GeoInfo, LogEventType, LogFlags, LoginOutcome, PlayerLog,
+    PlayerLogBuilder, PlayerLogSerializer, SerializerOptions, ServerVersion,
+};
+use crate::log_generator_seeded;
+use crate::wire::{self, Extensions, RecordFields, VersionField};
+
+struct Cursor<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn take(&mut self, len: usize, field: &str) -> Result<&'a [u8]> {
+        if self.data.len() - self.pos < len {
+            bail!(
+                "{field} needs {len} bytes at offset {} but only {} are left",
+                self.pos,
+                self.data.len() - self.pos
+            );
+        }
+
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self, field: &str) -> Result<u8> {
+        Ok(self.take(1, field)?[0])
+    }
+}
+
+/// Decodes one record from the start of `data`, returning it and the number of bytes it used.
+pub fn reference_decode_record(data: &[u8]) -> Result<(PlayerLog, usize)> {
+    let mut cursor = Cursor { data, pos: 0 };
+
+    let binary_version = cursor.byte("binary_version")?;
+    if binary_version != 1 && binary_version != 2 {
+        bail!("unknown binary_version {binary_version}");
+    }
+
+    // bit 0 is player auth, bit 1 is online, bit 3 is whitelist, bit 4 is cracked client and bit 5
+    // redacted.
+    // version 2 widens flags to two bytes, bit 8 says the port is written and bit 9 that the
+    // domain is left out because it's the dotted server ip
+    let flags = if binary_version == 2 {
+        let flags = cursor.take(2, "flags")?;
+        (flags[0] as u16) << 8 | flags[1] as u16
+    } else {
+        cursor.byte("flags")? as u16
+    };
+    if flags & !0b11_1111_1111_1011 != 0 {
+        bail!("undefined flag bits in {flags:#018b}");
+    }
+
+    let player_uuid = if flags & 0b10 != 0 {
+        let mut uuid = [0; 16];
+        uuid.copy_from_slice(cursor.take(16, "player_uuid")?);
+        Some(uuid)
+    } else {
+        None
+    };
+
+    let name_len = cursor.byte("player_name length")? as usize;
+    let max_name_len = if binary_version == 2 { 32 } else { 16 };
+    if name_len > max_name_len {
+        bail!("player_name of {name_len} bytes, at most {max_name_len} are allowed");
+    }
+    let player_name = cursor.take(name_len, "player_name")?.to_vec();
+
+    let mut player_ip = [0; 4];
+    player_ip.copy_from_slice(cursor.take(4, "player_ip")?);
+
+    let mut server_ip = [0; 4];
+    server_ip.copy_from_slice(cursor.take(4, "server_ip")?);
+
+    // version 2 leaves out the default port
+    let server_port = if binary_version == 1 || flags & 1 << 8 != 0 {
+        let port = cursor.take(2, "server_port")?;
+        (port[0] as u16) << 8 | port[1] as u16
+    } else {
+        25565
+    };
+
+    let server_domain = if binary_version == 2 && flags & 1 << 9 != 0 {
+        format!(
+            "{}.{}.{}.{}",
+            server_ip[0], server_ip[1], server_ip[2], server_ip[3]
+        )
+        .into_bytes()
+    } else {
+        let domain_len = cursor.byte("server_domain length")? as usize;
+        cursor.take(domain_len, "server_domain")?.to_vec()
+    };
+
+    // under version 2, id 255 is followed by the length prefixed name of a version newer than
+    // the VERSIONS table, at most 32 bytes of utf8
+    let server_version = match cursor.byte("server_version")? {
+        255 if binary_version == 2 => {
+            let name_len = cursor.byte("server_version name length")? as usize;
+            if name_len > 32 {
+                bail!("server version name of {name_len} bytes, at most 32 are allowed");
+            }
+            let name = cursor.take(name_len, "server_version name")?;
+            match std::str::from_utf8(name) {
+                Ok(name) => ServerVersion::Unknown(name.into()),
+                Err(e) => bail!("server version name isn't utf8: {e}"),
+            }
+        }
+        id => ServerVersion::Known(id),
+    };
+
+    // version 2 appends a timestamp, a session id, the client brand and locale when flag bit 10
+    // is set, and up to 8 extensions of up to 64 bytes
+    let mut timestamp = 0;
+    let mut session_id = 0;
+    let mut outcome = LoginOutcome::Success;
+    let mut geo = None;
+    let mut proxy_ip = None;
+    let mut client_brand = Vec::new();
+    let mut client_locale = Vec::new();
+    let mut extensions = Vec::new();
+    if binary_version == 2 {
+        for b in cursor.take(8, "timestamp")? {
+            timestamp = timestamp << 8 | *b as u64;
+        }
+        for b in cursor.take(4, "session_id")? {
+            session_id = session_id << 8 | *b as u32;
+        }
+
+        // unknown outcomes are kept rather than rejected
+        if flags & 1 << 11 != 0 {
+            outcome = match cursor.byte("outcome")? {
+                0 => LoginOutcome::Success,
+                1 => LoginOutcome::Banned,
+                2 => LoginOutcome::NotWhitelisted,
+                3 => LoginOutcome::Throttled,
+                4 => LoginOutcome::InvalidSession,
+                other => LoginOutcome::Other(other),
+            };
+        }
+        if flags & 1 << 12 != 0 {
+            let country = cursor.take(2, "country")?;
+            let mut asn = 0;
+            for b in cursor.take(4, "asn")? {
+                asn = asn << 8 | *b as u32;
+            }
+            geo = Some(GeoInfo {
+                country: [country[0], country[1]],
+                asn,
+            });
+        }
+        if flags & 1 << 13 != 0 {
+            let ip = cursor.take(4, "proxy_ip")?;
+            proxy_ip = Some([ip[0], ip[1], ip[2], ip[3]]);
+        }
+        if flags & 1 << 10 != 0 {
+            let brand_len = cursor.byte("client_brand length")? as usize;
+            if brand_len > 64 {
+                bail!("client brand of {brand_len} bytes, at most 64 are allowed");
+            }
+            client_brand = cursor.take(brand_len, "client_brand")?.to_vec();
+
+            let locale_len = cursor.byte("client_locale length")? as usize;
+            if locale_len > 16 {
+                bail!("client locale of {locale_len} bytes, at most 16 are allowed");
+            }
+            client_locale = cursor.take(locale_len, "client_locale")?.to_vec();
+        }
+
+        let extension_count = cursor.byte("extension count")?;
+        if extension_count > 8 {
+            bail!("{extension_count} extensions, at most 8 are allowed");
+        }
+
+        for _ in 0..extension_count {
+            let kind = cursor.byte("extension kind")?;
+            let len = cursor.byte("extension length")? as usize;
+            if len > 64 {
+                bail!("extension of {len} bytes, at most 64 are allowed");
+            }
+
+            let data = cursor.take(len, "extension data")?.to_vec();
+            extensions.push(Extension { kind, data });
+        }
+    }
+
+    let log = PlayerLog {
+        binary_version,
+        flags,
+        player_uuid,
+        player_name: NameBytes::from_vec(player_name),
+        player_ip,
+        server_ip,
+        server_port,
+        server_domain: DomainBytes::from_vec(server_domain),
+        server_version,
+        timestamp,
+        session_id,
+        outcome,
+        geo,
+        proxy_ip,
+        extensions,
+        client_brand,
+        client_locale,
+    };
+
+    Ok((log, cursor.pos))
+}
+
+/// Encodes a record byte by byte from the wire layout.
+pub fn reference_encode_record(log: &PlayerLog) -> Vec<u8> {
+    let mut out = vec![log.binary_version];
+    if log.binary_version == 2 {
+        out.extend_from_slice(&log.flags.to_be_bytes());
+    } else {
+        out.push(log.flags as u8);
+    }
+
+    if let Some(uuid) = log.player_uuid.filter(|_| log.flags & 0b10 != 0) {
+        out.extend_from_slice(&uuid);
+    }
+
+    out.push(log.player_name.len() as u8);
+    out.extend_from_slice(&log.player_name);
+    out.extend_from_slice(&log.player_ip);
+    out.extend_from_slice(&log.server_ip);
+    if log.binary_version == 1 || log.flags & 1 << 8 != 0 {
+        out.extend_from_slice(&log.server_port.to_be_bytes());
+    }
+    if log.binary_version == 1 || log.flags & 1 << 9 == 0 {
+        out.push(log.server_domain.len() as u8);
+        out.extend_from_slice(&log.server_domain);
+    }
+    match &log.server_version {
+        ServerVersion::Known(id) => out.push(*id),
+        ServerVersion::Unknown(name) => {
+            out.push(255);
+            out.push(name.len() as u8);
+            out.extend_from_slice(name.as_bytes());
+        }
+    }
+
+    if log.binary_version == 2 {
+        out.extend_from_slice(&log.timestamp.to_be_bytes());
+        out.extend_from_slice(&log.session_id.to_be_bytes());
+        if log.flags & 1 << 11 != 0 {
+            out.push(match log.outcome {
+                LoginOutcome::Success => 0,
+                LoginOutcome::Banned => 1,
+                LoginOutcome::NotWhitelisted => 2,
+                LoginOutcome::Throttled => 3,
+                LoginOutcome::InvalidSession => 4,
+                LoginOutcome::Other(other) => other,
+            });
+        }
+        if let Some(geo) = log.geo.filter(|_| log.flags & 1 << 12 != 0) {
+            out.extend_from_slice(&geo.country);
+            out.extend_from_slice(&geo.asn.to_be_bytes());
+        }
+        if let Some(proxy_ip) = log.proxy_ip.filter(|_| log.flags & 1 << 13 != 0) {
+            out.extend_from_slice(&proxy_ip);
+        }
+        if log.flags & 1 << 10 != 0 {
+            out.push(log.client_brand.len() as u8);
+            out.extend_from_slice(&log.client_brand);
+            out.push(log.client_locale.len() as u8);
+            out.extend_from_slice(&log.client_locale);
+        }
+        out.push(log.extensions.len() as u8);
+        for extension in &log.extensions {
+            out.push(extension.kind);
+            out.push(extension.data.len() as u8);
+            out.extend_from_slice(&extension.data);
+        }
+    }
+
+    out
+}
+
+/// Decodes a whole batch, returning every record with its offset in the uncompressed payload.
+pub fn reference_decode_batch(data: &[u8]) -> Result<Vec<(usize, PlayerLog)>> {
+    let mut cursor = Cursor { data, pos: 0 };
+
+    if cursor.take(4, "magic")? != b"PLGB" {
+        bail!("bad magic");
+    }
+
+    let container_version = cursor.byte("container_version")?;
+    if container_version != 1 {
+        bail!("unknown container_version {container_version}");
+    }
+
+    let body_len = cursor.take(2, "body_len")?;
+    let body_len = (body_len[0] as usize) << 8 | body_len[1] as usize;
+    // codec + batch id + record count + producer length
+    if body_len < 1 + 16 + 8 + 1 {
+        bail!("body_len {body_len} is too short");
+    }
+
+    let mut body = Cursor {
+        data: cursor.take(body_len, "header body")?,
+        pos: 0,
+    };
+
+    let codec = body.byte("codec")?;
+    body.take(16, "batch_id")?;
+
+    let record_count = body
+        .take(8, "record_count")?
+        .iter()
+        .fold(0u64, |count, b| count << 8 | *b as u64);
+
+    let producer_len = body.byte("producer length")? as usize;
+    if std::str::from_utf8(body.take(producer_len, "producer")?).is_err() {
+        bail!("producer is not utf-8");
+    }
+
+    let compressed = &data[cursor.pos..];
+    let mut payload = Vec::new();
+    match codec {
+        0 => payload.extend_from_slice(compressed),
+        1 => {
+            ZlibDecoder::new(compressed).read_to_end(&mut payload)?;
+        }
+        2 => {
+            FrameDecoder::new(compressed).read_to_end(&mut payload)?;
+        }
+        _ => bail!("unknown codec {codec}"),
+    }
+
+    // exactly record_count records, with nothing after them
+    let mut records = Vec::new();
+    let mut offset = 0;
+    for i in 0..record_count {
+        match reference_decode_record(&payload[offset..]) {
+            Ok((log, len)) => {
+                records.push((offset, log));
+                offset += len;
+            }
+            Err(e) => bail!("record {i} at payload offset {offset}: {e}"),
+        }
+    }
+    if offset != payload.len() {
+        bail!("{} bytes after the last record", payload.len() - offset);
+    }
+
+    Ok(records)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Divergence {
+    /// Exactly one of the decoders rejected the input.
+    Acceptance {
+        reference: Result<(), String>,
+        implementation: Result<(), String>,
+    },
+    RecordCount {
+        reference: usize,
+        implementation: usize,
+    },
+    /// `byte` is the offset in the uncompressed payload of the first byte the two decoded
+    /// records disagree on.
+    Record {
+        index: usize,
+        byte: usize,
+        reference: Box<PlayerLog>,
+        implementation: Box<PlayerLog>,
+    },
+    /// The [`crate::wire`] encoder or decoder disagrees with [`PlayerLog`] on a record.
+    Wire { index: usize, detail: String },
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Acceptance {
+                reference,
+                implementation,
+            } => write!(
+                f,
+                "reference decoder: {}, implementation: {}",
+                reference.as_ref().err().map_or("ok", String::as_str),
+                implementation.as_ref().err().map_or("ok", String::as_str)
+            ),
+            Self::RecordCount {
+                reference,
+                implementation,
+            } => write!(
+                f,
+                "reference decoded {reference} records, implementation decoded {implementation}"
+            ),
+            Self::Record {
+                index,
+                byte,
+                reference,
+                implementation,
+            } => write!(
+                f,
+                "record {index} differs from payload byte {byte}: reference {reference:?}, implementation {implementation:?}"
+            ),
+            Self::Wire { index, detail } => write!(f, "record {index}: {detail}"),
+        }
+    }
+}
+
+/// Decodes `data` as a batch with both decoders and reports the first place they disagree.
+pub fn cross_check_batch(data: &[u8]) -> Option<Divergence> {
+    let reference = reference_decode_batch(data);
+    let implementation = PlayerLogSerializer::deserialize_many(data);
+
+    let (reference, implementation) = match (reference, implementation) {
+        (Ok(reference), Ok(implementation)) => (reference, implementation),
+        (Err(_), Err(_)) => return None,
+        (reference, implementation) => {
+            return Some(Divergence::Acceptance {
+                reference: reference.map(|_| ()).map_err(|e| format!("{e:#}")),
+                implementation: implementation.map(|_| ()).map_err(|e| format!("{e:#}")),
+            })
+        }
+    };
+
+    if reference.len() != implementation.len() {
+        return Some(Divergence::RecordCount {
+            reference: reference.len(),
+            implementation: implementation.len(),
+        });
+    }
+
+    let mismatch = reference
+        .into_iter()
+        .zip(&implementation)
+        .enumerate()
+        .find(|(_, ((_, reference), implementation))| reference != *implementation)
+        .map(|(index, ((offset, reference), implementation))| {
+            let reference_bytes = reference_encode_record(&reference);
+            let implementation_bytes = reference_encode_record(implementation);
+            let byte = reference_bytes
+                .iter()
+                .zip(&implementation_bytes)
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| reference_bytes.len().min(implementation_bytes.len()));
+
+            Divergence::Record {
+                index,
+                byte: offset + byte,
+                reference: Box::new(reference),
+                implementation: Box::new(implementation.clone()),
+            }
+        });
+    if mismatch.is_some() {
+        return mismatch;
+    }
+
+    implementation.iter().enumerate().find_map(|(index, log)| {
+        cross_check_wire(log)
+            .err()
+            .map(|detail| Divergence::Wire { index, detail })
+    })
+}
+
+/// Encodes `log` with both [`PlayerLog::serialize`] and [`wire::encode_record`], then decodes the
+/// result with [`wire::decode_record`].
+pub fn cross_check_wire(log: &PlayerLog) -> Result<(), String> {
+    let mut expected = Vec::new();
+    log.serialize(&mut expected)
+        .map_err(|e| format!("std encoder: {e:#}"))?;
+
+    let mut extensions = Vec::new();
+    let fields = wire_fields(log, &mut extensions)?;
+
+    let mut out = [0; PlayerLog::MAX_ENCODED_SIZE];
+    let len = wire::encode_record(fields, &mut out).map_err(|e| format!("wire encoder: {e}"))?;
+    if out[..len] != expected {
+        return Err("wire encoder output differs from PlayerLog::serialize".to_string());
+    }
+
+    match wire::decode_record(&out[..len]) {
+        Ok((decoded, used)) if decoded == fields && used == len => Ok(()),
+        Ok((decoded, _)) => Err(format!("wire decoder read back {decoded:?}")),
+        Err(e) => Err(format!("wire decoder: {e}")),
+    }
+}
+
+/// The [`wire::encode_record`] fields of `log`, its extensions are encoded into `extensions`.
+pub fn wire_fields<'a>(
+    log: &'a PlayerLog,
+    extensions: &'a mut Vec<u8>,
+) -> Result<RecordFields<'a>, String> {
+    extensions.clear();
+    for extension in &log.extensions {
+        extensions.push(extension.kind);
+        extensions.push(extension.data.len() as u8);
+        extensions.extend_from_slice(&extension.data);
+    }
+    let extensions: &'a [u8] = extensions;
+
+    let domain_is_ip = log.binary_version >= 2
+        && LogFlags::from_bits_retain(log.flags).contains(LogFlags::DOMAIN_IS_IP);
+    Ok(RecordFields {
+        binary_version: log.binary_version,
+        flags: log.flags,
+        player_uuid: log.player_uuid.as_ref(),
+        player_name: &log.player_name,
+        player_ip: log.player_ip,
+        server_ip: log.server_ip,
+        server_port: log.server_port,
+        server_domain: if domain_is_ip {
+            &[]
+        } else {
+            &log.server_domain
+        },
+        server_version: match &log.server_version {
+            ServerVersion::Known(id) => VersionField::Known(*id),
+            ServerVersion::Unknown(name) => VersionField::Unknown(name),
+        },
+        timestamp: log.timestamp,
+        session_id: log.session_id,
+        outcome: log.outcome.to_byte(),
+        geo: log.geo.map(|geo| (geo.country, geo.asn)),
+        proxy_ip: log.proxy_ip,
+        client_brand: &log.client_brand,
+        client_locale: &log.client_locale,
+        extensions: Extensions::from_wire(extensions).map_err(|e| format!("extensions: {e}"))?,
+    })
+}
+
+fn vector_log(online: bool, name: &str, domain_len: usize, version: &str) -> PlayerLog {
+    PlayerLogBuilder {
+        flags: if online {
+            LogFlags::IS_ONLINE | LogFlags::PLAYER_AUTH
+        } else {
+            LogFlags::empty()
+        },
+        player_uuid: online.then(|| uuid::Uuid::from_bytes([0xAB; 16])),
+        player_name: name.to_string(),
+        player_ip: [10, 0, 0, 1].into(),
+        server_ip: [192, 168, 1, 254].into(),
+        server_port: 25565,
+        server_domain: "d".repeat(domain_len),
+        server_version: version.to_string(),
+        timestamp: None,
+        session_id: 0,
+        extensions: Vec::new(),
+        client_brand: None,
+        client_locale: None,
+        outcome: LoginOutcome::Success,
+        geo: None,
+        proxy_ip: None,
+    }
+    .build()
+    .expect("coverage vector logs are valid")
+}
+
+fn v2_log(extensions: usize, extension_len: usize) -> PlayerLog {
+    let mut builder = PlayerLogBuilder::from_log(&vector_log(true, "Notch", 11, "1.21"))
+        .expect("coverage vector logs are valid");
+    builder.timestamp = Some(1_700_000_000_000);
+    builder.session_id = 0xDEAD_BEEF;
+    builder.extensions = (0..extensions)
+        .map(|kind| Extension {
+            kind: kind as u8,
+            data: vec![0xE0 | kind as u8; extension_len],
+        })
+        .collect();
+
+    builder.build().expect("coverage vector logs are valid")
+}
+
+fn batch(logs: &[PlayerLog], codec: Codec, producer: Option<&str>) -> Vec<u8> {
+    let options = SerializerOptions {
+        codec,
+        batch_id: Some([0x42; 16]),
+        producer: producer.map(str::to_string),
+        ..SerializerOptions::default()
+    };
+
+    PlayerLogSerializer::serialize_many_with_options(logs, &options)
+        .expect("coverage vector batches are valid")
+}
+
+/// Named batches that exercise every field boundary, codec and header path, including inputs
+/// both decoders have to reject.
+pub fn coverage_vectors() -> Vec<(&'static str, Vec<u8>)> {
+    let online = vector_log(true, "Notch", 11, "1.8");
+    let offline = vector_log(false, "a", 0, "1.21");
+    let longest = vector_log(true, "ABCDEFGHIJKLMNOP", PlayerLog::MAX_DOMAIN_LEN, "1.12");
+    let empty_name = vector_log(false, "", 1, "1.16");
+    let logs = [online.clone(), offline.clone(), longest, empty_name];
+
+    let plain = batch(&logs, Codec::None, None);
+
+    let mut trailing_header = plain.clone();
+    trailing_header[6] += 3;
+    for _ in 0..3 {
+        trailing_header.insert(33, 0xEE);
+    }
+
+    // written before build dropped nil uuids, readers still have to take it as it is
+    let mut nil_uuid = online.clone();
+    nil_uuid.player_uuid = Some([0; 16]);
+
+    let mut flagged = vector_log(false, "cracked", 3, "1.19");
+    flagged.flags =
+        (LogFlags::CRACKED_CLIENT | LogFlags::SERVER_WHITELIST | LogFlags::REDACTED).bits();
+
+    // every combination of the flags that don't follow from the fields, the rest are covered by
+    // the records carrying those fields
+    let independent = [
+        LogFlags::PLAYER_AUTH,
+        LogFlags::SERVER_WHITELIST,
+        LogFlags::CRACKED_CLIENT,
+        LogFlags::REDACTED,
+    ];
+    let flag_combinations = (0..1 << independent.len())
+        .map(|mask| {
+            let mut log = offline.clone();
+            for (i, flag) in independent.iter().enumerate() {
+                if mask & 1 << i != 0 {
+                    log.flags |= flag.bits();
+                }
+            }
+            log
+        })
+        .collect::<Vec<_>>();
+
+    // bits 6 and 7 are the event type
+    let event_types = [
+        LogEventType::Login,
+        LogEventType::Disconnect,
+        LogEventType::Ping,
+        LogEventType::ServerListPing,
+    ]
+    .map(|event_type| {
+        let mut log = offline.clone();
+        log.flags |= event_type.bits();
+        log
+    });
+
+    let mut bad_flags = batch(slice::from_ref(&offline), Codec::None, None);
+    bad_flags[34] = 0b100;
+
+    let mut bad_binary_version = batch(slice::from_ref(&offline), Codec::None, None);
+    bad_binary_version[33] = 3;
+
+    let v2 = v2_log(1, 2);
+    let v2_max = v2_log(PlayerLog::MAX_EXTENSIONS, PlayerLog::MAX_EXTENSION_LEN);
+    let mut compact = PlayerLogBuilder::from_log(&v2).expect("coverage vector logs are valid");
+    compact.server_domain = compact.server_ip.to_string();
+    let compact = compact.build().expect("coverage vector logs are valid");
+
+    let mut ip_port = PlayerLogBuilder::from_log(&compact).expect("coverage vector logs are valid");
+    ip_port.server_port = 8080;
+    let ip_port = ip_port.build().expect("coverage vector logs are valid");
+
+    let mut client_info = PlayerLogBuilder::from_log(&v2).expect("coverage vector logs are valid");
+    client_info.client_brand = Some("fabric".to_string());
+    client_info.client_locale = Some("en_US".to_string());
+    let client_info = client_info.build().expect("coverage vector logs are valid");
+
+    let mut client_info_max =
+        PlayerLogBuilder::from_log(&v2_max).expect("coverage vector logs are valid");
+    client_info_max.client_brand = Some("b".repeat(PlayerLog::MAX_CLIENT_BRAND_LEN));
+    client_info_max.client_locale = Some("l".repeat(PlayerLog::MAX_CLIENT_LOCALE_LEN));
+    let client_info_max = client_info_max
+        .build()
+        .expect("coverage vector logs are valid");
+
+    let mut brand_only = PlayerLogBuilder::from_log(&v2).expect("coverage vector logs are valid");
+    brand_only.client_brand = Some("vanilla".to_string());
+    let brand_only = brand_only.build().expect("coverage vector logs are valid");
+
+    // the brand length takes the place of the extension count of the plain v2 record
+    let brand_len_at = 33 + v2.serialized_size() - 5;
+    let mut long_brand = batch(slice::from_ref(&client_info), Codec::None, None);
+    long_brand[brand_len_at] = PlayerLog::MAX_CLIENT_BRAND_LEN as u8 + 1;
+
+    let outcomes = [
+        LoginOutcome::Success,
+        LoginOutcome::Banned,
+        LoginOutcome::NotWhitelisted,
+        LoginOutcome::Throttled,
+        LoginOutcome::InvalidSession,
+        LoginOutcome::Other(200),
+    ]
+    .map(|outcome| {
+        let mut builder = PlayerLogBuilder::from_log(&v2).expect("coverage vector logs are valid");
+        builder.outcome = outcome;
+        builder.build().expect("coverage vector logs are valid")
+    });
+
+    let mut geo = PlayerLogBuilder::from_log(&client_info).expect("coverage vector logs are valid");
+    geo.outcome = LoginOutcome::Banned;
+    geo.geo = Some(GeoInfo {
+        country: *b"NL",
+        asn: u32::MAX,
+    });
+    let geo = geo.build().expect("coverage vector logs are valid");
+
+    let mut proxied = PlayerLogBuilder::from_log(&geo).expect("coverage vector logs are valid");
+    proxied.proxy_ip = Some([172, 16, 0, 2].into());
+    let proxied = proxied.build().expect("coverage vector logs are valid");
+    let mut proxied_only = PlayerLogBuilder::from_log(&v2).expect("coverage vector logs are valid");
+    proxied_only.proxy_ip = Some([172, 16, 0, 3].into());
+    let proxied_only = proxied_only
+        .build()
+        .expect("coverage vector logs are valid");
+
+    let unknown_versions = ["1.22-pre3", &"9".repeat(ServerVersion::MAX_UNKNOWN_LEN)].map(|name| {
+        let mut builder =
+            PlayerLogBuilder::from_log(&offline).expect("coverage vector logs are valid");
+        builder.server_version = name.to_string();
+        builder.build().expect("coverage vector logs are valid")
+    });
+
+    let v2_mixed = [online.clone(), v2.clone(), offline.clone(), v2_max.clone()];
+    let client_info_mixed = [
+        v2.clone(),
+        client_info.clone(),
+        online.clone(),
+        brand_only,
+        client_info_max.clone(),
+    ];
+
+    // the record ends with the extension count, kind, length and two data bytes
+    let count_at = 33 + v2.serialized_size() - 5;
+    let mut too_many_extensions = batch(slice::from_ref(&v2), Codec::None, None);
+    too_many_extensions[count_at] = PlayerLog::MAX_EXTENSIONS as u8 + 1;
+
+    let mut long_extension = batch(slice::from_ref(&v2), Codec::None, None);
+    long_extension[count_at + 2] = PlayerLog::MAX_EXTENSION_LEN as u8 + 1;
+
+    let mut v2_as_v1 = batch(slice::from_ref(&v2), Codec::None, None);
+    v2_as_v1[33] = 1;
+
+    let mut overcounted = plain.clone();
+    overcounted[31] += 1;
+
+    let mut trailing_payload = batch(slice::from_ref(&online), Codec::None, None);
+    trailing_payload.extend_from_slice(&[1, 2, 3]);
+
+    let mut bad_codec = plain.clone();
+    bad_codec[7] = 9;
+
+    // 16 characters and 20 bytes, only fits under version 2
+    let long_name = PlayerLogBuilder::from_log(&offline)
+        .and_then(|mut builder| {
+            builder.player_name = "§a§l§o§nNotch_Xx".to_string();
+            builder.build_with_options(&BuildOptions {
+                allow_long_name_bytes: true,
+                ..BuildOptions::default()
+            })
+        })
+        .expect("coverage vector logs are valid");
+
+    // the name length follows the version and flags of an offline record
+    let mut v1_name_too_long = batch(slice::from_ref(&offline), Codec::None, None);
+    v1_name_too_long[35] = PlayerLog::MAX_NAME_LEN as u8 + 1;
+    let mut v2_name_too_long = batch(slice::from_ref(&long_name), Codec::None, None);
+    v2_name_too_long[36] = PlayerLog::MAX_LONG_NAME_LEN as u8 + 1;
+
+    let mut bad_magic = plain.clone();
+    bad_magic[0] = b'X';
+
+    vec![
+        ("empty batch", batch(&[], Codec::None, None)),
+        (
+            "empty zlib batch",
+            batch(&[], Codec::Zlib(Compression::best()), None),
+        ),
+        ("empty lz4 batch", batch(&[], Codec::Lz4, None)),
+        (
+            "empty batch with a producer",
+            batch(&[], Codec::None, Some("conformance")),
+        ),
+        (
+            "online record",
+            batch(slice::from_ref(&online), Codec::None, None),
+        ),
+        ("offline record", batch(&[offline], Codec::None, None)),
+        ("nil uuid record", batch(&[nil_uuid], Codec::None, None)),
+        ("field boundaries", plain.clone()),
+        ("every flag", batch(&[flagged], Codec::None, None)),
+        (
+            "flag combinations",
+            batch(&flag_combinations, Codec::None, None),
+        ),
+        ("every event type", batch(&event_types, Codec::None, None)),
+        ("v2 record", batch(slice::from_ref(&v2), Codec::None, None)),
+        (
+            "v2 default port and ip domain",
+            batch(&[compact], Codec::None, None),
+        ),
+        (
+            "v2 ip domain with a port",
+            batch(&[ip_port], Codec::None, None),
+        ),
+        ("v2 extension limits", batch(&[v2_max], Codec::None, None)),
+        ("mixed versions", batch(&v2_mixed, Codec::Lz4, None)),
+        ("every login outcome", batch(&outcomes, Codec::None, None)),
+        (
+            "unknown server versions",
+            batch(&unknown_versions, Codec::None, None),
+        ),
+        (
+            "geo with outcome and client info",
+            batch(&[v2.clone(), geo, v2.clone()], Codec::None, None),
+        ),
+        (
+            "proxied records",
+            batch(&[proxied, v2.clone(), proxied_only], Codec::None, None),
+        ),
+        ("client info", batch(&[client_info], Codec::None, None)),
+        (
+            "client info limits",
+            batch(&[client_info_max], Codec::None, None),
+        ),
+        (
+            "mixed client info",
+            batch(&client_info_mixed, Codec::Zlib(Compression::fast()), None),
+        ),
+        (
+            "multi-byte name",
+            batch(&[long_name, online.clone()], Codec::None, None),
+        ),
+        ("v1 name too long", v1_name_too_long),
+        ("v2 name too long", v2_name_too_long),
+        ("client brand too long", long_brand),
+        ("too many extensions", too_many_extensions),
+        ("extension too long", long_extension),
+        ("v2 fields under binary_version 1", v2_as_v1),
+        ("zlib", batch(&logs, Codec::Zlib(Compression::best()), None)),
+        ("lz4", batch(&logs, Codec::Lz4, None)),
+        (
+            "single zlib record",
+            batch(
+                slice::from_ref(&online),
+                Codec::Zlib(Compression::none()),
+                None,
+            ),
+        ),
+        (
+            "single lz4 record",
+            batch(slice::from_ref(&online), Codec::Lz4, None),
+        ),
+        ("producer", batch(&logs, Codec::None, Some("conformance"))),
+        ("unknown header bytes", trailing_header),
+        ("trailing payload bytes", trailing_payload),
+        ("truncated record", plain[..plain.len() - 1].to_vec()),
+        ("truncated header", plain[..20].to_vec()),
+        ("more records than payload", overcounted),
+        ("undefined flag bits", bad_flags),
+        ("unknown binary version", bad_binary_version),
+        ("unknown codec", bad_codec),
+        ("bad magic", bad_magic),
+    ]
+}
+
+/// Cross-checks the coverage vectors, then `random_batches` generated batches along with a
+/// corrupted and a truncated copy of each, returning every divergence found.
+pub fn run(seed: u64, random_batches: usize) -> Vec<(String, Divergence)> {
+    let mut divergences = coverage_vectors()
+        .into_iter()
+        .filter_map(|(name, data)| cross_check_batch(&data).map(|d| (name.to_string(), d)))
+        .collect::<Vec<_>>();
+
+    let rng = &mut StdRng::seed_from_u64(seed);
+    for i in 0..random_batches {
+        let logs = (0..rng.gen_range(0..200))
+            .map(|_| log_generator_seeded(rng).build())
+            .collect::<Result<Vec<_>>>()
+            .expect("generated logs are valid");
+
+        let codec = match rng.gen_range(0..3) {
+            0 => Codec::None,
+            1 => Codec::Zlib(Compression::new(rng.gen_range(0..10))),
+            _ => Codec::Lz4,
+        };
+
+        let data = batch(&logs, codec, None);
+
+        let mut corrupted = data.clone();
+        let at = rng.gen_range(0..corrupted.len());
+        corrupted[at] ^= 1 << rng.gen_range(0..8);
+
+        let truncated = &data[..rng.gen_range(0..data.len())];
+
+        for (variant, data) in [
+            ("", &data[..]),
+            (" corrupted", &corrupted),
+            (" truncated", truncated),
+        ] {
+            if let Some(divergence) = cross_check_batch(data) {
+                divergences.push((format!("random batch {i}{variant}"), divergence));
+            }
+        }
+    }
+
+    divergences
+}
+
+/// Cross-checks every file in `dir`, e.g. a fuzzing corpus.
+pub fn run_corpus(dir: &Path) -> Result<Vec<(PathBuf, Divergence)>> {
+    let mut paths = fs::read_dir(dir)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    paths.sort();
+
+    let mut divergences = Vec::new();
+    for path in paths {
+        if path.is_file() {
+            if let Some(divergence) = cross_check_batch(&fs::read(&path)?) {
+                divergences.push((path, divergence));
+            }
+        }
+    }
+
+    Ok(divergences)
+}
+
+const GOLDEN_SEED: u64 = 0x504C_4742;
+const GOLDEN_LOGS: usize = 2000;
+
+/// Sha256 over the [`check_determinism`] batches. It only changes with the format, the generator
+/// or the compression crates, never with the platform or the number of threads.
+pub const GOLDEN_HASH: [u8; 32] = [
+    0x46, 0xea, 0x2a, 0x7e, 0xea, 0x13, 0x47, 0xae, 0x73, 0xa4, 0xdf, 0xbd, 0x26, 0xca, 0xd5, 0x46,
+    0xac, 0x13, 0x55, 0x70, 0x9c, 0x4c, 0x4e, 0xb8, 0xbe, 0x13, 0x7d, 0x84, 0xfa, 0x5f, 0xe2, 0x45,
+];
+
+// every codec and the dictionary batch, concatenated
+fn golden_batches() -> Result<Vec<u8>> {
+    let rng = &mut StdRng::seed_from_u64(GOLDEN_SEED);
+    let logs = (0..GOLDEN_LOGS)
+        .map(|_| log_generator_seeded(rng).build())
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut out = Vec::new();
+    for codec in [Codec::None, Codec::Zlib(Compression::new(6)), Codec::Lz4] {
+        let options = SerializerOptions {
+            codec,
+            ..SerializerOptions::default()
+        };
+        out.extend(PlayerLogSerializer::serialize_many_with_options(
+            &logs, &options,
+        )?);
+    }
+
+    let dict = DomainDictionary::build(logs.iter().step_by(3).map(|log| &*log.server_domain))?;
+    out.extend(dict.to_bytes());
+    out.extend(PlayerLogSerializer::serialize_many_with_dict(&logs, &dict)?);
+
+    Ok(out)
+}
+
+#[cfg(feature = "parallel")]
+fn single_threaded<T: Send>(f: impl FnOnce() -> T + Send) -> Result<T> {
+    Ok(rayon::ThreadPoolBuilder::new()
+        .num_threads(1)
+        .build()?
+        .install(f))
+}
+
+#[cfg(not(feature = "parallel"))]
+fn single_threaded<T>(f: impl FnOnce() -> T) -> Result<T> {
+    Ok(f())
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// Serializes the same seeded logs on a single thread and on the global rayon pool, the output has
+/// to be byte for byte the same and hash to [`GOLDEN_HASH`].
+///
+/// Without the `parallel` feature there is only the one thread, the hash is what ties the two
+/// builds together.
+pub fn check_determinism() -> Result<()> {
+    let single_thread = single_threaded(golden_batches)??;
+
+    #[cfg(feature = "parallel")]
+    {
+        let global_pool = golden_batches()?;
+        if single_thread != global_pool {
+            let at = single_thread
+                .iter()
+                .zip(&global_pool)
+                .position(|(a, b)| a != b)
+                .unwrap_or_else(|| single_thread.len().min(global_pool.len()));
+            bail!(
+                "serializing on one thread and on {} differs at byte {at}",
+                rayon::current_num_threads()
+            );
+        }
+    }
+
+    let hash: [u8; 32] = Sha256::digest(&single_thread).into();
+    if hash != GOLDEN_HASH {
+        bail!(
+            "golden batches hash to {}, expected {}",
+            hex(&hash),
+            hex(&GOLDEN_HASH)
+        );
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn the_reference_decoder_agrees() {
+        assert_eq!(run(0, 200), []);
+    }
+
+    #[test]
+    fn serializing_is_thread_count_independent() {
+        check_determinism().unwrap();
+    }
+
+    // what ties a build without the parallel feature to one with it
+    #[test]
+    fn golden_batches_hash_to_the_golden_hash() {
+        let hash: [u8; 32] = Sha256::digest(golden_batches().unwrap()).into();
+        assert_eq!(hex(&hash), hex(&GOLDEN_HASH));
+    }
+}
@@ -0,0 +1,90 @@
+//! Field checks for logs that didn't come from the builder, e.g. decoded ones or ones put
+//! together by hand.
+
+use std::fmt;
+
+use super::{tombstone, validate_flags, LogFlags, PlayerLog, ServerVersion};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConsistencyError {
+    InvalidVersion(u8),
+    UnknownFlags(u16),
+    NameTooLong(usize),
+    DomainTooLong(usize),
+    /// A known id missing from [`VERSIONS`](super::VERSIONS), or an unknown name that's empty or
+    /// too long.
+    InvalidServerVersion(ServerVersion),
+    OnlineWithoutUuid,
+    UuidWhileOffline,
+    /// An extension of a kind only [tombstones and gaps](super::tombstone) may carry.
+    ReservedExtension(u8),
+}
+
+impl fmt::Display for ConsistencyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidVersion(version) => write!(f, "invalid binary version {version}"),
+            Self::UnknownFlags(flags) => write!(f, "unknown flags {flags:#06x}"),
+            Self::NameTooLong(len) => write!(f, "player name of {len} bytes"),
+            Self::DomainTooLong(len) => write!(f, "server domain of {len} bytes"),
+            Self::InvalidServerVersion(version) => write!(f, "invalid server version {version:?}"),
+            Self::OnlineWithoutUuid => f.write_str("IS_ONLINE without a uuid"),
+            Self::UuidWhileOffline => f.write_str("uuid without IS_ONLINE"),
+            Self::ReservedExtension(kind) => write!(f, "reserved extension kind {kind:#04x}"),
+        }
+    }
+}
+
+impl std::error::Error for ConsistencyError {}
+
+impl PlayerLog {
+    /// Every violation at once, not just the first.
+    pub fn validate_consistency(&self) -> Result<(), Vec<ConsistencyError>> {
+        let mut errors = Vec::new();
+
+        if !(1..=Self::CURRENT_VERSION).contains(&self.binary_version) {
+            errors.push(ConsistencyError::InvalidVersion(self.binary_version));
+        }
+        if validate_flags(self.flags).is_err() {
+            errors.push(ConsistencyError::UnknownFlags(self.flags));
+        }
+        if self.player_name.len() > Self::max_name_len(self.binary_version) {
+            errors.push(ConsistencyError::NameTooLong(self.player_name.len()));
+        }
+        if self.server_domain.len() > Self::MAX_DOMAIN_LEN {
+            errors.push(ConsistencyError::DomainTooLong(self.server_domain.len()));
+        }
+
+        let version_valid = match &self.server_version {
+            ServerVersion::Known(_) => self.server_version.name().is_some(),
+            ServerVersion::Unknown(name) => {
+                !name.is_empty() && name.len() <= ServerVersion::MAX_UNKNOWN_LEN
+            }
+        };
+        if !version_valid {
+            errors.push(ConsistencyError::InvalidServerVersion(
+                self.server_version.clone(),
+            ));
+        }
+
+        let online = LogFlags::from_bits_retain(self.flags).contains(LogFlags::IS_ONLINE);
+        match (online, self.player_uuid.is_some()) {
+            (true, false) => errors.push(ConsistencyError::OnlineWithoutUuid),
+            (false, true) => errors.push(ConsistencyError::UuidWhileOffline),
+            _ => {}
+        }
+
+        errors.extend(
+            self.extensions
+                .iter()
+                .filter(|e| tombstone::is_reserved(e.kind))
+                .map(|e| ConsistencyError::ReservedExtension(e.kind)),
+        );
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+}
@@ -0,0 +1,361 @@
+//! Domains shared across batches.
+//!
+//! A batch written with [`PlayerLogSerializer::serialize_many_with_dict`] stores a u16 index in
+//! place of every domain the dictionary has, and records which dictionary it needs in its header.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt, WriteBytesExt};
+use sha2::{Digest, Sha256};
+
+use super::storage_impl::{ByteStorage, DomainBytes};
+use super::{Endianness, PlayerLog, PlayerLogSerializer, SerializerOptions};
+
+pub const DICTIONARY_MAGIC: [u8; 4] = *b"PLGD";
+
+// written in place of an index when the domain is inline in the record
+const MISS: u16 = u16::MAX;
+
+/// Identifies the first `len` entries of a dictionary, so a dictionary that only had entries
+/// appended can still read batches written against the older one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DictionaryId {
+    pub len: u16,
+    pub hash: [u8; 16],
+}
+
+impl fmt::Display for DictionaryId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for b in self.hash {
+            write!(f, "{b:02x}")?;
+        }
+        write!(f, " ({} entries)", self.len)
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DomainDictionary {
+    domains: Vec<Vec<u8>>,
+    indexes: HashMap<Vec<u8>, u16>,
+}
+
+impl DomainDictionary {
+    /// One index short of u16, that one marks an inline domain.
+    pub const MAX_ENTRIES: usize = MISS as usize;
+
+    /// Indexes domains in the order they're first seen, duplicates are skipped.
+    pub fn build<I, D>(domains: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = D>,
+        D: AsRef<[u8]>,
+    {
+        Self::default().extend(domains)
+    }
+
+    /// A new dictionary with `domains` appended, every existing entry keeps its index.
+    pub fn extend<I, D>(&self, domains: I) -> Result<Self>
+    where
+        I: IntoIterator<Item = D>,
+        D: AsRef<[u8]>,
+    {
+        let mut dict = self.clone();
+        for domain in domains {
+            let domain = domain.as_ref();
+            if dict.indexes.contains_key(domain) {
+                continue;
+            }
+
+            if domain.len() > PlayerLog::MAX_DOMAIN_LEN {
+                bail!("domain {} too long", String::from_utf8_lossy(domain));
+            }
+            if dict.domains.len() == Self::MAX_ENTRIES {
+                bail!("more than {} domains", Self::MAX_ENTRIES);
+            }
+
+            dict.indexes
+                .insert(domain.to_vec(), dict.domains.len() as u16);
+            dict.domains.push(domain.to_vec());
+        }
+
+        Ok(dict)
+    }
+
+    pub fn index_of(&self, domain: &[u8]) -> Option<u16> {
+        self.indexes.get(domain).copied()
+    }
+
+    pub fn get(&self, index: u16) -> Option<&[u8]> {
+        self.domains.get(index as usize).map(Vec::as_slice)
+    }
+
+    pub const fn len(&self) -> usize {
+        self.domains.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.domains.is_empty()
+    }
+
+    pub fn id(&self) -> DictionaryId {
+        self.id_of_prefix(self.domains.len())
+    }
+
+    fn id_of_prefix(&self, len: usize) -> DictionaryId {
+        let mut hasher = Sha256::new();
+        for domain in &self.domains[..len] {
+            hasher.update([domain.len() as u8]);
+            hasher.update(domain);
+        }
+
+        let mut hash = [0; 16];
+        hash.copy_from_slice(&hasher.finalize()[..16]);
+        DictionaryId {
+            len: len as u16,
+            hash,
+        }
+    }
+
+    /// Whether batches written against `id` can be read with this dictionary.
+    pub fn covers(&self, id: DictionaryId) -> bool {
+        id.len as usize <= self.domains.len() && self.id_of_prefix(id.len as usize) == id
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(
+            DICTIONARY_MAGIC.len() + 2 + self.domains.iter().map(|d| 1 + d.len()).sum::<usize>(),
+        );
+        out.extend_from_slice(&DICTIONARY_MAGIC);
+        out.extend_from_slice(&(self.domains.len() as u16).to_be_bytes());
+        for domain in &self.domains {
+            out.push(domain.len() as u8);
+            out.extend_from_slice(domain);
+        }
+        out
+    }
+
+    pub fn from_bytes(mut data: &[u8]) -> Result<Self> {
+        if !data.starts_with(&DICTIONARY_MAGIC) {
+            bail!("invalid dictionary magic");
+        }
+        data = &data[DICTIONARY_MAGIC.len()..];
+
+        let count = data
+            .read_u16::<BigEndian>()
+            .context("truncated dictionary")?;
+        let mut domains = Vec::with_capacity(count as usize);
+        for i in 0..count {
+            let len = data
+                .read_u8()
+                .with_context(|| format!("truncated dictionary at entry {i}"))?
+                as usize;
+            if data.len() < len {
+                bail!("truncated dictionary at entry {i}");
+            }
+            domains.push(&data[..len]);
+            data = &data[len..];
+        }
+
+        if !data.is_empty() {
+            bail!("{} bytes after the last dictionary entry", data.len());
+        }
+
+        let dict = Self::build(&domains)?;
+        if dict.len() != domains.len() {
+            bail!("duplicate dictionary entries");
+        }
+        Ok(dict)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        Self::from_bytes(&fs::read(path)?).with_context(|| path.display().to_string())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // write then rename so a crash can't leave a half written dictionary
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, self.to_bytes())?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+}
+
+impl PlayerLogSerializer {
+    /// Like [`PlayerLogSerializer::serialize_many`], every record is preceded by the dictionary
+    /// index of its domain and only keeps the domain inline when the dictionary doesn't have it.
+    pub fn serialize_many_with_dict(
+        logs: &[PlayerLog],
+        dict: &DomainDictionary,
+    ) -> Result<Vec<u8>> {
+        let mut payload =
+            Vec::with_capacity(logs.iter().map(|log| 2 + log.serialized_size()).sum());
+
+        for log in logs {
+            // domains that are the server ip aren't written anyway
+            match dict
+                .index_of(&log.server_domain)
+                .filter(|_| log.writes_domain())
+            {
+                Some(index) => {
+                    payload.write_u16::<BigEndian>(index)?;
+                    log.serialize_with_domain::<BigEndian, _>(&mut payload, &[])?;
+                }
+                None => {
+                    payload.write_u16::<BigEndian>(MISS)?;
+                    log.serialize(&mut payload)?;
+                }
+            }
+        }
+
+        Self::write_container(
+            &payload,
+            logs.len() as u64,
+            &SerializerOptions::default(),
+            Some(dict.id()),
+        )
+    }
+
+    /// Reads a batch written by [`PlayerLogSerializer::serialize_many_with_dict`] with `dict` or
+    /// any dictionary extended from it. Batches without a dictionary are read as usual.
+    pub fn deserialize_many_with_dict(
+        data: &[u8],
+        dict: &DomainDictionary,
+    ) -> Result<Vec<PlayerLog>> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        let Some(id) = header.dictionary else {
+            return Self::deserialize_many(data);
+        };
+
+        if !dict.covers(id) {
+            bail!("batch needs domain dictionary {id}, got {}", dict.id());
+        }
+        let mut reader = header.codec.decoder(&data[header_len..]);
+        let logs = (0..header.record_count)
+            .map(|i| -> Result<PlayerLog> {
+                let index = match header.endianness {
+                    Endianness::Big => reader.read_u16::<BigEndian>()?,
+                    Endianness::Little => reader.read_u16::<LittleEndian>()?,
+                };
+                let mut log =
+                    PlayerLog::deserialize_with_endianness(&mut reader, header.endianness)?;
+
+                if index != MISS {
+                    if index >= id.len {
+                        bail!(
+                            "record {i} has domain index {index}, the dictionary has {} entries",
+                            id.len
+                        );
+                    }
+                    if !log.server_domain.is_empty() {
+                        bail!("record {i} has both a domain index and an inline domain");
+                    }
+                    log.server_domain = DomainBytes::from_slice(&dict.domains[index as usize]);
+                }
+                Ok(log)
+            })
+            .filter(|log| !log.as_ref().is_ok_and(PlayerLog::is_marker))
+            .collect::<Result<Vec<_>>>()?;
+
+        super::check_payload_end(&mut reader)?;
+
+        Ok(logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn batches_read_back_with_the_dictionary_or_an_extension_of_it() {
+        let logs = logs(100);
+        // half the domains, the other half stay inline
+        let dict =
+            DomainDictionary::build(logs.iter().step_by(2).map(|log| &*log.server_domain)).unwrap();
+        let data = PlayerLogSerializer::serialize_many_with_dict(&logs, &dict).unwrap();
+        assert_eq!(
+            PlayerLogSerializer::probe(&data).unwrap().dictionary,
+            Some(dict.id())
+        );
+
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_with_dict(&data, &dict).unwrap(),
+            logs
+        );
+        let extended = dict.extend([b"new.example.com"]).unwrap();
+        assert!(extended.covers(dict.id()));
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_with_dict(&data, &extended).unwrap(),
+            logs
+        );
+
+        let other = DomainDictionary::build([b"other.example.com"]).unwrap();
+        assert!(!other.covers(dict.id()));
+        assert!(PlayerLogSerializer::deserialize_many_with_dict(&data, &other).is_err());
+        let e = PlayerLogSerializer::deserialize_many(&data).unwrap_err();
+        assert!(e.to_string().contains("needs domain dictionary"), "{e}");
+
+        // plain batches don't need one
+        let plain = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        assert!(data.len() < plain.len());
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_with_dict(&plain, &other).unwrap(),
+            logs
+        );
+    }
+
+    #[test]
+    fn dictionaries_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("domains.plgd");
+        let dict =
+            DomainDictionary::build(["a.example.com", "b.example.com", "a.example.com"]).unwrap();
+        assert_eq!(dict.len(), 2);
+        assert_eq!(dict.index_of(b"b.example.com"), Some(1));
+
+        dict.save(&path).unwrap();
+        assert_eq!(DomainDictionary::load(&path).unwrap(), dict);
+        assert_eq!(
+            DomainDictionary::from_bytes(&dict.to_bytes()).unwrap().id(),
+            dict.id()
+        );
+    }
+
+    #[test]
+    fn malformed_dictionaries_fail() {
+        let bytes = DomainDictionary::build(["a.example.com", "b.example.com"])
+            .unwrap()
+            .to_bytes();
+
+        for cut in 0..bytes.len() {
+            assert!(
+                DomainDictionary::from_bytes(&bytes[..cut]).is_err(),
+                "{cut}"
+            );
+        }
+        let mut trailing = bytes;
+        trailing.push(0);
+        assert!(DomainDictionary::from_bytes(&trailing).is_err());
+
+        let mut duplicate = DICTIONARY_MAGIC.to_vec();
+        duplicate.extend([0, 2, 1, b'a', 1, b'a']);
+        let e = DomainDictionary::from_bytes(&duplicate).unwrap_err();
+        assert!(e.to_string().contains("duplicate"), "{e}");
+
+        assert!(DomainDictionary::build([[b'd'; PlayerLog::MAX_DOMAIN_LEN + 1]]).is_err());
+    }
+}
@@ -0,0 +1,147 @@
+//! A patch turning one batch of logs into another, for syncing a copy that already has most of
+//! them.
+//!
+//! The patch is a sequence of frames, each an op byte followed by its data. `ADD` carries a
+//! serialized record and `KEEP` the [`content_hash`] of a baseline record, in the order of the
+//! updated logs. The `REMOVE` frames for the baseline records that are gone come last. A baseline
+//! holding the same log twice has two records with that hash, each op uses up one of them.
+
+use std::collections::{HashMap, VecDeque};
+use std::io::{ErrorKind, Read, Write};
+
+use anyhow::{anyhow, bail, Context, Result};
+
+use super::verify::content_hash;
+use super::PlayerLog;
+
+const ADD: u8 = 0;
+const REMOVE: u8 = 1;
+const KEEP: u8 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PatchStats {
+    pub added: usize,
+    pub removed: usize,
+    pub kept: usize,
+    pub patch_bytes: usize,
+}
+
+/// Keeps every log of `updated` that's in `baseline`, even if it moved.
+pub fn write_patch(
+    baseline: &[PlayerLog],
+    updated: &[PlayerLog],
+    mut writer: impl Write,
+) -> Result<PatchStats> {
+    let mut unused = fingerprints(baseline);
+    let mut stats = PatchStats::default();
+    let mut record = Vec::with_capacity(PlayerLog::MAX_ENCODED_SIZE);
+
+    for log in updated {
+        let fingerprint = content_hash(log);
+        if unused
+            .get_mut(&fingerprint)
+            .and_then(VecDeque::pop_front)
+            .is_some()
+        {
+            write_fingerprint(&mut writer, KEEP, &fingerprint)?;
+            stats.kept += 1;
+            stats.patch_bytes += 1 + fingerprint.len();
+        } else {
+            record.clear();
+            log.serialize(&mut record)?;
+            writer.write_all(&[ADD])?;
+            writer.write_all(&record)?;
+            stats.added += 1;
+            stats.patch_bytes += 1 + record.len();
+        }
+    }
+
+    // what's left is removed, in baseline order so the same input always gives the same patch
+    let mut removed = unused
+        .into_iter()
+        .flat_map(|(fingerprint, indices)| indices.into_iter().map(move |i| (i, fingerprint)))
+        .collect::<Vec<_>>();
+    removed.sort_unstable_by_key(|&(i, _)| i);
+    for (_, fingerprint) in removed {
+        write_fingerprint(&mut writer, REMOVE, &fingerprint)?;
+        stats.removed += 1;
+        stats.patch_bytes += 1 + fingerprint.len();
+    }
+
+    writer.flush()?;
+    Ok(stats)
+}
+
+/// Rebuilds the updated logs from `baseline` and a patch from [`write_patch`].
+///
+/// Fails if the patch wasn't written against this baseline, i.e. an op names a record it doesn't
+/// have or a record is neither kept nor removed.
+pub fn apply_patch(baseline: &[PlayerLog], mut reader: impl Read) -> Result<Vec<PlayerLog>> {
+    let mut unused = fingerprints(baseline);
+    let mut logs = Vec::with_capacity(baseline.len());
+
+    for frame in 0.. {
+        let Some(op) = read_op(&mut reader)? else {
+            break;
+        };
+
+        let result = match op {
+            ADD => PlayerLog::deserialize(&mut reader).map(|log| logs.push(log)),
+            KEEP => take(&mut reader, &mut unused).map(|i| logs.push(baseline[i].clone())),
+            REMOVE => take(&mut reader, &mut unused).map(drop),
+            _ => Err(anyhow!("unknown op {op}")),
+        };
+        result.with_context(|| format!("frame {frame}"))?;
+    }
+
+    let left = unused.values().map(VecDeque::len).sum::<usize>();
+    if left > 0 {
+        bail!("{left} baseline records are neither kept nor removed by the patch");
+    }
+
+    Ok(logs)
+}
+
+// every fingerprint with the baseline indices that have it, in order
+fn fingerprints(baseline: &[PlayerLog]) -> HashMap<[u8; 32], VecDeque<usize>> {
+    let mut fingerprints = HashMap::<_, VecDeque<_>>::with_capacity(baseline.len());
+    for (i, log) in baseline.iter().enumerate() {
+        fingerprints
+            .entry(content_hash(log))
+            .or_default()
+            .push_back(i);
+    }
+
+    fingerprints
+}
+
+// uses up the baseline record the fingerprint read from `reader` names
+fn take<R: Read>(reader: &mut R, unused: &mut HashMap<[u8; 32], VecDeque<usize>>) -> Result<usize> {
+    let mut fingerprint = [0; 32];
+    reader.read_exact(&mut fingerprint)?;
+
+    unused
+        .get_mut(&fingerprint)
+        .and_then(VecDeque::pop_front)
+        .context("the baseline has no record left with this fingerprint")
+}
+
+fn write_fingerprint<W: Write>(writer: &mut W, op: u8, fingerprint: &[u8; 32]) -> Result<()> {
+    writer.write_all(&[op])?;
+    writer.write_all(fingerprint)?;
+
+    Ok(())
+}
+
+/// Returns `None` on a clean end of the patch between frames.
+fn read_op<R: Read>(reader: &mut R) -> Result<Option<u8>> {
+    let mut op = [0];
+    loop {
+        match reader.read(&mut op) {
+            Ok(0) => return Ok(None),
+            Ok(_) => return Ok(Some(op[0])),
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e.into()),
+        }
+    }
+}
@@ -0,0 +1,242 @@
+//! Writing every record to the legacy layout and the batch container at once, for the window
+//! where some readers still expect the legacy layout.
+
+use std::io::{Seek, SeekFrom, Write};
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, LittleEndian, WriteBytesExt};
+
+use super::serial::IncrementalSerializer;
+use super::{
+    Codec, DeserializerOptions, Endianness, PlayerLog, PlayerLogSerializer, SerializerOptions,
+};
+
+/// How many bytes [`DualWriter::finish`] left in each writer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DualLengths {
+    pub legacy: u64,
+    pub container: u64,
+}
+
+/// Serializes each pushed record once and writes the same bytes into an uncompressed legacy
+/// buffer and an [`IncrementalSerializer`] batch, so the two can't end up with different records.
+///
+/// Both record counts are patched in by [`DualWriter::finish`]. The legacy count is written in
+/// the options' endianness, like the records, and the container takes the same restrictions as
+/// [`IncrementalSerializer::begin_with_options`].
+pub struct DualWriter<L: Write + Seek, C: Write + Seek> {
+    legacy: L,
+    legacy_start: u64,
+    container: IncrementalSerializer<C>,
+    container_start: u64,
+    endianness: Endianness,
+    record: Vec<u8>,
+}
+
+impl<L: Write + Seek, C: Write + Seek> DualWriter<L, C> {
+    /// Starts both outputs at their writers' current positions.
+    pub fn new(mut legacy: L, mut container: C, options: SerializerOptions) -> Result<Self> {
+        let endianness = options.endianness;
+
+        let container_start = container.stream_position()?;
+        let container = IncrementalSerializer::begin_with_options(container, options)?;
+
+        let legacy_start = legacy.stream_position()?;
+        legacy.write_u64::<BigEndian>(0)?;
+
+        Ok(Self {
+            legacy,
+            legacy_start,
+            container,
+            container_start,
+            endianness,
+            record: Vec::with_capacity(PlayerLog::MAX_ENCODED_SIZE),
+        })
+    }
+
+    pub fn push(&mut self, log: &PlayerLog) -> Result<()> {
+        self.record.clear();
+        log.serialize_with_endianness(&mut self.record, self.endianness)?;
+
+        self.legacy.write_all(&self.record)?;
+        self.container.write_record(&self.record)
+    }
+
+    pub const fn record_count(&self) -> u64 {
+        self.container.record_count()
+    }
+
+    /// Writes both record counts and leaves each writer at the end of its output.
+    pub fn finish(mut self) -> Result<DualLengths> {
+        let record_count = self.container.record_count();
+
+        let legacy_end = self.legacy.stream_position()?;
+        self.legacy.seek(SeekFrom::Start(self.legacy_start))?;
+        match self.endianness {
+            Endianness::Big => self.legacy.write_u64::<BigEndian>(record_count)?,
+            Endianness::Little => self.legacy.write_u64::<LittleEndian>(record_count)?,
+        }
+        self.legacy.seek(SeekFrom::Start(legacy_end))?;
+        self.legacy.flush()?;
+
+        let container_end = self.container.finish()?.stream_position()?;
+
+        Ok(DualLengths {
+            legacy: legacy_end - self.legacy_start,
+            container: container_end - self.container_start,
+        })
+    }
+}
+
+/// Fails unless the `legacy` buffer and the `container` batch hold the same records in the same
+/// order. The legacy records are read in the container's endianness.
+pub fn assert_dual_outputs_equivalent(legacy: &[u8], container: &[u8]) -> Result<()> {
+    let header = PlayerLogSerializer::probe(container).context("container")?;
+    let options = DeserializerOptions::default();
+
+    let migrated =
+        PlayerLogSerializer::migrate_legacy_with_endianness(legacy, Codec::None, header.endianness)
+            .context("legacy")?;
+    let legacy_logs = PlayerLogSerializer::deserialize_many_with_options(&migrated, &options)
+        .context("legacy")?;
+    let container_logs = PlayerLogSerializer::deserialize_many_with_options(container, &options)
+        .context("container")?;
+
+    if legacy_logs.len() != container_logs.len() {
+        bail!(
+            "legacy has {} records, container has {}",
+            legacy_logs.len(),
+            container_logs.len()
+        );
+    }
+    if let Some(i) = legacy_logs
+        .iter()
+        .zip(&container_logs)
+        .position(|(a, b)| a != b)
+    {
+        bail!("record {i} differs");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    // writes `logs` after a few bytes of something else in each writer
+    fn dual(logs: &[PlayerLog], endianness: Endianness) -> (Vec<u8>, Vec<u8>, DualLengths) {
+        let mut legacy = Cursor::new(b"abc".to_vec());
+        let mut container = Cursor::new(b"defgh".to_vec());
+        legacy.seek(SeekFrom::End(0)).unwrap();
+        container.seek(SeekFrom::End(0)).unwrap();
+
+        let options = SerializerOptions {
+            batch_id: Some([7; 16]),
+            endianness,
+            ..SerializerOptions::default()
+        };
+        let mut writer = DualWriter::new(&mut legacy, &mut container, options).unwrap();
+        for log in logs {
+            writer.push(log).unwrap();
+        }
+        assert_eq!(writer.record_count(), logs.len() as u64);
+        let lengths = writer.finish().unwrap();
+
+        let legacy = legacy.into_inner();
+        let container = container.into_inner();
+        assert_eq!(&legacy[..3], b"abc");
+        assert_eq!(&container[..5], b"defgh");
+
+        (legacy[3..].to_vec(), container[5..].to_vec(), lengths)
+    }
+
+    #[test]
+    fn both_outputs_hold_the_same_records() {
+        let logs = logs(50);
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let (legacy, container, lengths) = dual(&logs, endianness);
+            assert_eq!(
+                lengths,
+                DualLengths {
+                    legacy: legacy.len() as u64,
+                    container: container.len() as u64,
+                }
+            );
+
+            let options = SerializerOptions {
+                batch_id: Some([7; 16]),
+                endianness,
+                ..SerializerOptions::default()
+            };
+            assert_eq!(
+                container,
+                PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap()
+            );
+            let migrated = PlayerLogSerializer::migrate_legacy_with_endianness(
+                &legacy,
+                Codec::None,
+                endianness,
+            )
+            .unwrap();
+            assert_eq!(
+                PlayerLogSerializer::deserialize_many(&migrated).unwrap(),
+                logs
+            );
+            assert_dual_outputs_equivalent(&legacy, &container).unwrap();
+        }
+    }
+
+    #[test]
+    fn differing_outputs_are_caught() {
+        let logs = logs(5);
+        let (legacy, container, _) = dual(&logs, Endianness::Big);
+
+        let (shorter, _, _) = dual(&logs[..4], Endianness::Big);
+        let e = assert_dual_outputs_equivalent(&shorter, &container).unwrap_err();
+        assert_eq!(e.to_string(), "legacy has 4 records, container has 5");
+
+        let mut swapped = logs;
+        swapped.swap(1, 2);
+        let (_, other, _) = dual(&swapped, Endianness::Big);
+        let e = assert_dual_outputs_equivalent(&legacy, &other).unwrap_err();
+        assert_eq!(e.to_string(), "record 1 differs");
+
+        let e = assert_dual_outputs_equivalent(&legacy, &legacy).unwrap_err();
+        assert_eq!(e.to_string(), "container");
+    }
+
+    #[test]
+    fn compressed_containers_and_bad_records_fail() {
+        let options = SerializerOptions {
+            codec: Codec::Lz4,
+            ..SerializerOptions::default()
+        };
+        assert!(
+            DualWriter::new(Cursor::new(Vec::new()), Cursor::new(Vec::new()), options).is_err()
+        );
+
+        let mut invalid = logs(1).remove(0);
+        invalid.binary_version = 0;
+        let mut writer = DualWriter::new(
+            Cursor::new(Vec::new()),
+            Cursor::new(Vec::new()),
+            SerializerOptions::default(),
+        )
+        .unwrap();
+        assert!(writer.push(&invalid).is_err());
+        assert_eq!(writer.record_count(), 0);
+    }
+}
@@ -0,0 +1,140 @@
+use std::collections::HashMap;
+use std::net::Ipv4Addr;
+
+use anyhow::{Context, Result};
+
+use super::{parallel, GeoInfo, PlayerLog, PlayerLogBuilder};
+
+/// Looks up where a player ip is. Lookups may be slow (a database read per ip), so they're only
+/// made once per unique ip of a bulk build.
+pub trait Enricher {
+    fn enrich(&self, ip: Ipv4Addr) -> Option<GeoInfo>;
+}
+
+/// Leaves every log without geo info.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NoopEnricher;
+
+impl Enricher for NoopEnricher {
+    fn enrich(&self, _ip: Ipv4Addr) -> Option<GeoInfo> {
+        None
+    }
+}
+
+impl PlayerLogBuilder {
+    /// [`PlayerLogBuilder::build_many`], filling in the geo info of every builder that doesn't
+    /// already have it.
+    pub fn build_many_enriched<E: Enricher + ?Sized>(
+        builders: &[Self],
+        enricher: &E,
+    ) -> Result<Vec<PlayerLog>> {
+        let mut cache = HashMap::new();
+        for builder in builders.iter().filter(|b| b.geo.is_none()) {
+            cache
+                .entry(builder.player_ip)
+                .or_insert_with(|| enricher.enrich(builder.player_ip));
+        }
+
+        parallel::map_items(builders, |builder| {
+            if builder.geo.is_some() {
+                return builder.build();
+            }
+
+            let mut builder = builder.clone();
+            builder.geo = cache[&builder.player_ip];
+            builder.build()
+        })
+        .into_iter()
+        .enumerate()
+        .map(|(i, log)| log.with_context(|| format!("builder {i} failed")))
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::{LogFlags, PlayerLogSerializer};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    // knows the 10.0.0.0/8 range and remembers every lookup
+    #[derive(Default)]
+    struct Lookups(RefCell<Vec<Ipv4Addr>>);
+
+    impl Enricher for Lookups {
+        fn enrich(&self, ip: Ipv4Addr) -> Option<GeoInfo> {
+            self.0.borrow_mut().push(ip);
+            (ip.octets()[0] == 10).then_some(GeoInfo {
+                country: *b"NL",
+                asn: u32::from(ip.octets()[3]),
+            })
+        }
+    }
+
+    fn builders() -> Vec<PlayerLogBuilder> {
+        let mut rng = StdRng::seed_from_u64(0);
+        let ips = [[10, 0, 0, 1], [10, 0, 0, 2], [192, 0, 2, 1]];
+        (0..30)
+            .map(|i| {
+                let mut builder = log_generator_seeded(&mut rng);
+                builder.player_ip = ips[i % ips.len()].into();
+                builder.geo = None;
+                builder
+            })
+            .collect()
+    }
+
+    #[test]
+    fn every_unique_ip_is_looked_up_once() {
+        let mut builders = builders();
+        let preset = GeoInfo {
+            country: *b"DE",
+            asn: 3320,
+        };
+        builders[0].geo = Some(preset);
+
+        let lookups = Lookups::default();
+        let logs = PlayerLogBuilder::build_many_enriched(&builders, &lookups).unwrap();
+
+        let mut looked_up = lookups.0.into_inner();
+        looked_up.sort();
+        assert_eq!(
+            looked_up,
+            [[10, 0, 0, 1], [10, 0, 0, 2], [192, 0, 2, 1]].map(Ipv4Addr::from)
+        );
+
+        assert_eq!(logs[0].geo, Some(preset));
+        for (log, builder) in logs.iter().zip(&builders).skip(1) {
+            let expected = (builder.player_ip.octets()[0] == 10).then_some(GeoInfo {
+                country: *b"NL",
+                asn: u32::from(builder.player_ip.octets()[3]),
+            });
+            assert_eq!(log.geo, expected);
+            assert_eq!(
+                LogFlags::from_bits_retain(log.flags).contains(LogFlags::HAS_GEO),
+                expected.is_some()
+            );
+        }
+
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        assert_eq!(PlayerLogSerializer::deserialize_many(&data).unwrap(), logs);
+    }
+
+    #[test]
+    fn the_noop_enricher_builds_like_build_many() {
+        let builders = builders();
+        assert_eq!(
+            PlayerLogBuilder::build_many_enriched(&builders, &NoopEnricher).unwrap(),
+            PlayerLogBuilder::build_many(&builders).unwrap()
+        );
+
+        let mut broken = builders;
+        broken[4].client_brand = Some(String::new());
+        let e = PlayerLogBuilder::build_many_enriched(&broken, &NoopEnricher).unwrap_err();
+        assert_eq!(e.to_string(), "builder 4 failed");
+    }
+}
@@ -0,0 +1,168 @@
+//! Single records without a batch container, e.g. as the value of a key-value store.
+//!
+//! A plain record is just [`PlayerLog::serialize`], it starts with its binary_version byte. A
+//! compressed one starts with [`COMPRESSED_RECORD_MARKER`] and the codec id instead, neither can
+//! be mistaken for [`BATCH_MAGIC`].
+
+use std::io::Read;
+
+use anyhow::{bail, Context, Result};
+
+use super::{BatchHeader, Codec, PlayerLog, PlayerLogSerializer, BATCH_MAGIC};
+
+/// First byte of [`PlayerLog::to_bytes_compressed`], past any binary_version and the batch magic.
+pub const COMPRESSED_RECORD_MARKER: u8 = 0xC7;
+
+/// What [`PlayerLogSerializer::probe_kind`] found at the start of a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BufferKind<'a> {
+    Batch(BatchHeader<'a>),
+    Record { binary_version: u8 },
+    CompressedRecord { codec: Codec },
+}
+
+impl PlayerLog {
+    /// [`PlayerLog::serialize`] into a buffer of its own.
+    pub fn to_bytes(&self) -> Result<Vec<u8>> {
+        let mut out = Vec::with_capacity(self.serialized_size());
+        self.serialize(&mut out)?;
+
+        Ok(out)
+    }
+
+    /// Reads a record written by [`PlayerLog::to_bytes`], which has to use all of `data`.
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        let mut reader = data;
+        let log = Self::deserialize(&mut reader)?;
+        if !reader.is_empty() {
+            bail!("{} trailing bytes after the record", reader.len());
+        }
+
+        Ok(log)
+    }
+
+    /// Worth it for records with long domains or client info, short ones come out larger.
+    pub fn to_bytes_compressed(&self, codec: Codec) -> Result<Vec<u8>> {
+        let record = self.to_bytes()?;
+
+        let mut out = vec![COMPRESSED_RECORD_MARKER, codec.id()];
+        codec.encode_into(&record, &mut out)?;
+
+        Ok(out)
+    }
+
+    pub fn from_bytes_compressed(data: &[u8]) -> Result<Self> {
+        let [marker, codec, payload @ ..] = data else {
+            bail!("truncated compressed record");
+        };
+        if *marker != COMPRESSED_RECORD_MARKER {
+            bail!("not a compressed record, it starts with {marker:#04x}");
+        }
+
+        // one byte more than any record so an oversized one is caught without inflating all of it
+        let mut record = Vec::new();
+        Codec::from_id(*codec)?
+            .decoder(payload)
+            .take(Self::MAX_ENCODED_SIZE as u64 + 1)
+            .read_to_end(&mut record)
+            .context("corrupt compressed record")?;
+        if record.len() > Self::MAX_ENCODED_SIZE {
+            bail!(
+                "compressed record inflates past {} bytes",
+                Self::MAX_ENCODED_SIZE
+            );
+        }
+
+        Self::from_bytes(&record)
+    }
+}
+
+impl PlayerLogSerializer {
+    /// Tells a batch from a single record by the first bytes, only the batch header is validated.
+    pub fn probe_kind(data: &[u8]) -> Result<BufferKind<'_>> {
+        match data {
+            [] => bail!("empty buffer"),
+            [b, ..] if *b == BATCH_MAGIC[0] => Self::probe(data).map(BufferKind::Batch),
+            [COMPRESSED_RECORD_MARKER, codec, ..] => Ok(BufferKind::CompressedRecord {
+                codec: Codec::from_id(*codec)?,
+            }),
+            [COMPRESSED_RECORD_MARKER] => bail!("truncated compressed record"),
+            [v @ 1..=PlayerLog::CURRENT_VERSION, ..] => {
+                Ok(BufferKind::Record { binary_version: *v })
+            }
+            [b, ..] => bail!("neither a batch nor a record, it starts with {b:#04x}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn codecs() -> [Codec; 3] {
+        [Codec::None, Codec::Zlib(Compression::default()), Codec::Lz4]
+    }
+
+    #[test]
+    fn records_round_trip_plain_and_compressed() {
+        for log in logs(50) {
+            let plain = log.to_bytes().unwrap();
+            assert_eq!(PlayerLog::from_bytes(&plain).unwrap(), log);
+            assert_eq!(
+                PlayerLogSerializer::probe_kind(&plain).unwrap(),
+                BufferKind::Record {
+                    binary_version: log.binary_version
+                }
+            );
+
+            for codec in codecs() {
+                let compressed = log.to_bytes_compressed(codec).unwrap();
+                assert_eq!(PlayerLog::from_bytes_compressed(&compressed).unwrap(), log);
+                assert!(matches!(
+                    PlayerLogSerializer::probe_kind(&compressed).unwrap(),
+                    BufferKind::CompressedRecord { codec: found } if found.id() == codec.id()
+                ));
+            }
+        }
+
+        let batch = PlayerLogSerializer::serialize_many(&logs(3)).unwrap();
+        assert!(matches!(
+            PlayerLogSerializer::probe_kind(&batch).unwrap(),
+            BufferKind::Batch(header) if header.record_count == 3
+        ));
+    }
+
+    #[test]
+    fn envelopes_that_dont_hold_one_record_fail() {
+        let log = &logs(1)[0];
+        let mut trailing = log.to_bytes().unwrap();
+        trailing.push(0);
+        let e = PlayerLog::from_bytes(&trailing).unwrap_err();
+        assert!(e.to_string().contains("1 trailing bytes"), "{e}");
+
+        // inflates to far more than any record could be
+        let mut bomb = vec![COMPRESSED_RECORD_MARKER, Codec::Lz4.id()];
+        Codec::Lz4
+            .encode_into(&vec![1; 1 << 20], &mut bomb)
+            .unwrap();
+        let e = PlayerLog::from_bytes_compressed(&bomb).unwrap_err();
+        assert!(e.to_string().contains("inflates past"), "{e}");
+
+        let plain = log.to_bytes().unwrap();
+        assert!(PlayerLog::from_bytes_compressed(&plain).is_err());
+        for data in [&[][..], &[COMPRESSED_RECORD_MARKER], &[0], &[0xFF, 0]] {
+            assert!(PlayerLogSerializer::probe_kind(data).is_err(), "{data:?}");
+        }
+    }
+}
@@ -0,0 +1,385 @@
+use std::fmt::Write as _;
+use std::io::Read;
+use std::net::Ipv4Addr;
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use uuid::Uuid;
+
+use super::{
+    validate_flags, Endianness, LogEventType, LogFlags, LoginOutcome, PlayerLog,
+    PlayerLogSerializer, ServerVersion, VERSIONS,
+};
+
+const BYTES_PER_LINE: usize = 16;
+
+struct Explainer<'a> {
+    data: &'a [u8],
+    pos: usize,
+    out: String,
+    endianness: Endianness,
+}
+
+impl<'a> Explainer<'a> {
+    // writes the field's line and returns its decoded value, or writes the error and returns None
+    fn field<T>(
+        &mut self,
+        name: &str,
+        len: usize,
+        decode: impl FnOnce(&'a [u8]) -> Result<(T, String)>,
+    ) -> Option<T> {
+        let available = &self.data[self.pos..];
+        let (bytes, result) = if available.len() < len {
+            (
+                available,
+                Err(anyhow::anyhow!(
+                    "needs {len} bytes but only {} are left",
+                    available.len()
+                )),
+            )
+        } else {
+            (&available[..len], decode(&available[..len]))
+        };
+
+        let description = match &result {
+            Ok((_, value)) => format!("{name}: {value}"),
+            Err(e) => format!("{name}: error: {e:#}"),
+        };
+        self.line(bytes, &description);
+        self.pos += bytes.len();
+
+        result.ok().map(|(value, _)| value)
+    }
+
+    fn line(&mut self, bytes: &[u8], description: &str) {
+        let mut chunks = bytes.chunks(BYTES_PER_LINE);
+        let first = chunks.next().unwrap_or_default();
+        let _ = writeln!(
+            self.out,
+            "{:04x}  {:<width$}  {description}",
+            self.pos,
+            hex(first),
+            width = BYTES_PER_LINE * 3 - 1
+        );
+
+        for (i, chunk) in chunks.enumerate() {
+            let _ = writeln!(
+                self.out,
+                "{:04x}  {}",
+                self.pos + (i + 1) * BYTES_PER_LINE,
+                hex(chunk)
+            );
+        }
+    }
+
+    fn record(&mut self) -> Option<()> {
+        let endianness = self.endianness;
+        let binary_version = self.field("binary_version", 1, |b| match b[0] {
+            v @ 1..=PlayerLog::CURRENT_VERSION => Ok((v, v.to_string())),
+            v => bail!("invalid binary version {v}"),
+        })?;
+
+        let flags_len = if binary_version >= 2 { 2 } else { 1 };
+        let flags = self.field("flags", flags_len, |b| {
+            let bits = uint(endianness, b) as u16;
+            let flags = validate_flags(bits)?;
+            let mut names = String::new();
+            bitflags::parser::to_writer(
+                &LogFlags::from_bits_retain(bits & !LogFlags::EVENT_TYPE_BITS),
+                &mut names,
+            )?;
+            if names.is_empty() {
+                names.push_str("none");
+            }
+            Ok((
+                flags,
+                format!("{names}, event: {}", LogEventType::from_flags(bits).name()),
+            ))
+        })?;
+
+        if flags.contains(LogFlags::IS_ONLINE) {
+            self.field("player_uuid", PlayerLog::UUID_LEN, |b| {
+                Ok(((), Uuid::from_slice(b)?.to_string()))
+            })?;
+        }
+
+        let name_len = self.field("player_name length", 1, |b| Ok((b[0], b[0].to_string())))?;
+        self.field("player_name", name_len as usize, |b| {
+            Ok(((), format!("{:?}", String::from_utf8_lossy(b))))
+        })?;
+
+        self.field("player_ip", 4, |b| Ok(((), ip(b).to_string())))?;
+        let server_ip = self.field("server_ip", 4, |b| Ok((ip(b), ip(b).to_string())))?;
+        if binary_version < 2 || flags.contains(LogFlags::NONDEFAULT_PORT) {
+            self.field("server_port", 2, |b| {
+                Ok(((), uint(endianness, b).to_string()))
+            })?;
+        } else {
+            self.line(
+                &[],
+                &format!("server_port: {} (default)", PlayerLog::DEFAULT_PORT),
+            );
+        }
+
+        if binary_version >= 2 && flags.contains(LogFlags::DOMAIN_IS_IP) {
+            self.line(&[], &format!("server_domain: \"{server_ip}\" (server ip)"));
+        } else {
+            let domain_len =
+                self.field("server_domain length", 1, |b| Ok((b[0], b[0].to_string())))?;
+            self.field("server_domain", domain_len as usize, |b| {
+                Ok(((), format!("{:?}", String::from_utf8_lossy(b))))
+            })?;
+        }
+
+        let unknown_version = self.field("server_version", 1, |b| {
+            if b[0] == ServerVersion::UNKNOWN_ID && binary_version >= 2 {
+                return Ok((true, format!("{} (unknown, the name follows)", b[0])));
+            }
+
+            let name = VERSIONS
+                .entries()
+                .find(|(_, n)| **n == b[0])
+                .map_or("unknown", |(name, _)| name);
+            Ok((false, format!("{} ({name})", b[0])))
+        })?;
+        if unknown_version {
+            self.bounded_string("server_version name", ServerVersion::MAX_UNKNOWN_LEN)?;
+        }
+
+        if binary_version < 2 {
+            return Some(());
+        }
+
+        self.field("timestamp", 8, |b| {
+            let millis = uint(endianness, b);
+            let value = match millis {
+                0 => "unknown".to_string(),
+                millis => {
+                    humantime::format_rfc3339_millis(UNIX_EPOCH + Duration::from_millis(millis))
+                        .to_string()
+                }
+            };
+            Ok(((), value))
+        })?;
+        self.field("session_id", 4, |b| {
+            Ok(((), uint(endianness, b).to_string()))
+        })?;
+
+        if flags.contains(LogFlags::LOGIN_REFUSED) {
+            self.field("outcome", 1, |b| {
+                Ok(((), format!("{} ({})", b[0], LoginOutcome::from_byte(b[0]))))
+            })?;
+        }
+        if flags.contains(LogFlags::HAS_GEO) {
+            self.field("country", 2, |b| {
+                Ok(((), format!("{:?}", String::from_utf8_lossy(b))))
+            })?;
+            self.field("asn", 4, |b| Ok(((), format!("AS{}", uint(endianness, b)))))?;
+        }
+        if flags.contains(LogFlags::VIA_PROXY) {
+            self.field("proxy_ip", 4, |b| Ok(((), ip(b).to_string())))?;
+        }
+        if flags.contains(LogFlags::HAS_CLIENT_INFO) {
+            self.bounded_string("client_brand", PlayerLog::MAX_CLIENT_BRAND_LEN)?;
+            self.bounded_string("client_locale", PlayerLog::MAX_CLIENT_LOCALE_LEN)?;
+        }
+
+        let extension_count = self.field("extension count", 1, |b| match b[0] as usize {
+            count @ 0..=PlayerLog::MAX_EXTENSIONS => Ok((count, count.to_string())),
+            count => bail!(
+                "{count} extensions, at most {} are allowed",
+                PlayerLog::MAX_EXTENSIONS
+            ),
+        })?;
+
+        for i in 0..extension_count {
+            self.field(&format!("extension {i} kind"), 1, |b| {
+                Ok(((), b[0].to_string()))
+            })?;
+            let len = self.field(&format!("extension {i} length"), 1, |b| {
+                match b[0] as usize {
+                    len @ 0..=PlayerLog::MAX_EXTENSION_LEN => Ok((len, len.to_string())),
+                    len => bail!(
+                        "{len} bytes, at most {} are allowed",
+                        PlayerLog::MAX_EXTENSION_LEN
+                    ),
+                }
+            })?;
+            self.field(&format!("extension {i} data"), len, |b| {
+                Ok(((), format!("{} bytes", b.len())))
+            })?;
+        }
+
+        Some(())
+    }
+
+    fn bounded_string(&mut self, name: &str, max_len: usize) -> Option<()> {
+        let len = self.field(&format!("{name} length"), 1, |b| match b[0] as usize {
+            len if len <= max_len => Ok((len, len.to_string())),
+            len => bail!("{len} bytes, at most {max_len} are allowed"),
+        })?;
+        self.field(name, len, |b| {
+            Ok(((), format!("{:?}", String::from_utf8_lossy(b))))
+        })
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// the integer fields are at most 8 bytes, and never empty
+fn uint(endianness: Endianness, bytes: &[u8]) -> u64 {
+    match endianness {
+        Endianness::Big => BigEndian::read_uint(bytes, bytes.len()),
+        Endianness::Little => LittleEndian::read_uint(bytes, bytes.len()),
+    }
+}
+
+fn ip(bytes: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::new(bytes[0], bytes[1], bytes[2], bytes[3])
+}
+
+// returns the dump and the number of bytes the record used
+fn explain_record(data: &[u8], endianness: Endianness) -> (String, usize) {
+    let mut explainer = Explainer {
+        data,
+        pos: 0,
+        out: String::new(),
+        endianness,
+    };
+    let _ = explainer.record();
+
+    (explainer.out, explainer.pos)
+}
+
+impl PlayerLog {
+    /// Annotated hex dump of the big endian record at the start of `data`, one field per line,
+    /// stopping at the first field that can't be decoded.
+    pub fn explain(data: &[u8]) -> String {
+        let (mut out, used) = explain_record(data, Endianness::Big);
+        if used < data.len() {
+            let _ = writeln!(out, "({} more bytes after the record)", data.len() - used);
+        }
+
+        out
+    }
+
+    /// [`PlayerLog::explain`] for the record at `index` of a batch, offsets are relative to the
+    /// start of the record.
+    pub fn explain_record_at(batch: &[u8], index: usize) -> Result<String> {
+        let (header, header_len) = PlayerLogSerializer::probe_with_len(batch)?;
+        header.check_no_dictionary()?;
+        if index as u64 >= header.record_count {
+            bail!(
+                "record {index} out of range, the batch has {} records",
+                header.record_count
+            );
+        }
+
+        // a corrupt stream is still explained as far as it decodes
+        let mut payload = Vec::new();
+        let _ = header
+            .codec
+            .decoder(&batch[header_len..])
+            .read_to_end(&mut payload);
+
+        let mut records = payload.as_slice();
+        for i in 0..index {
+            Self::deserialize_with_endianness(&mut records, header.endianness)
+                .with_context(|| format!("record {i} before the explained one is invalid"))?;
+        }
+
+        let offset = payload.len() - records.len();
+        let (dump, _) = explain_record(records, header.endianness);
+        Ok(format!(
+            "record {index} at payload offset {offset:#x}\n{dump}"
+        ))
+    }
+
+    /// [`PlayerLog::explain_record_at`] for every record of a batch in one pass, ending with the
+    /// first record that can't be decoded.
+    pub fn explain_records(batch: &[u8]) -> Result<Vec<String>> {
+        let (header, header_len) = PlayerLogSerializer::probe_with_len(batch)?;
+        header.check_no_dictionary()?;
+
+        let mut payload = Vec::new();
+        let _ = header
+            .codec
+            .decoder(&batch[header_len..])
+            .read_to_end(&mut payload);
+
+        let mut records = payload.as_slice();
+        let mut dumps = Vec::new();
+        for index in 0..header.record_count {
+            let offset = payload.len() - records.len();
+            let (dump, _) = explain_record(records, header.endianness);
+            dumps.push(format!(
+                "record {index} at payload offset {offset:#x}\n{dump}"
+            ));
+
+            if Self::deserialize_with_endianness(&mut records, header.endianness).is_err() {
+                break;
+            }
+        }
+
+        Ok(dumps)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a v1 offline login of "Notch" to mc.example.com on 1.8
+    const RECORD: &[u8] =
+        b"\x01\x00\x05Notch\x0a\x00\x00\x01\xc0\xa8\x01\xfe\x63\xdd\x0emc.example.com\x01";
+
+    #[test]
+    fn explains_a_record_field_by_field() {
+        let log = PlayerLog::deserialize(&mut &RECORD[..]).unwrap();
+        assert_eq!(&*log.player_name, b"Notch");
+
+        let mut data = RECORD.to_vec();
+        data.extend([0xAA, 0xBB]);
+        assert_eq!(
+            PlayerLog::explain(&data),
+            concat!(
+            "0000  01                                               binary_version: 1\n",
+            "0001  00                                               flags: none, event: login\n",
+            "0002  05                                               player_name length: 5\n",
+            "0003  4e 6f 74 63 68                                   player_name: \"Notch\"\n",
+            "0008  0a 00 00 01                                      player_ip: 10.0.0.1\n",
+            "000c  c0 a8 01 fe                                      server_ip: 192.168.1.254\n",
+            "0010  63 dd                                            server_port: 25565\n",
+            "0012  0e                                               server_domain length: 14\n",
+            "0013  6d 63 2e 65 78 61 6d 70 6c 65 2e 63 6f 6d        server_domain: \"mc.example.com\"\n",
+            "0021  01                                               server_version: 1 (1.8)\n",
+            "(2 more bytes after the record)\n",
+        )
+        );
+    }
+
+    #[test]
+    fn stops_at_the_field_the_record_ends_in() {
+        assert_eq!(
+            PlayerLog::explain(&RECORD[..24]),
+            concat!(
+            "0000  01                                               binary_version: 1\n",
+            "0001  00                                               flags: none, event: login\n",
+            "0002  05                                               player_name length: 5\n",
+            "0003  4e 6f 74 63 68                                   player_name: \"Notch\"\n",
+            "0008  0a 00 00 01                                      player_ip: 10.0.0.1\n",
+            "000c  c0 a8 01 fe                                      server_ip: 192.168.1.254\n",
+            "0010  63 dd                                            server_port: 25565\n",
+            "0012  0e                                               server_domain length: 14\n",
+            "0013  6d 63 2e 65 78                                   server_domain: error: needs 14 bytes but only 5 are left\n",
+        )
+        );
+    }
+}
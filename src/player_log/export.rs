@@ -0,0 +1,521 @@
+use std::borrow::Cow;
+#[cfg(feature = "statsd")]
+use std::collections::HashSet;
+use std::io::{BufRead, Read, Write};
+use std::net::Ipv4Addr;
+#[cfg(feature = "statsd")]
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use uuid::Uuid;
+
+use super::{
+    Extension, FieldPresenceMask, GeoInfo, LogFlags, LoginOutcome, PlayerLog, PlayerLogBuilder,
+    PlayerLogSerializer,
+};
+
+/// Reads one JSON encoded [`PlayerLogBuilder`] per line, skipping blank lines. A malformed line
+/// yields an error without ending the iteration.
+pub struct NdjsonReader<R: BufRead> {
+    reader: R,
+    line: usize,
+    buf: String,
+}
+
+impl<R: BufRead> NdjsonReader<R> {
+    pub const fn new(reader: R) -> Self {
+        Self {
+            reader,
+            line: 0,
+            buf: String::new(),
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for NdjsonReader<R> {
+    type Item = Result<PlayerLogBuilder>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            self.buf.clear();
+            self.line += 1;
+
+            match self.reader.read_line(&mut self.buf) {
+                Ok(0) => return None,
+                Ok(_) if self.buf.trim().is_empty() => continue,
+                Ok(_) => {
+                    return Some(
+                        serde_json::from_str(&self.buf)
+                            .with_context(|| format!("line {}", self.line)),
+                    )
+                }
+                Err(e) => {
+                    return Some(Err(
+                        anyhow::Error::from(e).context(format!("line {}", self.line))
+                    ))
+                }
+            }
+        }
+    }
+}
+
+pub struct NdjsonWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> NdjsonWriter<W> {
+    pub const fn new(writer: W) -> Self {
+        Self { writer }
+    }
+
+    pub fn write(&mut self, builder: &PlayerLogBuilder) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, builder)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+/// One JSON encoded [`PlayerLogBuilder`] per line.
+pub fn write_ndjson<W: Write>(logs: &[PlayerLog], writer: W) -> Result<()> {
+    let mut writer = NdjsonWriter::new(writer);
+    for log in logs {
+        writer.write(&PlayerLogBuilder::from_log(log)?)?;
+    }
+
+    writer.flush()
+}
+
+pub fn read_ndjson<R: BufRead>(reader: R) -> Result<Vec<PlayerLog>> {
+    let mut reader = NdjsonReader::new(reader);
+    let mut logs = Vec::new();
+
+    while let Some(builder) = reader.next() {
+        // parse errors already name their line
+        let log = builder?
+            .build()
+            .with_context(|| format!("line {}", reader.line))?;
+        logs.push(log);
+    }
+
+    Ok(logs)
+}
+
+const CSV_HEADER: &str = "flags,player_uuid,player_name,player_ip,server_ip,server_port,\
+    server_domain,server_version,timestamp,session_id,client_brand,client_locale,outcome,country,asn,proxy_ip";
+
+pub fn write_csv<W: Write>(logs: &[PlayerLog], mut writer: W) -> Result<()> {
+    writeln!(writer, "{CSV_HEADER}")?;
+
+    for log in logs {
+        let builder = PlayerLogBuilder::from_log(log)?;
+
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+            builder.flags.bits(),
+            builder
+                .player_uuid
+                .map(|uuid| uuid.to_string())
+                .unwrap_or_default(),
+            csv_field(&builder.player_name),
+            builder.player_ip,
+            builder.server_ip,
+            builder.server_port,
+            csv_field(&builder.server_domain),
+            csv_field(&builder.server_version),
+            builder
+                .timestamp
+                .map(|timestamp| timestamp.to_string())
+                .unwrap_or_default(),
+            builder.session_id,
+            csv_field(builder.client_brand.as_deref().unwrap_or_default()),
+            csv_field(builder.client_locale.as_deref().unwrap_or_default()),
+            builder.outcome,
+            builder.geo.as_ref().map_or("", |geo| geo.country_code()),
+            builder
+                .geo
+                .map(|geo| geo.asn.to_string())
+                .unwrap_or_default(),
+            builder
+                .proxy_ip
+                .map(|ip| ip.to_string())
+                .unwrap_or_default(),
+        )?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+fn csv_field(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+// csv can't hold nested values, so geo is split up and the extensions are flattened into a string
+#[derive(Serialize, Deserialize)]
+struct CsvRow {
+    flags: u16,
+    player_uuid: Option<Uuid>,
+    player_name: String,
+    player_ip: Ipv4Addr,
+    server_ip: Ipv4Addr,
+    server_port: u16,
+    server_domain: String,
+    server_version: String,
+    timestamp: Option<u64>,
+    session_id: u32,
+    client_brand: Option<String>,
+    client_locale: Option<String>,
+    outcome: u8,
+    country: Option<String>,
+    asn: Option<u32>,
+    proxy_ip: Option<Ipv4Addr>,
+    /// `kind=hexdata` per extension, space separated.
+    extensions: String,
+}
+
+impl CsvRow {
+    fn from_builder(builder: PlayerLogBuilder) -> Self {
+        let extensions = builder
+            .extensions
+            .iter()
+            .map(|ext| {
+                let data = ext
+                    .data
+                    .iter()
+                    .map(|b| format!("{b:02x}"))
+                    .collect::<String>();
+                format!("{}={data}", ext.kind)
+            })
+            .collect::<Vec<_>>()
+            .join(" ");
+
+        Self {
+            flags: builder.flags.bits(),
+            player_uuid: builder.player_uuid,
+            player_name: builder.player_name,
+            player_ip: builder.player_ip,
+            server_ip: builder.server_ip,
+            server_port: builder.server_port,
+            server_domain: builder.server_domain,
+            server_version: builder.server_version,
+            timestamp: builder.timestamp,
+            session_id: builder.session_id,
+            client_brand: builder.client_brand,
+            client_locale: builder.client_locale,
+            outcome: builder.outcome.to_byte(),
+            country: builder.geo.map(|geo| geo.country_code().to_string()),
+            asn: builder.geo.map(|geo| geo.asn),
+            proxy_ip: builder.proxy_ip,
+            extensions,
+        }
+    }
+
+    fn into_builder(self) -> Result<PlayerLogBuilder> {
+        let geo = match (self.country, self.asn) {
+            (Some(country), Some(asn)) => Some(GeoInfo {
+                country: country
+                    .as_bytes()
+                    .try_into()
+                    .with_context(|| format!("invalid country {country}"))?,
+                asn,
+            }),
+            (None, None) => None,
+            _ => bail!("country and asn have to be set together"),
+        };
+
+        let extensions = self
+            .extensions
+            .split_whitespace()
+            .map(|ext| {
+                let (kind, data) = ext
+                    .split_once('=')
+                    .with_context(|| format!("invalid extension {ext}"))?;
+                if data.len() % 2 != 0 {
+                    bail!("odd length extension data {data}");
+                }
+
+                Ok(Extension {
+                    kind: kind.parse().context("invalid extension kind")?,
+                    data: (0..data.len())
+                        .step_by(2)
+                        .map(|i| u8::from_str_radix(&data[i..i + 2], 16))
+                        .collect::<Result<_, _>>()
+                        .with_context(|| format!("invalid extension data {data}"))?,
+                })
+            })
+            .collect::<Result<_>>()?;
+
+        Ok(PlayerLogBuilder {
+            flags: LogFlags::from_bits_retain(self.flags),
+            player_uuid: self.player_uuid,
+            player_name: self.player_name,
+            player_ip: self.player_ip,
+            server_ip: self.server_ip,
+            server_port: self.server_port,
+            server_domain: self.server_domain,
+            server_version: self.server_version,
+            timestamp: self.timestamp,
+            session_id: self.session_id,
+            extensions,
+            client_brand: self.client_brand,
+            client_locale: self.client_locale,
+            outcome: LoginOutcome::from_byte(self.outcome),
+            geo,
+            proxy_ip: self.proxy_ip,
+        })
+    }
+}
+
+impl PlayerLogSerializer {
+    /// Like [`write_csv`] but through the csv crate, with an extra extensions column so
+    /// [`PlayerLogSerializer::read_csv_serde`] gets back the same logs. Missing values are empty.
+    pub fn write_csv_serde<W: Write>(logs: &[PlayerLog], writer: W) -> Result<()> {
+        let mut writer = csv::Writer::from_writer(writer);
+        for log in logs {
+            writer.serialize(CsvRow::from_builder(PlayerLogBuilder::from_log(log)?))?;
+        }
+
+        writer.flush()?;
+        Ok(())
+    }
+
+    pub fn read_csv_serde<R: Read>(reader: R) -> Result<Vec<PlayerLog>> {
+        csv::Reader::from_reader(reader)
+            .deserialize::<CsvRow>()
+            .enumerate()
+            .map(|(i, row)| {
+                row.map_err(anyhow::Error::from)
+                    .and_then(CsvRow::into_builder)
+                    .and_then(|builder| builder.build())
+                    .with_context(|| format!("row {}", i + 1))
+            })
+            .collect()
+    }
+}
+
+/// Maps a log onto Elastic Common Schema fields, anything without an ECS equivalent goes under
+/// `minecraft`.
+pub fn to_ecs_json(log: &PlayerLog) -> Result<Value> {
+    let builder = PlayerLogBuilder::from_log(log)?;
+
+    let mut user = json!({ "name": builder.player_name });
+    if let Some(uuid) = builder.player_uuid {
+        user["id"] = json!(uuid.to_string());
+    }
+
+    let mut document = json!({
+        "event": {
+            "kind": "event",
+            "action": log.event_type().name(),
+            "category": ["network"],
+            "dataset": "player_logs",
+            "outcome": if builder.outcome == LoginOutcome::Success { "success" } else { "failure" },
+        },
+        "user": user,
+        "source": { "ip": builder.player_ip.to_string() },
+        "destination": {
+            "ip": builder.server_ip.to_string(),
+            "port": builder.server_port,
+            "domain": builder.server_domain,
+        },
+        "service": { "version": builder.server_version },
+        "minecraft": {
+            "online_mode": builder.flags.contains(LogFlags::IS_ONLINE),
+            "player_auth": builder.flags.contains(LogFlags::PLAYER_AUTH),
+            "flags": builder.flags.bits(),
+            "login_outcome": builder.outcome.to_string(),
+        },
+    });
+
+    if let Some(timestamp) = builder.timestamp.filter(|t| *t != 0) {
+        document["@timestamp"] = json!(humantime::format_rfc3339_millis(
+            UNIX_EPOCH + Duration::from_millis(timestamp)
+        )
+        .to_string());
+    }
+    if log.present_fields().contains(FieldPresenceMask::SESSION_ID) {
+        document["minecraft"]["session_id"] = json!(builder.session_id);
+    }
+    if let Some(geo) = &builder.geo {
+        document["source"]["geo"] = json!({ "country_iso_code": geo.country_code() });
+        document["source"]["as"] = json!({ "number": geo.asn });
+    }
+    if let Some(proxy_ip) = builder.proxy_ip {
+        document["minecraft"]["proxy_ip"] = json!(proxy_ip.to_string());
+    }
+    if let Some(brand) = &builder.client_brand {
+        document["user_agent"] = json!({ "name": brand });
+    }
+    if let Some(locale) = &builder.client_locale {
+        document["minecraft"]["client_locale"] = json!(locale);
+    }
+
+    Ok(document)
+}
+
+/// Writes Elasticsearch Bulk API input, an index action line followed by the ECS document for
+/// every log.
+pub fn write_es_bulk<W: Write>(logs: &[PlayerLog], mut writer: W, index_name: &str) -> Result<()> {
+    let action = json!({ "index": { "_index": index_name } });
+
+    for log in logs {
+        serde_json::to_writer(&mut writer, &action)?;
+        writer.write_all(b"\n")?;
+        serde_json::to_writer(&mut writer, &to_ecs_json(log)?)?;
+        writer.write_all(b"\n")?;
+    }
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// One `connections` counter per log tagged with its server version and auth, plus gauges of the
+/// unique player ips and servers (ip and port) of the whole batch.
+#[cfg(feature = "statsd")]
+pub fn to_statsd_lines(logs: &[PlayerLog], prefix: &str) -> Vec<String> {
+    let mut player_ips = HashSet::new();
+    let mut servers = HashSet::new();
+
+    let mut lines = Vec::with_capacity(logs.len() + 2);
+    for log in logs {
+        player_ips.insert(log.player_ip);
+        servers.insert((log.server_ip, log.server_port));
+
+        let version = log.server_version.name().unwrap_or("unknown");
+        lines.push(format!(
+            "{prefix}.connections:1|c|#version:{},auth:{}",
+            statsd_tag(version),
+            log.flags & LogFlags::PLAYER_AUTH.bits() != 0,
+        ));
+    }
+
+    lines.push(format!("{prefix}.unique_player_ips:{}|g", player_ips.len()));
+    lines.push(format!("{prefix}.unique_servers:{}|g", servers.len()));
+    lines
+}
+
+// unknown version names come from the wire, they can't be allowed to break up the tag list
+#[cfg(feature = "statsd")]
+fn statsd_tag(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '|', '#', ':', '\n']) {
+        Cow::Owned(value.replace([',', '|', '#', ':', '\n'], "_"))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Sends every line as its own UDP datagram, returning how many were sent.
+#[cfg(feature = "statsd")]
+pub fn send_statsd(lines: &[String], addr: SocketAddr) -> Result<usize> {
+    let bind: SocketAddr = if addr.is_ipv4() {
+        ([0, 0, 0, 0], 0).into()
+    } else {
+        ([0u16; 8], 0).into()
+    };
+    let socket = UdpSocket::bind(bind)?;
+
+    for (i, line) in lines.iter().enumerate() {
+        socket
+            .send_to(line.as_bytes(), addr)
+            .with_context(|| format!("line {i}"))?;
+    }
+
+    Ok(lines.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn serde_csv_round_trips() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut awkward = log_generator_seeded(&mut rng);
+        awkward.server_domain = "a,\"quoted\"\ndomain".to_string();
+        awkward.extensions = vec![
+            Extension {
+                kind: 7,
+                data: vec![0x00, 0xFF, 0x10],
+            },
+            Extension {
+                kind: 8,
+                data: Vec::new(),
+            },
+        ];
+        awkward.geo = Some(GeoInfo {
+            country: *b"NL",
+            asn: 1136,
+        });
+
+        let mut logs = logs(200);
+        logs.push(awkward.build().unwrap());
+
+        let mut csv = Vec::new();
+        PlayerLogSerializer::write_csv_serde(&logs, &mut csv).unwrap();
+        assert_eq!(
+            PlayerLogSerializer::read_csv_serde(csv.as_slice()).unwrap(),
+            logs
+        );
+    }
+
+    #[test]
+    fn malformed_rows_name_the_row() {
+        let mut csv = Vec::new();
+        PlayerLogSerializer::write_csv_serde(&logs(3), &mut csv).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let header = csv.lines().next().unwrap();
+        let row = csv.lines().nth(2).unwrap();
+        let columns = header.split(',').collect::<Vec<_>>();
+
+        let broken = |column: &str, value: &str| {
+            let at = columns.iter().position(|c| *c == column).unwrap();
+            let mut fields = row.split(',').collect::<Vec<_>>();
+            fields[at] = value;
+            let data = format!(
+                "{header}\n{}\n{}\n",
+                csv.lines().nth(1).unwrap(),
+                fields.join(",")
+            );
+            PlayerLogSerializer::read_csv_serde(data.as_bytes()).unwrap_err()
+        };
+
+        for (column, value) in [
+            ("extensions", "7"),
+            ("extensions", "7=abc"),
+            ("extensions", "x=00"),
+            ("country", "NL"),
+            ("player_ip", "not an ip"),
+        ] {
+            let e = broken(column, value);
+            assert_eq!(e.to_string(), "row 2", "{column}={value}: {e:#}");
+        }
+    }
+}
@@ -0,0 +1,296 @@
+//! Splitting one stream of logs into a store per route, e.g. one file per server domain.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+use anyhow::{Context, Result};
+use sha2::{Digest, Sha256};
+
+use super::storage::LogStore;
+use super::PlayerLog;
+
+/// Where a [`FanoutWriter`] sends a log. Any string works as a key, use
+/// [`RouteKey::file_name`] to put one in a path.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RouteKey(String);
+
+impl RouteKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    /// Routes by the lowercased server domain, invalid utf8 is replaced.
+    pub fn domain(log: &PlayerLog) -> Self {
+        Self(String::from_utf8_lossy(&log.server_domain).to_lowercase())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// The key as a single path component: ascii letters, digits, `-`, `_` and `.`, not starting
+    /// with a dot and at most 89 bytes. A key that had to be changed gets a hash of the original
+    /// appended, so two keys never share a file name.
+    pub fn file_name(&self) -> String {
+        let mut name: String = self
+            .0
+            .chars()
+            .take(80)
+            .map(|c| {
+                if c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.') {
+                    c
+                } else {
+                    '_'
+                }
+            })
+            .collect();
+        if name.starts_with('.') {
+            name.replace_range(..1, "_");
+        }
+
+        if name != self.0 || name.is_empty() {
+            let digest = Sha256::digest(self.0.as_bytes());
+            name.push('-');
+            name.extend(digest[..4].iter().map(|b| format!("{b:02x}")));
+        }
+
+        name
+    }
+}
+
+impl fmt::Display for RouteKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+struct OpenStore<S> {
+    store: S,
+    last_used: u64,
+}
+
+/// Appends every pushed log to the store of its route, opening stores as their keys first show
+/// up.
+///
+/// At most [`FanoutWriter::max_open`] stores are open at once, pushing to another key flushes
+/// and closes the least recently used one. `open_store` is called again if that key comes back,
+/// so it has to append to what's already there, like [`FileStore::open`] does:
+///
+/// ```no_run
+/// # use std::path::Path;
+/// # use binary_storage_test::player_log::{fanout::{FanoutWriter, RouteKey}, storage::FileStore};
+/// let dir = Path::new("archive");
+/// let writer = FanoutWriter::new(RouteKey::domain, |key: &RouteKey| {
+///     FileStore::open(&dir.join(key.file_name()))
+/// });
+/// ```
+///
+/// [`FileStore::open`]: super::storage::FileStore::open
+pub struct FanoutWriter<R, O, S> {
+    route: R,
+    open_store: O,
+    max_open: usize,
+    open: HashMap<RouteKey, OpenStore<S>>,
+    counts: BTreeMap<RouteKey, u64>,
+    pushes: u64,
+}
+
+impl<R, O, S> FanoutWriter<R, O, S>
+where
+    R: Fn(&PlayerLog) -> RouteKey,
+    O: FnMut(&RouteKey) -> Result<S>,
+    S: LogStore,
+{
+    pub const DEFAULT_MAX_OPEN: usize = 64;
+
+    pub fn new(route: R, open_store: O) -> Self {
+        Self {
+            route,
+            open_store,
+            max_open: Self::DEFAULT_MAX_OPEN,
+            open: HashMap::new(),
+            counts: BTreeMap::new(),
+            pushes: 0,
+        }
+    }
+
+    /// Caps the stores kept open at once, at least one always is.
+    pub fn with_max_open(mut self, max_open: usize) -> Self {
+        self.max_open = max_open.max(1);
+        self
+    }
+
+    pub const fn max_open(&self) -> usize {
+        self.max_open
+    }
+
+    pub fn push(&mut self, log: &PlayerLog) -> Result<()> {
+        let key = (self.route)(log);
+        self.pushes += 1;
+
+        if !self.open.contains_key(&key) {
+            if self.open.len() >= self.max_open {
+                self.close_least_recent()?;
+            }
+
+            let store = (self.open_store)(&key).with_context(|| format!("opening {key}"))?;
+            self.open.insert(
+                key.clone(),
+                OpenStore {
+                    store,
+                    last_used: 0,
+                },
+            );
+        }
+
+        let open = self.open.get_mut(&key).expect("opened above");
+        open.last_used = self.pushes;
+        open.store
+            .append(log)
+            .with_context(|| format!("appending to {key}"))?;
+        *self.counts.entry(key).or_default() += 1;
+
+        Ok(())
+    }
+
+    // a linear scan, eviction is rare next to pushes and max_open is small
+    fn close_least_recent(&mut self) -> Result<()> {
+        let Some(key) = self
+            .open
+            .iter()
+            .min_by_key(|(_, open)| open.last_used)
+            .map(|(key, _)| key.clone())
+        else {
+            return Ok(());
+        };
+
+        let mut open = self.open.remove(&key).expect("found above");
+        open.store.flush().with_context(|| format!("closing {key}"))
+    }
+
+    /// Flushes and closes every open store, returning how many logs went to each key.
+    pub fn close_all(mut self) -> Result<BTreeMap<RouteKey, u64>> {
+        for (key, open) in &mut self.open {
+            open.store
+                .flush()
+                .with_context(|| format!("closing {key}"))?;
+        }
+
+        Ok(self.counts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    use anyhow::bail;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::storage::FileStore;
+    use super::super::storage_impl::{ByteStorage, DomainBytes};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    // round robin over `domains`
+    fn logs(count: usize, domains: &[&str]) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|i| {
+                let mut builder = log_generator_seeded(&mut rng);
+                builder.server_domain = domains[i % domains.len()].to_string();
+                builder.build().unwrap()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn file_names_are_safe_and_distinct() {
+        assert_eq!(
+            RouteKey::new("mc.example.com").file_name(),
+            "mc.example.com"
+        );
+
+        let keys = [
+            "../etc/passwd",
+            ".hidden",
+            "",
+            "a/b",
+            "a_b",
+            "ü",
+            &"x".repeat(100),
+        ];
+        let names = keys.map(|key| RouteKey::new(key).file_name());
+        for (key, name) in keys.iter().zip(&names) {
+            assert!(!name.is_empty() && !name.starts_with('.'), "{key:?} {name}");
+            assert!(name.len() <= 89, "{key:?} {name}");
+            assert!(
+                name.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b"-_.".contains(&b)),
+                "{key:?} {name}"
+            );
+        }
+        assert_eq!(names[4], "a_b");
+        assert!(names[3].starts_with("a_b-"));
+        assert_eq!(names.iter().collect::<HashSet<_>>().len(), names.len());
+    }
+
+    #[test]
+    fn domains_route_case_insensitively() {
+        let mut log = PlayerLog {
+            server_domain: DomainBytes::from_slice(b"Play.Example.COM"),
+            ..PlayerLog::default()
+        };
+        assert_eq!(RouteKey::domain(&log).as_str(), "play.example.com");
+        log.server_domain = DomainBytes::from_slice(b"mc.\xFFexample.com");
+        assert_eq!(RouteKey::domain(&log).as_str(), "mc.\u{FFFD}example.com");
+    }
+
+    #[test]
+    fn evicted_stores_reopen_and_append() {
+        let dir = tempfile::tempdir().unwrap();
+        let domains = ["a.com", "b.com", "c.com"];
+        let logs = logs(30, &domains);
+
+        let mut opens = Vec::new();
+        let mut writer = FanoutWriter::new(RouteKey::domain, |key: &RouteKey| {
+            opens.push(key.clone());
+            FileStore::open(&dir.path().join(key.file_name()))
+        })
+        .with_max_open(2);
+        for log in &logs {
+            writer.push(log).unwrap();
+        }
+        let counts = writer.close_all().unwrap();
+
+        // round robin over three keys with two open evicts on every push
+        assert_eq!(opens.len(), 30);
+        for (i, domain) in domains.iter().enumerate() {
+            assert_eq!(counts[&RouteKey::new(*domain)], 10);
+            let stored = FileStore::read_all(&dir.path().join(domain)).unwrap();
+            let expected = logs.iter().skip(i).step_by(3).cloned().collect::<Vec<_>>();
+            assert_eq!(stored, expected, "{domain}");
+        }
+    }
+
+    #[test]
+    fn failures_name_the_key() {
+        fn refuse(key: &RouteKey) -> Result<FileStore> {
+            bail!("no store for {key}")
+        }
+        let logs = logs(1, &["a.com"]);
+
+        let mut writer = FanoutWriter::new(RouteKey::domain, refuse).with_max_open(0);
+        assert_eq!(writer.max_open(), 1);
+        let e = writer.push(&logs[0]).unwrap_err();
+        assert_eq!(format!("{e:#}"), "opening a.com: no store for a.com");
+
+        let missing = Path::new("/nonexistent/dir");
+        let mut writer = FanoutWriter::new(RouteKey::domain, |key: &RouteKey| {
+            FileStore::open(&missing.join(key.file_name()))
+        });
+        let e = writer.push(&logs[0]).unwrap_err();
+        assert!(format!("{e:#}").starts_with("opening a.com"), "{e:#}");
+    }
+}
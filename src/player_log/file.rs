@@ -0,0 +1,224 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use fs2::FileExt;
+
+use super::net::{read_frame, write_frame};
+use super::storage::{recover_frames, LogStore};
+use super::PlayerLog;
+
+// the fs2 calls are spelled out, std's File has its own lock methods with the same names
+
+/// A [`FileStore`](super::storage::FileStore) holding an exclusive lock on the file until dropped.
+///
+/// Writers in other processes or threads take turns. The lock is an advisory `flock` on unix and
+/// `LockFileEx` on windows, readers and writers that don't lock aren't held up.
+pub struct LockedFileWriter {
+    writer: BufWriter<File>,
+    scratch: Vec<u8>,
+    torn_bytes: u64,
+}
+
+impl LockedFileWriter {
+    /// Blocks until no other [`LockedFileWriter`] or [`LockedFileReader`] has the file open, then
+    /// opens it for appending, creating it if it doesn't exist. A torn last frame is truncated
+    /// away like [`FileStore::open`](super::storage::FileStore::open) does, which is safe here
+    /// since no other locked writer can be in the middle of it.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        FileExt::lock_exclusive(&file).with_context(|| format!("locking {}", path.display()))?;
+        let (_, torn_bytes) = recover_frames(&file)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            scratch: Vec::with_capacity(PlayerLog::MAX_ENCODED_SIZE),
+            torn_bytes,
+        })
+    }
+
+    /// Bytes of a torn last frame [`LockedFileWriter::open`] cut off.
+    pub const fn torn_bytes(&self) -> u64 {
+        self.torn_bytes
+    }
+
+    pub fn append(&mut self, log: &PlayerLog) -> Result<()> {
+        write_frame(&mut self.writer, log, &mut self.scratch)
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl LogStore for LockedFileWriter {
+    fn append(&mut self, log: &PlayerLog) -> Result<()> {
+        Self::append(self, log)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Self::flush(self)
+    }
+}
+
+impl Drop for LockedFileWriter {
+    // buffered records have to hit the file while the lock is still held
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+        let _ = FileExt::unlock(self.writer.get_ref());
+    }
+}
+
+/// Reads a framed log file under a shared lock, any number of readers can hold it at once but
+/// none while a [`LockedFileWriter`] has the file.
+pub struct LockedFileReader {
+    reader: BufReader<File>,
+}
+
+impl LockedFileReader {
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = File::open(path)?;
+        FileExt::lock_shared(&file).with_context(|| format!("locking {}", path.display()))?;
+
+        Ok(Self {
+            reader: BufReader::new(file),
+        })
+    }
+
+    /// `None` once every record has been read.
+    pub fn read_log(&mut self) -> Result<Option<PlayerLog>> {
+        read_frame(&mut self.reader)
+    }
+
+    pub fn read_all(&mut self) -> Result<Vec<PlayerLog>> {
+        let mut logs = Vec::new();
+        while let Some(log) = self.read_log()? {
+            logs.push(log);
+        }
+
+        Ok(logs)
+    }
+}
+
+impl Drop for LockedFileReader {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(self.reader.get_ref());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::sync::mpsc;
+    use std::thread;
+    use std::time::Duration;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn locked_writes_read_back() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins");
+        let logs = logs(20);
+
+        let mut writer = LockedFileWriter::open(&path).unwrap();
+        for log in &logs[..10] {
+            writer.append(log).unwrap();
+        }
+        drop(writer);
+
+        // a second writer appends behind the first one's records
+        let mut writer = LockedFileWriter::open(&path).unwrap();
+        assert_eq!(writer.torn_bytes(), 0);
+        for log in &logs[10..] {
+            LogStore::append(&mut writer, log).unwrap();
+        }
+        drop(writer);
+
+        let mut reader = LockedFileReader::open(&path).unwrap();
+        assert_eq!(reader.read_all().unwrap(), logs);
+        assert!(reader.read_log().unwrap().is_none());
+    }
+
+    #[test]
+    fn a_torn_last_frame_is_cut_off() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins");
+        let logs = logs(3);
+
+        let mut writer = LockedFileWriter::open(&path).unwrap();
+        for log in &logs[..2] {
+            writer.append(log).unwrap();
+        }
+        drop(writer);
+        let len = fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let mut writer = LockedFileWriter::open(&path).unwrap();
+        assert!(writer.torn_bytes() > 0);
+        writer.append(&logs[2]).unwrap();
+        drop(writer);
+
+        let read = LockedFileReader::open(&path).unwrap().read_all().unwrap();
+        assert_eq!(read, [logs[0].clone(), logs[2].clone()]);
+    }
+
+    #[test]
+    fn readers_share_and_writers_dont() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins");
+        let logs = logs(1);
+        LockedFileWriter::open(&path).unwrap();
+
+        let reader = LockedFileReader::open(&path).unwrap();
+        let second = LockedFileReader::open(&path).unwrap();
+        let probe = File::open(&path).unwrap();
+        assert!(FileExt::try_lock_exclusive(&probe).is_err());
+        drop(reader);
+        drop(second);
+
+        let mut writer = LockedFileWriter::open(&path).unwrap();
+        assert!(FileExt::try_lock_shared(&probe).is_err());
+
+        // a writer in another thread waits until this one is dropped
+        let (sent, opened) = mpsc::channel();
+        let waiting = {
+            let path = path.clone();
+            let log = logs[0].clone();
+            thread::spawn(move || {
+                let mut writer = LockedFileWriter::open(&path).unwrap();
+                sent.send(()).unwrap();
+                writer.append(&log).unwrap();
+            })
+        };
+        assert!(opened.recv_timeout(Duration::from_millis(100)).is_err());
+        writer.append(&logs[0]).unwrap();
+        drop(writer);
+        opened.recv_timeout(Duration::from_secs(10)).unwrap();
+        waiting.join().unwrap();
+
+        let read = LockedFileReader::open(&path).unwrap().read_all().unwrap();
+        assert_eq!(read, [logs[0].clone(), logs[0].clone()]);
+    }
+}
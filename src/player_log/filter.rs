@@ -0,0 +1,164 @@
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, Context, Result};
+
+use super::query::FilterExpr;
+use super::{PlayerLog, VERSIONS};
+
+/// Criteria a log has to meet to be kept, the default keeps everything.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PlayerLogFilter {
+    // inclusive server version ids
+    pub min_version: Option<u8>,
+    pub max_version: Option<u8>,
+    /// Only logs that came through this proxy.
+    pub proxy_ip: Option<Ipv4Addr>,
+    /// Only logs the expression holds for, see [`query`](super::query).
+    pub expr: Option<FilterExpr>,
+}
+
+fn version_id(name: &str) -> Result<u8> {
+    VERSIONS
+        .get(name)
+        .copied()
+        .with_context(|| format!("invalid server version {name}"))
+}
+
+impl PlayerLogFilter {
+    /// Keeps logs from `min_name` up to and including `max_name`.
+    pub fn by_version_range(min_name: &str, max_name: &str) -> Result<Self> {
+        let (min, max) = (version_id(min_name)?, version_id(max_name)?);
+        if min > max {
+            bail!("version range {min_name} to {max_name} is empty");
+        }
+
+        Ok(Self {
+            min_version: Some(min),
+            max_version: Some(max),
+            ..Self::default()
+        })
+    }
+
+    pub fn by_min_version(name: &str) -> Result<Self> {
+        Ok(Self {
+            min_version: Some(version_id(name)?),
+            ..Self::default()
+        })
+    }
+
+    pub fn by_max_version(name: &str) -> Result<Self> {
+        Ok(Self {
+            max_version: Some(version_id(name)?),
+            ..Self::default()
+        })
+    }
+
+    pub fn by_proxy(proxy_ip: Ipv4Addr) -> Self {
+        Self {
+            proxy_ip: Some(proxy_ip),
+            ..Self::default()
+        }
+    }
+
+    /// Parses `query`, a parse error is a [`QueryError`](super::query::QueryError) pointing at the
+    /// offending token.
+    pub fn by_query(query: &str) -> Result<Self> {
+        Ok(Self {
+            expr: Some(FilterExpr::parse(query)?),
+            ..Self::default()
+        })
+    }
+
+    /// Unknown server versions never match a version bound, there's no telling where they fall.
+    pub fn matches(&self, log: &PlayerLog) -> bool {
+        let id = log.server_version.id();
+
+        self.min_version
+            .is_none_or(|min| id.is_some_and(|id| id >= min))
+            && self
+                .max_version
+                .is_none_or(|max| id.is_some_and(|id| id <= max))
+            && self
+                .proxy_ip
+                .is_none_or(|proxy_ip| log.proxy_ip == Some(proxy_ip.octets()))
+            && self.expr.as_ref().is_none_or(|expr| expr.matches(log))
+    }
+
+    pub fn retain(&self, logs: &mut Vec<PlayerLog>) {
+        logs.retain(|log| self.matches(log));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::ServerVersion;
+    use super::*;
+
+    fn versions() -> Vec<PlayerLog> {
+        ["1.8", "1.12", "1.16", "1.21", "24w14a"]
+            .into_iter()
+            .map(|name| PlayerLog {
+                server_version: ServerVersion::from_name(name).unwrap(),
+                ..PlayerLog::default()
+            })
+            .collect()
+    }
+
+    fn kept(filter: &PlayerLogFilter) -> Vec<String> {
+        let mut logs = versions();
+        filter.retain(&mut logs);
+        logs.iter()
+            .map(|log| log.server_version.name().unwrap().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn version_bounds_are_inclusive() {
+        let range = PlayerLogFilter::by_version_range("1.12", "1.16").unwrap();
+        assert_eq!(kept(&range), ["1.12", "1.16"]);
+        assert_eq!(
+            kept(&PlayerLogFilter::by_min_version("1.16").unwrap()),
+            ["1.16", "1.21"]
+        );
+        assert_eq!(
+            kept(&PlayerLogFilter::by_max_version("1.12").unwrap()),
+            ["1.8", "1.12"]
+        );
+        assert_eq!(
+            kept(&PlayerLogFilter::by_version_range("1.21", "1.21").unwrap()),
+            ["1.21"]
+        );
+    }
+
+    #[test]
+    fn unknown_versions_only_pass_without_bounds() {
+        assert_eq!(kept(&PlayerLogFilter::default()).len(), 5);
+        assert_eq!(
+            kept(&PlayerLogFilter::by_min_version("1.8").unwrap()),
+            ["1.8", "1.12", "1.16", "1.21"]
+        );
+    }
+
+    #[test]
+    fn bad_ranges_fail() {
+        let e = PlayerLogFilter::by_version_range("1.16", "1.12").unwrap_err();
+        assert!(e.to_string().contains("is empty"), "{e}");
+        assert!(PlayerLogFilter::by_min_version("1.7").is_err());
+        assert!(PlayerLogFilter::by_version_range("1.8", "24w14a").is_err());
+    }
+
+    #[test]
+    fn proxy_filters_only_keep_that_proxy() {
+        let proxy = Ipv4Addr::new(172, 16, 0, 1);
+        let mut logs = [None, Some([172, 16, 0, 1]), Some([172, 16, 0, 2])]
+            .map(|proxy_ip| PlayerLog {
+                proxy_ip,
+                ..PlayerLog::default()
+            })
+            .to_vec();
+
+        PlayerLogFilter::by_proxy(proxy).retain(&mut logs);
+        assert_eq!(logs.len(), 1);
+        assert_eq!(logs[0].proxy_ip, Some(proxy.octets()));
+    }
+}
@@ -0,0 +1,44 @@
+//! A fast hash of who logged in where, for deduplicating logs without comparing them field by
+//! field.
+//!
+//! Unlike [`super::verify::content_hash`] it isn't cryptographic and leaves out the
+//! binary_version 2 fields, so a log and its upgraded copy share a fingerprint.
+
+use std::hash::Hasher;
+
+use twox_hash::XxHash64;
+
+use super::{parallel, PlayerLog, ServerVersion};
+
+/// XxHash64 seeded with 0 over, in order: the flags, the uuid (16 zero bytes without one), the
+/// name, the player ip, the server ip, the port, the domain and the server version.
+///
+/// Integers are big endian, the name and domain are prefixed with their length byte, and the
+/// version is its id, or [`ServerVersion::UNKNOWN_ID`] followed by the name for an unknown one.
+pub fn xxhash(log: &PlayerLog) -> u64 {
+    let mut hasher = XxHash64::with_seed(0);
+
+    hasher.write(&log.flags.to_be_bytes());
+    hasher.write(&log.player_uuid.unwrap_or_default());
+    hasher.write(&[log.player_name.len() as u8]);
+    hasher.write(&log.player_name);
+    hasher.write(&log.player_ip);
+    hasher.write(&log.server_ip);
+    hasher.write(&log.server_port.to_be_bytes());
+    hasher.write(&[log.server_domain.len() as u8]);
+    hasher.write(&log.server_domain);
+    match &log.server_version {
+        ServerVersion::Known(id) => hasher.write(&[*id]),
+        ServerVersion::Unknown(name) => {
+            hasher.write(&[ServerVersion::UNKNOWN_ID]);
+            hasher.write(name.as_bytes());
+        }
+    }
+
+    hasher.finish()
+}
+
+/// [`xxhash`] of every log, in order.
+pub fn batch_fingerprint(logs: &[PlayerLog]) -> Vec<u64> {
+    parallel::map_items(logs, xxhash)
+}
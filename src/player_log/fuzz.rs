@@ -0,0 +1,85 @@
+use std::fs;
+use std::path::Path;
+
+use anyhow::Result;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::{LogFlags, PlayerLog, PlayerLogBuilder, VERSIONS};
+
+fn record(flags: LogFlags, name_len: usize, domain_len: usize, version: &str) -> Vec<u8> {
+    let log = PlayerLogBuilder {
+        player_uuid: flags
+            .contains(LogFlags::IS_ONLINE)
+            .then(|| Uuid::from_bytes([0x5A; 16])),
+        flags,
+        player_name: "n".repeat(name_len),
+        player_ip: [10, 0, 0, 1].into(),
+        server_ip: [10, 0, 0, 2].into(),
+        server_port: 25565,
+        server_domain: "d".repeat(domain_len),
+        server_version: version.to_string(),
+        timestamp: None,
+        session_id: 0,
+        extensions: Vec::new(),
+        client_brand: None,
+        client_locale: None,
+        outcome: Default::default(),
+        geo: None,
+        proxy_ip: None,
+    }
+    .build()
+    .expect("corpus logs are valid");
+
+    let mut out = Vec::with_capacity(log.serialized_size());
+    log.serialize(&mut out).expect("corpus logs are valid");
+    out
+}
+
+/// Single records to seed a fuzzer of [`PlayerLog::deserialize`] with, valid ones at every
+/// boundary plus a few that have to be rejected.
+pub fn corpus_entries() -> Vec<Vec<u8>> {
+    let mut versions = VERSIONS.entries().collect::<Vec<_>>();
+    versions.sort_by_key(|(_, id)| **id);
+
+    let mut entries = versions
+        .iter()
+        .map(|(name, _)| record(LogFlags::empty(), 8, 16, name))
+        .collect::<Vec<_>>();
+
+    for name_len in [0, PlayerLog::MAX_NAME_LEN] {
+        entries.push(record(LogFlags::empty(), name_len, 16, "1.8"));
+    }
+    for domain_len in [0, PlayerLog::MAX_DOMAIN_LEN] {
+        entries.push(record(LogFlags::empty(), 8, domain_len, "1.8"));
+    }
+    for bits in 0..=3 {
+        entries.push(record(LogFlags::from_bits_retain(bits), 4, 16, "1.21"));
+    }
+
+    // cut halfway through the uuid
+    let online = record(LogFlags::IS_ONLINE, 8, 16, "1.21");
+    entries.push(online[..2 + PlayerLog::UUID_LEN / 2].to_vec());
+
+    entries.push(vec![0; PlayerLog::MIN_ENCODED_SIZE]);
+    entries.push(vec![0xFF; PlayerLog::MIN_ENCODED_SIZE]);
+
+    entries
+}
+
+/// Writes every corpus entry to `dir` (e.g. `fuzz/corpus/<target>`), named by its sha256 so
+/// writing it again doesn't duplicate entries.
+pub fn write_corpus(dir: &Path) -> Result<usize> {
+    fs::create_dir_all(dir)?;
+
+    let entries = corpus_entries();
+    for entry in &entries {
+        let name = Sha256::digest(entry)
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect::<String>();
+        fs::write(dir.join(name), entry)?;
+    }
+
+    Ok(entries.len())
+}
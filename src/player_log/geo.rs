@@ -0,0 +1,81 @@
+#[cfg(feature = "maxmind")]
+use std::net::{IpAddr, Ipv4Addr};
+#[cfg(feature = "maxmind")]
+use std::path::Path;
+
+#[cfg(feature = "maxmind")]
+use anyhow::Result;
+
+use super::{Extension, PlayerLog};
+
+/// The extension kind [`PlayerLog::with_country`] writes, the data is the 2 country code bytes.
+pub const COUNTRY_EXTENSION: u8 = 0xC0;
+
+/// Looks up the ISO 3166-1 alpha-2 country of an ip, e.g. `*b"NL"`.
+pub trait GeoResolver {
+    fn resolve_country(&self, ip: [u8; 4]) -> Option<[u8; 2]>;
+}
+
+impl PlayerLog {
+    /// Appends the player ip's country as a [`COUNTRY_EXTENSION`], upgrading the log to
+    /// binary_version 2 if it has to.
+    ///
+    /// The log is returned as it is if the resolver doesn't know the ip or gives something other
+    /// than two uppercase letters, if it already has a country, or if it has no room for another
+    /// extension.
+    pub fn with_country(mut self, resolver: &dyn GeoResolver) -> Self {
+        if self.country().is_some() || self.extensions.len() >= Self::MAX_EXTENSIONS {
+            return self;
+        }
+        let Some(country) = resolver
+            .resolve_country(self.player_ip)
+            .filter(|country| country.iter().all(u8::is_ascii_uppercase))
+        else {
+            return self;
+        };
+
+        self.upgrade();
+        self.extensions.push(Extension {
+            kind: COUNTRY_EXTENSION,
+            data: country.to_vec(),
+        });
+        self
+    }
+
+    /// The country of the geo info, or else of a [`COUNTRY_EXTENSION`].
+    pub fn country(&self) -> Option<[u8; 2]> {
+        self.geo.map(|geo| geo.country).or_else(|| {
+            self.extensions
+                .iter()
+                .find(|extension| extension.kind == COUNTRY_EXTENSION)
+                .and_then(|extension| extension.data.as_slice().try_into().ok())
+        })
+    }
+}
+
+/// A GeoIP2 or GeoLite2 country (or city) database, read into memory once.
+#[cfg(feature = "maxmind")]
+pub struct MaxmindDbResolver {
+    reader: maxminddb::Reader<Vec<u8>>,
+}
+
+#[cfg(feature = "maxmind")]
+impl MaxmindDbResolver {
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            reader: maxminddb::Reader::open_readfile(path)?,
+        })
+    }
+}
+
+#[cfg(feature = "maxmind")]
+impl GeoResolver for MaxmindDbResolver {
+    fn resolve_country(&self, ip: [u8; 4]) -> Option<[u8; 2]> {
+        let record = self
+            .reader
+            .lookup::<maxminddb::geoip2::Country>(IpAddr::V4(Ipv4Addr::from(ip)))
+            .ok()??;
+
+        record.country?.iso_code?.as_bytes().try_into().ok()
+    }
+}
@@ -0,0 +1,51 @@
+use std::collections::{HashMap, HashSet};
+
+use super::PlayerLog;
+
+/// A server as its ip and port, the domain a player typed isn't part of it.
+pub type ServerAddr = ([u8; 4], u16);
+
+/// Which servers every player name logged into and which names every server saw, for spotting
+/// players moving between servers of a network.
+///
+/// Names are kept as they were logged, so names differing in case are different players.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionGraph {
+    pub players: HashMap<Vec<u8>, HashSet<ServerAddr>>,
+    pub servers: HashMap<ServerAddr, HashSet<Vec<u8>>>,
+}
+
+impl ConnectionGraph {
+    pub fn build(logs: &[PlayerLog]) -> Self {
+        let mut graph = Self::default();
+        for log in logs {
+            let server = (log.server_ip, log.server_port);
+
+            graph
+                .players
+                .entry(log.player_name.to_vec())
+                .or_default()
+                .insert(server);
+            graph
+                .servers
+                .entry(server)
+                .or_default()
+                .insert(log.player_name.to_vec());
+        }
+
+        graph
+    }
+
+    /// Empty for a name that never logged in.
+    pub fn player_servers<'a>(&'a self, name: &[u8]) -> impl Iterator<Item = ServerAddr> + 'a {
+        self.players.get(name).into_iter().flatten().copied()
+    }
+
+    pub fn server_players<'a>(&'a self, server: ServerAddr) -> impl Iterator<Item = &'a [u8]> + 'a {
+        self.servers
+            .get(&server)
+            .into_iter()
+            .flatten()
+            .map(Vec::as_slice)
+    }
+}
@@ -0,0 +1,286 @@
+//! Limits on what a single batch from upstream may contain, checked before it's decoded for real.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use anyhow::{Context, Result};
+
+use super::projection::{PartialPlayerLog, Projection};
+use super::{PlayerLog, PlayerLogSerializer};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IngestGuards {
+    pub max_records_per_batch: u64,
+    /// Distinct server domains, a dictionary or index gets one entry for each.
+    pub max_unique_domains: usize,
+    pub max_domain_len: usize,
+    /// Records sharing a uuid, or a name for offline players.
+    pub max_records_per_player: usize,
+}
+
+impl Default for IngestGuards {
+    fn default() -> Self {
+        Self {
+            max_records_per_batch: 5_000_000,
+            max_unique_domains: 100_000,
+            max_domain_len: PlayerLog::MAX_DOMAIN_LEN,
+            max_records_per_player: 100_000,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Guard {
+    RecordsPerBatch,
+    UniqueDomains,
+    DomainLen,
+    RecordsPerPlayer,
+}
+
+/// `observed` is the value when the guard tripped, counting stops at the first one over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GuardTripped {
+    pub guard: Guard,
+    pub limit: u64,
+    pub observed: u64,
+}
+
+impl fmt::Display for GuardTripped {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{:?} guard tripped, {} is over the limit of {}",
+            self.guard, self.observed, self.limit
+        )
+    }
+}
+
+impl std::error::Error for GuardTripped {}
+
+#[derive(PartialEq, Eq, Hash)]
+enum PlayerKey {
+    Uuid([u8; 16]),
+    Name(Vec<u8>),
+}
+
+impl IngestGuards {
+    /// Checks `data` with one pass that only decodes the uuid, name and domain of each record.
+    /// The domain set stops growing at its limit, the per player counts at the batch size.
+    pub fn check(&self, data: &[u8]) -> Result<()> {
+        let (header, header_len) = PlayerLogSerializer::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        trip(
+            Guard::RecordsPerBatch,
+            self.max_records_per_batch,
+            header.record_count,
+        )?;
+
+        let projection = Projection::UUID | Projection::NAME | Projection::DOMAIN;
+        let mut reader = header.codec.decoder(&data[header_len..]);
+        let mut domains = HashSet::new();
+        let mut players = HashMap::<_, usize>::new();
+        for i in 0..header.record_count {
+            let log = PartialPlayerLog::deserialize_with_endianness(
+                &mut reader,
+                projection,
+                header.endianness,
+            )
+            .with_context(|| format!("record {i}"))?;
+            let domain = log.server_domain.unwrap_or_default();
+            let key = match log.player_uuid.filter(|uuid| *uuid != [0; 16]) {
+                Some(uuid) => PlayerKey::Uuid(uuid),
+                None => PlayerKey::Name(log.player_name.unwrap_or_default()),
+            };
+
+            self.check_record(domain, key, &mut domains, &mut players)
+                .with_context(|| format!("record {i}"))?;
+        }
+        super::check_payload_end(&mut reader)?;
+
+        Ok(())
+    }
+
+    fn check_record(
+        &self,
+        domain: Vec<u8>,
+        key: PlayerKey,
+        domains: &mut HashSet<Vec<u8>>,
+        players: &mut HashMap<PlayerKey, usize>,
+    ) -> Result<()> {
+        trip(
+            Guard::DomainLen,
+            self.max_domain_len as u64,
+            domain.len() as u64,
+        )?;
+
+        domains.insert(domain);
+        trip(
+            Guard::UniqueDomains,
+            self.max_unique_domains as u64,
+            domains.len() as u64,
+        )?;
+
+        let count = players.entry(key).or_default();
+        *count += 1;
+        trip(
+            Guard::RecordsPerPlayer,
+            self.max_records_per_player as u64,
+            *count as u64,
+        )?;
+
+        Ok(())
+    }
+}
+
+const fn trip(guard: Guard, limit: u64, observed: u64) -> Result<(), GuardTripped> {
+    if observed > limit {
+        return Err(GuardTripped {
+            guard,
+            limit,
+            observed,
+        });
+    }
+
+    Ok(())
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::deserialize_many`] after [`IngestGuards::check`] passes, no log is
+    /// built for a batch that trips a guard. The error downcasts to
+    /// [`GuardTripped`].
+    pub fn guarded_deserialize(data: &[u8], guards: &IngestGuards) -> Result<Vec<PlayerLog>> {
+        guards.check(data)?;
+        Self::deserialize_many(data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+    use super::super::{Codec, SerializerOptions};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn offline(name: &str, domain: &str) -> PlayerLog {
+        PlayerLog {
+            binary_version: 1,
+            player_name: NameBytes::from_slice(name.as_bytes()),
+            server_port: PlayerLog::DEFAULT_PORT,
+            server_domain: DomainBytes::from_slice(domain.as_bytes()),
+            ..PlayerLog::default()
+        }
+    }
+
+    fn tripped(logs: &[PlayerLog], guards: &IngestGuards) -> (GuardTripped, String) {
+        let options = SerializerOptions {
+            codec: Codec::Lz4,
+            ..SerializerOptions::default()
+        };
+        let data = PlayerLogSerializer::serialize_many_with_options(logs, &options).unwrap();
+        let e = PlayerLogSerializer::guarded_deserialize(&data, guards).unwrap_err();
+        let message = format!("{e:#}");
+        (*e.downcast_ref::<GuardTripped>().unwrap(), message)
+    }
+
+    #[test]
+    fn batches_under_every_limit_decode() {
+        let logs = logs(200);
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let guards = IngestGuards {
+            max_records_per_batch: 200,
+            max_records_per_player: 200,
+            ..IngestGuards::default()
+        };
+        assert_eq!(
+            PlayerLogSerializer::guarded_deserialize(&data, &guards).unwrap(),
+            logs
+        );
+    }
+
+    #[test]
+    fn each_guard_trips_at_its_first_record_over() {
+        let logs = logs(10);
+        let guards = IngestGuards {
+            max_records_per_batch: 9,
+            ..IngestGuards::default()
+        };
+        let (e, message) = tripped(&logs, &guards);
+        assert_eq!(
+            (e.guard, e.limit, e.observed),
+            (Guard::RecordsPerBatch, 9, 10)
+        );
+        assert_eq!(
+            message,
+            "RecordsPerBatch guard tripped, 10 is over the limit of 9"
+        );
+
+        let domains = ["a.com", "b.com", "a.com", "c.com"].map(|domain| offline("Steve", domain));
+        let guards = IngestGuards {
+            max_unique_domains: 2,
+            ..IngestGuards::default()
+        };
+        let (e, message) = tripped(&domains, &guards);
+        assert_eq!((e.guard, e.limit, e.observed), (Guard::UniqueDomains, 2, 3));
+        assert!(message.starts_with("record 3: "), "{message}");
+
+        let guards = IngestGuards {
+            max_domain_len: 4,
+            ..IngestGuards::default()
+        };
+        let (e, message) = tripped(&domains, &guards);
+        assert_eq!((e.guard, e.limit, e.observed), (Guard::DomainLen, 4, 5));
+        assert!(message.starts_with("record 0: "), "{message}");
+    }
+
+    #[test]
+    fn players_are_counted_by_uuid_then_name() {
+        let guards = IngestGuards {
+            max_records_per_player: 2,
+            ..IngestGuards::default()
+        };
+        let online = |uuid: u8| PlayerLog {
+            binary_version: 1,
+            flags: super::super::LogFlags::IS_ONLINE.bits(),
+            player_uuid: Some([uuid; 16]),
+            ..offline("Steve", "mc.example.com")
+        };
+
+        // the same name under two uuids, and offline, is three players
+        let logs = [online(1), online(2), offline("Steve", "a.com"), online(1)];
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        assert!(guards.check(&data).is_ok());
+
+        let logs = [online(1), online(2), online(1), online(1)];
+        let (e, message) = tripped(&logs, &guards);
+        assert_eq!(
+            (e.guard, e.limit, e.observed),
+            (Guard::RecordsPerPlayer, 2, 3)
+        );
+        assert!(message.starts_with("record 3: "), "{message}");
+
+        // a nil uuid is counted under the name, like offline records
+        let logs = [online(0), offline("Steve", "a.com"), online(0)];
+        let (e, message) = tripped(&logs, &guards);
+        assert_eq!(e.guard, Guard::RecordsPerPlayer);
+        assert!(message.starts_with("record 2: "), "{message}");
+    }
+
+    #[test]
+    fn truncated_batches_fail_without_tripping() {
+        let data = PlayerLogSerializer::serialize_many(&logs(5)).unwrap();
+        let e = IngestGuards::default()
+            .check(&data[..data.len() - 2])
+            .unwrap_err();
+        assert!(e.downcast_ref::<GuardTripped>().is_none());
+        assert!(format!("{e:#}").starts_with("record 4"), "{e:#}");
+    }
+}
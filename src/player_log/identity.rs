@@ -0,0 +1,223 @@
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+
+use super::PlayerLog;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum NameCase {
+    /// Minecraft names are unique regardless of case.
+    #[default]
+    Insensitive,
+    Sensitive,
+}
+
+impl NameCase {
+    fn key(self, name: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Insensitive => name.to_ascii_lowercase(),
+            Self::Sensitive => name.to_vec(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum PlayerKey {
+    Uuid([u8; 16]),
+    Name(Vec<u8>),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct IdentityMap {
+    pub case: NameCase,
+    /// Name keys that belong to exactly one online uuid within the batch.
+    pub names: HashMap<Vec<u8>, [u8; 16]>,
+    /// Name keys seen with more than one uuid, these are never linked.
+    pub ambiguous: Vec<Vec<u8>>,
+}
+
+impl IdentityMap {
+    pub fn uuid_for(&self, name: &[u8]) -> Option<[u8; 16]> {
+        self.names.get(&self.case.key(name)).copied()
+    }
+
+    pub fn key_for(&self, log: &PlayerLog) -> PlayerKey {
+        log.identity_uuid()
+            .or_else(|| self.uuid_for(&log.player_name))
+            .map_or_else(
+                || PlayerKey::Name(self.case.key(&log.player_name)),
+                PlayerKey::Uuid,
+            )
+    }
+}
+
+/// Links names to the uuids of the online records in the batch, so offline records of the same
+/// player can be merged with their online ones.
+pub fn reconcile_identities(logs: &[PlayerLog], case: NameCase) -> IdentityMap {
+    let mut names = HashMap::new();
+    let mut ambiguous = HashSet::new();
+
+    for log in logs {
+        let Some(uuid) = log.identity_uuid() else {
+            continue;
+        };
+
+        let key = case.key(&log.player_name);
+        if ambiguous.contains(&key) {
+            continue;
+        }
+
+        match names.entry(key) {
+            Entry::Vacant(e) => {
+                e.insert(uuid);
+            }
+            Entry::Occupied(e) if *e.get() != uuid => {
+                ambiguous.insert(e.remove_entry().0);
+            }
+            Entry::Occupied(_) => {}
+        }
+    }
+
+    let mut ambiguous = ambiguous.into_iter().collect::<Vec<_>>();
+    ambiguous.sort();
+
+    IdentityMap {
+        case,
+        names,
+        ambiguous,
+    }
+}
+
+/// Groups logs by uuid, falling back to the player name for offline records. With an
+/// [`IdentityMap`] offline records are merged into the group of their linked uuid.
+pub fn group_by_player<'a>(
+    logs: &'a [PlayerLog],
+    identities: Option<&IdentityMap>,
+) -> HashMap<PlayerKey, Vec<&'a PlayerLog>> {
+    let mut groups = HashMap::<PlayerKey, Vec<&PlayerLog>>::new();
+
+    for log in logs {
+        let key = match (identities, log.identity_uuid()) {
+            (Some(identities), _) => identities.key_for(log),
+            (None, Some(uuid)) => PlayerKey::Uuid(uuid),
+            (None, None) => PlayerKey::Name(log.player_name.to_vec()),
+        };
+
+        groups.entry(key).or_default().push(log);
+    }
+
+    groups
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::storage_impl::{ByteStorage, NameBytes};
+    use super::*;
+
+    fn log(name: &str, uuid: Option<u8>) -> PlayerLog {
+        PlayerLog {
+            player_name: NameBytes::from_slice(name.as_bytes()),
+            player_uuid: uuid.map(|byte| [byte; 16]),
+            ..PlayerLog::default()
+        }
+    }
+
+    #[test]
+    fn names_with_two_uuids_are_never_linked() {
+        let logs = [
+            log("Steve", Some(1)),
+            log("steve", Some(2)),
+            log("Steve", Some(1)),
+            log("Alex", Some(3)),
+            log("Steve", None),
+        ];
+        let identities = reconcile_identities(&logs, NameCase::Insensitive);
+
+        assert_eq!(identities.ambiguous, [b"steve".to_vec()]);
+        assert_eq!(identities.uuid_for(b"Steve"), None);
+        assert_eq!(identities.uuid_for(b"ALEX"), Some([3; 16]));
+        assert_eq!(
+            identities.key_for(&logs[4]),
+            PlayerKey::Name(b"steve".to_vec())
+        );
+    }
+
+    #[test]
+    fn offline_only_names_stay_names() {
+        let logs = [log("Herobrine", None), log("Herobrine", Some(0))];
+        let identities = reconcile_identities(&logs, NameCase::Insensitive);
+
+        assert!(identities.names.is_empty());
+        assert!(identities.ambiguous.is_empty());
+        for log in &logs {
+            assert_eq!(
+                identities.key_for(log),
+                PlayerKey::Name(b"herobrine".to_vec())
+            );
+        }
+
+        let groups = group_by_player(&logs, Some(&identities));
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[&PlayerKey::Name(b"herobrine".to_vec())].len(), 2);
+    }
+
+    #[test]
+    fn sensitive_names_only_link_the_exact_case() {
+        let logs = [log("Steve", Some(1)), log("steve", Some(2))];
+
+        let sensitive = reconcile_identities(&logs, NameCase::Sensitive);
+        assert!(sensitive.ambiguous.is_empty());
+        assert_eq!(sensitive.uuid_for(b"Steve"), Some([1; 16]));
+        assert_eq!(sensitive.uuid_for(b"steve"), Some([2; 16]));
+        assert_eq!(sensitive.uuid_for(b"STEVE"), None);
+        assert_eq!(
+            sensitive.key_for(&log("STEVE", None)),
+            PlayerKey::Name(b"STEVE".to_vec())
+        );
+
+        let insensitive = reconcile_identities(&logs, NameCase::Insensitive);
+        assert_eq!(insensitive.ambiguous, [b"steve".to_vec()]);
+    }
+
+    #[test]
+    fn offline_records_merge_into_their_uuid() {
+        let logs = [
+            log("Steve", Some(1)),
+            log("STEVE", None),
+            log("Alex", None),
+            log("Steve", Some(1)),
+        ];
+
+        let groups = group_by_player(&logs, None);
+        assert_eq!(groups.len(), 3);
+        assert_eq!(groups[&PlayerKey::Uuid([1; 16])].len(), 2);
+        assert_eq!(groups[&PlayerKey::Name(b"STEVE".to_vec())].len(), 1);
+
+        let identities = reconcile_identities(&logs, NameCase::Insensitive);
+        let groups = group_by_player(&logs, Some(&identities));
+        assert_eq!(groups.len(), 2);
+        assert_eq!(
+            groups[&PlayerKey::Uuid([1; 16])],
+            [&logs[0], &logs[1], &logs[3]]
+        );
+        assert_eq!(groups[&PlayerKey::Name(b"alex".to_vec())], [&logs[2]]);
+    }
+
+    #[test]
+    fn nil_uuids_dont_group_different_players() {
+        let logs = [
+            log("Steve", Some(0)),
+            log("Alex", Some(0)),
+            log("Steve", None),
+        ];
+
+        for identities in [
+            None,
+            Some(&reconcile_identities(&logs, NameCase::Sensitive)),
+        ] {
+            let groups = group_by_player(&logs, identities);
+            assert_eq!(groups.len(), 2);
+            assert_eq!(groups[&PlayerKey::Name(b"Steve".to_vec())].len(), 2);
+            assert_eq!(groups[&PlayerKey::Name(b"Alex".to_vec())].len(), 1);
+        }
+    }
+}
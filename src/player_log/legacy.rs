@@ -0,0 +1,271 @@
+use std::io::Read;
+
+use anyhow::{anyhow, bail, Result};
+use flate2::read::ZlibDecoder;
+
+use super::{Codec, Endianness, PlayerLog, PlayerLogSerializer, SerializerOptions, BATCH_MAGIC};
+
+/// The header-less layouts written before the batch container existed: a u64 record count
+/// followed by the records, optionally with the whole thing zlib wrapped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LegacyLayout {
+    Uncompressed,
+    Compressed,
+}
+
+impl PlayerLogSerializer {
+    /// Works out which legacy layout `data` uses. Both layouts are fully validated, and input that
+    /// parses as neither (or, in theory, both) is rejected instead of guessed at.
+    pub fn detect_legacy(data: &[u8]) -> Result<LegacyLayout> {
+        Self::decode_legacy(data, Endianness::Big).map(|(layout, _, _)| layout)
+    }
+
+    /// Rewrites a legacy buffer into the batch container without touching the record bytes.
+    /// Buffers that are already in the container format are an error.
+    pub fn migrate_legacy(data: &[u8], codec: Codec) -> Result<Vec<u8>> {
+        Self::migrate_legacy_with_endianness(data, codec, Endianness::Big)
+    }
+
+    /// [`PlayerLogSerializer::migrate_legacy`] for a legacy buffer whose record count and records
+    /// were written in `endianness`, there is nothing in the buffer to tell. The batch keeps the
+    /// record bytes as they are and records the endianness in its header.
+    pub fn migrate_legacy_with_endianness(
+        data: &[u8],
+        codec: Codec,
+        endianness: Endianness,
+    ) -> Result<Vec<u8>> {
+        let (_, record_count, payload) = Self::decode_legacy(data, endianness)?;
+
+        let options = SerializerOptions {
+            codec,
+            endianness,
+            ..SerializerOptions::default()
+        };
+
+        Self::write_container(&payload, record_count, &options, None)
+    }
+
+    pub(super) fn decode_legacy(
+        data: &[u8],
+        endianness: Endianness,
+    ) -> Result<(LegacyLayout, u64, Vec<u8>)> {
+        if data.starts_with(&BATCH_MAGIC) {
+            bail!("buffer is already in the batch container format");
+        }
+
+        let uncompressed = validate_legacy(data, endianness);
+        let compressed = if looks_like_zlib(data) {
+            let mut inflated = Vec::new();
+            ZlibDecoder::new(data)
+                .read_to_end(&mut inflated)
+                .map_err(Into::into)
+                .and_then(|_| validate_legacy(&inflated, endianness).map(|count| (count, inflated)))
+        } else {
+            Err(anyhow!("no zlib header"))
+        };
+
+        pick_layout(data, uncompressed, compressed)
+    }
+}
+
+// a zlib header read as a record count claims far more records than the buffer holds, so both
+// only parse in theory
+fn pick_layout(
+    data: &[u8],
+    uncompressed: Result<u64>,
+    compressed: Result<(u64, Vec<u8>)>,
+) -> Result<(LegacyLayout, u64, Vec<u8>)> {
+    match (uncompressed, compressed) {
+        (Ok(count), Err(_)) => Ok((LegacyLayout::Uncompressed, count, data[8..].to_vec())),
+        (Err(_), Ok((count, mut inflated))) => {
+            inflated.drain(..8);
+            Ok((LegacyLayout::Compressed, count, inflated))
+        }
+        (Ok(_), Ok(_)) => bail!("buffer is valid as both legacy layouts, refusing to guess"),
+        (Err(uncompressed), Err(compressed)) => {
+            bail!("not a legacy batch (uncompressed: {uncompressed:#}, compressed: {compressed:#})")
+        }
+    }
+}
+
+// CMF has to say deflate and CMF/FLG together have to be a multiple of 31
+pub(super) fn looks_like_zlib(data: &[u8]) -> bool {
+    match data {
+        [cmf, flg, ..] => cmf & 0x0F == 8 && (u16::from(*cmf) << 8 | u16::from(*flg)) % 31 == 0,
+        _ => false,
+    }
+}
+
+// every record has to parse and the records have to end exactly at the end of the buffer
+fn validate_legacy(data: &[u8], endianness: Endianness) -> Result<u64> {
+    let Some((count, mut records)) = data.split_first_chunk::<8>() else {
+        bail!("too short for a record count");
+    };
+    let count = match endianness {
+        Endianness::Big => u64::from_be_bytes(*count),
+        Endianness::Little => u64::from_le_bytes(*count),
+    };
+
+    for i in 0..count {
+        if records.is_empty() {
+            bail!("ran out of data after {i} of {count} records");
+        }
+
+        PlayerLog::deserialize_with_endianness(&mut records, endianness)
+            .map_err(|e| e.context(format!("record {i}")))?;
+    }
+
+    if !records.is_empty() {
+        bail!("{} trailing bytes after {count} records", records.len());
+    }
+
+    Ok(count)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::ZlibEncoder;
+    use flate2::Compression;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    // what serialize_many wrote before the container: the count, then the records
+    fn legacy(logs: &[PlayerLog], endianness: Endianness) -> Vec<u8> {
+        let mut data = match endianness {
+            Endianness::Big => (logs.len() as u64).to_be_bytes(),
+            Endianness::Little => (logs.len() as u64).to_le_bytes(),
+        }
+        .to_vec();
+        for log in logs {
+            log.serialize_with_endianness(&mut data, endianness)
+                .unwrap();
+        }
+        data
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn uncompressed_legacy_keeps_its_record_bytes() {
+        let logs = logs(40);
+        let data = legacy(&logs, Endianness::Big);
+        assert_eq!(
+            PlayerLogSerializer::detect_legacy(&data).unwrap(),
+            LegacyLayout::Uncompressed
+        );
+
+        let migrated = PlayerLogSerializer::migrate_legacy(&data, Codec::None).unwrap();
+        assert!(migrated.ends_with(&data[8..]));
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&migrated).unwrap(),
+            logs
+        );
+
+        let empty = legacy(&[], Endianness::Big);
+        let migrated = PlayerLogSerializer::migrate_legacy(&empty, Codec::Lz4).unwrap();
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&migrated).unwrap(),
+            []
+        );
+    }
+
+    #[test]
+    fn compressed_legacy_is_inflated() {
+        let logs = logs(40);
+        let data = zlib(&legacy(&logs, Endianness::Big));
+        assert_eq!(
+            PlayerLogSerializer::detect_legacy(&data).unwrap(),
+            LegacyLayout::Compressed
+        );
+
+        let migrated = PlayerLogSerializer::migrate_legacy(&data, Codec::Lz4).unwrap();
+        let header = PlayerLogSerializer::probe(&migrated).unwrap();
+        assert_eq!(header.codec, Codec::Lz4);
+        assert_eq!(header.record_count, 40);
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&migrated).unwrap(),
+            logs
+        );
+    }
+
+    #[test]
+    fn little_endian_legacy_needs_to_be_told() {
+        let logs = logs(10);
+        let data = legacy(&logs, Endianness::Little);
+        assert!(PlayerLogSerializer::migrate_legacy(&data, Codec::None).is_err());
+
+        let migrated = PlayerLogSerializer::migrate_legacy_with_endianness(
+            &data,
+            Codec::None,
+            Endianness::Little,
+        )
+        .unwrap();
+        assert_eq!(
+            PlayerLogSerializer::probe(&migrated).unwrap().endianness,
+            Endianness::Little
+        );
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&migrated).unwrap(),
+            logs
+        );
+    }
+
+    #[test]
+    fn migrated_batches_are_an_error() {
+        let data = legacy(&logs(5), Endianness::Big);
+        let migrated = PlayerLogSerializer::migrate_legacy(&data, Codec::None).unwrap();
+
+        let e = PlayerLogSerializer::migrate_legacy(&migrated, Codec::None).unwrap_err();
+        assert!(e.to_string().contains("already"), "{e}");
+    }
+
+    #[test]
+    fn garbage_is_not_legacy() {
+        let data = legacy(&logs(5), Endianness::Big);
+        for cut in [0, 7, 8, data.len() - 1] {
+            assert!(
+                PlayerLogSerializer::detect_legacy(&data[..cut]).is_err(),
+                "{cut}"
+            );
+        }
+        let mut trailing = data;
+        trailing.push(0);
+        assert!(PlayerLogSerializer::detect_legacy(&trailing).is_err());
+
+        let rng = &mut StdRng::seed_from_u64(1);
+        for _ in 0..100 {
+            let len = rng.gen_range(0..512);
+            let garbage: Vec<u8> = (0..len).map(|_| rng.gen()).collect();
+            assert!(PlayerLogSerializer::detect_legacy(&garbage).is_err());
+            assert!(PlayerLogSerializer::detect_legacy(&zlib(&garbage)).is_err());
+        }
+    }
+
+    #[test]
+    fn valid_as_both_layouts_fails() {
+        let e = pick_layout(&[0; 8], Ok(0), Ok((0, vec![0; 8]))).unwrap_err();
+        assert!(e.to_string().contains("refusing to guess"), "{e}");
+
+        let (layout, count, payload) = pick_layout(&[0; 8], Ok(0), Err(anyhow!("no"))).unwrap();
+        assert_eq!(
+            (layout, count, payload),
+            (LegacyLayout::Uncompressed, 0, vec![])
+        );
+        assert!(pick_layout(&[0; 8], Err(anyhow!("no")), Err(anyhow!("no"))).is_err());
+    }
+}
@@ -0,0 +1,512 @@
+use std::error::Error;
+use std::fmt;
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::net::TcpStream;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+
+use super::{PlayerLog, PlayerLogSerializer};
+
+// caps the up front allocation so a bogus count can't take the process down
+const MAX_PREALLOCATED_LOGS: u64 = 1 << 16;
+const MAX_FRAME_LEN: u32 = PlayerLog::MAX_ENCODED_SIZE as u32;
+/// How long [`recv_tcp`] waits on a single read when the stream has no read timeout of its own.
+pub const RECV_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Fails any single `read` of the inner reader that took longer than `timeout` with
+/// [`ErrorKind::TimedOut`].
+///
+/// A plain reader can only be timed once its read returns, so it has to be one that returns on
+/// its own, e.g. a socket with a read timeout. A slow read that did return still hands over its
+/// bytes, the read after it fails instead, and so does every read after a timeout.
+pub struct TimedReader<R: Read> {
+    pub reader: R,
+    pub timeout: Duration,
+    timed_out: bool,
+}
+
+impl<R: Read> TimedReader<R> {
+    pub const fn new(reader: R, timeout: Duration) -> Self {
+        Self {
+            reader,
+            timeout,
+            timed_out: false,
+        }
+    }
+}
+
+impl<R: Read> Read for TimedReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let timed_out = |timeout: Duration| {
+            io::Error::new(ErrorKind::TimedOut, format!("no data within {timeout:?}"))
+        };
+        if self.timed_out {
+            return Err(timed_out(self.timeout));
+        }
+
+        let start = Instant::now();
+        match self.reader.read(buf) {
+            // a socket read timeout shows up as WouldBlock on unix
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                self.timed_out = true;
+                Err(timed_out(self.timeout))
+            }
+            Ok(n) if start.elapsed() > self.timeout => {
+                self.timed_out = true;
+                Ok(n)
+            }
+            result => result,
+        }
+    }
+}
+
+/// Writes the 8 byte record count followed by the serialized records.
+pub fn send_tcp(logs: &[PlayerLog], stream: &mut TcpStream) -> Result<()> {
+    let mut writer = BufWriter::new(stream);
+
+    writer.write_u64::<BigEndian>(logs.len() as u64)?;
+    logs.iter().try_for_each(|log| log.serialize(&mut writer))?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads a batch written by [`send_tcp`]. Reads are buffered, so the stream shouldn't be reused
+/// for anything else afterwards.
+///
+/// A sender that stalls for longer than the stream's read timeout, or [`RECV_TIMEOUT`] if it has
+/// none, fails the receive with [`ErrorKind::TimedOut`]. The stream's own read timeout is put
+/// back before returning either way.
+pub fn recv_tcp(stream: &mut TcpStream) -> Result<Vec<PlayerLog>> {
+    let previous = stream.read_timeout()?;
+    let timeout = previous.unwrap_or(RECV_TIMEOUT);
+    stream.set_read_timeout(Some(timeout))?;
+
+    let logs = recv_counted(TimedReader::new(&mut *stream, timeout));
+    stream.set_read_timeout(previous)?;
+
+    logs
+}
+
+fn recv_counted<R: Read>(reader: TimedReader<R>) -> Result<Vec<PlayerLog>> {
+    let mut reader = BufReader::new(reader);
+
+    let count = reader.read_u64::<BigEndian>()?;
+    let mut logs = Vec::with_capacity(count.min(MAX_PREALLOCATED_LOGS) as usize);
+    for _ in 0..count {
+        logs.push(PlayerLog::deserialize(&mut reader)?);
+    }
+
+    Ok(logs)
+}
+
+/// Writes every record as its own length delimited frame, there is no up front count.
+pub fn send_tcp_framed(logs: &[PlayerLog], stream: &mut TcpStream) -> Result<()> {
+    let mut writer = BufWriter::new(stream);
+    let mut scratch = Vec::with_capacity(PlayerLog::MAX_ENCODED_SIZE);
+
+    logs.iter()
+        .try_for_each(|log| write_frame(&mut writer, log, &mut scratch))?;
+
+    writer.flush()?;
+    Ok(())
+}
+
+/// Reads frames until the sender closes its side of the connection. Times out like
+/// [`recv_tcp`].
+pub fn recv_tcp_framed(stream: &mut TcpStream) -> Result<Vec<PlayerLog>> {
+    let previous = stream.read_timeout()?;
+    let timeout = previous.unwrap_or(RECV_TIMEOUT);
+    stream.set_read_timeout(Some(timeout))?;
+
+    let logs = recv_frames(TimedReader::new(&mut *stream, timeout));
+    stream.set_read_timeout(previous)?;
+
+    logs
+}
+
+fn recv_frames<R: Read>(reader: TimedReader<R>) -> Result<Vec<PlayerLog>> {
+    let mut reader = BufReader::new(reader);
+
+    let mut logs = Vec::new();
+    while let Some(log) = read_frame(&mut reader)? {
+        logs.push(log);
+    }
+
+    Ok(logs)
+}
+
+/// What a [`PlayerLogIter`] does when the stream ends in the middle of a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TruncationPolicy {
+    /// Yields the read error, like any other failed read.
+    #[default]
+    ReturnError,
+    /// Treats the torn frame as the end of the stream, the records before it are kept.
+    StopIteration,
+    /// Yields a [`TruncatedRecord`] carrying where the torn frame started.
+    YieldPartialError,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct IterConfig {
+    pub on_truncation: TruncationPolicy,
+}
+
+/// The stream ended inside the frame starting `offset` bytes into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TruncatedRecord {
+    pub offset: u64,
+}
+
+impl fmt::Display for TruncatedRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "stream ended inside the record at byte {}", self.offset)
+    }
+}
+
+impl Error for TruncatedRecord {}
+
+/// Reads length delimited frames one record at a time. Iteration stops after the first error.
+pub struct PlayerLogIter<R: Read> {
+    reader: R,
+    config: IterConfig,
+    frame: Vec<u8>,
+    offset: u64,
+    done: bool,
+}
+
+impl<R: Read> PlayerLogIter<R> {
+    pub fn new(reader: R) -> Self {
+        Self::with_config(reader, IterConfig::default())
+    }
+
+    pub fn with_config(reader: R, config: IterConfig) -> Self {
+        Self {
+            reader,
+            config,
+            frame: Vec::with_capacity(PlayerLog::MAX_ENCODED_SIZE),
+            offset: 0,
+            done: false,
+        }
+    }
+
+    /// Bytes of the stream consumed by the records read so far.
+    pub const fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+impl<R: Read> Iterator for PlayerLogIter<R> {
+    type Item = Result<PlayerLog>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = match read_frame_bytes(&mut self.reader, &mut self.frame) {
+            Ok(true) => {
+                self.offset += 4 + self.frame.len() as u64;
+                match decode_frame(&self.frame) {
+                    Ok(log) => return Some(Ok(log)),
+                    Err(e) => Err(e),
+                }
+            }
+            Ok(false) => {
+                self.done = true;
+                return None;
+            }
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => match self.config.on_truncation {
+                TruncationPolicy::ReturnError => Err(e.into()),
+                TruncationPolicy::StopIteration => {
+                    self.done = true;
+                    return None;
+                }
+                TruncationPolicy::YieldPartialError => Err(TruncatedRecord {
+                    offset: self.offset,
+                }
+                .into()),
+            },
+            Err(e) => Err(e.into()),
+        };
+
+        self.done = true;
+        Some(result)
+    }
+}
+
+pub(crate) fn write_frame<W: Write>(
+    writer: &mut W,
+    log: &PlayerLog,
+    scratch: &mut Vec<u8>,
+) -> Result<()> {
+    scratch.clear();
+    log.serialize(scratch)?;
+
+    writer.write_u32::<BigEndian>(scratch.len() as u32)?;
+    writer.write_all(scratch)?;
+
+    Ok(())
+}
+
+/// Returns `None` on a clean end of stream between frames.
+pub(crate) fn read_frame<R: Read>(reader: &mut R) -> Result<Option<PlayerLog>> {
+    let mut frame = Vec::new();
+    if !read_frame_bytes(reader, &mut frame)? {
+        return Ok(None);
+    }
+
+    decode_frame(&frame).map(Some)
+}
+
+/// Reads the next frame's record bytes into `frame`, `false` on a clean end between frames. A
+/// stream ending inside a frame fails with [`ErrorKind::UnexpectedEof`].
+pub(crate) fn read_frame_bytes<R: Read>(reader: &mut R, frame: &mut Vec<u8>) -> io::Result<bool> {
+    let mut len = [0; 4];
+    let mut filled = 0;
+    while filled < len.len() {
+        match reader.read(&mut len[filled..]) {
+            Ok(0) if filled == 0 => return Ok(false),
+            Ok(0) => {
+                return Err(io::Error::new(
+                    ErrorKind::UnexpectedEof,
+                    "stream ended inside a frame length",
+                ))
+            }
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    let len = u32::from_be_bytes(len);
+    if len > MAX_FRAME_LEN {
+        return Err(io::Error::new(
+            ErrorKind::InvalidData,
+            format!("frame length {len} is too large"),
+        ));
+    }
+
+    frame.resize(len as usize, 0);
+    reader.read_exact(frame)?;
+
+    Ok(true)
+}
+
+pub(crate) fn decode_frame(frame: &[u8]) -> Result<PlayerLog> {
+    let mut cursor = frame;
+    let log = PlayerLog::deserialize(&mut cursor)?;
+    if !cursor.is_empty() {
+        bail!("{} trailing bytes in frame", cursor.len());
+    }
+
+    Ok(log)
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::deserialize_many`] on a batch read from `reader` up to its end,
+    /// through a [`TimedReader`] so a stalled source fails instead of hanging.
+    pub fn deserialize_many_timed(reader: impl Read, timeout: Duration) -> Result<Vec<PlayerLog>> {
+        let mut data = Vec::new();
+        TimedReader::new(reader, timeout)
+            .read_to_end(&mut data)
+            .context("reading the batch")?;
+
+        Self::deserialize_many(&data)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::TcpListener;
+    use std::thread;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::{BatchHeader, ProbeResult, SerializerOptions};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    // a connected pair over loopback, the sender's end first
+    fn connected() -> (TcpStream, TcpStream) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let sender = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+        let (receiver, _) = listener.accept().unwrap();
+        (sender, receiver)
+    }
+
+    fn timed_out(e: &anyhow::Error) -> bool {
+        e.chain()
+            .filter_map(|cause| cause.downcast_ref::<io::Error>())
+            .any(|e| e.kind() == ErrorKind::TimedOut)
+    }
+
+    #[test]
+    fn batches_cross_a_loopback_connection() {
+        let logs = logs(100);
+        let (mut sender, mut receiver) = connected();
+
+        let sent = logs.clone();
+        let sending = thread::spawn(move || send_tcp(&sent, &mut sender));
+        assert_eq!(recv_tcp(&mut receiver).unwrap(), logs);
+        sending.join().unwrap().unwrap();
+
+        // the stream had no timeout of its own, and still hasn't
+        assert_eq!(receiver.read_timeout().unwrap(), None);
+    }
+
+    #[test]
+    fn a_stalled_sender_times_out_and_keeps_the_stream_timeout() {
+        let (mut sender, mut receiver) = connected();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        // the kernel rounds the timeout to its tick
+        let timeout = receiver.read_timeout().unwrap();
+
+        // the count promises a record that never comes
+        sender.write_u64::<BigEndian>(1).unwrap();
+        let e = recv_tcp(&mut receiver).unwrap_err();
+        assert!(timed_out(&e), "{e:#}");
+        assert_eq!(receiver.read_timeout().unwrap(), timeout);
+        drop(sender);
+    }
+
+    // returns its bytes only after sleeping past the timeout
+    struct Slow<'a>(&'a [u8]);
+
+    impl Read for Slow<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            thread::sleep(Duration::from_millis(20));
+            self.0.read(buf)
+        }
+    }
+
+    #[test]
+    fn framed_batches_cross_and_time_out() {
+        let logs = logs(100);
+        let (mut sender, mut receiver) = connected();
+
+        let sent = logs.clone();
+        let sending = thread::spawn(move || send_tcp_framed(&sent, &mut sender));
+        assert_eq!(recv_tcp_framed(&mut receiver).unwrap(), logs);
+        sending.join().unwrap().unwrap();
+        assert_eq!(receiver.read_timeout().unwrap(), None);
+
+        let (mut sender, mut receiver) = connected();
+        receiver
+            .set_read_timeout(Some(Duration::from_millis(50)))
+            .unwrap();
+        let timeout = receiver.read_timeout().unwrap();
+
+        // a whole frame, then a sender that stays connected without closing
+        send_tcp_framed(&logs[..1], &mut sender).unwrap();
+        let e = recv_tcp_framed(&mut receiver).unwrap_err();
+        assert!(timed_out(&e), "{e:#}");
+        assert_eq!(receiver.read_timeout().unwrap(), timeout);
+        drop(sender);
+    }
+
+    // hands out a single byte per read, like a connection that trickles in
+    struct Trickle<'a>(&'a [u8]);
+
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let len = buf.len().min(1);
+            self.0.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn frames_read_a_byte_at_a_time() {
+        let logs = logs(20);
+        let mut data = Vec::new();
+        let mut scratch = Vec::new();
+        for log in &logs {
+            write_frame(&mut data, log, &mut scratch).unwrap();
+        }
+
+        let mut iter = PlayerLogIter::new(Trickle(&data));
+        let read = iter.by_ref().collect::<Result<Vec<_>>>().unwrap();
+        assert_eq!(read, logs);
+        assert_eq!(iter.offset(), data.len() as u64);
+
+        let torn = &data[..data.len() - 1];
+        let config = IterConfig {
+            on_truncation: TruncationPolicy::StopIteration,
+        };
+        let read = PlayerLogIter::with_config(Trickle(torn), config)
+            .collect::<Result<Vec<_>>>()
+            .unwrap();
+        assert_eq!(read, logs[..19]);
+    }
+
+    #[test]
+    fn probing_a_byte_at_a_time_asks_for_what_is_missing() {
+        let options = SerializerOptions {
+            producer: Some("probe".to_string()),
+            ..SerializerOptions::default()
+        };
+        let data = PlayerLogSerializer::serialize_many_with_options(&logs(3), &options).unwrap();
+        let header_len = PlayerLogSerializer::probe(&data).unwrap().encoded_len();
+
+        let mut needed = Vec::new();
+        for len in 0..=header_len {
+            match PlayerLogSerializer::probe_prefix(&data[..len]).unwrap() {
+                ProbeResult::NeedMoreData(more) => {
+                    // never asks for more than the header still lacks
+                    assert!(len + more <= header_len, "{len} {more}");
+                    needed.push(more);
+                }
+                ProbeResult::Complete {
+                    header,
+                    header_len: parsed_len,
+                } => {
+                    assert_eq!(len, header_len);
+                    assert_eq!(parsed_len, header_len);
+                    assert_eq!(header.producer, Some("probe"));
+                    assert_eq!(header.record_count, 3);
+                }
+            }
+        }
+
+        // the prefix first, then the rest of the header once its length is known
+        let prefix = BatchHeader::PREFIX_SIZE;
+        let expected = (1..=prefix)
+            .rev()
+            .chain((1..=header_len - prefix).rev())
+            .collect::<Vec<_>>();
+        assert_eq!(needed, expected);
+
+        let mut wrong = data[..2].to_vec();
+        wrong[1] ^= 0xFF;
+        assert!(PlayerLogSerializer::probe_prefix(&wrong).is_err());
+    }
+
+    #[test]
+    fn a_late_read_keeps_its_bytes_and_fails_the_next_one() {
+        let mut reader = TimedReader::new(Slow(b"abc"), Duration::from_millis(1));
+
+        let mut buf = [0; 8];
+        assert_eq!(reader.read(&mut buf).unwrap(), 3);
+        assert_eq!(&buf[..3], b"abc");
+        assert_eq!(
+            reader.read(&mut buf).unwrap_err().kind(),
+            ErrorKind::TimedOut
+        );
+    }
+}
@@ -0,0 +1,236 @@
+//! Records addressed by their position in the batch they were written in.
+
+use std::fmt;
+use std::io::Read;
+
+use anyhow::{bail, Context, Result};
+
+use super::filter::PlayerLogFilter;
+use super::projection::{PartialPlayerLog, Projection};
+use super::{BatchId, Endianness, PlayerLog, PlayerLogSerializer};
+
+/// Record `ordinal` of batch `batch_id`, 20 bytes that stay valid however the batch is read back.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RecordRef {
+    pub batch_id: BatchId,
+    pub ordinal: u32,
+}
+
+impl RecordRef {
+    pub const fn new(batch_id: BatchId, ordinal: u32) -> Self {
+        Self { batch_id, ordinal }
+    }
+}
+
+impl fmt::Display for RecordRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.batch_id
+            .iter()
+            .try_for_each(|b| write!(f, "{b:02x}"))?;
+        write!(f, "#{}", self.ordinal)
+    }
+}
+
+/// Where every record of a batch starts in its decompressed payload, so any of them can be
+/// decoded without the ones before it.
+#[derive(Debug, Clone)]
+pub struct OffsetIndex {
+    batch_id: BatchId,
+    endianness: Endianness,
+    payload: Vec<u8>,
+    offsets: Vec<usize>,
+}
+
+impl OffsetIndex {
+    /// Decompresses the batch and walks its records once, without allocating any of them.
+    pub fn build(data: &[u8]) -> Result<Self> {
+        let (header, header_len) = PlayerLogSerializer::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        let record_count = header.ordinal_count()?;
+
+        let mut payload = Vec::new();
+        header
+            .codec
+            .decoder(&data[header_len..])
+            .read_to_end(&mut payload)
+            .context("corrupt payload")?;
+
+        let mut offsets = Vec::with_capacity(record_count.min(1 << 16) as usize);
+        let mut records = payload.as_slice();
+        for i in 0..record_count {
+            offsets.push(payload.len() - records.len());
+            PartialPlayerLog::deserialize_with_endianness(
+                &mut records,
+                Projection::empty(),
+                header.endianness,
+            )
+            .with_context(|| format!("record {i}"))?;
+        }
+        if !records.is_empty() {
+            bail!("{} bytes after the last record", records.len());
+        }
+
+        Ok(Self {
+            batch_id: header.batch_id,
+            endianness: header.endianness,
+            payload,
+            offsets,
+        })
+    }
+
+    pub const fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    pub const fn batch_id(&self) -> BatchId {
+        self.batch_id
+    }
+
+    /// Byte order of the records in the payload, a replaced record has to be written in it.
+    pub const fn endianness(&self) -> Endianness {
+        self.endianness
+    }
+
+    pub const fn record_ref(&self, ordinal: u32) -> RecordRef {
+        RecordRef::new(self.batch_id, ordinal)
+    }
+
+    /// Offset of record `ordinal` in the decompressed payload.
+    pub fn offset(&self, ordinal: u32) -> Option<usize> {
+        self.offsets.get(ordinal as usize).copied()
+    }
+
+    pub fn fetch(&self, ordinal: u32) -> Result<PlayerLog> {
+        let Some(offset) = self.offset(ordinal) else {
+            bail!(
+                "record {ordinal} out of range, the batch has {} records",
+                self.len()
+            );
+        };
+
+        let log =
+            PlayerLog::deserialize_with_endianness(&mut &self.payload[offset..], self.endianness)?;
+        if log.is_marker() {
+            bail!("record {ordinal} is a tombstone or gap");
+        }
+
+        Ok(log)
+    }
+
+    /// Replaces record `ordinal` with `record`, moving the offsets after it if the length changed.
+    pub(super) fn replace(&mut self, ordinal: u32, record: &[u8]) -> Result<()> {
+        let ordinal = ordinal as usize;
+        let Some(&start) = self.offsets.get(ordinal) else {
+            bail!(
+                "record {ordinal} out of range, the batch has {} records",
+                self.len()
+            );
+        };
+        let end = self
+            .offsets
+            .get(ordinal + 1)
+            .copied()
+            .unwrap_or(self.payload.len());
+
+        if end - start == record.len() {
+            self.payload[start..end].copy_from_slice(record);
+            return Ok(());
+        }
+
+        self.payload.splice(start..end, record.iter().copied());
+        for offset in &mut self.offsets[ordinal + 1..] {
+            *offset = *offset - (end - start) + record.len();
+        }
+
+        Ok(())
+    }
+
+    pub(super) fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    pub(super) const fn set_batch_id(&mut self, batch_id: BatchId) {
+        self.batch_id = batch_id;
+    }
+
+    /// Looks up `record`, failing if it points into another batch.
+    pub fn resolve(&self, record: RecordRef) -> Result<PlayerLog> {
+        if record.batch_id != self.batch_id {
+            bail!("{record} is not in this batch");
+        }
+
+        self.fetch(record.ordinal)
+    }
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::deserialize_many`] with every record paired with its ordinal.
+    pub fn deserialize_many_indexed(data: &[u8]) -> Result<Vec<(u32, PlayerLog)>> {
+        Self::probe(data)?.ordinal_count()?;
+
+        Ok((0..)
+            .zip(Self::deserialize_many_with_markers(data)?)
+            .filter(|(_, log)| !log.is_marker())
+            .collect())
+    }
+
+    /// The records `filter` keeps, each with its ordinal in the full batch rather than its
+    /// position among the kept ones.
+    pub fn deserialize_many_filtered(
+        data: &[u8],
+        filter: &PlayerLogFilter,
+    ) -> Result<Vec<(u32, PlayerLog)>> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        let record_count = header.ordinal_count()?;
+        let mut reader = header.codec.decoder(&data[header_len..]);
+
+        let mut logs = Vec::new();
+        for ordinal in 0..record_count {
+            let log = PlayerLog::deserialize_with_endianness(&mut reader, header.endianness)
+                .with_context(|| format!("record {ordinal}"))?;
+            if !log.is_marker() && filter.matches(&log) {
+                logs.push((ordinal, log));
+            }
+        }
+
+        super::check_payload_end(&mut reader)?;
+
+        Ok(logs)
+    }
+
+    /// Decodes only record `ordinal`, skipping over the ones before it. Build an [`OffsetIndex`]
+    /// to fetch more than a few records from the same batch.
+    pub fn fetch(data: &[u8], ordinal: u32) -> Result<PlayerLog> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        if u64::from(ordinal) >= header.record_count {
+            bail!(
+                "record {ordinal} out of range, the batch has {} records",
+                header.record_count
+            );
+        }
+
+        let mut reader = header.codec.decoder(&data[header_len..]);
+        for i in 0..ordinal {
+            PartialPlayerLog::deserialize_with_endianness(
+                &mut reader,
+                Projection::empty(),
+                header.endianness,
+            )
+            .with_context(|| format!("record {i} before the fetched one is invalid"))?;
+        }
+
+        let log = PlayerLog::deserialize_with_endianness(&mut reader, header.endianness)
+            .with_context(|| format!("record {ordinal}"))?;
+        if log.is_marker() {
+            bail!("record {ordinal} is a tombstone or gap");
+        }
+
+        Ok(log)
+    }
+}
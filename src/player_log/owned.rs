@@ -0,0 +1,289 @@
+//! Decoding a batch without copying the player names and server domains out of it.
+
+use std::io::Read;
+use std::net::Ipv4Addr;
+use std::ops::Range;
+
+use anyhow::{Context, Result};
+
+use super::projection::{PartialPlayerLog, Projection};
+use super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+use super::{Codec, LogFlags, PlayerLog, PlayerLogSerializer};
+
+/// A decoded record whose name and domain are left in the batch buffer.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct OwnedRecord {
+    // every field but the name and the domain, unless the domain is the server ip
+    fields: PlayerLog,
+    name: Range<usize>,
+    // None when the domain wasn't written and `fields` holds the one rebuilt from the server ip
+    domain: Option<Range<usize>>,
+}
+
+/// The records of a batch along with the buffer they were decoded from.
+///
+/// An uncompressed batch keeps the buffer it was read from, a compressed one is decompressed
+/// into a new buffer first. Either way no name or domain is copied until
+/// [`OwnedBatch::into_logs`] or [`OwnedLog::to_log`] asks for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedBatch {
+    data: Vec<u8>,
+    records: Vec<OwnedRecord>,
+}
+
+/// One record of an [`OwnedBatch`].
+#[derive(Debug, Clone, Copy)]
+pub struct OwnedLog<'a> {
+    data: &'a [u8],
+    record: &'a OwnedRecord,
+}
+
+impl<'a> OwnedLog<'a> {
+    pub fn player_name(&self) -> &'a [u8] {
+        &self.data[self.record.name.clone()]
+    }
+
+    pub fn server_domain(&self) -> &'a [u8] {
+        match &self.record.domain {
+            Some(domain) => &self.data[domain.clone()],
+            None => &self.record.fields.server_domain,
+        }
+    }
+
+    /// Every other field, the player name is left empty and so is the server domain unless it's
+    /// the server ip.
+    pub const fn fields(&self) -> &'a PlayerLog {
+        &self.record.fields
+    }
+
+    pub fn to_log(&self) -> PlayerLog {
+        PlayerLog {
+            player_name: NameBytes::from_slice(self.player_name()),
+            server_domain: DomainBytes::from_slice(self.server_domain()),
+            ..self.record.fields.clone()
+        }
+    }
+}
+
+impl OwnedBatch {
+    pub const fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    pub fn get(&self, index: usize) -> Option<OwnedLog<'_>> {
+        self.records.get(index).map(|record| OwnedLog {
+            data: &self.data,
+            record,
+        })
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = OwnedLog<'_>> + '_ {
+        self.records.iter().map(|record| OwnedLog {
+            data: &self.data,
+            record,
+        })
+    }
+
+    /// Copies every name and domain out into independent logs.
+    pub fn into_logs(self) -> Vec<PlayerLog> {
+        let data = self.data;
+        self.records
+            .into_iter()
+            .map(|mut record| {
+                record.fields.player_name = NameBytes::from_slice(&data[record.name]);
+                if let Some(domain) = record.domain {
+                    record.fields.server_domain = DomainBytes::from_slice(&data[domain]);
+                }
+                record.fields
+            })
+            .collect()
+    }
+}
+
+// the projection decodes everything the record stores apart from the name and domain
+fn into_fields(partial: PartialPlayerLog) -> PlayerLog {
+    PlayerLog {
+        binary_version: partial.binary_version,
+        flags: partial.flags,
+        player_uuid: partial.player_uuid,
+        player_ip: partial.player_ip.unwrap_or_default(),
+        server_ip: partial.server_ip.unwrap_or_default(),
+        server_port: partial.server_port.unwrap_or_default(),
+        server_version: partial.server_version.unwrap_or_default(),
+        timestamp: partial.timestamp.unwrap_or_default(),
+        session_id: partial.session_id.unwrap_or_default(),
+        outcome: partial.outcome.unwrap_or_default(),
+        geo: partial.geo,
+        proxy_ip: partial.proxy_ip,
+        extensions: partial.extensions.unwrap_or_default(),
+        client_brand: partial.client_brand.unwrap_or_default(),
+        client_locale: partial.client_locale.unwrap_or_default(),
+        ..PlayerLog::default()
+    }
+}
+
+// where the name and domain of the record starting at `start` sit, the projection already
+// checked their lengths
+fn field_ranges(
+    data: &[u8],
+    start: usize,
+    fields: &PlayerLog,
+) -> (Range<usize>, Option<Range<usize>>) {
+    let v2 = fields.binary_version >= 2;
+    let flags = LogFlags::from_bits_retain(fields.flags);
+
+    let mut pos = start + 1 + if v2 { 2 } else { 1 };
+    if flags.contains(LogFlags::IS_ONLINE) {
+        pos += PlayerLog::UUID_LEN;
+    }
+    let name = pos + 1..pos + 1 + data[pos] as usize;
+
+    // player ip, server ip and the port if it's written
+    pos = name.end + 8;
+    if !v2 || flags.contains(LogFlags::NONDEFAULT_PORT) {
+        pos += 2;
+    }
+    let domain = (!(v2 && flags.contains(LogFlags::DOMAIN_IS_IP)))
+        .then(|| pos + 1..pos + 1 + data[pos] as usize);
+
+    (name, domain)
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::deserialize_many`] taking ownership of `data`, see [`OwnedBatch`].
+    pub fn deserialize_many_owned(data: Vec<u8>) -> Result<OwnedBatch> {
+        let (header, header_len) = Self::probe_with_len(&data)?;
+        header.check_no_dictionary()?;
+        let record_count = header.record_count;
+        let endianness = header.endianness;
+
+        let (data, payload_start) = match header.codec {
+            Codec::None => (data, header_len),
+            codec => {
+                let mut payload = Vec::new();
+                codec
+                    .decoder(&data[header_len..])
+                    .read_to_end(&mut payload)
+                    .context("corrupt payload")?;
+                (payload, 0)
+            }
+        };
+
+        let projection = Projection::all().difference(Projection::NAME | Projection::DOMAIN);
+        let mut records = Vec::with_capacity(record_count.min(1 << 16) as usize);
+        let mut reader = &data[payload_start..];
+        for i in 0..record_count {
+            let start = data.len() - reader.len();
+            let partial =
+                PartialPlayerLog::deserialize_with_endianness(&mut reader, projection, endianness)
+                    .with_context(|| format!("record {i}"))?;
+
+            let mut fields = into_fields(partial);
+            if fields.is_marker() {
+                continue;
+            }
+            let (name, domain) = field_ranges(&data, start, &fields);
+            if domain.is_none() {
+                fields.server_domain = DomainBytes::from_vec(
+                    Ipv4Addr::from(fields.server_ip).to_string().into_bytes(),
+                );
+            }
+
+            records.push(OwnedRecord {
+                fields,
+                name,
+                domain,
+            });
+        }
+
+        Ok(OwnedBatch { data, records })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::{Endianness, SerializerOptions};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn owned_batches_decode_like_deserialize_many() {
+        let logs = logs(200);
+        assert!(logs
+            .iter()
+            .any(|log| { LogFlags::from_bits_retain(log.flags).contains(LogFlags::DOMAIN_IS_IP) }));
+
+        for codec in [Codec::None, Codec::Zlib(Compression::default()), Codec::Lz4] {
+            for endianness in [Endianness::Big, Endianness::Little] {
+                let options = SerializerOptions {
+                    codec,
+                    endianness,
+                    ..SerializerOptions::default()
+                };
+                let data =
+                    PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap();
+                let batch = PlayerLogSerializer::deserialize_many_owned(data).unwrap();
+
+                assert_eq!(batch.len(), logs.len());
+                for (owned, log) in batch.iter().zip(&logs) {
+                    assert_eq!(owned.player_name(), &*log.player_name);
+                    assert_eq!(owned.server_domain(), &*log.server_domain);
+                    assert!(owned.fields().player_name.is_empty());
+                    assert_eq!(owned.fields().session_id, log.session_id);
+                    assert_eq!(&owned.to_log(), log);
+                }
+                assert_eq!(batch.get(3).unwrap().to_log(), logs[3]);
+                assert!(batch.get(logs.len()).is_none());
+                assert_eq!(batch.into_logs(), logs, "{codec:?} {endianness:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn plain_batches_keep_their_buffer() {
+        let logs = logs(20);
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let buffer = data.as_ptr_range();
+
+        let batch = PlayerLogSerializer::deserialize_many_owned(data).unwrap();
+        for owned in batch.iter() {
+            assert!(buffer.contains(&owned.player_name().as_ptr()));
+        }
+    }
+
+    #[test]
+    fn empty_and_malformed_batches() {
+        let data = PlayerLogSerializer::serialize_many(&[]).unwrap();
+        assert!(PlayerLogSerializer::deserialize_many_owned(data)
+            .unwrap()
+            .is_empty());
+
+        let data = PlayerLogSerializer::serialize_many(&logs(5)).unwrap();
+        let e = PlayerLogSerializer::deserialize_many_owned(data[..data.len() - 2].to_vec())
+            .unwrap_err();
+        assert!(format!("{e:#}").starts_with("record 4"), "{e:#}");
+
+        let options = SerializerOptions {
+            codec: Codec::Zlib(Compression::default()),
+            ..SerializerOptions::default()
+        };
+        let data = PlayerLogSerializer::serialize_many_with_options(&logs(5), &options).unwrap();
+        let e = PlayerLogSerializer::deserialize_many_owned(data[..data.len() - 6].to_vec())
+            .unwrap_err();
+        assert!(format!("{e:#}").starts_with("corrupt payload"), "{e:#}");
+    }
+}
@@ -0,0 +1,32 @@
+//! Chunked loops that run on rayon with the `parallel` feature and on the calling thread without
+//! it. Results always come back in input order, so the output and the first error are the same
+//! either way.
+
+#[cfg(feature = "parallel")]
+use rayon::{iter::ParallelIterator, slice::ParallelSlice};
+
+// small enough to spread a few thousand items over every core, large enough to not matter
+const ITEM_CHUNK_LEN: usize = 256;
+
+/// Runs `f` on every chunk of `chunk_len` items, the last one may be shorter.
+pub fn for_each_chunk<T: Sync, R: Send>(
+    items: &[T],
+    chunk_len: usize,
+    f: impl Fn(&[T]) -> R + Sync + Send,
+) -> Vec<R> {
+    #[cfg(feature = "parallel")]
+    return items.par_chunks(chunk_len).map(f).collect();
+
+    #[cfg(not(feature = "parallel"))]
+    return items.chunks(chunk_len).map(f).collect();
+}
+
+/// [`for_each_chunk`] for a function of one item.
+pub fn map_items<T: Sync, R: Send>(items: &[T], f: impl Fn(&T) -> R + Sync + Send) -> Vec<R> {
+    for_each_chunk(items, ITEM_CHUNK_LEN, |chunk| {
+        chunk.iter().map(&f).collect::<Vec<_>>()
+    })
+    .into_iter()
+    .flatten()
+    .collect()
+}
@@ -0,0 +1,278 @@
+use std::fs::File;
+use std::path::Path;
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+use parquet::column::writer::ColumnWriter;
+use parquet::data_type::{ByteArray, FixedLenByteArray};
+use parquet::file::properties::WriterProperties;
+use parquet::file::writer::SerializedFileWriter;
+use parquet::schema::parser::parse_message_type;
+
+use super::{PlayerLog, ServerVersion};
+
+// geo is a nested group, extensions and alias_domains are 3-level lists so readers see them as
+// lists of structs and strings. the uuid logical type is only defined on 16 fixed bytes
+const SCHEMA: &str = "
+message player_log {
+    required int32 binary_version (INTEGER(8, false));
+    required int32 flags (INTEGER(16, false));
+    optional fixed_len_byte_array(16) player_uuid (UUID);
+    required binary player_name (STRING);
+    required fixed_len_byte_array(4) player_ip;
+    required fixed_len_byte_array(4) server_ip;
+    required int32 server_port (INTEGER(16, false));
+    required binary server_domain (STRING);
+    required group alias_domains (LIST) {
+        repeated group list {
+            required binary element (STRING);
+        }
+    }
+    required int32 server_version (INTEGER(8, false));
+    optional binary server_version_name (STRING);
+    optional int64 timestamp (TIMESTAMP(MILLIS, true));
+    required int64 session_id (INTEGER(64, false));
+    required int32 outcome (INTEGER(8, false));
+    optional group geo {
+        required binary country (STRING);
+        required int64 asn (INTEGER(64, false));
+    }
+    optional fixed_len_byte_array(4) proxy_ip;
+    optional binary client_brand (STRING);
+    optional binary client_locale (STRING);
+    required group extensions (LIST) {
+        repeated group list {
+            required group element {
+                required int32 kind (INTEGER(8, false));
+                required binary data;
+            }
+        }
+    }
+}
+";
+
+const ROW_GROUP_LEN: usize = 1 << 16;
+
+enum Values {
+    Int32(Vec<i32>),
+    Int64(Vec<i64>),
+    Bytes(Vec<ByteArray>),
+    Fixed(Vec<FixedLenByteArray>),
+}
+
+/// The values of one leaf column with its definition and repetition levels, `None` for a
+/// required column that isn't in a list.
+struct Leaf {
+    values: Values,
+    def_levels: Option<Vec<i16>>,
+    rep_levels: Option<Vec<i16>>,
+}
+
+impl Leaf {
+    const fn required(values: Values) -> Self {
+        Self {
+            values,
+            def_levels: None,
+            rep_levels: None,
+        }
+    }
+
+    // the present values, level 1 for a row that has one and 0 for a null
+    fn optional<T>(
+        rows: impl Iterator<Item = Option<T>>,
+        values: impl Fn(Vec<T>) -> Values,
+    ) -> Self {
+        let mut present = Vec::new();
+        let mut def_levels = Vec::new();
+        for row in rows {
+            def_levels.push(i16::from(row.is_some()));
+            present.extend(row);
+        }
+
+        Self {
+            values: values(present),
+            def_levels: Some(def_levels),
+            rep_levels: None,
+        }
+    }
+
+    // an empty list is a single level 0 entry, every element after a row's first repeats it
+    fn list<T>(rows: impl Iterator<Item = Vec<T>>, values: impl Fn(Vec<T>) -> Values) -> Self {
+        let mut elements = Vec::new();
+        let mut def_levels = Vec::new();
+        let mut rep_levels = Vec::new();
+        for row in rows {
+            if row.is_empty() {
+                def_levels.push(0);
+                rep_levels.push(0);
+                continue;
+            }
+
+            for i in 0..row.len() {
+                def_levels.push(1);
+                rep_levels.push(i16::from(i > 0));
+            }
+            elements.extend(row);
+        }
+
+        Self {
+            values: values(elements),
+            def_levels: Some(def_levels),
+            rep_levels: Some(rep_levels),
+        }
+    }
+}
+
+fn text(bytes: &[u8]) -> ByteArray {
+    ByteArray::from(String::from_utf8_lossy(bytes).into_owned().into_bytes())
+}
+
+fn fixed(bytes: &[u8]) -> FixedLenByteArray {
+    FixedLenByteArray::from(bytes.to_vec())
+}
+
+// empty means the client didn't send it
+fn non_empty(bytes: &[u8]) -> Option<ByteArray> {
+    (!bytes.is_empty()).then(|| text(bytes))
+}
+
+// in the order of SCHEMA
+fn leaves(logs: &[PlayerLog]) -> Vec<Leaf> {
+    let int32 = |f: fn(&PlayerLog) -> i32| Values::Int32(logs.iter().map(f).collect());
+
+    vec![
+        Leaf::required(int32(|log| log.binary_version.into())),
+        Leaf::required(int32(|log| log.flags.into())),
+        Leaf::optional(
+            logs.iter()
+                .map(|log| log.player_uuid.map(|uuid| fixed(&uuid))),
+            Values::Fixed,
+        ),
+        Leaf::required(Values::Bytes(
+            logs.iter().map(|log| text(&log.player_name)).collect(),
+        )),
+        Leaf::required(Values::Fixed(
+            logs.iter().map(|log| fixed(&log.player_ip)).collect(),
+        )),
+        Leaf::required(Values::Fixed(
+            logs.iter().map(|log| fixed(&log.server_ip)).collect(),
+        )),
+        Leaf::required(int32(|log| log.server_port.into())),
+        Leaf::required(Values::Bytes(
+            logs.iter().map(|log| text(&log.server_domain)).collect(),
+        )),
+        // nothing records aliases yet, every row has an empty list
+        Leaf::list(logs.iter().map(|_| Vec::new()), Values::Bytes),
+        Leaf::required(int32(|log| {
+            log.server_version
+                .id()
+                .unwrap_or(ServerVersion::UNKNOWN_ID)
+                .into()
+        })),
+        Leaf::optional(
+            logs.iter().map(|log| {
+                log.server_version
+                    .name()
+                    .filter(|_| log.server_version.id().is_none())
+                    .map(ByteArray::from)
+            }),
+            Values::Bytes,
+        ),
+        Leaf::optional(
+            logs.iter()
+                .map(|log| (log.timestamp != 0).then_some(log.timestamp as i64)),
+            Values::Int64,
+        ),
+        Leaf::required(Values::Int64(
+            logs.iter().map(|log| log.session_id.into()).collect(),
+        )),
+        Leaf::required(int32(|log| log.outcome.to_byte().into())),
+        Leaf::optional(
+            logs.iter().map(|log| log.geo.map(|geo| text(&geo.country))),
+            Values::Bytes,
+        ),
+        Leaf::optional(
+            logs.iter().map(|log| log.geo.map(|geo| i64::from(geo.asn))),
+            Values::Int64,
+        ),
+        Leaf::optional(
+            logs.iter().map(|log| log.proxy_ip.map(|ip| fixed(&ip))),
+            Values::Fixed,
+        ),
+        Leaf::optional(
+            logs.iter().map(|log| non_empty(&log.client_brand)),
+            Values::Bytes,
+        ),
+        Leaf::optional(
+            logs.iter().map(|log| non_empty(&log.client_locale)),
+            Values::Bytes,
+        ),
+        Leaf::list(
+            logs.iter().map(|log| {
+                log.extensions
+                    .iter()
+                    .map(|extension| i32::from(extension.kind))
+                    .collect()
+            }),
+            Values::Int32,
+        ),
+        Leaf::list(
+            logs.iter().map(|log| {
+                log.extensions
+                    .iter()
+                    .map(|extension| ByteArray::from(extension.data.clone()))
+                    .collect()
+            }),
+            Values::Bytes,
+        ),
+    ]
+}
+
+/// Writes a Parquet file with the geo info as a nested group and the extensions as a list of
+/// structs, rather than the flat columns of the other exports.
+///
+/// Names and domains that aren't utf8 are converted lossily.
+///
+/// `alias_domains` is an empty list of strings on every row for now, so readers can rely on the
+/// column once other domains a server answers to are recorded.
+pub fn write_parquet_nested(logs: &[PlayerLog], path: &Path) -> Result<()> {
+    let schema = Arc::new(parse_message_type(SCHEMA)?);
+    let properties = Arc::new(WriterProperties::builder().build());
+    let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+
+    for chunk in logs.chunks(ROW_GROUP_LEN) {
+        let mut leaves = leaves(chunk).into_iter();
+        let mut row_group = writer.next_row_group()?;
+
+        while let Some(mut column) = row_group.next_column()? {
+            let Some(leaf) = leaves.next() else {
+                bail!("the schema has more columns than there are leaves");
+            };
+            let def_levels = leaf.def_levels.as_deref();
+            let rep_levels = leaf.rep_levels.as_deref();
+
+            match (column.untyped(), &leaf.values) {
+                (ColumnWriter::Int32ColumnWriter(w), Values::Int32(v)) => {
+                    w.write_batch(v, def_levels, rep_levels)?;
+                }
+                (ColumnWriter::Int64ColumnWriter(w), Values::Int64(v)) => {
+                    w.write_batch(v, def_levels, rep_levels)?;
+                }
+                (ColumnWriter::ByteArrayColumnWriter(w), Values::Bytes(v)) => {
+                    w.write_batch(v, def_levels, rep_levels)?;
+                }
+                (ColumnWriter::FixedLenByteArrayColumnWriter(w), Values::Fixed(v)) => {
+                    w.write_batch(v, def_levels, rep_levels)?;
+                }
+                _ => bail!("a leaf doesn't match the type of its column"),
+            }
+
+            column.close()?;
+        }
+
+        row_group.close()?;
+    }
+
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,246 @@
+//! Fixing fields of one record in a stored batch without re-encoding the records around it.
+
+use anyhow::{anyhow, Context, Result};
+
+use super::ordinal::OffsetIndex;
+use super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+use super::{default_batch_id, PlayerLog, PlayerLogSerializer, SerializerOptions, ServerVersion};
+
+/// The fields to change in a record, `None` leaves a field as it is.
+///
+/// A patch that keeps the record's encoded length, like a new ip, port or known server version,
+/// overwrites it in place. A new name or domain of another length moves every record after it.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RecordPatch {
+    /// Has to agree with the rest of the record, e.g. NONDEFAULT_PORT with the server port.
+    pub flags: Option<u16>,
+    pub player_name: Option<Vec<u8>>,
+    pub player_ip: Option<[u8; 4]>,
+    pub server_ip: Option<[u8; 4]>,
+    pub server_port: Option<u16>,
+    pub server_domain: Option<Vec<u8>>,
+    pub server_version: Option<ServerVersion>,
+}
+
+impl RecordPatch {
+    pub fn apply(&self, log: &mut PlayerLog) {
+        if let Some(flags) = self.flags {
+            log.flags = flags;
+        }
+        if let Some(name) = &self.player_name {
+            log.player_name = NameBytes::from_slice(name);
+        }
+        if let Some(ip) = self.player_ip {
+            log.player_ip = ip;
+        }
+        if let Some(ip) = self.server_ip {
+            log.server_ip = ip;
+        }
+        if let Some(port) = self.server_port {
+            log.server_port = port;
+        }
+        if let Some(domain) = &self.server_domain {
+            log.server_domain = DomainBytes::from_slice(domain);
+        }
+        if let Some(version) = &self.server_version {
+            log.server_version = version.clone();
+        }
+    }
+}
+
+impl OffsetIndex {
+    /// Applies `patch` to record `ordinal`, failing without touching the batch if the patched
+    /// record is inconsistent or can't be written.
+    ///
+    /// A batch id that is the default hash of the payload is recomputed for the patched payload,
+    /// so [`RecordRef`](super::ordinal::RecordRef)s into the old batch no longer resolve. An
+    /// explicit batch id is kept.
+    pub fn patch(&mut self, ordinal: u32, patch: &RecordPatch) -> Result<()> {
+        let record_count = self.len() as u64;
+        let rehash = self.batch_id() == default_batch_id(record_count, self.payload());
+
+        let mut log = self.fetch(ordinal)?;
+        patch.apply(&mut log);
+        log.validate_consistency().map_err(|errors| {
+            let errors: Vec<_> = errors.iter().map(ToString::to_string).collect();
+            anyhow!(
+                "patched record {ordinal} is inconsistent: {}",
+                errors.join(", ")
+            )
+        })?;
+
+        let mut record = Vec::with_capacity(log.serialized_size());
+        log.serialize_with_endianness(&mut record, self.endianness())
+            .with_context(|| format!("patched record {ordinal} can't be written"))?;
+        self.replace(ordinal, &record)?;
+
+        if rehash {
+            self.set_batch_id(default_batch_id(record_count, self.payload()));
+        }
+
+        Ok(())
+    }
+}
+
+impl PlayerLogSerializer {
+    /// A copy of `data` with `patch` applied to record `ordinal`, written with the same codec and
+    /// producer. See [`OffsetIndex::patch`] for when the batch id changes, and to patch several
+    /// records of a batch without decompressing it each time.
+    pub fn patch(data: &[u8], ordinal: u32, patch: RecordPatch) -> Result<Vec<u8>> {
+        let header = Self::probe(data)?;
+        let mut index = OffsetIndex::build(data)?;
+        index.patch(ordinal, &patch)?;
+
+        let options = SerializerOptions {
+            codec: header.codec,
+            batch_id: Some(index.batch_id()),
+            producer: header.producer.map(str::to_owned),
+            endianness: header.endianness,
+        };
+
+        Self::write_container(index.payload(), header.record_count, &options, None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::{Codec, Endianness, LogFlags};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn options(codec: Codec, endianness: Endianness) -> SerializerOptions {
+        SerializerOptions {
+            codec,
+            producer: Some("collector-1".to_string()),
+            endianness,
+            ..SerializerOptions::default()
+        }
+    }
+
+    #[test]
+    fn patched_batches_decode_like_patched_logs() {
+        let logs = logs(20);
+        let patches = [
+            RecordPatch {
+                player_ip: Some([10, 0, 0, 9]),
+                server_version: Some(ServerVersion::from_name("1.20").unwrap()),
+                ..RecordPatch::default()
+            },
+            RecordPatch {
+                player_name: Some(b"a_much_longer_nm".to_vec()),
+                ..RecordPatch::default()
+            },
+            RecordPatch {
+                player_name: Some(b"Al".to_vec()),
+                server_domain: Some(b"play.example.com".to_vec()),
+                ..RecordPatch::default()
+            },
+        ];
+
+        for codec in [Codec::None, Codec::Zlib(Compression::default()), Codec::Lz4] {
+            for endianness in [Endianness::Big, Endianness::Little] {
+                let options = options(codec, endianness);
+                let data =
+                    PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap();
+
+                for (ordinal, patch) in [5, 0, 19].into_iter().zip(&patches) {
+                    let patched =
+                        PlayerLogSerializer::patch(&data, ordinal, patch.clone()).unwrap();
+                    let header = PlayerLogSerializer::probe(&patched).unwrap();
+                    assert_eq!(header.codec, codec);
+                    assert_eq!(header.endianness, endianness);
+                    assert_eq!(header.producer, Some("collector-1"));
+
+                    let mut expected = logs.clone();
+                    patch.apply(&mut expected[ordinal as usize]);
+                    assert_eq!(
+                        PlayerLogSerializer::deserialize_many(&patched).unwrap(),
+                        expected,
+                        "{codec:?} {endianness:?} {ordinal}"
+                    );
+                    // the default id is of the new payload, as if it had been written that way
+                    assert_eq!(
+                        patched,
+                        PlayerLogSerializer::serialize_many_with_options(&expected, &options)
+                            .unwrap()
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn explicit_batch_ids_are_kept() {
+        let logs = logs(5);
+        let options = SerializerOptions {
+            batch_id: Some([7; 16]),
+            ..options(Codec::None, Endianness::Big)
+        };
+        let data = PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap();
+        let patch = RecordPatch {
+            server_ip: Some([192, 168, 1, 254]),
+            ..RecordPatch::default()
+        };
+
+        let mut index = OffsetIndex::build(&data).unwrap();
+        index.patch(2, &patch).unwrap();
+        assert_eq!(index.batch_id(), [7; 16]);
+        assert_eq!(index.fetch(2).unwrap().server_ip, [192, 168, 1, 254]);
+        assert_eq!(index.fetch(3).unwrap(), logs[3]);
+
+        let patched = PlayerLogSerializer::patch(&data, 2, patch).unwrap();
+        assert_eq!(
+            PlayerLogSerializer::probe(&patched).unwrap().batch_id,
+            [7; 16]
+        );
+    }
+
+    #[test]
+    fn bad_patches_leave_the_batch_alone() {
+        let logs = logs(5);
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let mut index = OffsetIndex::build(&data).unwrap();
+        let batch_id = index.batch_id();
+
+        let out_of_range = index.patch(5, &RecordPatch::default()).unwrap_err();
+        assert!(
+            out_of_range.to_string().contains("out of range"),
+            "{out_of_range}"
+        );
+
+        // online without a uuid or offline with one
+        let mut flags = LogFlags::from_bits_retain(logs[1].flags);
+        flags.toggle(LogFlags::IS_ONLINE);
+        let inconsistent = RecordPatch {
+            flags: Some(flags.bits()),
+            ..RecordPatch::default()
+        };
+        let e = index.patch(1, &inconsistent).unwrap_err();
+        assert!(
+            e.to_string()
+                .starts_with("patched record 1 is inconsistent"),
+            "{e}"
+        );
+
+        let too_long = RecordPatch {
+            player_name: Some(vec![b'x'; 40]),
+            ..RecordPatch::default()
+        };
+        assert!(index.patch(1, &too_long).is_err());
+
+        assert_eq!(index.batch_id(), batch_id);
+        for (ordinal, log) in logs.iter().enumerate() {
+            assert_eq!(&index.fetch(ordinal as u32).unwrap(), log);
+        }
+    }
+}
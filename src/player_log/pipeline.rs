@@ -0,0 +1,349 @@
+//! Rewrites a batch through a chain of filter, map and redact stages.
+//!
+//! Without a sort the records go from the input to the output payload one at a time. A sort has
+//! to see every record first, so [`BatchPipeline::sort`] turns the pipeline into a
+//! [`SortedPipeline`] that can't take any more stages.
+
+use anyhow::{Context, Result};
+
+use super::filter::PlayerLogFilter;
+use super::{Codec, LogFlags, PlayerLog, PlayerLogSerializer, SerializerOptions};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RedactPolicy {
+    /// [`PlayerLog::clone_redacted`], the uuid, player ip and name.
+    Identity,
+    /// The identity along with the geo info and proxy ip, which narrow down where the player is.
+    Network,
+}
+
+impl RedactPolicy {
+    fn apply(self, log: &PlayerLog) -> PlayerLog {
+        let mut redacted = log.clone_redacted();
+        if self == Self::Network {
+            let mut flags = LogFlags::from_bits_retain(redacted.flags);
+            flags.remove(LogFlags::HAS_GEO | LogFlags::VIA_PROXY);
+            redacted.flags = flags.bits();
+            redacted.geo = None;
+            redacted.proxy_ip = None;
+        }
+
+        redacted
+    }
+}
+
+/// Sorts are stable, records with equal keys keep their order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortKey {
+    Timestamp,
+    PlayerName,
+    PlayerIp,
+}
+
+enum Stage<'a> {
+    Filter(PlayerLogFilter),
+    Map(Box<dyn FnMut(PlayerLog) -> PlayerLog + 'a>),
+    Redact(RedactPolicy),
+}
+
+/// Stages run in the order they were added.
+#[derive(Default)]
+pub struct BatchPipeline<'a> {
+    stages: Vec<Stage<'a>>,
+}
+
+impl<'a> BatchPipeline<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn filter(mut self, filter: PlayerLogFilter) -> Self {
+        self.stages.push(Stage::Filter(filter));
+        self
+    }
+
+    /// The mapped log is checked when it's written, an invalid one fails the run.
+    pub fn map(mut self, f: impl FnMut(PlayerLog) -> PlayerLog + 'a) -> Self {
+        self.stages.push(Stage::Map(Box::new(f)));
+        self
+    }
+
+    pub fn redact(mut self, policy: RedactPolicy) -> Self {
+        self.stages.push(Stage::Redact(policy));
+        self
+    }
+
+    pub const fn sort(self, key: SortKey) -> SortedPipeline<'a> {
+        SortedPipeline {
+            pipeline: self,
+            key,
+        }
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.stages.is_empty()
+    }
+
+    /// Runs one log through every stage, `None` if a filter dropped it.
+    pub fn apply(&mut self, mut log: PlayerLog) -> Option<PlayerLog> {
+        for stage in &mut self.stages {
+            log = match stage {
+                Stage::Filter(filter) if !filter.matches(&log) => return None,
+                Stage::Filter(_) => log,
+                Stage::Map(f) => f(log),
+                Stage::Redact(policy) => policy.apply(&log),
+            };
+        }
+
+        Some(log)
+    }
+
+    /// Writes the kept records of `input` as a new batch, keeping its producer.
+    pub fn run(&mut self, input: &[u8], codec: Codec) -> Result<Vec<u8>> {
+        let mut payload = Vec::new();
+        let mut kept = 0;
+        let producer = for_each_record(input, |i, log| {
+            if let Some(log) = self.apply(log) {
+                log.serialize(&mut payload)
+                    .with_context(|| format!("record {i} after the pipeline"))?;
+                kept += 1;
+            }
+
+            Ok(())
+        })?;
+
+        write_batch(&payload, kept, codec, producer)
+    }
+}
+
+/// A [`BatchPipeline`] ending in a sort, which buffers every kept record before writing any.
+pub struct SortedPipeline<'a> {
+    pipeline: BatchPipeline<'a>,
+    key: SortKey,
+}
+
+impl SortedPipeline<'_> {
+    pub fn run(&mut self, input: &[u8], codec: Codec) -> Result<Vec<u8>> {
+        let mut logs = Vec::new();
+        let producer = for_each_record(input, |_, log| {
+            logs.extend(self.pipeline.apply(log));
+            Ok(())
+        })?;
+
+        match self.key {
+            SortKey::Timestamp => logs.sort_by_key(|log| log.timestamp),
+            SortKey::PlayerName => logs.sort_by(|a, b| a.player_name.cmp(&b.player_name)),
+            SortKey::PlayerIp => logs.sort_by_key(|log| log.player_ip),
+        }
+
+        let mut payload = Vec::new();
+        for (i, log) in logs.iter().enumerate() {
+            log.serialize(&mut payload)
+                .with_context(|| format!("sorted record {i} after the pipeline"))?;
+        }
+
+        write_batch(&payload, logs.len() as u64, codec, producer)
+    }
+}
+
+// decodes the records of `input` one at a time, returns the producer of the batch
+fn for_each_record(
+    input: &[u8],
+    mut f: impl FnMut(u64, PlayerLog) -> Result<()>,
+) -> Result<Option<String>> {
+    let (header, header_len) = PlayerLogSerializer::probe_with_len(input)?;
+    header.check_no_dictionary()?;
+
+    let mut reader = header.codec.decoder(&input[header_len..]);
+    for i in 0..header.record_count {
+        let log = PlayerLog::deserialize_with_endianness(&mut reader, header.endianness)
+            .with_context(|| format!("record {i}"))?;
+        if !log.is_marker() {
+            f(i, log)?;
+        }
+    }
+    super::check_payload_end(&mut reader)?;
+
+    Ok(header.producer.map(str::to_string))
+}
+
+// the contents changed, so the batch id is derived anew
+fn write_batch(
+    payload: &[u8],
+    record_count: u64,
+    codec: Codec,
+    producer: Option<String>,
+) -> Result<Vec<u8>> {
+    let options = SerializerOptions {
+        codec,
+        producer,
+        ..SerializerOptions::default()
+    };
+
+    PlayerLogSerializer::write_container(payload, record_count, &options, None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::GeoInfo;
+    use super::*;
+    use crate::log_generator_seeded;
+
+    const PROXY: Ipv4Addr = Ipv4Addr::new(172, 16, 0, 1);
+
+    // every other log comes through the proxy and every one has geo info
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|i| {
+                let mut builder = log_generator_seeded(&mut rng);
+                builder.proxy_ip = (i % 2 == 0).then_some(PROXY);
+                builder.geo = Some(GeoInfo {
+                    country: *b"DE",
+                    asn: 3320,
+                });
+                builder.build().unwrap()
+            })
+            .collect()
+    }
+
+    fn batch(logs: &[PlayerLog], producer: &str) -> Vec<u8> {
+        let options = SerializerOptions {
+            producer: Some(producer.to_string()),
+            ..SerializerOptions::default()
+        };
+        PlayerLogSerializer::serialize_many_with_options(logs, &options).unwrap()
+    }
+
+    #[test]
+    fn stages_run_in_order_like_they_would_on_a_vec() {
+        let logs = logs(100);
+        let input = batch(&logs, "ingest");
+
+        let mut pipeline = BatchPipeline::new()
+            .filter(PlayerLogFilter::by_proxy(PROXY))
+            .map(|mut log| {
+                log.session_id = 7;
+                log
+            })
+            .redact(RedactPolicy::Identity);
+        let output = pipeline
+            .run(&input, Codec::Zlib(Compression::default()))
+            .unwrap();
+
+        let expected = logs
+            .iter()
+            .step_by(2)
+            .map(|log| {
+                let mut log = log.clone();
+                log.session_id = 7;
+                log.clone_redacted()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&output).unwrap(),
+            expected
+        );
+        assert_eq!(
+            PlayerLogSerializer::probe(&output).unwrap().producer,
+            Some("ingest")
+        );
+
+        let mut empty = BatchPipeline::new();
+        assert!(empty.is_empty());
+        let copied = empty.run(&input, Codec::Lz4).unwrap();
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&copied).unwrap(),
+            logs
+        );
+    }
+
+    #[test]
+    fn network_redaction_drops_where_the_player_is() {
+        let logs = logs(10);
+        let mut pipeline = BatchPipeline::new().redact(RedactPolicy::Network);
+        let output = pipeline.run(&batch(&logs, "ingest"), Codec::None).unwrap();
+
+        for (redacted, log) in PlayerLogSerializer::deserialize_many(&output)
+            .unwrap()
+            .iter()
+            .zip(&logs)
+        {
+            let flags = LogFlags::from_bits_retain(redacted.flags);
+            assert!(!flags.intersects(LogFlags::HAS_GEO | LogFlags::VIA_PROXY));
+            assert_eq!((redacted.geo, redacted.proxy_ip), (None, None));
+            assert_eq!(redacted.player_name, log.clone_redacted().player_name);
+            assert_eq!(redacted.server_domain, log.server_domain);
+        }
+    }
+
+    #[test]
+    fn sorts_are_stable_and_see_only_kept_records() {
+        let mut logs = logs(60);
+        for (i, log) in logs.iter_mut().enumerate() {
+            log.timestamp = (i % 3) as u64;
+            log.session_id = i as u32;
+        }
+        let input = batch(&logs, "ingest");
+
+        let mut sorted = BatchPipeline::new()
+            .filter(PlayerLogFilter::by_proxy(PROXY))
+            .sort(SortKey::Timestamp);
+        let output = sorted.run(&input, Codec::Lz4).unwrap();
+
+        let mut expected = logs.iter().step_by(2).cloned().collect::<Vec<_>>();
+        expected.sort_by_key(|log| log.timestamp);
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many(&output).unwrap(),
+            expected
+        );
+
+        for (key, mut expected) in [
+            (SortKey::PlayerName, logs.clone()),
+            (SortKey::PlayerIp, logs.clone()),
+        ] {
+            if key == SortKey::PlayerName {
+                expected.sort_by(|a, b| a.player_name.cmp(&b.player_name));
+            } else {
+                expected.sort_by_key(|log| log.player_ip);
+            }
+            let output = BatchPipeline::new()
+                .sort(key)
+                .run(&input, Codec::None)
+                .unwrap();
+            assert_eq!(
+                PlayerLogSerializer::deserialize_many(&output).unwrap(),
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn invalid_records_fail_the_run() {
+        let logs = logs(5);
+        let input = batch(&logs, "ingest");
+
+        let mut pipeline = BatchPipeline::new().map(|mut log| {
+            if log.session_id == logs[3].session_id {
+                log.flags |= LogFlags::IS_ONLINE.bits();
+                log.player_uuid = None;
+            }
+            log
+        });
+        let e = pipeline.run(&input, Codec::None).unwrap_err();
+        assert!(
+            format!("{e:#}").starts_with("record 3 after the pipeline"),
+            "{e:#}"
+        );
+
+        let e = BatchPipeline::new()
+            .run(&input[..input.len() - 2], Codec::None)
+            .unwrap_err();
+        assert!(format!("{e:#}").starts_with("record 4"), "{e:#}");
+    }
+}
@@ -0,0 +1,177 @@
+use anyhow::{bail, Context, Result};
+use polars::prelude::*;
+use uuid::Uuid;
+
+use super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+use super::{Extension, GeoInfo, LogFlags, LoginOutcome, PlayerLog, ServerVersion};
+
+// ips are packed big endian, the way Ipv4Addr converts to a u32
+const fn pack_ip(ip: [u8; 4]) -> u32 {
+    u32::from_be_bytes(ip)
+}
+
+fn required<T>(value: Option<T>, name: &str) -> Result<T> {
+    value.with_context(|| format!("{name} is null"))
+}
+
+fn text(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+/// One row per log with the same columns as the sqlite table, plus `authenticated` derived from
+/// the flags. Names and domains that aren't utf8 are converted lossily.
+pub fn to_dataframe(logs: &[PlayerLog]) -> DataFrame {
+    fn column<T, F>(logs: &[PlayerLog], name: &str, f: F) -> Column
+    where
+        F: Fn(&PlayerLog) -> T,
+        Series: NamedFrom<Vec<T>, [T]>,
+    {
+        Column::new(name.into(), logs.iter().map(f).collect::<Vec<_>>())
+    }
+
+    // the same kind, length, data layout the record uses
+    let extensions = logs
+        .iter()
+        .map(|log| {
+            let mut bytes = Vec::new();
+            for extension in &log.extensions {
+                bytes.push(extension.kind);
+                bytes.push(extension.data.len() as u8);
+                bytes.extend_from_slice(&extension.data);
+            }
+            Some(bytes)
+        })
+        .collect::<BinaryChunked>()
+        .with_name("extensions".into());
+
+    DataFrame::new(vec![
+        column(logs, "binary_version", |log| log.binary_version),
+        column(logs, "flags", |log| log.flags),
+        column(logs, "authenticated", |log| {
+            LogFlags::from_bits_retain(log.flags).contains(LogFlags::PLAYER_AUTH)
+        }),
+        column(logs, "player_uuid", |log| {
+            log.player_uuid
+                .map(|uuid| Uuid::from_bytes(uuid).to_string())
+        }),
+        column(logs, "player_name", |log| text(&log.player_name)),
+        column(logs, "player_ip", |log| pack_ip(log.player_ip)),
+        column(logs, "server_ip", |log| pack_ip(log.server_ip)),
+        column(logs, "server_port", |log| log.server_port),
+        column(logs, "server_domain", |log| text(&log.server_domain)),
+        column(logs, "server_version", |log| {
+            log.server_version.id().unwrap_or(ServerVersion::UNKNOWN_ID)
+        }),
+        column(logs, "server_version_name", |log| {
+            log.server_version
+                .name()
+                .filter(|_| log.server_version.id().is_none())
+                .map(str::to_string)
+        }),
+        column(logs, "timestamp", |log| log.timestamp),
+        column(logs, "session_id", |log| log.session_id),
+        column(logs, "outcome", |log| log.outcome.to_byte()),
+        column(logs, "country", |log| log.geo.map(|geo| text(&geo.country))),
+        column(logs, "asn", |log| log.geo.map(|geo| geo.asn)),
+        column(logs, "proxy_ip", |log| log.proxy_ip.map(pack_ip)),
+        column(logs, "client_brand", |log| text(&log.client_brand)),
+        column(logs, "client_locale", |log| text(&log.client_locale)),
+        extensions.into_column(),
+    ])
+    .expect("every column has one row per log")
+}
+
+/// Reads back a frame written by [`to_dataframe`], `authenticated` is ignored in favour of the
+/// flags. Every other column is required.
+pub fn from_dataframe(df: &DataFrame) -> Result<Vec<PlayerLog>> {
+    let binary_version = df.column("binary_version")?.u8()?;
+    let flags = df.column("flags")?.u16()?;
+    let player_uuid = df.column("player_uuid")?.str()?;
+    let player_name = df.column("player_name")?.str()?;
+    let player_ip = df.column("player_ip")?.u32()?;
+    let server_ip = df.column("server_ip")?.u32()?;
+    let server_port = df.column("server_port")?.u16()?;
+    let server_domain = df.column("server_domain")?.str()?;
+    let server_version = df.column("server_version")?.u8()?;
+    let server_version_name = df.column("server_version_name")?.str()?;
+    let timestamp = df.column("timestamp")?.u64()?;
+    let session_id = df.column("session_id")?.u32()?;
+    let outcome = df.column("outcome")?.u8()?;
+    let country = df.column("country")?.str()?;
+    let asn = df.column("asn")?.u32()?;
+    let proxy_ip = df.column("proxy_ip")?.u32()?;
+    let client_brand = df.column("client_brand")?.str()?;
+    let client_locale = df.column("client_locale")?.str()?;
+    let extensions = df.column("extensions")?.binary()?;
+
+    (0..df.height())
+        .map(|i| -> Result<PlayerLog> {
+            let player_uuid = player_uuid
+                .get(i)
+                .map(|uuid| Uuid::parse_str(uuid).map(Uuid::into_bytes))
+                .transpose()
+                .context("invalid player_uuid")?;
+
+            let server_version = match server_version_name.get(i) {
+                Some(name) => ServerVersion::Unknown(name.into()),
+                None => ServerVersion::Known(required(server_version.get(i), "server_version")?),
+            };
+
+            let geo = match (country.get(i), asn.get(i)) {
+                (Some(country), Some(asn)) => Some(GeoInfo {
+                    country: country
+                        .as_bytes()
+                        .try_into()
+                        .with_context(|| format!("invalid country {country}"))?,
+                    asn,
+                }),
+                (None, None) => None,
+                _ => bail!("country and asn have to be set together"),
+            };
+
+            let mut parsed_extensions = Vec::new();
+            let mut data = required(extensions.get(i), "extensions")?.iter().copied();
+            while let Some(kind) = data.next() {
+                let Some(len) = data.next() else {
+                    bail!("truncated extensions");
+                };
+
+                let data = data.by_ref().take(len as usize).collect::<Vec<_>>();
+                if data.len() != len as usize {
+                    bail!("truncated extensions");
+                }
+                parsed_extensions.push(Extension { kind, data });
+            }
+
+            Ok(PlayerLog {
+                binary_version: required(binary_version.get(i), "binary_version")?,
+                flags: required(flags.get(i), "flags")?,
+                player_uuid,
+                player_name: NameBytes::from_slice(
+                    required(player_name.get(i), "player_name")?.as_bytes(),
+                ),
+                player_ip: required(player_ip.get(i), "player_ip")?.to_be_bytes(),
+                server_ip: required(server_ip.get(i), "server_ip")?.to_be_bytes(),
+                server_port: required(server_port.get(i), "server_port")?,
+                server_domain: DomainBytes::from_slice(
+                    required(server_domain.get(i), "server_domain")?.as_bytes(),
+                ),
+                server_version,
+                timestamp: required(timestamp.get(i), "timestamp")?,
+                session_id: required(session_id.get(i), "session_id")?,
+                outcome: LoginOutcome::from_byte(required(outcome.get(i), "outcome")?),
+                geo,
+                proxy_ip: proxy_ip.get(i).map(u32::to_be_bytes),
+                extensions: parsed_extensions,
+                client_brand: required(client_brand.get(i), "client_brand")?
+                    .as_bytes()
+                    .to_vec(),
+                client_locale: required(client_locale.get(i), "client_locale")?
+                    .as_bytes()
+                    .to_vec(),
+            })
+        })
+        .enumerate()
+        .map(|(i, log)| log.with_context(|| format!("row {i}")))
+        .collect()
+}
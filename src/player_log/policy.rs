@@ -0,0 +1,163 @@
+//! Picking a batch's codec from its size and how soon someone is waiting on it, without trial
+//! compressing it like [`PlayerLogSerializer::select_codec`] does.
+
+use anyhow::Result;
+use flate2::Compression;
+
+use super::{Codec, PlayerLog, PlayerLogSerializer, SerializerOptions};
+
+/// How long the batch's reader can wait for it to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum LatencyClass {
+    /// Read back right away, e.g. by a dashboard. Never worse than lz4.
+    Interactive,
+    #[default]
+    Standard,
+    /// Written once and rarely read, worth the slowest codec.
+    Archival,
+}
+
+/// Thresholds for [`PlayerLogSerializer::serialize_many_auto`], every bound is inclusive.
+///
+/// A batch under either of the `min_compressed` bounds is written uncompressed, the container
+/// overhead eats most of the saving. Past them interactive batches use lz4 and archival ones
+/// `archival_level` zlib. Standard batches use lz4 until their payload reaches
+/// `standard_zlib_bytes`, and `standard_level` zlib from there.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CodecPolicy {
+    pub latency: LatencyClass,
+    pub min_compressed_records: u64,
+    /// Uncompressed payload bytes.
+    pub min_compressed_bytes: u64,
+    pub standard_zlib_bytes: u64,
+    pub standard_level: Compression,
+    pub archival_level: Compression,
+}
+
+impl Default for CodecPolicy {
+    fn default() -> Self {
+        Self {
+            latency: LatencyClass::Standard,
+            min_compressed_records: 16,
+            min_compressed_bytes: 4 << 10,
+            standard_zlib_bytes: 1 << 20,
+            standard_level: Compression::new(6),
+            archival_level: Compression::best(),
+        }
+    }
+}
+
+impl CodecPolicy {
+    pub fn for_latency(latency: LatencyClass) -> Self {
+        Self {
+            latency,
+            ..Self::default()
+        }
+    }
+
+    /// The codec for `record_count` records taking up `payload_bytes` uncompressed.
+    pub const fn choose(&self, record_count: u64, payload_bytes: u64) -> Codec {
+        if record_count < self.min_compressed_records || payload_bytes < self.min_compressed_bytes {
+            return Codec::None;
+        }
+
+        match self.latency {
+            LatencyClass::Interactive => Codec::Lz4,
+            LatencyClass::Standard if payload_bytes < self.standard_zlib_bytes => Codec::Lz4,
+            LatencyClass::Standard => Codec::Zlib(self.standard_level),
+            LatencyClass::Archival => Codec::Zlib(self.archival_level),
+        }
+    }
+}
+
+impl PlayerLogSerializer {
+    /// Serializes `logs` with the codec `policy` picks for them, see [`CodecPolicy`]. The codec
+    /// is recorded in the header like any other, so [`PlayerLogSerializer::deserialize_many`]
+    /// reads the batch back.
+    pub fn serialize_many_auto(
+        logs: &[PlayerLog],
+        policy: &CodecPolicy,
+    ) -> Result<(Vec<u8>, Codec)> {
+        let payload_bytes = logs.iter().map(PlayerLog::serialized_size).sum::<usize>();
+        let codec = policy.choose(logs.len() as u64, payload_bytes as u64);
+
+        let options = SerializerOptions {
+            codec,
+            ..SerializerOptions::default()
+        };
+
+        Ok((Self::serialize_many_with_options(logs, &options)?, codec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn the_bounds_are_inclusive() {
+        let policy = CodecPolicy::default();
+        assert_eq!(policy.choose(15, 1 << 30), Codec::None);
+        assert_eq!(policy.choose(1 << 30, (4 << 10) - 1), Codec::None);
+        assert_eq!(policy.choose(16, 4 << 10), Codec::Lz4);
+        assert_eq!(policy.choose(16, (1 << 20) - 1), Codec::Lz4);
+        assert_eq!(policy.choose(16, 1 << 20), Codec::Zlib(Compression::new(6)));
+    }
+
+    #[test]
+    fn latency_picks_the_codec_past_the_minimum() {
+        for (latency, small, large) in [
+            (LatencyClass::Interactive, Codec::Lz4, Codec::Lz4),
+            (
+                LatencyClass::Standard,
+                Codec::Lz4,
+                Codec::Zlib(Compression::new(6)),
+            ),
+            (
+                LatencyClass::Archival,
+                Codec::Zlib(Compression::best()),
+                Codec::Zlib(Compression::best()),
+            ),
+        ] {
+            let policy = CodecPolicy::for_latency(latency);
+            assert_eq!(policy.choose(1, 1 << 30), Codec::None, "{latency:?}");
+            assert_eq!(policy.choose(100, 8 << 10), small, "{latency:?}");
+            assert_eq!(policy.choose(100, 8 << 20), large, "{latency:?}");
+        }
+    }
+
+    #[test]
+    fn auto_batches_read_back_with_the_chosen_codec() {
+        let policy = CodecPolicy {
+            standard_zlib_bytes: 64 << 10,
+            ..CodecPolicy::default()
+        };
+        for (count, expected) in [(10, "none"), (100, "lz4"), (2000, "zlib")] {
+            let logs = logs(count);
+            let (data, codec) = PlayerLogSerializer::serialize_many_auto(&logs, &policy).unwrap();
+            assert_eq!(codec.name(), expected, "{count}");
+            assert_eq!(
+                PlayerLogSerializer::probe(&data).unwrap().codec.name(),
+                expected
+            );
+            assert_eq!(PlayerLogSerializer::deserialize_many(&data).unwrap(), logs);
+        }
+
+        let (data, codec) =
+            PlayerLogSerializer::serialize_many_auto(&[], &CodecPolicy::default()).unwrap();
+        assert_eq!(codec, Codec::None);
+        assert!(PlayerLogSerializer::deserialize_many(&data)
+            .unwrap()
+            .is_empty());
+    }
+}
@@ -0,0 +1,20 @@
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+use super::PlayerLog;
+
+/// A copy with the player ip replaced by the first 4 bytes of `HMAC-SHA256(salt, player_ip)`.
+///
+/// The same ip always gets the same pseudonym under one salt, so counting addresses per server
+/// still works, but pseudonyms made with different salts can't be matched up. The server ip,
+/// port and domain are kept as they are, along with everything else in the log.
+pub fn anonymize_preserving_server_buckets(log: &PlayerLog, salt: &[u8; 32]) -> PlayerLog {
+    let mut mac = Hmac::<Sha256>::new_from_slice(salt).expect("hmac takes keys of any length");
+    mac.update(&log.player_ip);
+    let digest = mac.finalize().into_bytes();
+
+    PlayerLog {
+        player_ip: [digest[0], digest[1], digest[2], digest[3]],
+        ..log.clone()
+    }
+}
@@ -0,0 +1,402 @@
+use std::io::Read;
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, Context, Result};
+use bitflags::bitflags;
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+
+use super::{
+    validate_flags, Endianness, Extension, GeoInfo, LogFlags, LoginOutcome, PlayerLog,
+    PlayerLogSerializer, ServerVersion,
+};
+
+bitflags! {
+    /// Which fields [`PlayerLogSerializer::deserialize_many_projected`] decodes, the rest are
+    /// skipped over without being allocated.
+    #[derive(Debug, PartialEq, Eq, Clone, Copy)]
+    pub struct Projection: u16 {
+        const UUID = 1;
+        const NAME = 1 << 1;
+        const PLAYER_IP = 1 << 2;
+        const SERVER_IP = 1 << 3;
+        const SERVER_PORT = 1 << 4;
+        const DOMAIN = 1 << 5;
+        const SERVER_VERSION = 1 << 6;
+        const TIMESTAMP = 1 << 7;
+        const SESSION_ID = 1 << 8;
+        const OUTCOME = 1 << 9;
+        const GEO = 1 << 10;
+        const PROXY_IP = 1 << 11;
+        const CLIENT_INFO = 1 << 12;
+        const EXTENSIONS = 1 << 13;
+    }
+}
+
+/// A record decoded with a [`Projection`]. Every projected field holds what a full decode
+/// would, wrapped in `Some` for the fields that aren't already optional; anything outside the
+/// projection is `None`.
+#[derive(Debug, PartialEq, Eq, Clone, Default)]
+pub struct PartialPlayerLog {
+    pub binary_version: u8,
+    pub flags: u16,
+    pub player_uuid: Option<[u8; 16]>,
+    pub player_name: Option<Vec<u8>>,
+    pub player_ip: Option<[u8; 4]>,
+    pub server_ip: Option<[u8; 4]>,
+    pub server_port: Option<u16>,
+    pub server_domain: Option<Vec<u8>>,
+    pub server_version: Option<ServerVersion>,
+    pub timestamp: Option<u64>,
+    pub session_id: Option<u32>,
+    pub outcome: Option<LoginOutcome>,
+    pub geo: Option<GeoInfo>,
+    pub proxy_ip: Option<[u8; 4]>,
+    pub client_brand: Option<Vec<u8>>,
+    pub client_locale: Option<Vec<u8>>,
+    pub extensions: Option<Vec<Extension>>,
+}
+
+// every skipped field is a u8 length or a fixed size, so a stack buffer always fits it
+fn skip<R: Read>(reader: &mut R, len: usize) -> Result<()> {
+    let mut buf = [0; u8::MAX as usize];
+    reader.read_exact(&mut buf[..len])?;
+    Ok(())
+}
+
+// lengths are still checked for skipped fields, so a record is rejected under any projection
+// when the full decode would reject it for being malformed
+fn read_field<R: Read>(
+    reader: &mut R,
+    max_len: usize,
+    wanted: bool,
+    field: &str,
+) -> Result<Option<Vec<u8>>> {
+    let len = reader.read_u8()? as usize;
+    if len > max_len {
+        bail!("invalid {field}: {len} bytes, at most {max_len} are allowed");
+    }
+
+    if !wanted {
+        skip(reader, len)?;
+        return Ok(None);
+    }
+
+    let mut data = vec![0; len];
+    reader.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R, wanted: bool) -> Result<Option<[u8; N]>> {
+    if !wanted {
+        skip(reader, N)?;
+        return Ok(None);
+    }
+
+    let mut data = [0; N];
+    reader.read_exact(&mut data)?;
+    Ok(Some(data))
+}
+
+impl PartialPlayerLog {
+    /// [`PlayerLog::deserialize`], only decoding the fields in `projection`.
+    pub fn deserialize<R: Read>(reader: &mut R, projection: Projection) -> Result<Self> {
+        Self::deserialize_as::<BigEndian, _>(reader, projection)
+    }
+
+    /// [`PartialPlayerLog::deserialize`] for records written with `endianness`.
+    pub fn deserialize_with_endianness<R: Read>(
+        reader: &mut R,
+        projection: Projection,
+        endianness: Endianness,
+    ) -> Result<Self> {
+        match endianness {
+            Endianness::Big => Self::deserialize_as::<BigEndian, _>(reader, projection),
+            Endianness::Little => Self::deserialize_as::<LittleEndian, _>(reader, projection),
+        }
+    }
+
+    fn deserialize_as<B: ByteOrder, R: Read>(
+        reader: &mut R,
+        projection: Projection,
+    ) -> Result<Self> {
+        let binary_version = reader.read_u8()?;
+        if !(1..=PlayerLog::CURRENT_VERSION).contains(&binary_version) {
+            bail!("invalid binary version");
+        }
+
+        let flags = if binary_version >= 2 {
+            reader.read_u16::<B>()?
+        } else {
+            u16::from(reader.read_u8()?)
+        };
+        let parsed_flags = validate_flags(flags)?;
+        let v2 = binary_version >= 2;
+
+        let mut log = Self {
+            binary_version,
+            flags,
+            ..Self::default()
+        };
+
+        if parsed_flags.contains(LogFlags::IS_ONLINE) {
+            log.player_uuid = read_array(reader, projection.contains(Projection::UUID))?;
+        }
+        log.player_name = read_field(
+            reader,
+            PlayerLog::max_name_len(binary_version),
+            projection.contains(Projection::NAME),
+            "player name",
+        )?;
+        log.player_ip = read_array(reader, projection.contains(Projection::PLAYER_IP))?;
+
+        // a domain that is the server ip is rebuilt from it
+        let domain_is_ip = v2 && parsed_flags.contains(LogFlags::DOMAIN_IS_IP);
+        let server_ip = read_array(
+            reader,
+            projection.contains(Projection::SERVER_IP)
+                || (domain_is_ip && projection.contains(Projection::DOMAIN)),
+        )?;
+        log.server_ip = server_ip.filter(|_| projection.contains(Projection::SERVER_IP));
+
+        let server_port = if !v2 || parsed_flags.contains(LogFlags::NONDEFAULT_PORT) {
+            reader.read_u16::<B>()?
+        } else {
+            PlayerLog::DEFAULT_PORT
+        };
+        log.server_port =
+            Some(server_port).filter(|_| projection.contains(Projection::SERVER_PORT));
+
+        if domain_is_ip {
+            log.server_domain = server_ip
+                .filter(|_| projection.contains(Projection::DOMAIN))
+                .map(|ip| Ipv4Addr::from(ip).to_string().into_bytes());
+        } else {
+            log.server_domain = read_field(
+                reader,
+                PlayerLog::MAX_DOMAIN_LEN,
+                projection.contains(Projection::DOMAIN),
+                "server domain",
+            )?;
+        }
+
+        let wants_version = projection.contains(Projection::SERVER_VERSION);
+        log.server_version = match reader.read_u8()? {
+            // always read, it's rare and a full decode rejects names that aren't utf8
+            ServerVersion::UNKNOWN_ID if v2 => {
+                let name = read_field(
+                    reader,
+                    ServerVersion::MAX_UNKNOWN_LEN,
+                    true,
+                    "unknown server version",
+                )?
+                .unwrap_or_default();
+                let name = String::from_utf8(name).context("invalid unknown server version")?;
+                Some(ServerVersion::Unknown(name.into())).filter(|_| wants_version)
+            }
+            id => Some(ServerVersion::Known(id)).filter(|_| wants_version),
+        };
+
+        if !v2 {
+            // what a full decode leaves these at
+            log.timestamp = Some(0).filter(|_| projection.contains(Projection::TIMESTAMP));
+            log.session_id = Some(0).filter(|_| projection.contains(Projection::SESSION_ID));
+            log.outcome =
+                Some(LoginOutcome::Success).filter(|_| projection.contains(Projection::OUTCOME));
+            log.client_brand =
+                Some(Vec::new()).filter(|_| projection.contains(Projection::CLIENT_INFO));
+            log.client_locale = log.client_brand.clone();
+            log.extensions =
+                Some(Vec::new()).filter(|_| projection.contains(Projection::EXTENSIONS));
+            return Ok(log);
+        }
+
+        let timestamp = reader.read_u64::<B>()?;
+        log.timestamp = Some(timestamp).filter(|_| projection.contains(Projection::TIMESTAMP));
+        let session_id = reader.read_u32::<B>()?;
+        log.session_id = Some(session_id).filter(|_| projection.contains(Projection::SESSION_ID));
+
+        let outcome = if parsed_flags.contains(LogFlags::LOGIN_REFUSED) {
+            LoginOutcome::from_byte(reader.read_u8()?)
+        } else {
+            LoginOutcome::Success
+        };
+        log.outcome = Some(outcome).filter(|_| projection.contains(Projection::OUTCOME));
+
+        if parsed_flags.contains(LogFlags::HAS_GEO) {
+            let geo = read_array::<_, 6>(reader, projection.contains(Projection::GEO))?;
+            log.geo = geo.map(|geo| GeoInfo {
+                country: [geo[0], geo[1]],
+                asn: B::read_u32(&geo[2..]),
+            });
+        }
+        if parsed_flags.contains(LogFlags::VIA_PROXY) {
+            log.proxy_ip = read_array(reader, projection.contains(Projection::PROXY_IP))?;
+        }
+
+        let wants_client_info = projection.contains(Projection::CLIENT_INFO);
+        if parsed_flags.contains(LogFlags::HAS_CLIENT_INFO) {
+            log.client_brand = read_field(
+                reader,
+                PlayerLog::MAX_CLIENT_BRAND_LEN,
+                wants_client_info,
+                "client brand",
+            )?;
+            log.client_locale = read_field(
+                reader,
+                PlayerLog::MAX_CLIENT_LOCALE_LEN,
+                wants_client_info,
+                "client locale",
+            )?;
+        } else if wants_client_info {
+            log.client_brand = Some(Vec::new());
+            log.client_locale = Some(Vec::new());
+        }
+
+        let extension_count = reader.read_u8()? as usize;
+        if extension_count > PlayerLog::MAX_EXTENSIONS {
+            bail!("Too many extensions");
+        }
+
+        let wants_extensions = projection.contains(Projection::EXTENSIONS);
+        let mut extensions = Vec::with_capacity(if wants_extensions { extension_count } else { 0 });
+        for _ in 0..extension_count {
+            let kind = reader.read_u8()?;
+            if let Some(data) = read_field(
+                reader,
+                PlayerLog::MAX_EXTENSION_LEN,
+                wants_extensions,
+                "extension data",
+            )? {
+                extensions.push(Extension { kind, data });
+            }
+        }
+        log.extensions = Some(extensions).filter(|_| wants_extensions);
+
+        Ok(log)
+    }
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::deserialize_many`] for jobs that only need a few fields.
+    pub fn deserialize_many_projected(
+        data: &[u8],
+        projection: Projection,
+    ) -> Result<Vec<PartialPlayerLog>> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        let mut reader = header.codec.decoder(&data[header_len..]);
+
+        let logs = (0..header.record_count)
+            .map(|_| {
+                PartialPlayerLog::deserialize_with_endianness(
+                    &mut reader,
+                    projection,
+                    header.endianness,
+                )
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        super::check_payload_end(&mut reader)?;
+
+        Ok(logs)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    // what Projection::all() has to come up with
+    fn full(log: &PlayerLog) -> PartialPlayerLog {
+        PartialPlayerLog {
+            binary_version: log.binary_version,
+            flags: log.flags,
+            player_uuid: log.player_uuid,
+            player_name: Some(log.player_name.to_vec()),
+            player_ip: Some(log.player_ip),
+            server_ip: Some(log.server_ip),
+            server_port: Some(log.server_port),
+            server_domain: Some(log.server_domain.to_vec()),
+            server_version: Some(log.server_version.clone()),
+            timestamp: Some(log.timestamp),
+            session_id: Some(log.session_id),
+            outcome: Some(log.outcome),
+            geo: log.geo,
+            proxy_ip: log.proxy_ip,
+            client_brand: Some(log.client_brand.clone()),
+            client_locale: Some(log.client_locale.clone()),
+            extensions: Some(log.extensions.clone()),
+        }
+    }
+
+    #[test]
+    fn every_field_decodes_like_a_full_decode() {
+        let logs = logs(300);
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+
+        let projected =
+            PlayerLogSerializer::deserialize_many_projected(&data, Projection::all()).unwrap();
+        assert_eq!(projected, logs.iter().map(full).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn unprojected_fields_stay_empty() {
+        let logs = logs(50);
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+
+        let projection = Projection::NAME | Projection::TIMESTAMP;
+        let projected = PlayerLogSerializer::deserialize_many_projected(&data, projection).unwrap();
+        for (partial, log) in projected.iter().zip(&logs) {
+            assert_eq!(
+                *partial,
+                PartialPlayerLog {
+                    binary_version: log.binary_version,
+                    flags: log.flags,
+                    player_name: Some(log.player_name.to_vec()),
+                    timestamp: Some(log.timestamp),
+                    ..PartialPlayerLog::default()
+                }
+            );
+        }
+
+        let empty =
+            PlayerLogSerializer::deserialize_many_projected(&data, Projection::empty()).unwrap();
+        assert!(empty.iter().all(|partial| partial.player_name.is_none()));
+        assert_eq!(empty.len(), 50);
+    }
+
+    #[test]
+    fn malformed_skipped_fields_still_fail() {
+        // a v1 offline record claiming a 200 byte name
+        let mut record = vec![1, 0, 200];
+        record.extend([0; 200]);
+        record.extend([10, 0, 0, 1, 192, 168, 1, 254, 0x63, 0xDD, 0, 1]);
+        for projection in [
+            Projection::all(),
+            Projection::SERVER_IP,
+            Projection::empty(),
+        ] {
+            let e = PartialPlayerLog::deserialize(&mut record.as_slice(), projection).unwrap_err();
+            assert!(e.to_string().contains("200 bytes"), "{e}");
+        }
+
+        let mut valid = Vec::new();
+        logs(1)[0].serialize(&mut valid).unwrap();
+        for cut in 0..valid.len() {
+            assert!(
+                PartialPlayerLog::deserialize(&mut &valid[..cut], Projection::empty()).is_err(),
+                "{cut}"
+            );
+        }
+    }
+}
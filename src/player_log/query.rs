@@ -0,0 +1,491 @@
+//! A small text syntax for [`PlayerLogFilter`](super::filter::PlayerLogFilter), for filters typed
+//! into a command line:
+//!
+//! ```text
+//! domain = "mc.example.com" and version >= 1.19 and online
+//! not (name ^= "bot_" or port != 25565) and uuid = 069a79f4-44e9-4726-a5be-fca90e38aaf5
+//! ```
+//!
+//! - `name`, `domain`, `brand` and `locale` compare with `=`, `!=` and `^=` (starts with) against
+//!   a quoted string or a bare word.
+//! - `port`, `timestamp`, `session` and `binary_version` compare as numbers with `=`, `!=`, `<`,
+//!   `<=`, `>` and `>=`.
+//! - `version` compares server versions by release order. Unknown server versions never match.
+//! - `player_ip`, `server_ip`, `proxy_ip` and `uuid` only compare with `=` and `!=`. A record
+//!   without a proxy ip or uuid equals none.
+//! - A flag name on its own is true when the flag is set. Both the [`LogFlags`] names, in any
+//!   case, and the short names in [`FLAG_ALIASES`] work.
+//! - `not` binds tighter than `and`, which binds tighter than `or`. Parentheses group, and the
+//!   keywords are case insensitive.
+
+use std::fmt;
+use std::net::Ipv4Addr;
+use std::ops::Range;
+
+use uuid::Uuid;
+
+use super::{LogFlags, PlayerLog, VERSIONS};
+
+/// Short names for the flags, on top of the [`LogFlags`] names.
+pub const FLAG_ALIASES: [(&str, LogFlags); 9] = [
+    ("auth", LogFlags::PLAYER_AUTH),
+    ("online", LogFlags::IS_ONLINE),
+    ("whitelist", LogFlags::SERVER_WHITELIST),
+    ("cracked", LogFlags::CRACKED_CLIENT),
+    ("redacted", LogFlags::REDACTED),
+    ("client_info", LogFlags::HAS_CLIENT_INFO),
+    ("refused", LogFlags::LOGIN_REFUSED),
+    ("geo", LogFlags::HAS_GEO),
+    ("proxied", LogFlags::VIA_PROXY),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextField {
+    PlayerName,
+    ServerDomain,
+    ClientBrand,
+    ClientLocale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumberField {
+    ServerPort,
+    Timestamp,
+    SessionId,
+    BinaryVersion,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IpField {
+    PlayerIp,
+    ServerIp,
+    ProxyIp,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    /// Starts with, only for text.
+    Prefix,
+}
+
+impl Comparison {
+    fn holds<T: Ord + ?Sized>(self, a: &T, b: &T) -> bool {
+        match self {
+            Self::Eq => a == b,
+            Self::Ne => a != b,
+            Self::Lt => a < b,
+            Self::Le => a <= b,
+            Self::Gt => a > b,
+            Self::Ge => a >= b,
+            Self::Prefix => false,
+        }
+    }
+
+    const fn symbol(self) -> &'static str {
+        match self {
+            Self::Eq => "=",
+            Self::Ne => "!=",
+            Self::Lt => "<",
+            Self::Le => "<=",
+            Self::Gt => ">",
+            Self::Ge => ">=",
+            Self::Prefix => "^=",
+        }
+    }
+}
+
+/// A parsed query, see the [module docs](self) for the syntax.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterExpr {
+    And(Box<Self>, Box<Self>),
+    Or(Box<Self>, Box<Self>),
+    Not(Box<Self>),
+    Flag(LogFlags),
+    Text(TextField, Comparison, Vec<u8>),
+    Number(NumberField, Comparison, u64),
+    /// Compares the server version id.
+    Version(Comparison, u8),
+    Ip(IpField, Comparison, Ipv4Addr),
+    Uuid(Comparison, [u8; 16]),
+}
+
+impl FilterExpr {
+    pub fn parse(query: &str) -> Result<Self, QueryError> {
+        let tokens = tokenize(query)?;
+        let mut parser = Parser {
+            tokens: &tokens,
+            pos: 0,
+            end: query.len(),
+        };
+
+        let expr = parser.or()?;
+        if let Some(token) = parser.peek() {
+            return Err(QueryError::new(
+                token.span.clone(),
+                "expected `and`, `or` or the end of the query",
+            ));
+        }
+
+        Ok(expr)
+    }
+
+    pub fn matches(&self, log: &PlayerLog) -> bool {
+        match self {
+            Self::And(a, b) => a.matches(log) && b.matches(log),
+            Self::Or(a, b) => a.matches(log) || b.matches(log),
+            Self::Not(expr) => !expr.matches(log),
+            Self::Flag(flag) => LogFlags::from_bits_retain(log.flags).contains(flag.clone()),
+            Self::Text(field, Comparison::Prefix, value) => text(log, *field).starts_with(value),
+            Self::Text(field, comparison, value) => {
+                comparison.holds(text(log, *field), value.as_slice())
+            }
+            Self::Number(field, comparison, value) => {
+                let number = match field {
+                    NumberField::ServerPort => u64::from(log.server_port),
+                    NumberField::Timestamp => log.timestamp,
+                    NumberField::SessionId => u64::from(log.session_id),
+                    NumberField::BinaryVersion => u64::from(log.binary_version),
+                };
+                comparison.holds(&number, value)
+            }
+            Self::Version(comparison, id) => log
+                .server_version
+                .id()
+                .is_some_and(|version| comparison.holds(&version, id)),
+            Self::Ip(field, comparison, ip) => {
+                let octets = match field {
+                    IpField::PlayerIp => Some(log.player_ip),
+                    IpField::ServerIp => Some(log.server_ip),
+                    IpField::ProxyIp => log.proxy_ip,
+                };
+                comparison.holds(&octets, &Some(ip.octets()))
+            }
+            Self::Uuid(comparison, uuid) => comparison.holds(&log.player_uuid, &Some(*uuid)),
+        }
+    }
+}
+
+fn text(log: &PlayerLog, field: TextField) -> &[u8] {
+    match field {
+        TextField::PlayerName => &log.player_name,
+        TextField::ServerDomain => &log.server_domain,
+        TextField::ClientBrand => &log.client_brand,
+        TextField::ClientLocale => &log.client_locale,
+    }
+}
+
+/// Why a query didn't parse, and the byte range of the token it stopped at.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryError {
+    pub span: Range<usize>,
+    pub message: String,
+}
+
+impl QueryError {
+    fn new(span: Range<usize>, message: impl Into<String>) -> Self {
+        Self {
+            span,
+            message: message.into(),
+        }
+    }
+
+    /// `query` with a line of carets under the offending token.
+    pub fn pointer(&self, query: &str) -> String {
+        let start = query[..self.span.start].chars().count();
+        let width = query[self.span.clone()].chars().count().max(1);
+        format!("{query}\n{}{}", " ".repeat(start), "^".repeat(width))
+    }
+}
+
+impl fmt::Display for QueryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.span.start)
+    }
+}
+
+impl std::error::Error for QueryError {}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TokenKind {
+    Word(String),
+    Quoted(String),
+    Op(Comparison),
+    Open,
+    Close,
+}
+
+#[derive(Debug, Clone)]
+struct Token {
+    kind: TokenKind,
+    span: Range<usize>,
+}
+
+impl Token {
+    // a bare word, matched case insensitively
+    fn is_keyword(&self, keyword: &str) -> bool {
+        matches!(&self.kind, TokenKind::Word(word) if word.eq_ignore_ascii_case(keyword))
+    }
+}
+
+const fn ends_word(c: char) -> bool {
+    c.is_whitespace() || matches!(c, '(' | ')' | '"' | '\'' | '=' | '!' | '<' | '>' | '^')
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::CharIndices<'a>>;
+
+fn eat(chars: &mut Chars<'_>, expected: char) -> bool {
+    chars.next_if(|&(_, c)| c == expected).is_some()
+}
+
+fn tokenize(query: &str) -> Result<Vec<Token>, QueryError> {
+    let mut tokens = Vec::new();
+    let mut chars = query.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        let kind = match c {
+            c if c.is_whitespace() => continue,
+            '(' => TokenKind::Open,
+            ')' => TokenKind::Close,
+            '=' => {
+                // `==` is accepted too
+                eat(&mut chars, '=');
+                TokenKind::Op(Comparison::Eq)
+            }
+            '<' if eat(&mut chars, '=') => TokenKind::Op(Comparison::Le),
+            '<' => TokenKind::Op(Comparison::Lt),
+            '>' if eat(&mut chars, '=') => TokenKind::Op(Comparison::Ge),
+            '>' => TokenKind::Op(Comparison::Gt),
+            '!' if eat(&mut chars, '=') => TokenKind::Op(Comparison::Ne),
+            '^' if eat(&mut chars, '=') => TokenKind::Op(Comparison::Prefix),
+            '!' | '^' => {
+                return Err(QueryError::new(
+                    start..start + 1,
+                    format!("`{c}` has to be followed by `=`"),
+                ))
+            }
+            '"' | '\'' => {
+                // a backslash escapes the next character, whatever it is
+                let mut value = String::new();
+                let mut closed = false;
+                while let Some((_, next)) = chars.next() {
+                    match next {
+                        '\\' => value.extend(chars.next().map(|(_, escaped)| escaped)),
+                        quote if quote == c => {
+                            closed = true;
+                            break;
+                        }
+                        other => value.push(other),
+                    }
+                }
+                if !closed {
+                    return Err(QueryError::new(start..query.len(), "unterminated string"));
+                }
+                TokenKind::Quoted(value)
+            }
+            _ => {
+                while chars.next_if(|&(_, c)| !ends_word(c)).is_some() {}
+                let end = chars.peek().map_or(query.len(), |&(i, _)| i);
+                TokenKind::Word(query[start..end].to_string())
+            }
+        };
+
+        let end = chars.peek().map_or(query.len(), |&(i, _)| i);
+        tokens.push(Token {
+            kind,
+            span: start..end,
+        });
+    }
+
+    Ok(tokens)
+}
+
+enum Field {
+    Text(TextField),
+    Number(NumberField),
+    Version,
+    Ip(IpField),
+    Uuid,
+}
+
+fn field(name: &str) -> Option<Field> {
+    Some(match name.to_ascii_lowercase().as_str() {
+        "name" => Field::Text(TextField::PlayerName),
+        "domain" => Field::Text(TextField::ServerDomain),
+        "brand" => Field::Text(TextField::ClientBrand),
+        "locale" => Field::Text(TextField::ClientLocale),
+        "port" => Field::Number(NumberField::ServerPort),
+        "timestamp" => Field::Number(NumberField::Timestamp),
+        "session" => Field::Number(NumberField::SessionId),
+        "binary_version" => Field::Number(NumberField::BinaryVersion),
+        "version" => Field::Version,
+        "player_ip" => Field::Ip(IpField::PlayerIp),
+        "server_ip" => Field::Ip(IpField::ServerIp),
+        "proxy_ip" => Field::Ip(IpField::ProxyIp),
+        "uuid" => Field::Uuid,
+        _ => return None,
+    })
+}
+
+fn flag(name: &str) -> Option<LogFlags> {
+    FLAG_ALIASES
+        .iter()
+        .find(|(alias, _)| alias.eq_ignore_ascii_case(name))
+        .map(|(_, flag)| flag.clone())
+        .or_else(|| LogFlags::from_name(&name.to_ascii_uppercase()))
+}
+
+struct Parser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+    // where errors about a missing token point
+    end: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self, expected: &str) -> Result<&'a Token, QueryError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .ok_or_else(|| QueryError::new(self.end..self.end, format!("expected {expected}")))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat_keyword(&mut self, keyword: &str) -> bool {
+        let found = self.peek().is_some_and(|token| token.is_keyword(keyword));
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn or(&mut self) -> Result<FilterExpr, QueryError> {
+        let mut expr = self.and()?;
+        while self.eat_keyword("or") {
+            expr = FilterExpr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<FilterExpr, QueryError> {
+        let mut expr = self.unary()?;
+        while self.eat_keyword("and") {
+            expr = FilterExpr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<FilterExpr, QueryError> {
+        if self.eat_keyword("not") {
+            return Ok(FilterExpr::Not(Box::new(self.unary()?)));
+        }
+
+        let token = self.next("a field, a flag or `(`")?;
+        match &token.kind {
+            TokenKind::Open => {
+                let open = token.span.clone();
+                let expr = self.or()?;
+                match self.peek() {
+                    Some(Token {
+                        kind: TokenKind::Close,
+                        ..
+                    }) => {
+                        self.pos += 1;
+                        Ok(expr)
+                    }
+                    Some(token) => Err(QueryError::new(token.span.clone(), "expected `)`")),
+                    None => Err(QueryError::new(open, "unclosed `(`")),
+                }
+            }
+            TokenKind::Word(word) => self.predicate(word, token.span.clone()),
+            _ => Err(QueryError::new(
+                token.span.clone(),
+                "expected a field, a flag or `(`",
+            )),
+        }
+    }
+
+    fn predicate(&mut self, name: &str, span: Range<usize>) -> Result<FilterExpr, QueryError> {
+        let (comparison, op_span) = match self.peek() {
+            Some(Token {
+                kind: TokenKind::Op(comparison),
+                span,
+            }) => (*comparison, span.clone()),
+            _ => {
+                return flag(name).map(FilterExpr::Flag).ok_or_else(|| {
+                    let message = if field(name).is_some() {
+                        format!("`{name}` has to be compared to something")
+                    } else {
+                        format!("unknown field or flag `{name}`")
+                    };
+                    QueryError::new(span, message)
+                });
+            }
+        };
+        let Some(field) = field(name) else {
+            return Err(QueryError::new(span, format!("unknown field `{name}`")));
+        };
+        self.pos += 1;
+
+        let allowed: &[Comparison] = match field {
+            Field::Text(_) => &[Comparison::Eq, Comparison::Ne, Comparison::Prefix],
+            Field::Number(_) | Field::Version => &[
+                Comparison::Eq,
+                Comparison::Ne,
+                Comparison::Lt,
+                Comparison::Le,
+                Comparison::Gt,
+                Comparison::Ge,
+            ],
+            Field::Ip(_) | Field::Uuid => &[Comparison::Eq, Comparison::Ne],
+        };
+        if !allowed.contains(&comparison) {
+            return Err(QueryError::new(
+                op_span,
+                format!("`{name}` can't be compared with `{}`", comparison.symbol()),
+            ));
+        }
+
+        let token = self.next("a value")?;
+        let value = match &token.kind {
+            TokenKind::Word(value) | TokenKind::Quoted(value) => value.as_str(),
+            _ => return Err(QueryError::new(token.span.clone(), "expected a value")),
+        };
+        let invalid = |what: &str| QueryError::new(token.span.clone(), format!("invalid {what}"));
+
+        Ok(match field {
+            Field::Text(field) => FilterExpr::Text(field, comparison, value.as_bytes().to_vec()),
+            Field::Number(field) => FilterExpr::Number(
+                field,
+                comparison,
+                value.parse().map_err(|_| invalid("number"))?,
+            ),
+            Field::Version => FilterExpr::Version(
+                comparison,
+                VERSIONS
+                    .get(value)
+                    .copied()
+                    .ok_or_else(|| invalid("server version"))?,
+            ),
+            Field::Ip(field) => {
+                FilterExpr::Ip(field, comparison, value.parse().map_err(|_| invalid("ip"))?)
+            }
+            Field::Uuid => FilterExpr::Uuid(
+                comparison,
+                Uuid::parse_str(value)
+                    .map_err(|_| invalid("uuid"))?
+                    .into_bytes(),
+            ),
+        })
+    }
+}
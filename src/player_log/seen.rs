@@ -0,0 +1,242 @@
+use std::collections::{HashMap, VecDeque};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+use super::BatchId;
+
+/// Bounded LRU of batch ids the ingest side has already accepted.
+pub struct SeenBatches {
+    capacity: usize,
+    // every time an id was seen, front is the oldest. Only the entry with the id's current
+    // generation is live, the rest are dropped when they reach the front or on compaction
+    order: VecDeque<(u64, BatchId)>,
+    // the generation each id was last seen at
+    ids: HashMap<BatchId, u64>,
+    generation: u64,
+}
+
+impl SeenBatches {
+    pub fn new(capacity: usize) -> Self {
+        // a huge capacity is a bound, not a size to allocate up front
+        let preallocate = capacity.min(1 << 16);
+
+        Self {
+            capacity: capacity.max(1),
+            order: VecDeque::with_capacity(preallocate),
+            ids: HashMap::with_capacity(preallocate),
+            generation: 0,
+        }
+    }
+
+    /// Loads a sidecar written by [`SeenBatches::save`], a missing file starts empty.
+    pub fn load(path: &Path, capacity: usize) -> Result<Self> {
+        let mut seen = Self::new(capacity);
+
+        let data = match fs::read(path) {
+            Ok(data) => data,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(seen),
+            Err(e) => return Err(e.into()),
+        };
+
+        if data.len() % 16 != 0 {
+            bail!("corrupt seen batches sidecar");
+        }
+
+        for chunk in data.chunks_exact(16) {
+            let mut id = [0; 16];
+            id.copy_from_slice(chunk);
+            seen.check_and_record(id);
+        }
+
+        Ok(seen)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let data = self
+            .order
+            .iter()
+            .filter(|entry| self.is_live(entry))
+            .flat_map(|(_, id)| id)
+            .copied()
+            .collect::<Vec<u8>>();
+
+        // write then rename so a crash can't leave a half written sidecar, next to it rather than
+        // in place of whatever shares its stem
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = PathBuf::from(tmp);
+        fs::write(&tmp, data)?;
+        fs::rename(tmp, path)?;
+
+        Ok(())
+    }
+
+    /// Returns `true` if the id was already seen (a duplicate), and marks it as most recently used.
+    pub fn check_and_record(&mut self, id: BatchId) -> bool {
+        self.generation += 1;
+        let seen = self.ids.insert(id, self.generation).is_some();
+        self.order.push_back((self.generation, id));
+
+        if !seen && self.ids.len() > self.capacity {
+            while let Some(entry) = self.order.pop_front() {
+                if self.is_live(&entry) {
+                    self.ids.remove(&entry.1);
+                    break;
+                }
+            }
+        }
+
+        // stale entries never outnumber the live ones for long
+        if self.order.len() > self.capacity.saturating_mul(2) {
+            let ids = &self.ids;
+            self.order
+                .retain(|(generation, id)| ids.get(id) == Some(generation));
+        }
+
+        seen
+    }
+
+    fn is_live(&self, (generation, id): &(u64, BatchId)) -> bool {
+        self.ids.get(id) == Some(generation)
+    }
+
+    pub fn contains(&self, id: &BatchId) -> bool {
+        self.ids.contains_key(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::{export, Codec, PlayerLog, PlayerLogSerializer, SerializerOptions};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn batch_id(data: &[u8]) -> BatchId {
+        PlayerLogSerializer::probe(data).unwrap().batch_id
+    }
+
+    fn id(n: u8) -> BatchId {
+        [n; 16]
+    }
+
+    #[test]
+    fn evicts_the_least_recently_seen() {
+        let mut seen = SeenBatches::new(3);
+        for n in 0..3 {
+            assert!(!seen.check_and_record(id(n)));
+        }
+        // 0 is now the most recent, so 1 goes first
+        assert!(seen.check_and_record(id(0)));
+        assert!(!seen.check_and_record(id(3)));
+
+        assert!(!seen.contains(&id(1)));
+        assert!([0, 2, 3].iter().all(|&n| seen.contains(&id(n))));
+        assert_eq!(seen.len(), 3);
+    }
+
+    #[test]
+    fn repeated_ids_stay_bounded() {
+        let mut seen = SeenBatches::new(4);
+        assert!(!seen.check_and_record(id(0)));
+        for _ in 0..1000 {
+            assert!(seen.check_and_record(id(0)));
+        }
+        assert_eq!(seen.len(), 1);
+        assert!(seen.order.len() <= 8);
+    }
+
+    #[test]
+    fn a_huge_capacity_is_not_preallocated() {
+        let mut seen = SeenBatches::new(usize::MAX);
+        assert!(seen.ids.capacity() < 1 << 20);
+        assert!(!seen.check_and_record(id(0)));
+    }
+
+    #[test]
+    fn saves_in_recency_order_next_to_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("seen.bin");
+        let neighbour = dir.path().join("seen.tmp");
+        fs::write(&neighbour, b"not ours").unwrap();
+
+        let mut seen = SeenBatches::new(2);
+        for n in [0, 1, 0] {
+            seen.check_and_record(id(n));
+        }
+        seen.save(&path).unwrap();
+
+        assert_eq!(fs::read(&neighbour).unwrap(), b"not ours");
+        assert!(!dir.path().join("seen.bin.tmp").exists());
+
+        // 0 was the most recent, so a smaller load evicts 1
+        let loaded = SeenBatches::load(&path, 1).unwrap();
+        assert!(loaded.contains(&id(0)));
+        assert!(!loaded.contains(&id(1)));
+    }
+
+    #[test]
+    fn the_same_logs_get_the_same_default_id() {
+        let logs = logs(50);
+        let first = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let retried = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        assert_eq!(batch_id(&first), batch_id(&retried));
+
+        let mut seen = SeenBatches::new(8);
+        assert!(!seen.check_and_record(batch_id(&first)));
+        assert!(seen.check_and_record(batch_id(&retried)));
+
+        // the payload is hashed, so the codec doesn't matter but one changed record does
+        let lz4 = SerializerOptions {
+            codec: Codec::Lz4,
+            ..SerializerOptions::default()
+        };
+        let compressed = PlayerLogSerializer::serialize_many_with_options(&logs, &lz4).unwrap();
+        assert_eq!(batch_id(&compressed), batch_id(&first));
+        let other = PlayerLogSerializer::serialize_many(&logs[1..]).unwrap();
+        assert!(!seen.check_and_record(batch_id(&other)));
+    }
+
+    #[test]
+    fn converting_a_batch_keeps_its_id() {
+        let logs = logs(50);
+        let options = SerializerOptions {
+            batch_id: Some([9; 16]),
+            producer: Some("uploader".to_string()),
+            ..SerializerOptions::default()
+        };
+        let data = PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap();
+
+        let recompressed = PlayerLogSerializer::recompress(&data, None, Codec::Lz4).unwrap();
+        assert_eq!(batch_id(&recompressed), [9; 16]);
+        let back = PlayerLogSerializer::recompress(&recompressed, Some(Codec::Lz4), Codec::None);
+        assert_eq!(back.unwrap(), data);
+
+        // a default id comes back from the records after a trip through ndjson
+        let data = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let mut ndjson = Vec::new();
+        export::write_ndjson(&logs, &mut ndjson).unwrap();
+        let converted =
+            PlayerLogSerializer::serialize_many(&export::read_ndjson(&ndjson[..]).unwrap())
+                .unwrap();
+        assert_eq!(batch_id(&converted), batch_id(&data));
+    }
+}
@@ -0,0 +1,786 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, ReadBytesExt, WriteBytesExt};
+use uuid::Uuid;
+
+use super::clock::{ClockSkewGuard, SkewAction};
+use super::ordinal::RecordRef;
+use super::projection::Projection;
+use super::tombstone::{self, Marker};
+use super::{BatchId, Codec, Extension, PlayerLog, PlayerLogSerializer, SerializerOptions};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SegmentedIndex {
+    pub codec: Codec,
+    /// `(first record number, byte offset of the segment, compressed segment length)`
+    pub offsets: Vec<(u64, u64, usize)>,
+}
+
+impl SegmentedIndex {
+    fn write<W: Write>(&self, writer: &mut W) -> Result<()> {
+        writer.write_u8(self.codec.id())?;
+        writer.write_u64::<BigEndian>(self.offsets.len() as u64)?;
+
+        for &(record_start, byte_offset, segment_len) in &self.offsets {
+            writer.write_u64::<BigEndian>(record_start)?;
+            writer.write_u64::<BigEndian>(byte_offset)?;
+            writer.write_u64::<BigEndian>(segment_len as u64)?;
+        }
+
+        Ok(())
+    }
+
+    /// Reads the index back using the offset stored in the final 8 bytes of the stream.
+    pub fn read_from<R: Read + Seek>(mut reader: R) -> Result<Self> {
+        reader.seek(SeekFrom::End(-8))?;
+        let index_offset = reader.read_u64::<BigEndian>()?;
+        reader.seek(SeekFrom::Start(index_offset))?;
+
+        let codec = Codec::from_id(reader.read_u8()?)?;
+
+        let len = reader.read_u64::<BigEndian>()?;
+        let offsets = (0..len)
+            .map(|_| -> Result<(u64, u64, usize)> {
+                Ok((
+                    reader.read_u64::<BigEndian>()?,
+                    reader.read_u64::<BigEndian>()?,
+                    reader.read_u64::<BigEndian>()? as usize,
+                ))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Self { codec, offsets })
+    }
+}
+
+/// Writes `logs` as independently compressed segments of `records_per_segment` records, followed
+/// by the index and the index's offset as the final 8 bytes.
+pub fn write_segmented<W: Write + Seek>(
+    logs: &[PlayerLog],
+    records_per_segment: usize,
+    algo: Codec,
+    mut writer: W,
+) -> Result<SegmentedIndex> {
+    if records_per_segment == 0 {
+        bail!("records_per_segment must be at least 1");
+    }
+
+    let mut index = SegmentedIndex {
+        codec: algo,
+        offsets: Vec::with_capacity(logs.len().div_ceil(records_per_segment)),
+    };
+
+    let mut raw = Vec::new();
+    let mut encoded = Vec::new();
+    for (i, segment) in logs.chunks(records_per_segment).enumerate() {
+        raw.clear();
+        segment.iter().try_for_each(|log| log.serialize(&mut raw))?;
+
+        encoded.clear();
+        algo.encode_into(&raw, &mut encoded)?;
+
+        let byte_offset = writer.stream_position()?;
+        writer.write_all(&encoded)?;
+
+        index
+            .offsets
+            .push(((i * records_per_segment) as u64, byte_offset, encoded.len()));
+    }
+
+    let index_offset = writer.stream_position()?;
+    index.write(&mut writer)?;
+    writer.write_u64::<BigEndian>(index_offset)?;
+    writer.flush()?;
+
+    Ok(index)
+}
+
+pub fn read_segment_n<R: Read + Seek>(
+    mut reader: R,
+    index: &SegmentedIndex,
+    n: usize,
+) -> Result<Vec<PlayerLog>> {
+    let &(_, byte_offset, segment_len) = index
+        .offsets
+        .get(n)
+        .with_context(|| format!("segment {n} out of range"))?;
+
+    // the index is read from the stream too, so the length is checked before it's allocated
+    let stream_len = reader.seek(SeekFrom::End(0))?;
+    if byte_offset
+        .checked_add(segment_len as u64)
+        .is_none_or(|end| end > stream_len)
+    {
+        bail!(
+            "segment {n} of {segment_len} bytes at {byte_offset} runs past the end of the stream"
+        );
+    }
+
+    reader.seek(SeekFrom::Start(byte_offset))?;
+    let mut encoded = vec![0; segment_len];
+    reader.read_exact(&mut encoded)?;
+
+    let mut raw = Vec::new();
+    index.codec.decoder(&encoded).read_to_end(&mut raw)?;
+
+    let mut records = raw.as_slice();
+    let mut logs = Vec::new();
+    while !records.is_empty() {
+        logs.push(PlayerLog::deserialize(&mut records)?);
+    }
+
+    Ok(logs)
+}
+
+#[derive(Debug, Clone)]
+pub struct SegmentConfig {
+    pub max_records: usize,
+    /// Uncompressed size of the records, a single larger record still gets a segment of its own.
+    pub max_bytes: usize,
+    pub codec: Codec,
+    /// Checks every timestamp against the latest one appended before it, across segments.
+    pub clock_skew: Option<ClockSkewGuard>,
+}
+
+/// Splits a stream of logs into batch files next to `base_path`, named like `logins.00003.plgb`
+/// for a base path of `logins`. [`scan_segments`] finds them again.
+///
+/// Buffered records only reach the disk once a segment fills up or on [`SegmentedWriter::close`].
+pub struct SegmentedWriter {
+    base_path: PathBuf,
+    config: SegmentConfig,
+    pending: Vec<PlayerLog>,
+    pending_bytes: usize,
+    next_segment: u32,
+    paths: Vec<PathBuf>,
+    latest_timestamp: u64,
+    skew_warnings: usize,
+}
+
+impl SegmentedWriter {
+    /// Numbering continues after the segments already next to `base_path`.
+    pub fn new(base_path: &Path, config: SegmentConfig) -> Result<Self> {
+        if config.max_records == 0 || config.max_bytes == 0 {
+            bail!("segment limits must be at least 1");
+        }
+
+        let next_segment = scan_segments(base_path)?
+            .last()
+            .and_then(|path| segment_number(base_path, path))
+            .map_or(0, |n| n + 1);
+
+        Ok(Self {
+            base_path: base_path.to_path_buf(),
+            config,
+            pending: Vec::new(),
+            pending_bytes: 0,
+            next_segment,
+            paths: Vec::new(),
+            latest_timestamp: 0,
+            skew_warnings: 0,
+        })
+    }
+
+    /// Fails with a [`ClockSkewExceeded`](super::clock::ClockSkewExceeded) if the clock skew
+    /// guard rejects the log, which is then dropped. Tombstones have no time and skip the guard.
+    pub fn append(&mut self, log: PlayerLog) -> Result<()> {
+        if log.is_tombstone() {
+            return self.push(log);
+        }
+
+        if let Some(guard) = self.config.clock_skew {
+            if let Err(e) = guard.check(self.latest_timestamp, log.timestamp) {
+                match guard.action {
+                    SkewAction::Warn => self.skew_warnings += 1,
+                    SkewAction::Reject => return Err(e.into()),
+                }
+            }
+        }
+        self.latest_timestamp = self.latest_timestamp.max(log.timestamp);
+
+        self.push(log)
+    }
+
+    fn push(&mut self, log: PlayerLog) -> Result<()> {
+        let size = log.serialized_size();
+        if !self.pending.is_empty() && self.pending_bytes + size > self.config.max_bytes {
+            self.flush_segment()?;
+        }
+
+        self.pending_bytes += size;
+        self.pending.push(log);
+        if self.pending.len() >= self.config.max_records {
+            self.flush_segment()?;
+        }
+
+        Ok(())
+    }
+
+    /// Logs the clock skew guard let through with [`SkewAction::Warn`].
+    pub const fn skew_warnings(&self) -> usize {
+        self.skew_warnings
+    }
+
+    /// Writes the buffered records out as a segment of their own, even if it isn't full yet.
+    pub fn flush(&mut self) -> Result<()> {
+        if !self.pending.is_empty() {
+            self.flush_segment()?;
+        }
+
+        Ok(())
+    }
+
+    /// Writes the last segment, returns the paths of every segment this writer wrote.
+    pub fn close(mut self) -> Result<Vec<PathBuf>> {
+        self.flush()?;
+
+        Ok(self.paths)
+    }
+
+    fn flush_segment(&mut self) -> Result<()> {
+        let batch = PlayerLogSerializer::serialize_many_with_options(
+            &self.pending,
+            &SerializerOptions {
+                codec: self.config.codec,
+                // the hash of the records would give two segments with the same records the same
+                // id, and a RecordRef into one would match the other
+                batch_id: Some(*Uuid::new_v4().as_bytes()),
+                ..Default::default()
+            },
+        )?;
+
+        let path = segment_path(&self.base_path, self.next_segment);
+        fs::write(&path, batch).with_context(|| format!("writing {}", path.display()))?;
+
+        self.next_segment += 1;
+        self.paths.push(path);
+        self.pending.clear();
+        self.pending_bytes = 0;
+
+        Ok(())
+    }
+}
+
+fn segment_path(base_path: &Path, n: u32) -> PathBuf {
+    let mut name = base_path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(".{n:05}.plgb"));
+    base_path.with_file_name(name)
+}
+
+fn segment_number(base_path: &Path, path: &Path) -> Option<u32> {
+    let base = base_path.file_name()?.to_str()?;
+    let digits = path
+        .file_name()?
+        .to_str()?
+        .strip_prefix(base)?
+        .strip_prefix('.')?
+        .strip_suffix(".plgb")?;
+
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// The segments a [`SegmentedWriter`] wrote for `base_path`, in order.
+pub fn scan_segments(base_path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = match base_path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = base_path.with_file_name(entry?.file_name());
+        if let Some(n) = segment_number(base_path, &path) {
+            segments.push((n, path));
+        }
+    }
+    segments.sort();
+
+    Ok(segments.into_iter().map(|(_, path)| path).collect())
+}
+
+fn read_file(path: &Path) -> Result<Vec<u8>> {
+    fs::read(path).with_context(|| format!("reading {}", path.display()))
+}
+
+/// What [`SegmentStore::compact_segment`] dropped from the segment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compaction {
+    pub records: usize,
+    pub tombstones: usize,
+}
+
+// what the tombstones and gaps of every segment add up to
+#[derive(Debug, Default)]
+struct TombstoneScan {
+    dead: HashSet<RecordRef>,
+    // the ordinals compaction dropped from each segment
+    gaps: HashMap<BatchId, Vec<Range<u32>>>,
+}
+
+impl TombstoneScan {
+    // the target was compacted away, or was never in the store
+    fn is_gone(&self, target: &RecordRef) -> bool {
+        self.gaps
+            .get(&target.batch_id)
+            .is_none_or(|gaps| gaps.iter().any(|gap| gap.contains(&target.ordinal)))
+    }
+}
+
+// every record with its marker and its first ordinal
+fn number<T>(
+    records: Vec<T>,
+    extensions: impl Fn(&T) -> &[Extension],
+) -> Result<Vec<(u32, Marker, T)>> {
+    let mut ordinal = 0u32;
+    records
+        .into_iter()
+        .map(|record| {
+            let marker = Marker::of(extensions(&record));
+            let first = ordinal;
+            ordinal = ordinal
+                .checked_add(marker.ordinals())
+                .context("segment ordinals past u32::MAX")?;
+            Ok((first, marker, record))
+        })
+        .collect()
+}
+
+/// The segments of a [`SegmentedWriter`] with records that can be deleted.
+///
+/// A record is deleted by appending a [tombstone](PlayerLog::tombstone) for it, the space it takes
+/// is reclaimed later by [`SegmentStore::compact_segment`].
+///
+/// Records are addressed by the batch id of their segment and their ordinal in it. Compaction
+/// keeps both, so a [`RecordRef`] stays valid for as long as its record is in the store. Once a
+/// segment is compacted its ordinals are no longer positions in its batch, only the store numbers
+/// its records the same way as before, an [`OffsetIndex`](super::ordinal::OffsetIndex) of the
+/// file doesn't.
+///
+/// Only records that were flushed to a segment are read back, and only flushed tombstones delete
+/// anything.
+pub struct SegmentStore {
+    base_path: PathBuf,
+    writer: SegmentedWriter,
+}
+
+impl SegmentStore {
+    /// New segments are numbered after the ones already next to `base_path`.
+    pub fn open(base_path: &Path, config: SegmentConfig) -> Result<Self> {
+        Ok(Self {
+            base_path: base_path.to_path_buf(),
+            writer: SegmentedWriter::new(base_path, config)?,
+        })
+    }
+
+    /// Fails on a log carrying a kind of extension reserved for tombstones and gaps, which the
+    /// store would read back as one.
+    pub fn append(&mut self, log: PlayerLog) -> Result<()> {
+        if let Some(extension) = log
+            .extensions
+            .iter()
+            .find(|e| tombstone::is_reserved(e.kind))
+        {
+            bail!(
+                "extension kind {:#04x} is reserved for tombstones and gaps",
+                extension.kind
+            );
+        }
+
+        self.writer.append(log)
+    }
+
+    /// Deletes `target`, which isn't checked to exist, a tombstone for a record the store doesn't
+    /// have deletes nothing.
+    pub fn append_tombstone(&mut self, target: &RecordRef) -> Result<()> {
+        self.writer.append(PlayerLog::tombstone(target))
+    }
+
+    /// Writes the buffered records and tombstones out as a segment.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()
+    }
+
+    pub fn segments(&self) -> Result<Vec<PathBuf>> {
+        scan_segments(&self.base_path)
+    }
+
+    /// Every record a tombstone in some segment targets.
+    pub fn tombstoned(&self) -> Result<HashSet<RecordRef>> {
+        Ok(self.scan_tombstones()?.dead)
+    }
+
+    fn scan_tombstones(&self) -> Result<TombstoneScan> {
+        let mut scan = TombstoneScan::default();
+        for path in self.segments()? {
+            let data = read_file(&path)?;
+            let batch_id = PlayerLogSerializer::probe(&data)?.batch_id;
+            let logs =
+                PlayerLogSerializer::deserialize_many_projected(&data, Projection::EXTENSIONS)
+                    .with_context(|| path.display().to_string())?;
+
+            let gaps = scan.gaps.entry(batch_id).or_default();
+            for (ordinal, marker, _) in
+                number(logs, |log| log.extensions.as_deref().unwrap_or_default())?
+            {
+                match marker {
+                    Marker::Record => {}
+                    Marker::Tombstone(target) => {
+                        scan.dead.insert(target);
+                    }
+                    Marker::Gap(len) => gaps.push(ordinal..ordinal + len),
+                }
+            }
+        }
+
+        Ok(scan)
+    }
+
+    /// The records of every segment in order with their refs, leaving out the tombstones and the
+    /// records they delete.
+    ///
+    /// The tombstones of all segments are gathered before the first record is read, so a record
+    /// is left out whether its tombstone is in a segment before or after it.
+    pub fn iter(&self) -> Result<impl Iterator<Item = Result<(RecordRef, PlayerLog)>>> {
+        let dead = self.tombstoned()?;
+
+        Ok(self.segments()?.into_iter().flat_map(move |path| {
+            let read = || -> Result<_> {
+                let data = read_file(&path)?;
+                let batch_id = PlayerLogSerializer::probe(&data)?.batch_id;
+                let logs = PlayerLogSerializer::deserialize_many_with_markers(&data)
+                    .with_context(|| path.display().to_string())?;
+
+                Ok(number(logs, |log| log.extensions.as_slice())?
+                    .into_iter()
+                    .filter(|&(_, marker, _)| marker == Marker::Record)
+                    .map(|(ordinal, _, log)| (RecordRef::new(batch_id, ordinal), log))
+                    .filter(|(record, _)| !dead.contains(record))
+                    .map(Ok)
+                    .collect::<Vec<_>>())
+            };
+
+            read().unwrap_or_else(|e| vec![Err(e)])
+        }))
+    }
+
+    /// Rewrites segment `n` without the records tombstoned anywhere in the store, and without the
+    /// tombstones of its own whose target is gone, from this segment or from one compacted before.
+    /// Buffered tombstones are flushed first so they count.
+    ///
+    /// The segment keeps its batch id, and each run of dropped records leaves a
+    /// [gap](PlayerLog::gap) behind so the records after it keep their ordinals. Tombstones
+    /// elsewhere targeting the dropped records are dropped when their own segment is compacted. A
+    /// segment with nothing to drop is left as it is.
+    pub fn compact_segment(&mut self, n: u32) -> Result<Compaction> {
+        self.flush()?;
+
+        let path = segment_path(&self.base_path, n);
+        if !path.is_file() {
+            bail!("no segment {n} at {}", path.display());
+        }
+        let scan = self.scan_tombstones()?;
+
+        let data = read_file(&path)?;
+        let header = PlayerLogSerializer::probe(&data)?;
+        let logs = PlayerLogSerializer::deserialize_many_with_markers(&data)
+            .with_context(|| path.display().to_string())?;
+
+        let mut compaction = Compaction::default();
+        let mut kept = Vec::with_capacity(logs.len());
+        // ordinals dropped since the last kept record, the gaps already there included
+        let mut gap = 0;
+        for (ordinal, marker, log) in number(logs, |log| log.extensions.as_slice())? {
+            let dropped = match marker {
+                Marker::Record => {
+                    let dead = scan
+                        .dead
+                        .contains(&RecordRef::new(header.batch_id, ordinal));
+                    compaction.records += usize::from(dead);
+                    dead
+                }
+                Marker::Tombstone(target) => {
+                    // a target in this segment is dropped along with it
+                    let gone = target.batch_id == header.batch_id || scan.is_gone(&target);
+                    compaction.tombstones += usize::from(gone);
+                    gone
+                }
+                Marker::Gap(_) => true,
+            };
+
+            if dropped {
+                gap += marker.ordinals();
+            } else {
+                if gap > 0 {
+                    kept.push(PlayerLog::gap(gap));
+                    gap = 0;
+                }
+                kept.push(log);
+            }
+        }
+        if compaction == Compaction::default() {
+            return Ok(compaction);
+        }
+        // kept so a tombstone targeting the end of the segment is known to be gone
+        if gap > 0 {
+            kept.push(PlayerLog::gap(gap));
+        }
+
+        let batch = PlayerLogSerializer::serialize_many_with_options(
+            &kept,
+            &SerializerOptions {
+                codec: header.codec,
+                batch_id: Some(header.batch_id),
+                producer: header.producer.map(str::to_owned),
+                endianness: header.endianness,
+            },
+        )?;
+
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, batch).with_context(|| format!("writing {}", tmp.display()))?;
+        fs::rename(tmp, &path)?;
+
+        Ok(compaction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io;
+
+    use rand::{rngs::StdRng, SeedableRng};
+    use tempfile::TempDir;
+
+    use super::super::clock::ClockSkewExceeded;
+    use super::*;
+    use crate::log_generator_seeded;
+
+    // a store of 3 record segments holding `count` logs, flushed
+    fn store_with(count: usize) -> (TempDir, SegmentStore, Vec<(RecordRef, PlayerLog)>) {
+        let dir = tempfile::tempdir().unwrap();
+        let config = SegmentConfig {
+            max_records: 3,
+            max_bytes: usize::MAX,
+            codec: Codec::Lz4,
+            clock_skew: None,
+        };
+        let mut store = SegmentStore::open(&dir.path().join("logins"), config).unwrap();
+
+        let mut rng = StdRng::seed_from_u64(0);
+        for _ in 0..count {
+            store
+                .append(log_generator_seeded(&mut rng).build().unwrap())
+                .unwrap();
+        }
+        store.flush().unwrap();
+        let records = read(&store);
+
+        (dir, store, records)
+    }
+
+    fn read(store: &SegmentStore) -> Vec<(RecordRef, PlayerLog)> {
+        store.iter().unwrap().collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn deleted_records_are_not_read() {
+        let (_dir, mut store, records) = store_with(7);
+        assert_eq!(records.len(), 7);
+
+        store.append_tombstone(&records[1].0).unwrap();
+        store.append_tombstone(&records[5].0).unwrap();
+        store.flush().unwrap();
+
+        let mut expected = records;
+        expected.remove(5);
+        expected.remove(1);
+        assert_eq!(read(&store), expected);
+    }
+
+    #[test]
+    fn tombstones_reach_records_in_earlier_segments() {
+        let (_dir, mut store, records) = store_with(6);
+
+        // the tombstone is buffered behind three more records, so it lands two segments later
+        store.append_tombstone(&records[0].0).unwrap();
+        let mut rng = StdRng::seed_from_u64(1);
+        for _ in 0..3 {
+            store
+                .append(log_generator_seeded(&mut rng).build().unwrap())
+                .unwrap();
+        }
+        store.flush().unwrap();
+        assert_eq!(store.segments().unwrap().len(), 4);
+
+        let read = read(&store);
+        assert_eq!(read.len(), 8);
+        assert!(read.iter().all(|(record, _)| *record != records[0].0));
+        assert_eq!(read[..5], records[1..]);
+    }
+
+    #[test]
+    fn segments_with_the_same_records_have_different_ids() {
+        let (_dir, mut store, records) = store_with(3);
+        for (_, log) in &records {
+            store.append(log.clone()).unwrap();
+        }
+        store.flush().unwrap();
+
+        store.append_tombstone(&records[0].0).unwrap();
+        store.flush().unwrap();
+
+        let read = read(&store);
+        assert_eq!(read.len(), 5);
+        assert_eq!(read[..2], records[1..]);
+        assert_eq!(read[2].1, records[0].1);
+    }
+
+    #[test]
+    fn compaction_drops_dead_records_and_keeps_refs() {
+        let (_dir, mut store, records) = store_with(6);
+        store.append_tombstone(&records[0].0).unwrap();
+        store.append_tombstone(&records[4].0).unwrap();
+        store.flush().unwrap();
+        let before = read(&store);
+
+        let compaction = store.compact_segment(0).unwrap();
+        assert_eq!(
+            compaction,
+            Compaction {
+                records: 1,
+                tombstones: 0
+            }
+        );
+        assert_eq!(store.compact_segment(0).unwrap(), Compaction::default());
+
+        // the records after the dropped one keep their refs
+        assert_eq!(read(&store), before);
+        assert!(store.iter().unwrap().any(|r| r.unwrap().0 == records[2].0));
+
+        // the tombstone for segment 0 is resolved, the one for segment 1 isn't yet
+        assert_eq!(
+            store.compact_segment(2).unwrap(),
+            Compaction {
+                records: 0,
+                tombstones: 1
+            }
+        );
+        assert_eq!(read(&store), before);
+
+        store.compact_segment(1).unwrap();
+        assert_eq!(
+            store.compact_segment(2).unwrap(),
+            Compaction {
+                records: 0,
+                tombstones: 1
+            }
+        );
+        assert_eq!(read(&store), before);
+        assert!(store.tombstoned().unwrap().is_empty());
+
+        // a ref into the compacted segment still deletes the right record
+        store.append_tombstone(&records[2].0).unwrap();
+        store.flush().unwrap();
+        let after = read(&store);
+        assert_eq!(after.len(), before.len() - 1);
+        assert!(after.iter().all(|(record, _)| *record != records[2].0));
+    }
+
+    #[test]
+    fn compacting_a_missing_segment_fails() {
+        let (_dir, mut store, _) = store_with(1);
+        assert!(store.compact_segment(5).is_err());
+    }
+
+    #[test]
+    fn segmented_streams_read_back_by_segment() {
+        let (_dir, _, records) = store_with(7);
+        let logs: Vec<_> = records.into_iter().map(|(_, log)| log).collect();
+
+        let mut stream = io::Cursor::new(Vec::new());
+        let index = write_segmented(&logs, 3, Codec::Lz4, &mut stream).unwrap();
+        assert_eq!(SegmentedIndex::read_from(&mut stream).unwrap(), index);
+        assert_eq!(index.offsets.len(), 3);
+
+        assert_eq!(read_segment_n(&mut stream, &index, 1).unwrap(), logs[3..6]);
+        assert_eq!(read_segment_n(&mut stream, &index, 2).unwrap(), logs[6..]);
+        assert!(read_segment_n(&mut stream, &index, 3).is_err());
+    }
+
+    #[test]
+    fn a_segment_length_past_the_stream_fails_before_allocating() {
+        let (_dir, _, records) = store_with(4);
+        let logs: Vec<_> = records.into_iter().map(|(_, log)| log).collect();
+
+        let mut stream = io::Cursor::new(Vec::new());
+        let mut index = write_segmented(&logs, 2, Codec::None, &mut stream).unwrap();
+        for len in [usize::MAX, 1 << 40, stream.get_ref().len()] {
+            index.offsets[1].2 = len;
+            let e = read_segment_n(&mut stream, &index, 1).unwrap_err();
+            assert!(e.to_string().contains("past the end"), "{e}");
+        }
+    }
+
+    #[test]
+    fn the_clock_skew_guard_spans_segments() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut rng = StdRng::seed_from_u64(0);
+        let logs = [5000, 6000, 4500, 1000, 0, 7000].map(|timestamp| {
+            let mut builder = log_generator_seeded(&mut rng);
+            builder.timestamp = Some(timestamp);
+            builder.build().unwrap()
+        });
+
+        for action in [SkewAction::Warn, SkewAction::Reject] {
+            let config = SegmentConfig {
+                max_records: 2,
+                max_bytes: usize::MAX,
+                codec: Codec::None,
+                clock_skew: Some(ClockSkewGuard {
+                    max_skew_millis: 1000,
+                    action,
+                }),
+            };
+            let base = dir.path().join(format!("{action:?}"));
+            let mut writer = SegmentedWriter::new(&base, config).unwrap();
+
+            let mut rejected = Vec::new();
+            for (i, log) in logs.iter().enumerate() {
+                if let Err(e) = writer.append(log.clone()) {
+                    let e = e.downcast_ref::<ClockSkewExceeded>().unwrap();
+                    assert_eq!(e.latest, 6000);
+                    rejected.push(i);
+                }
+            }
+
+            let expected = match action {
+                SkewAction::Warn => {
+                    assert_eq!(writer.skew_warnings(), 2);
+                    logs.to_vec()
+                }
+                SkewAction::Reject => {
+                    assert_eq!(rejected, [2, 3]);
+                    assert_eq!(writer.skew_warnings(), 0);
+                    [&logs[..2], &logs[4..]].concat()
+                }
+            };
+
+            let mut read = Vec::new();
+            for path in writer.close().unwrap() {
+                let data = std::fs::read(path).unwrap();
+                read.extend(PlayerLogSerializer::deserialize_many(&data).unwrap());
+            }
+            assert_eq!(read, expected, "{action:?}");
+        }
+    }
+}
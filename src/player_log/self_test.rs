@@ -0,0 +1,257 @@
+//! Quick checks that a build encodes and decodes the format the way every other build does, for
+//! running before a deployment or at service startup.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, Context, Result};
+use flate2::Compression;
+
+use super::visit::{NetworkColumns, StatsVisitor};
+use super::{conformance, Codec, PlayerLog, PlayerLogSerializer, SerializerOptions, ServerVersion};
+use crate::generate_builders;
+
+const CODECS: [Codec; 3] = [Codec::None, Codec::Zlib(Compression::new(6)), Codec::Lz4];
+
+// enough records to cover several serialize chunks, few enough to take well under a second
+const SAMPLE_LOGS: usize = 20_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Check {
+    /// Every coverage vector that decodes round trips through every codec unchanged.
+    CoverageRoundTrip,
+    /// The seeded golden batches hash to [`conformance::GOLDEN_HASH`] on one thread and on the
+    /// global pool, see [`conformance::check_determinism`].
+    GoldenHash,
+    /// The chunked serializer writes the same payload as serializing record by record.
+    ParallelAgreement,
+    /// [`PlayerLogSerializer::visit`] sees the same fields as a full decode of every coverage
+    /// vector that decodes.
+    VisitorAgreement,
+    /// Serializes and deserializes a generated batch and reports how fast it went. Only fails if
+    /// the records don't survive the round trip.
+    Throughput,
+}
+
+impl Check {
+    pub const ALL: [Self; 5] = [
+        Self::CoverageRoundTrip,
+        Self::GoldenHash,
+        Self::ParallelAgreement,
+        Self::VisitorAgreement,
+        Self::Throughput,
+    ];
+
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::CoverageRoundTrip => "coverage round trip",
+            Self::GoldenHash => "golden hash",
+            Self::ParallelAgreement => "parallel agreement",
+            Self::VisitorAgreement => "visitor agreement",
+            Self::Throughput => "throughput",
+        }
+    }
+
+    pub fn run(self) -> CheckResult {
+        let start = Instant::now();
+        let outcome = match self {
+            Self::CoverageRoundTrip => coverage_round_trip(),
+            Self::GoldenHash => conformance::check_determinism().map(|()| String::new()),
+            Self::ParallelAgreement => parallel_agreement(),
+            Self::VisitorAgreement => visitor_agreement(),
+            Self::Throughput => throughput(),
+        };
+
+        CheckResult {
+            check: self,
+            outcome: outcome.map_err(|e| format!("{e:#}")),
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheckResult {
+    pub check: Check,
+    /// What the check found either way, empty if a passing check has nothing to add.
+    pub outcome: Result<String, String>,
+    pub elapsed: Duration,
+}
+
+impl CheckResult {
+    pub const fn passed(&self) -> bool {
+        self.outcome.is_ok()
+    }
+}
+
+impl fmt::Display for CheckResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed() { "pass" } else { "FAIL" };
+        write!(f, "{status} {} ({:?})", self.check.name(), self.elapsed)?;
+
+        match &self.outcome {
+            Ok(detail) if detail.is_empty() => Ok(()),
+            Ok(detail) | Err(detail) => write!(f, ": {detail}"),
+        }
+    }
+}
+
+/// Runs every [`Check`], in the order of [`Check::ALL`].
+pub fn run() -> Vec<CheckResult> {
+    Check::ALL.into_iter().map(Check::run).collect()
+}
+
+fn coverage_round_trip() -> Result<String> {
+    let mut decoded = 0;
+    for (name, data) in conformance::coverage_vectors() {
+        // the vectors that have to be rejected are the conformance run's business
+        let Ok(logs) = PlayerLogSerializer::deserialize_many(&data) else {
+            continue;
+        };
+        decoded += 1;
+
+        for codec in CODECS {
+            let options = SerializerOptions {
+                codec,
+                ..SerializerOptions::default()
+            };
+            let data = PlayerLogSerializer::serialize_many_with_options(&logs, &options)
+                .with_context(|| format!("{name} with {}", codec.name()))?;
+            let round_tripped = PlayerLogSerializer::deserialize_many(&data)
+                .with_context(|| format!("{name} with {}", codec.name()))?;
+
+            if round_tripped != logs {
+                bail!("{name} changed going through {}", codec.name());
+            }
+        }
+    }
+
+    if decoded == 0 {
+        bail!("none of the coverage vectors decoded");
+    }
+
+    Ok(format!("{decoded} vectors through {} codecs", CODECS.len()))
+}
+
+fn sample_logs() -> Result<Vec<PlayerLog>> {
+    generate_builders(SAMPLE_LOGS)
+        .iter()
+        .map(|builder| builder.build())
+        .collect()
+}
+
+fn parallel_agreement() -> Result<String> {
+    let logs = sample_logs()?;
+
+    let data = PlayerLogSerializer::serialize_many(&logs)?;
+    let (header, header_len) = PlayerLogSerializer::probe_with_len(&data)?;
+    if header.record_count != logs.len() as u64 {
+        bail!(
+            "header counts {} records, expected {}",
+            header.record_count,
+            logs.len()
+        );
+    }
+
+    let mut sequential = Vec::with_capacity(data.len() - header_len);
+    logs.iter()
+        .try_for_each(|log| log.serialize(&mut sequential))?;
+
+    let chunked = &data[header_len..];
+    if chunked != sequential {
+        let at = chunked
+            .iter()
+            .zip(&sequential)
+            .position(|(a, b)| a != b)
+            .unwrap_or_else(|| chunked.len().min(sequential.len()));
+        bail!("chunked and sequential payloads differ at byte {at}");
+    }
+
+    Ok(format!("{} records", logs.len()))
+}
+
+fn visitor_agreement() -> Result<String> {
+    let mut compared = 0;
+    for (name, data) in conformance::coverage_vectors() {
+        let Ok(logs) = PlayerLogSerializer::deserialize_many(&data) else {
+            continue;
+        };
+        compared += 1;
+
+        let mut columns = NetworkColumns::default();
+        let mut stats = StatsVisitor::default();
+        PlayerLogSerializer::visit(&data, &mut columns).with_context(|| name.to_string())?;
+        PlayerLogSerializer::visit(&data, &mut stats).with_context(|| name.to_string())?;
+
+        let expected = NetworkColumns {
+            player_ips: logs.iter().map(|log| log.player_ip).collect(),
+            server_ips: logs.iter().map(|log| log.server_ip).collect(),
+            server_ports: logs.iter().map(|log| log.server_port).collect(),
+            timestamps: logs.iter().map(|log| log.timestamp).collect(),
+            server_versions: logs
+                .iter()
+                .map(|log| match log.server_version {
+                    ServerVersion::Known(id) => Some(id),
+                    ServerVersion::Unknown(_) => None,
+                })
+                .collect(),
+        };
+        if columns != expected {
+            bail!("{name}: visited columns differ from the decoded records");
+        }
+
+        let mut present_fields = BTreeMap::new();
+        let mut binary_versions = BTreeMap::new();
+        for log in &logs {
+            *binary_versions.entry(log.binary_version).or_default() += 1;
+            for (field, _) in log.present_fields().iter_names() {
+                *present_fields.entry(field).or_default() += 1;
+            }
+        }
+        if stats.present_fields != present_fields || stats.binary_versions != binary_versions {
+            bail!("{name}: visited stats differ from the decoded records");
+        }
+    }
+
+    if compared == 0 {
+        bail!("none of the coverage vectors decoded");
+    }
+
+    Ok(format!("{compared} vectors"))
+}
+
+fn throughput() -> Result<String> {
+    let logs = sample_logs()?;
+
+    let start = Instant::now();
+    let data = PlayerLogSerializer::serialize_many(&logs)?;
+    let serialized = start.elapsed();
+
+    let start = Instant::now();
+    let round_tripped = PlayerLogSerializer::deserialize_many(&data)?;
+    let deserialized = start.elapsed();
+
+    if round_tripped != logs {
+        bail!("records changed in the round trip");
+    }
+
+    let mb_per_sec = |elapsed: Duration| data.len() as f64 / 1e6 / elapsed.as_secs_f64().max(1e-9);
+    Ok(format!(
+        "serialize {:.0} MB/s, deserialize {:.0} MB/s",
+        mb_per_sec(serialized),
+        mb_per_sec(deserialized)
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn visitor_agreement_passes_and_names_itself() {
+        let result = Check::VisitorAgreement.run();
+        assert!(result.passed(), "{result}");
+        assert!(result.to_string().starts_with("pass visitor agreement ("));
+    }
+}
@@ -0,0 +1,167 @@
+use std::io::{Seek, SeekFrom, Write};
+
+use anyhow::{bail, Result};
+use byteorder::{BigEndian, WriteBytesExt};
+use uuid::Uuid;
+
+use super::{BatchHeader, Codec, Endianness, PlayerLog, SerializerOptions};
+
+/// Writes an uncompressed batch one record at a time, without knowing up front how many there
+/// will be.
+///
+/// The header goes out with a record count of zero, [`IncrementalSerializer::finish`] seeks back
+/// and fills in the real one.
+///
+/// A batch that is never finished reads back as empty. The records can't be hashed into the
+/// default batch id before the count is known, so batches without an explicit id get a random
+/// one.
+pub struct IncrementalSerializer<W: Write + Seek> {
+    writer: W,
+    record_count: u64,
+    count_offset: u64,
+    endianness: Endianness,
+}
+
+impl<W: Write + Seek> IncrementalSerializer<W> {
+    /// Starts the batch at the writer's current position.
+    pub fn begin(writer: W) -> Result<Self> {
+        Self::begin_with_options(writer, SerializerOptions::default())
+    }
+
+    /// Only [`Codec::None`] can be written this way, a compressed payload can't be patched.
+    pub fn begin_with_options(mut writer: W, options: SerializerOptions) -> Result<Self> {
+        if options.codec != Codec::None {
+            bail!(
+                "{} batches can't be written incrementally",
+                options.codec.name()
+            );
+        }
+
+        let header = BatchHeader {
+            codec: Codec::None,
+            batch_id: options
+                .batch_id
+                .unwrap_or_else(|| Uuid::new_v4().into_bytes()),
+            record_count: 0,
+            producer: options.producer.as_deref(),
+            dictionary: None,
+            endianness: options.endianness,
+        };
+
+        // the count follows the prefix, the codec id and the batch id
+        let count_offset = writer.stream_position()? + BatchHeader::PREFIX_SIZE as u64 + 1 + 16;
+        header.write(&mut writer)?;
+
+        Ok(Self {
+            writer,
+            record_count: 0,
+            count_offset,
+            endianness: options.endianness,
+        })
+    }
+
+    pub fn write_log(&mut self, log: &PlayerLog) -> Result<()> {
+        log.serialize_with_endianness(&mut self.writer, self.endianness)?;
+        self.record_count += 1;
+
+        Ok(())
+    }
+
+    // a record already serialized in the batch's endianness
+    pub(super) fn write_record(&mut self, record: &[u8]) -> Result<()> {
+        self.writer.write_all(record)?;
+        self.record_count += 1;
+
+        Ok(())
+    }
+
+    pub const fn record_count(&self) -> u64 {
+        self.record_count
+    }
+
+    /// Writes the final record count and leaves the writer at the end of the batch.
+    pub fn finish(mut self) -> Result<W> {
+        let end = self.writer.stream_position()?;
+        self.writer.seek(SeekFrom::Start(self.count_offset))?;
+        self.writer.write_u64::<BigEndian>(self.record_count)?;
+        self.writer.seek(SeekFrom::Start(end))?;
+        self.writer.flush()?;
+
+        Ok(self.writer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::PlayerLogSerializer;
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn incremental_batches_match_the_serializer() {
+        let logs = logs(30);
+        for endianness in [Endianness::Big, Endianness::Little] {
+            let options = SerializerOptions {
+                batch_id: Some([7; 16]),
+                producer: Some("collector-1".to_string()),
+                endianness,
+                ..SerializerOptions::default()
+            };
+            let mut serializer =
+                IncrementalSerializer::begin_with_options(Cursor::new(Vec::new()), options.clone())
+                    .unwrap();
+
+            // records serialized ahead of time go in the same as logs
+            let mut record = Vec::new();
+            for (i, log) in logs.iter().enumerate() {
+                if i % 2 == 0 {
+                    serializer.write_log(log).unwrap();
+                } else {
+                    record.clear();
+                    log.serialize_with_endianness(&mut record, endianness)
+                        .unwrap();
+                    serializer.write_record(&record).unwrap();
+                }
+            }
+            assert_eq!(serializer.record_count(), 30);
+
+            let data = serializer.finish().unwrap().into_inner();
+            assert_eq!(
+                data,
+                PlayerLogSerializer::serialize_many_with_options(&logs, &options).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn unfinished_batches_read_back_empty() {
+        let mut data = Cursor::new(Vec::new());
+        IncrementalSerializer::begin(&mut data)
+            .unwrap()
+            .write_log(&logs(1)[0])
+            .unwrap();
+
+        let data = data.into_inner();
+        assert_eq!(PlayerLogSerializer::probe(&data).unwrap().record_count, 0);
+
+        let options = SerializerOptions {
+            codec: Codec::Lz4,
+            ..SerializerOptions::default()
+        };
+        let e = IncrementalSerializer::begin_with_options(Cursor::new(Vec::new()), options)
+            .err()
+            .unwrap();
+        assert_eq!(e.to_string(), "lz4 batches can't be written incrementally");
+    }
+}
@@ -0,0 +1,219 @@
+//! Reading a framed log file while a [`FileStore`](super::storage::FileStore) appends to it.
+//!
+//! A reader only ever sees a snapshot: the records that were complete when it was opened or last
+//! refreshed. Readers in the writer's process share its flushed length, so a record counts once
+//! it's flushed. Readers elsewhere go by the framing alone and count every frame that's fully on
+//! disk, whether or not the writer has flushed all of its buffer yet.
+
+use std::fs::File;
+use std::io::{BufReader, Read, Take};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Result};
+
+use super::net::read_frame;
+use super::storage::complete_frames;
+use super::PlayerLog;
+
+pub struct SnapshotReader {
+    path: PathBuf,
+    // the writer's flushed length when it's in this process
+    committed: Option<Arc<AtomicU64>>,
+    len: u64,
+    records: u64,
+}
+
+impl SnapshotReader {
+    /// Snapshots the complete frames of a file that may still be appended to by another process.
+    pub fn open(path: &Path) -> Result<Self> {
+        Self::with_committed(path, None)
+    }
+
+    pub(super) fn shared(path: &Path, committed: Arc<AtomicU64>) -> Result<Self> {
+        Self::with_committed(path, Some(committed))
+    }
+
+    fn with_committed(path: &Path, committed: Option<Arc<AtomicU64>>) -> Result<Self> {
+        let mut reader = Self {
+            path: path.to_path_buf(),
+            committed,
+            len: 0,
+            records: 0,
+        };
+        reader.refresh()?;
+
+        Ok(reader)
+    }
+
+    /// Bytes of the file in the snapshot.
+    pub const fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub const fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Moves the snapshot up to everything committed by now, returning how many records that
+    /// added.
+    pub fn refresh(&mut self) -> Result<u64> {
+        let file = File::open(&self.path)?;
+        let end = match &self.committed {
+            Some(committed) => committed.load(Ordering::Acquire),
+            None => file.metadata()?.len(),
+        };
+
+        let (added, len) = complete_frames(&file, self.len, end)?;
+        self.len = len;
+        self.records += added;
+
+        Ok(added)
+    }
+
+    /// The records in the snapshot, anything appended after it is left for the next
+    /// [`SnapshotReader::refresh`].
+    pub fn iter(&self) -> Result<SnapshotIter> {
+        Ok(SnapshotIter {
+            reader: BufReader::new(File::open(&self.path)?.take(self.len)),
+            remaining: self.records,
+        })
+    }
+}
+
+pub struct SnapshotIter {
+    reader: BufReader<Take<File>>,
+    remaining: u64,
+}
+
+impl Iterator for SnapshotIter {
+    type Item = Result<PlayerLog>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        // only a file truncated under the reader can come up short
+        Some(
+            read_frame(&mut self.reader).and_then(|log| {
+                log.ok_or_else(|| anyhow!("the file is shorter than its snapshot"))
+            }),
+        )
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining as usize, Some(self.remaining as usize))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs::{self, OpenOptions};
+    use std::io::Write;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::storage::FileStore;
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn read(reader: &SnapshotReader) -> Vec<PlayerLog> {
+        reader.iter().unwrap().collect::<Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn shared_readers_see_flushed_records() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins");
+        let logs = logs(6);
+
+        let mut store = FileStore::open(&path).unwrap();
+        let mut reader = store.snapshot_reader().unwrap();
+        assert!(reader.is_empty());
+
+        store.append_one(&logs[0]).unwrap();
+        store.append_one(&logs[1]).unwrap();
+        assert_eq!(reader.refresh().unwrap(), 0);
+        store.flush().unwrap();
+        assert_eq!(reader.refresh().unwrap(), 2);
+        assert_eq!(reader.len(), fs::metadata(&path).unwrap().len());
+
+        // the snapshot stays put until it's refreshed
+        for log in &logs[2..] {
+            store.append_one(log).unwrap();
+        }
+        store.flush().unwrap();
+        let iter = reader.iter().unwrap();
+        assert_eq!(iter.size_hint(), (2, Some(2)));
+        assert_eq!(iter.collect::<Result<Vec<_>>>().unwrap(), logs[..2]);
+
+        assert_eq!(reader.refresh().unwrap(), 4);
+        assert_eq!(reader.records(), 6);
+        assert_eq!(read(&reader), logs);
+    }
+
+    #[test]
+    fn other_readers_count_complete_frames() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins");
+        let logs = logs(3);
+
+        let mut store = FileStore::open(&path).unwrap();
+        store.append_one(&logs[0]).unwrap();
+        store.append_one(&logs[1]).unwrap();
+        store.flush().unwrap();
+        drop(store);
+        let full = fs::read(&path).unwrap();
+
+        // the second frame only half written
+        let half = full.len() - 5;
+        fs::write(&path, &full[..half]).unwrap();
+        let mut reader = SnapshotReader::open(&path).unwrap();
+        assert_eq!(reader.records(), 1);
+        assert_eq!(read(&reader), logs[..1]);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(&full[half..]).unwrap();
+        assert_eq!(reader.refresh().unwrap(), 1);
+        assert_eq!(read(&reader), logs[..2]);
+    }
+
+    #[test]
+    fn a_file_truncated_under_the_reader_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins");
+
+        let mut store = FileStore::open(&path).unwrap();
+        for log in &logs(3) {
+            store.append_one(log).unwrap();
+        }
+        store.flush().unwrap();
+        drop(store);
+
+        let reader = SnapshotReader::open(&path).unwrap();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(0)
+            .unwrap();
+        let e = reader.iter().unwrap().next().unwrap().unwrap_err();
+        assert_eq!(e.to_string(), "the file is shorter than its snapshot");
+
+        assert!(SnapshotReader::open(&dir.path().join("missing")).is_err());
+    }
+}
@@ -0,0 +1,234 @@
+//! Working out what a buffer that isn't a batch holds instead, so reading it fails with what to
+//! do about it rather than a bad magic.
+
+use std::fmt;
+
+use super::legacy::{looks_like_zlib, LegacyLayout};
+use super::{Endianness, PlayerLogSerializer, BATCH_MAGIC};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DetectedFormat {
+    Batch,
+    /// A header-less batch, the records decode with this endianness.
+    Legacy(LegacyLayout, Endianness),
+    Gzip,
+    Zlib,
+    Zstd,
+    /// A json document or ndjson lines, like [`export::write_ndjson`](super::export::write_ndjson)
+    /// writes.
+    Json,
+    Unknown,
+}
+
+impl DetectedFormat {
+    pub const fn name(self) -> &'static str {
+        match self {
+            Self::Batch => "a batch",
+            Self::Legacy(LegacyLayout::Uncompressed, _) => "a legacy batch",
+            Self::Legacy(LegacyLayout::Compressed, _) => "a zlib compressed legacy batch",
+            Self::Gzip => "gzip",
+            Self::Zlib => "zlib",
+            Self::Zstd => "zstd",
+            Self::Json => "json",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    const fn hint(self) -> &'static str {
+        match self {
+            Self::Batch | Self::Unknown => "",
+            Self::Legacy(_, Endianness::Big) => {
+                "convert it with migrate_legacy or the migrate command"
+            }
+            Self::Legacy(_, Endianness::Little) => {
+                "convert it with migrate_legacy_with_endianness and Endianness::Little"
+            }
+            Self::Gzip => "gunzip it first",
+            Self::Zlib => "inflate it first, batches compress their payload inside the container",
+            Self::Zstd => {
+                "decompress it with zstd -d first, or read a blocked seekable file with \
+                 deserialize_seekable_block"
+            }
+            Self::Json => {
+                "read it with export::read_ndjson or logconv convert --input-format ndjson"
+            }
+        }
+    }
+}
+
+/// Looks at `data` the way a person would guess what they passed in. A valid legacy batch is
+/// always reported as one, even when its first bytes happen to look like another format.
+pub fn sniff(data: &[u8]) -> DetectedFormat {
+    if data.starts_with(&BATCH_MAGIC) {
+        return DetectedFormat::Batch;
+    }
+
+    // fully decoded, so nothing else can be mistaken for one
+    for endianness in [Endianness::Big, Endianness::Little] {
+        if let Ok((layout, _, _)) = PlayerLogSerializer::decode_legacy(data, endianness) {
+            return DetectedFormat::Legacy(layout, endianness);
+        }
+    }
+
+    if data.starts_with(&GZIP_MAGIC) {
+        DetectedFormat::Gzip
+    } else if data.starts_with(&ZSTD_MAGIC) {
+        DetectedFormat::Zstd
+    } else if looks_like_zlib(data) {
+        DetectedFormat::Zlib
+    } else if matches!(
+        data.iter().find(|b| !b.is_ascii_whitespace()),
+        Some(b'[' | b'{')
+    ) {
+        DetectedFormat::Json
+    } else {
+        DetectedFormat::Unknown
+    }
+}
+
+/// The input wasn't a batch but something [`sniff`] recognized.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WrongFormat {
+    pub detected: &'static str,
+    pub hint: &'static str,
+}
+
+impl WrongFormat {
+    /// `None` unless `format` is something other than a batch that [`sniff`] recognized.
+    pub const fn from_detected(format: DetectedFormat) -> Option<Self> {
+        match format {
+            DetectedFormat::Batch | DetectedFormat::Unknown => None,
+            format => Some(Self {
+                detected: format.name(),
+                hint: format.hint(),
+            }),
+        }
+    }
+}
+
+impl fmt::Display for WrongFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "input is {} instead of the batch container: {}",
+            self.detected, self.hint
+        )
+    }
+}
+
+impl std::error::Error for WrongFormat {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::{export, PlayerLog};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn legacy(logs: &[PlayerLog], endianness: Endianness) -> Vec<u8> {
+        let mut data = match endianness {
+            Endianness::Big => (logs.len() as u64).to_be_bytes(),
+            Endianness::Little => (logs.len() as u64).to_le_bytes(),
+        }
+        .to_vec();
+        for log in logs {
+            log.serialize_with_endianness(&mut data, endianness)
+                .unwrap();
+        }
+        data
+    }
+
+    fn zlib(data: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn names_what_it_was_given() {
+        let logs = logs(20);
+        let batch = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let big = legacy(&logs, Endianness::Big);
+        let mut gzip = GzEncoder::new(Vec::new(), Compression::default());
+        gzip.write_all(&batch).unwrap();
+        let mut ndjson = Vec::new();
+        export::write_ndjson(&logs, &mut ndjson).unwrap();
+
+        let cases = [
+            (batch.clone(), DetectedFormat::Batch),
+            (
+                big.clone(),
+                DetectedFormat::Legacy(LegacyLayout::Uncompressed, Endianness::Big),
+            ),
+            (
+                legacy(&logs, Endianness::Little),
+                DetectedFormat::Legacy(LegacyLayout::Uncompressed, Endianness::Little),
+            ),
+            (
+                zlib(&big),
+                DetectedFormat::Legacy(LegacyLayout::Compressed, Endianness::Big),
+            ),
+            (gzip.finish().unwrap(), DetectedFormat::Gzip),
+            (zlib(&batch), DetectedFormat::Zlib),
+            (
+                zstd::encode_all(&batch[..], 3).unwrap(),
+                DetectedFormat::Zstd,
+            ),
+            (ndjson, DetectedFormat::Json),
+            (b"  \n[1, 2]".to_vec(), DetectedFormat::Json),
+            (b"hello".to_vec(), DetectedFormat::Unknown),
+            (Vec::new(), DetectedFormat::Unknown),
+        ];
+        for (data, format) in cases {
+            assert_eq!(sniff(&data), format, "{}", format.name());
+        }
+    }
+
+    #[test]
+    fn reading_the_wrong_format_says_what_to_do() {
+        let logs = logs(5);
+        let mut ndjson = Vec::new();
+        export::write_ndjson(&logs, &mut ndjson).unwrap();
+
+        let e = PlayerLogSerializer::deserialize_many(&ndjson).unwrap_err();
+        assert_eq!(
+            e.downcast_ref::<WrongFormat>(),
+            Some(&WrongFormat {
+                detected: "json",
+                hint: DetectedFormat::Json.hint(),
+            })
+        );
+        assert_eq!(
+            e.to_string(),
+            "input is json instead of the batch container: read it with export::read_ndjson or \
+             logconv convert --input-format ndjson"
+        );
+
+        let e =
+            PlayerLogSerializer::deserialize_many(&legacy(&logs, Endianness::Little)).unwrap_err();
+        assert!(e.to_string().contains("Endianness::Little"), "{e}");
+
+        // a broken batch still fails as one, and unknown input as a bad magic
+        let batch = PlayerLogSerializer::serialize_many(&logs).unwrap();
+        let e = PlayerLogSerializer::deserialize_many(&batch[..6]).unwrap_err();
+        assert!(e.downcast_ref::<WrongFormat>().is_none());
+        let e = PlayerLogSerializer::deserialize_many(b"hello world").unwrap_err();
+        assert!(e.downcast_ref::<WrongFormat>().is_none());
+        assert!(WrongFormat::from_detected(DetectedFormat::Unknown).is_none());
+    }
+}
@@ -0,0 +1,257 @@
+//! Decoding batches that decompress to more than fits in memory, by writing the records that
+//! don't fit out to temporary batch files.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{Context, Result};
+
+use super::{Codec, PlayerLog, PlayerLogSerializer, SerializerOptions};
+
+static NEXT_SPILL_DIR: AtomicU64 = AtomicU64::new(0);
+
+/// The records of a batch, the earlier ones in spill files and the rest in memory. The spill
+/// files and their directory are removed on drop.
+#[derive(Debug)]
+pub struct SpilledBatch {
+    dir: Option<PathBuf>,
+    spills: Vec<PathBuf>,
+    memory: Vec<PlayerLog>,
+    len: usize,
+}
+
+impl SpilledBatch {
+    /// Every record in batch order, reading one spill file at a time.
+    pub fn iter(&self) -> impl Iterator<Item = Result<PlayerLog>> + '_ {
+        self.spills
+            .iter()
+            .flat_map(|path| {
+                let (logs, error) = match read_spill(path) {
+                    Ok(logs) => (logs, None),
+                    Err(e) => (Vec::new(), Some(Err(e))),
+                };
+                logs.into_iter().map(Ok).chain(error)
+            })
+            .chain(self.memory.iter().cloned().map(Ok))
+    }
+
+    pub const fn len(&self) -> usize {
+        self.len
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// How many spill files the records didn't fit in memory for.
+    pub const fn spill_count(&self) -> usize {
+        self.spills.len()
+    }
+}
+
+impl Drop for SpilledBatch {
+    fn drop(&mut self) {
+        if let Some(dir) = &self.dir {
+            let _ = fs::remove_dir_all(dir);
+        }
+    }
+}
+
+fn read_spill(path: &Path) -> Result<Vec<PlayerLog>> {
+    let data = fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    PlayerLogSerializer::deserialize_many(&data)
+        .with_context(|| format!("decoding {}", path.display()))
+}
+
+// a directory of our own under `spill_dir`, so dropping the batch can remove all of it
+fn create_spill_dir(spill_dir: &Path) -> Result<PathBuf> {
+    loop {
+        let dir = spill_dir.join(format!(
+            "plgb-spill-{}-{}",
+            process::id(),
+            NEXT_SPILL_DIR.fetch_add(1, Ordering::Relaxed)
+        ));
+
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {}
+            Err(e) => return Err(e).with_context(|| format!("creating {}", dir.display())),
+        }
+    }
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::deserialize_many`] keeping at most about `budget_bytes` of decoded
+    /// records in memory. Whenever the next record wouldn't fit, the records held so far are
+    /// written to a new batch file in a directory created under `spill_dir`.
+    ///
+    /// Nothing is written unless the budget runs out.
+    pub fn deserialize_many_compressed_bounded(
+        data: &[u8],
+        budget_bytes: usize,
+        spill_dir: &Path,
+    ) -> Result<SpilledBatch> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        let mut reader = header.codec.decoder(&data[header_len..]);
+
+        let options = SerializerOptions {
+            codec: Codec::Lz4,
+            ..SerializerOptions::default()
+        };
+        let mut batch = SpilledBatch {
+            dir: None,
+            spills: Vec::new(),
+            memory: Vec::new(),
+            len: 0,
+        };
+        let mut memory_bytes = 0;
+
+        for i in 0..header.record_count {
+            let log = PlayerLog::deserialize_with_endianness(&mut reader, header.endianness)
+                .with_context(|| format!("record {i}"))?;
+            if log.is_marker() {
+                continue;
+            }
+            let size = log.memory_size();
+
+            if !batch.memory.is_empty() && memory_bytes + size > budget_bytes {
+                let dir = match &batch.dir {
+                    Some(dir) => dir.clone(),
+                    None => batch.dir.insert(create_spill_dir(spill_dir)?).clone(),
+                };
+                let path = dir.join(format!("{:05}.plgb", batch.spills.len()));
+                let spill = Self::serialize_many_with_options(&batch.memory, &options)?;
+                fs::write(&path, spill).with_context(|| format!("writing {}", path.display()))?;
+
+                batch.spills.push(path);
+                batch.memory.clear();
+                memory_bytes = 0;
+            }
+
+            memory_bytes += size;
+            batch.memory.push(log);
+            batch.len += 1;
+        }
+
+        super::check_payload_end(&mut reader)?;
+
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn compressed(logs: &[PlayerLog]) -> Vec<u8> {
+        let options = SerializerOptions {
+            codec: Codec::Lz4,
+            ..SerializerOptions::default()
+        };
+        PlayerLogSerializer::serialize_many_with_options(logs, &options).unwrap()
+    }
+
+    fn entries(dir: &Path) -> usize {
+        fs::read_dir(dir).unwrap().count()
+    }
+
+    #[test]
+    fn batches_in_budget_never_touch_the_disk() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = logs(100);
+        let batch = PlayerLogSerializer::deserialize_many_compressed_bounded(
+            &compressed(&logs),
+            usize::MAX,
+            dir.path(),
+        )
+        .unwrap();
+
+        assert_eq!(batch.spill_count(), 0);
+        assert_eq!(entries(dir.path()), 0);
+        assert_eq!(batch.len(), 100);
+        assert_eq!(batch.iter().collect::<Result<Vec<_>>>().unwrap(), logs);
+    }
+
+    #[test]
+    fn spilled_records_read_back_in_order_and_are_removed() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = logs(100);
+        let budget = logs[..10].iter().map(PlayerLog::memory_size).sum::<usize>();
+        let batch = PlayerLogSerializer::deserialize_many_compressed_bounded(
+            &compressed(&logs),
+            budget,
+            dir.path(),
+        )
+        .unwrap();
+
+        assert!(batch.spill_count() >= 5, "{}", batch.spill_count());
+        assert_eq!(batch.len(), 100);
+        assert_eq!(batch.iter().collect::<Result<Vec<_>>>().unwrap(), logs);
+        assert_eq!(entries(dir.path()), 1);
+
+        drop(batch);
+        assert_eq!(entries(dir.path()), 0);
+
+        // a record over the budget still goes to memory alone
+        let batch = PlayerLogSerializer::deserialize_many_compressed_bounded(
+            &compressed(&logs[..5]),
+            0,
+            dir.path(),
+        )
+        .unwrap();
+        assert_eq!(batch.spill_count(), 4);
+        assert_eq!(batch.iter().collect::<Result<Vec<_>>>().unwrap(), logs[..5]);
+    }
+
+    #[test]
+    fn a_missing_spill_fails_the_iteration() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = logs(4);
+        let batch = PlayerLogSerializer::deserialize_many_compressed_bounded(
+            &compressed(&logs),
+            0,
+            dir.path(),
+        )
+        .unwrap();
+        fs::remove_file(&batch.spills[1]).unwrap();
+
+        let read = batch.iter().collect::<Vec<_>>();
+        assert_eq!(read.len(), 4);
+        assert_eq!(read[0].as_ref().unwrap(), &logs[0]);
+        assert!(read[1]
+            .as_ref()
+            .unwrap_err()
+            .to_string()
+            .starts_with("reading "));
+        assert_eq!(read[2].as_ref().unwrap(), &logs[2]);
+    }
+
+    #[test]
+    fn a_corrupt_batch_leaves_no_spills_behind() {
+        let dir = tempfile::tempdir().unwrap();
+        let data = PlayerLogSerializer::serialize_many(&logs(20)).unwrap();
+        let e = PlayerLogSerializer::deserialize_many_compressed_bounded(
+            &data[..data.len() - 2],
+            0,
+            dir.path(),
+        )
+        .unwrap_err();
+
+        assert!(format!("{e:#}").starts_with("record 19"), "{e:#}");
+        assert_eq!(entries(dir.path()), 0);
+    }
+}
@@ -0,0 +1,178 @@
+use anyhow::{bail, Context, Result};
+use rusqlite::types::{ToSqlOutput, ValueRef};
+use rusqlite::{params, Connection, Row};
+
+use super::filter::PlayerLogFilter;
+use super::storage_impl::{ByteStorage, DomainBytes, NameBytes};
+use super::{Extension, GeoInfo, LoginOutcome, PlayerLog, ServerVersion};
+
+// the columns after server_version hold the binary_version 2 fields
+const CREATE_TABLE: &str = "CREATE TABLE IF NOT EXISTS player_logs (
+    id INTEGER PRIMARY KEY,
+    binary_version INTEGER,
+    flags INTEGER,
+    player_uuid BLOB,
+    player_name TEXT,
+    player_ip BLOB,
+    server_ip BLOB,
+    server_port INTEGER,
+    server_domain TEXT,
+    server_version INTEGER,
+    server_version_name TEXT,
+    timestamp INTEGER,
+    session_id INTEGER,
+    outcome INTEGER,
+    country TEXT,
+    asn INTEGER,
+    proxy_ip BLOB,
+    client_brand TEXT,
+    client_locale TEXT,
+    extensions BLOB
+)";
+
+const COLUMNS: &str = "binary_version, flags, player_uuid, player_name, player_ip, server_ip, \
+    server_port, server_domain, server_version, server_version_name, timestamp, session_id, outcome, country, asn, \
+    proxy_ip, client_brand, client_locale, extensions";
+
+// names and domains are written as they are, without checking they're utf8
+const fn text(bytes: &[u8]) -> ToSqlOutput<'_> {
+    ToSqlOutput::Borrowed(ValueRef::Text(bytes))
+}
+
+/// Inserts every log in one transaction, creating the table if it doesn't exist.
+pub fn write_logs(conn: &Connection, logs: &[PlayerLog]) -> Result<usize> {
+    conn.execute(CREATE_TABLE, [])?;
+
+    let tx = conn.unchecked_transaction()?;
+    {
+        let mut insert = tx.prepare(&format!(
+            "INSERT INTO player_logs ({COLUMNS}) VALUES \
+             (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, ?17, ?18, ?19)"
+        ))?;
+
+        for log in logs {
+            // the same kind, length, data layout the record uses
+            let mut extensions = Vec::new();
+            for extension in &log.extensions {
+                extensions.push(extension.kind);
+                extensions.push(extension.data.len() as u8);
+                extensions.extend_from_slice(&extension.data);
+            }
+
+            insert.execute(params![
+                log.binary_version,
+                log.flags,
+                log.player_uuid.as_ref().map(<[u8; 16]>::as_slice),
+                text(&log.player_name),
+                log.player_ip.as_slice(),
+                log.server_ip.as_slice(),
+                log.server_port,
+                text(&log.server_domain),
+                log.server_version.id().unwrap_or(ServerVersion::UNKNOWN_ID),
+                log.server_version
+                    .name()
+                    .filter(|_| log.server_version.id().is_none()),
+                log.timestamp as i64,
+                log.session_id,
+                log.outcome.to_byte(),
+                log.geo.as_ref().map(|geo| text(&geo.country)),
+                log.geo.map(|geo| geo.asn),
+                log.proxy_ip.as_ref().map(<[u8; 4]>::as_slice),
+                text(&log.client_brand),
+                text(&log.client_locale),
+                extensions,
+            ])?;
+        }
+    }
+    tx.commit()?;
+
+    Ok(logs.len())
+}
+
+/// Reads back the logs [`write_logs`] wrote that match `filter`, in insertion order.
+pub fn read_logs(conn: &Connection, filter: &PlayerLogFilter) -> Result<Vec<PlayerLog>> {
+    conn.execute(CREATE_TABLE, [])?;
+
+    // the version range is narrowed down by sqlite, everything else by the filter itself
+    let mut select = conn.prepare(&format!(
+        "SELECT {COLUMNS} FROM player_logs \
+         WHERE (?1 IS NULL OR server_version >= ?1) AND (?2 IS NULL OR server_version <= ?2) \
+         ORDER BY id"
+    ))?;
+
+    let mut rows = select.query(params![filter.min_version, filter.max_version])?;
+    let mut logs = Vec::new();
+    while let Some(row) = rows.next()? {
+        let log = read_row(row).with_context(|| format!("row {}", logs.len()))?;
+        if filter.matches(&log) {
+            logs.push(log);
+        }
+    }
+
+    Ok(logs)
+}
+
+fn read_row(row: &Row<'_>) -> Result<PlayerLog> {
+    let bytes = |i| -> Result<Vec<u8>> { Ok(row.get_ref(i)?.as_bytes()?.to_vec()) };
+    let ip = |i| -> Result<[u8; 4]> { bytes(i)?.try_into().ok().context("ip isn't 4 bytes") };
+
+    let player_uuid = match row.get_ref(2)? {
+        ValueRef::Null => None,
+        uuid => Some(uuid.as_bytes()?.try_into().context("uuid isn't 16 bytes")?),
+    };
+
+    let geo = match row.get_ref(13)? {
+        ValueRef::Null => None,
+        country => Some(GeoInfo {
+            country: country
+                .as_bytes()?
+                .try_into()
+                .context("country isn't 2 bytes")?,
+            asn: row.get(14)?,
+        }),
+    };
+
+    let server_version = match row.get_ref(9)? {
+        ValueRef::Null => ServerVersion::Known(row.get(8)?),
+        name => ServerVersion::Unknown(name.as_str()?.into()),
+    };
+
+    let proxy_ip = match row.get_ref(15)? {
+        ValueRef::Null => None,
+        _ => Some(ip(15)?),
+    };
+
+    let mut extensions = Vec::new();
+    let mut data = bytes(18)?.into_iter();
+    while let Some(kind) = data.next() {
+        let Some(len) = data.next() else {
+            bail!("truncated extensions");
+        };
+
+        let data = data.by_ref().take(len as usize).collect::<Vec<_>>();
+        if data.len() != len as usize {
+            bail!("truncated extensions");
+        }
+        extensions.push(Extension { kind, data });
+    }
+
+    Ok(PlayerLog {
+        binary_version: row.get(0)?,
+        flags: row.get(1)?,
+        player_uuid,
+        player_name: NameBytes::from_vec(bytes(3)?),
+        player_ip: ip(4)?,
+        server_ip: ip(5)?,
+        server_port: row.get(6)?,
+        server_domain: DomainBytes::from_vec(bytes(7)?),
+        server_version,
+        timestamp: row.get::<_, i64>(10)? as u64,
+        session_id: row.get(11)?,
+        outcome: LoginOutcome::from_byte(row.get(12)?),
+        geo,
+        proxy_ip,
+        extensions,
+        client_brand: bytes(16)?,
+        client_locale: bytes(17)?,
+    })
+}
@@ -0,0 +1,14 @@
+use std::collections::HashMap;
+
+use super::PlayerLog;
+
+/// How many logs there are of every country, see [`PlayerLog::country`]. Logs without a country
+/// aren't counted.
+pub fn country_distribution(logs: &[PlayerLog]) -> HashMap<[u8; 2], usize> {
+    let mut distribution = HashMap::new();
+    for country in logs.iter().filter_map(PlayerLog::country) {
+        *distribution.entry(country).or_default() += 1;
+    }
+
+    distribution
+}
@@ -0,0 +1,455 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+use std::fs::{File, OpenOptions};
+use std::hash::Hash;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use anyhow::{bail, Result};
+
+use super::net::{read_frame, write_frame};
+use super::snapshot::SnapshotReader;
+use super::PlayerLog;
+
+pub trait LogStore {
+    fn append(&mut self, log: &PlayerLog) -> Result<()>;
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MemoryStore {
+    pub logs: Vec<PlayerLog>,
+}
+
+impl MemoryStore {
+    pub const fn new() -> Self {
+        Self { logs: Vec::new() }
+    }
+}
+
+impl LogStore for MemoryStore {
+    fn append(&mut self, log: &PlayerLog) -> Result<()> {
+        self.logs.push(log.clone());
+        Ok(())
+    }
+}
+
+/// Appends length framed records to a file, the same framing as the framed tcp stream.
+///
+/// The file has no header, appending never touches bytes that were already written. The record
+/// count is found by skipping from frame length to frame length when the file is opened.
+pub struct FileStore {
+    path: PathBuf,
+    writer: BufWriter<File>,
+    scratch: Vec<u8>,
+    records: u64,
+    torn_bytes: u64,
+    // bytes of complete frames handed to the writer, and how many of them were flushed
+    len: u64,
+    committed: Arc<AtomicU64>,
+}
+
+impl FileStore {
+    /// Opens `path` for appending, creating it if it doesn't exist. A last frame cut short by a
+    /// crash during an append is truncated away, see [`FileStore::torn_bytes`]. Another store
+    /// still appending to the file would lose the frame it's writing.
+    pub fn open(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .read(true)
+            .append(true)
+            .open(path)?;
+        let (records, torn_bytes) = recover_frames(&file)?;
+        let len = file.metadata()?.len();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            writer: BufWriter::new(file),
+            scratch: Vec::with_capacity(PlayerLog::MAX_ENCODED_SIZE),
+            records,
+            torn_bytes,
+            len,
+            committed: Arc::new(AtomicU64::new(len)),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Complete records in the file, including the ones still buffered.
+    pub const fn records(&self) -> u64 {
+        self.records
+    }
+
+    /// Bytes of a torn last frame [`FileStore::open`] cut off, 0 if the file was intact.
+    pub const fn torn_bytes(&self) -> u64 {
+        self.torn_bytes
+    }
+
+    /// Buffers one record, a live server can call this per login and [`FileStore::sync`] on its
+    /// own schedule.
+    pub fn append_one(&mut self, log: &PlayerLog) -> Result<()> {
+        write_frame(&mut self.writer, log, &mut self.scratch)?;
+        self.records += 1;
+        self.len += 4 + self.scratch.len() as u64;
+
+        Ok(())
+    }
+
+    /// Writes out the buffer, which commits the buffered records for
+    /// [`FileStore::snapshot_reader`]s.
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.committed.store(self.len, Ordering::Release);
+
+        Ok(())
+    }
+
+    /// Flushes the buffer and waits for the data to reach the disk.
+    pub fn sync(&mut self) -> Result<()> {
+        self.flush()?;
+        self.writer.get_ref().sync_data()?;
+
+        Ok(())
+    }
+
+    /// A reader of the records flushed so far, which [`SnapshotReader::refresh`] moves up to the
+    /// last flush. Readers in other processes use [`SnapshotReader::open`] instead.
+    pub fn snapshot_reader(&self) -> Result<SnapshotReader> {
+        SnapshotReader::shared(&self.path, Arc::clone(&self.committed))
+    }
+
+    pub fn read_all(path: &Path) -> Result<Vec<PlayerLog>> {
+        let mut reader = BufReader::new(File::open(path)?);
+
+        let mut logs = Vec::new();
+        while let Some(log) = read_frame(&mut reader)? {
+            logs.push(log);
+        }
+
+        Ok(logs)
+    }
+}
+
+impl LogStore for FileStore {
+    fn append(&mut self, log: &PlayerLog) -> Result<()> {
+        self.append_one(log)
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Self::flush(self)
+    }
+}
+
+/// Counts the complete frames of a framed log file by their lengths alone, and truncates the file
+/// after the last one. Returns the count and how many bytes were cut off.
+pub(crate) fn recover_frames(file: &File) -> Result<(u64, u64)> {
+    let file_len = file.metadata()?.len();
+    let (records, offset) = complete_frames(file, 0, file_len)?;
+
+    let torn_bytes = file_len - offset;
+    if torn_bytes > 0 {
+        file.set_len(offset)?;
+    }
+
+    Ok((records, torn_bytes))
+}
+
+/// Walks the frames from `start`, which has to be the start of one, up to `end` by their lengths
+/// alone. Returns how many are complete and where the last complete one ends.
+pub(crate) fn complete_frames(file: &File, start: u64, end: u64) -> Result<(u64, u64)> {
+    let mut reader = BufReader::new(file);
+    reader.seek(SeekFrom::Start(start))?;
+
+    let mut records = 0;
+    let mut offset = start;
+    while end.saturating_sub(offset) >= 4 {
+        let mut len = [0; 4];
+        reader.read_exact(&mut len)?;
+        let len = u32::from_be_bytes(len) as u64;
+        // a torn append leaves a short frame, not a wrong length
+        if len > PlayerLog::MAX_ENCODED_SIZE as u64 {
+            bail!("frame {records} at offset {offset} has length {len}, the file is corrupt");
+        }
+        if offset + 4 + len > end {
+            break;
+        }
+
+        reader.seek_relative(len as i64)?;
+        offset += 4 + len;
+        records += 1;
+    }
+
+    Ok((records, offset))
+}
+
+/// Every sink that failed, by its index in the mux. The log still went to the other sinks.
+#[derive(Debug)]
+pub struct MuxError {
+    pub sink_errors: Vec<(usize, anyhow::Error)>,
+}
+
+impl fmt::Display for MuxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} sinks failed", self.sink_errors.len())?;
+        for (i, e) in &self.sink_errors {
+            write!(f, ", sink {i}: {e:#}")?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for MuxError {}
+
+/// Writes every log to all of its sinks.
+#[derive(Default)]
+pub struct LogMux {
+    pub sinks: Vec<Box<dyn LogStore>>,
+}
+
+impl LogMux {
+    pub fn new(sinks: Vec<Box<dyn LogStore>>) -> Self {
+        Self { sinks }
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn LogStore>) {
+        self.sinks.push(sink);
+    }
+
+    /// Drops a sink that keeps failing, the indices of the sinks after it shift down by one.
+    pub fn remove_sink(&mut self, index: usize) -> Box<dyn LogStore> {
+        self.sinks.remove(index)
+    }
+
+    /// A failing sink doesn't stop the others, the returned [`MuxError`] lists every failure.
+    pub fn append(&mut self, log: PlayerLog) -> Result<()> {
+        self.each_sink(|sink| sink.append(&log))
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.each_sink(|sink| sink.flush())
+    }
+
+    fn each_sink(&mut self, mut f: impl FnMut(&mut dyn LogStore) -> Result<()>) -> Result<()> {
+        let sink_errors = self
+            .sinks
+            .iter_mut()
+            .enumerate()
+            .filter_map(|(i, sink)| f(sink.as_mut()).err().map(|e| (i, e)))
+            .collect::<Vec<_>>();
+
+        if sink_errors.is_empty() {
+            Ok(())
+        } else {
+            Err(MuxError { sink_errors }.into())
+        }
+    }
+}
+
+/// Appends every log to the one sink its key maps to, e.g. sharding by server version id across a
+/// few [`FileStore`]s. Logs whose key has no sink go to the catch-all.
+pub struct LogRouter<K: Eq + Hash> {
+    pub routing_fn: Box<dyn Fn(&PlayerLog) -> K>,
+    pub sinks: HashMap<K, Box<dyn LogStore>>,
+    pub catch_all: Box<dyn LogStore>,
+}
+
+impl<K: Eq + Hash> LogRouter<K> {
+    pub fn new(
+        routing_fn: impl Fn(&PlayerLog) -> K + 'static,
+        catch_all: Box<dyn LogStore>,
+    ) -> Self {
+        Self {
+            routing_fn: Box::new(routing_fn),
+            sinks: HashMap::new(),
+            catch_all,
+        }
+    }
+
+    /// Returns the sink that was registered for `key` before, if any.
+    pub fn add_sink(&mut self, key: K, sink: Box<dyn LogStore>) -> Option<Box<dyn LogStore>> {
+        self.sinks.insert(key, sink)
+    }
+
+    pub fn route(&mut self, log: PlayerLog) -> Result<()> {
+        let key = (self.routing_fn)(&log);
+        self.sinks
+            .get_mut(&key)
+            .unwrap_or(&mut self.catch_all)
+            .append(&log)
+    }
+
+    /// Flushes every sink even if one fails, returning the first error.
+    pub fn flush(&mut self) -> Result<()> {
+        let mut result = self.catch_all.flush();
+        for sink in self.sinks.values_mut() {
+            let flushed = sink.flush();
+            result = result.and(flushed);
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn opening_a_torn_file_keeps_every_complete_record() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = logs(5);
+
+        let path = dir.path().join("logins");
+        let mut store = FileStore::open(&path).unwrap();
+        let mut boundaries = vec![0];
+        for log in &logs {
+            store.append_one(log).unwrap();
+            store.flush().unwrap();
+            boundaries.push(fs::metadata(&path).unwrap().len());
+        }
+        drop(store);
+        let full = fs::read(&path).unwrap();
+
+        let torn = dir.path().join("torn");
+        for cut in 0..=full.len() {
+            fs::write(&torn, &full[..cut]).unwrap();
+
+            let kept = boundaries
+                .iter()
+                .rposition(|&end| end <= cut as u64)
+                .unwrap();
+            let store = FileStore::open(&torn).unwrap();
+            assert_eq!(store.records(), kept as u64, "cut at {cut}");
+            assert_eq!(store.torn_bytes(), cut as u64 - boundaries[kept]);
+            drop(store);
+
+            assert_eq!(fs::metadata(&torn).unwrap().len(), boundaries[kept]);
+            assert_eq!(FileStore::read_all(&torn).unwrap(), logs[..kept]);
+        }
+    }
+
+    #[test]
+    fn appending_after_recovery_continues_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = logs(3);
+
+        let path = dir.path().join("logins");
+        let mut store = FileStore::open(&path).unwrap();
+        store.append_one(&logs[0]).unwrap();
+        store.append_one(&logs[1]).unwrap();
+        store.sync().unwrap();
+        drop(store);
+
+        let len = fs::metadata(&path).unwrap().len();
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_len(len - 3)
+            .unwrap();
+
+        let mut store = FileStore::open(&path).unwrap();
+        assert_eq!(store.records(), 1);
+        store.append_one(&logs[2]).unwrap();
+        store.flush().unwrap();
+
+        assert_eq!(
+            FileStore::read_all(&path).unwrap(),
+            [logs[0].clone(), logs[2].clone()]
+        );
+    }
+
+    #[test]
+    fn an_impossible_frame_length_fails_to_open() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("logins");
+        fs::write(&path, u32::MAX.to_be_bytes()).unwrap();
+
+        assert!(FileStore::open(&path).is_err());
+        assert_eq!(fs::metadata(&path).unwrap().len(), 4);
+    }
+
+    // refuses every log, like a sink whose disk is full
+    struct Failing;
+
+    impl LogStore for Failing {
+        fn append(&mut self, _: &PlayerLog) -> Result<()> {
+            bail!("disk full")
+        }
+    }
+
+    #[test]
+    fn a_failing_sink_doesnt_stop_the_others() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = logs(10);
+        let (a, b) = (dir.path().join("a"), dir.path().join("b"));
+
+        let mut mux = LogMux::new(vec![
+            Box::new(FileStore::open(&a).unwrap()),
+            Box::new(Failing),
+        ]);
+        mux.add_sink(Box::new(FileStore::open(&b).unwrap()));
+
+        for log in &logs[..5] {
+            let e = mux.append(log.clone()).unwrap_err();
+            let e = e.downcast_ref::<MuxError>().unwrap();
+            assert_eq!(e.sink_errors.len(), 1);
+            assert_eq!(e.sink_errors[0].0, 1);
+            assert_eq!(e.to_string(), "1 sinks failed, sink 1: disk full");
+        }
+
+        mux.remove_sink(1);
+        for log in &logs[5..] {
+            mux.append(log.clone()).unwrap();
+        }
+        mux.flush().unwrap();
+
+        assert_eq!(FileStore::read_all(&a).unwrap(), logs);
+        assert_eq!(FileStore::read_all(&b).unwrap(), logs);
+    }
+
+    #[test]
+    fn routed_logs_land_in_their_keys_sink() {
+        let dir = tempfile::tempdir().unwrap();
+        let logs = logs(60);
+        let path = |name: &str| dir.path().join(name);
+        let store =
+            |name: &str| -> Box<dyn LogStore> { Box::new(FileStore::open(&path(name)).unwrap()) };
+
+        let mut router = LogRouter::new(|log: &PlayerLog| log.is_online(), store("rest"));
+        assert!(router.add_sink(true, Box::new(Failing)).is_none());
+        // replacing a sink hands back the old one
+        assert!(router.add_sink(true, store("online")).is_some());
+
+        for log in &logs {
+            router.route(log.clone()).unwrap();
+        }
+        router.flush().unwrap();
+
+        let (online, offline): (Vec<_>, Vec<_>) = logs.into_iter().partition(PlayerLog::is_online);
+        assert!(!online.is_empty() && !offline.is_empty());
+        assert_eq!(FileStore::read_all(&path("online")).unwrap(), online);
+        assert_eq!(FileStore::read_all(&path("rest")).unwrap(), offline);
+    }
+}
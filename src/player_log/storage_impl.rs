@@ -0,0 +1,345 @@
+//! How [`PlayerLog`] holds the player name and server domain bytes.
+//!
+//! [`NameBytes`] and [`DomainBytes`] are plain `Vec<u8>`s unless the `inline_storage` feature is
+//! on, which swaps them for [`InlineBytes`]. Either way the wire format and the serde output stay
+//! the same, the `Storage` bench group is there to compare the two.
+
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Read};
+use std::ops::Deref;
+
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+#[cfg(feature = "inline_storage")]
+use super::PlayerLog;
+
+#[cfg(not(feature = "inline_storage"))]
+pub type NameBytes = Vec<u8>;
+#[cfg(not(feature = "inline_storage"))]
+pub type DomainBytes = Vec<u8>;
+
+// every ascii name fits inline, multi-byte names and domains past 64 bytes are rare enough to spill
+#[cfg(feature = "inline_storage")]
+pub type NameBytes = InlineBytes<{ PlayerLog::MAX_NAME_LEN }>;
+#[cfg(feature = "inline_storage")]
+pub type DomainBytes = InlineBytes<64>;
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl Sealed for Vec<u8> {}
+    impl<const N: usize> Sealed for super::InlineBytes<N> {}
+}
+
+/// Byte storage for the variable length fields, only implemented in this module.
+pub trait ByteStorage:
+    sealed::Sealed
+    + Deref<Target = [u8]>
+    + Clone
+    + Default
+    + fmt::Debug
+    + Eq
+    + Hash
+    + Serialize
+    + DeserializeOwned
+{
+    /// Shown in bench ids.
+    const NAME: &'static str;
+
+    fn from_vec(bytes: Vec<u8>) -> Self;
+
+    fn from_slice(bytes: &[u8]) -> Self;
+
+    /// Only copies bytes that were stored inline.
+    fn into_vec(self) -> Vec<u8>;
+
+    fn clear(&mut self);
+
+    fn extend_from_slice(&mut self, bytes: &[u8]);
+
+    /// Reads exactly `len` bytes, without going through a heap buffer when they fit inline.
+    fn read_from<R: Read>(reader: &mut R, len: usize) -> io::Result<Self>;
+}
+
+impl ByteStorage for Vec<u8> {
+    const NAME: &'static str = "vec";
+
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        bytes
+    }
+
+    fn from_slice(bytes: &[u8]) -> Self {
+        bytes.to_vec()
+    }
+
+    fn into_vec(self) -> Vec<u8> {
+        self
+    }
+
+    fn clear(&mut self) {
+        Self::clear(self);
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        Self::extend_from_slice(self, bytes);
+    }
+
+    fn read_from<R: Read>(reader: &mut R, len: usize) -> io::Result<Self> {
+        let mut bytes = vec![0; len];
+        reader.read_exact(&mut bytes)?;
+        Ok(bytes)
+    }
+}
+
+#[derive(Clone)]
+enum Repr<const N: usize> {
+    Inline { len: u8, buf: [u8; N] },
+    Heap(Vec<u8>),
+}
+
+/// Up to `N` bytes (at most 255) in place, anything longer on the heap.
+#[derive(Clone)]
+pub struct InlineBytes<const N: usize>(Repr<N>);
+
+impl<const N: usize> InlineBytes<N> {
+    const FITS_LEN: () = assert!(N <= u8::MAX as usize, "inline length has to fit a u8");
+
+    /// Whether the bytes went to the heap.
+    pub const fn spilled(&self) -> bool {
+        matches!(self.0, Repr::Heap(_))
+    }
+
+    fn push_slice(&mut self, bytes: &[u8]) {
+        match &mut self.0 {
+            Repr::Inline { len, buf } => {
+                let old_len = *len as usize;
+                let new_len = old_len + bytes.len();
+                if new_len <= N {
+                    buf[old_len..new_len].copy_from_slice(bytes);
+                    *len = new_len as u8;
+                } else {
+                    let mut heap = Vec::with_capacity(new_len);
+                    heap.extend_from_slice(&buf[..old_len]);
+                    heap.extend_from_slice(bytes);
+                    self.0 = Repr::Heap(heap);
+                }
+            }
+            Repr::Heap(heap) => heap.extend_from_slice(bytes),
+        }
+    }
+}
+
+impl<const N: usize> Default for InlineBytes<N> {
+    fn default() -> Self {
+        let () = Self::FITS_LEN;
+        Self(Repr::Inline {
+            len: 0,
+            buf: [0; N],
+        })
+    }
+}
+
+impl<const N: usize> Deref for InlineBytes<N> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match &self.0 {
+            Repr::Inline { len, buf } => &buf[..*len as usize],
+            Repr::Heap(heap) => heap,
+        }
+    }
+}
+
+// compared by content, an inline and a spilled copy of the same bytes are equal
+impl<const N: usize> PartialEq for InlineBytes<N> {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl<const N: usize> Eq for InlineBytes<N> {}
+
+impl<const N: usize> Hash for InlineBytes<N> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state);
+    }
+}
+
+// same output as a Vec<u8>, so debug dumps don't depend on the storage either
+impl<const N: usize> fmt::Debug for InlineBytes<N> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
+}
+
+impl<const N: usize> Serialize for InlineBytes<N> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (**self).serialize(serializer)
+    }
+}
+
+impl<'de, const N: usize> Deserialize<'de> for InlineBytes<N> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::deserialize(deserializer).map(Self::from_vec)
+    }
+}
+
+impl<const N: usize> ByteStorage for InlineBytes<N> {
+    const NAME: &'static str = "inline";
+
+    fn from_vec(bytes: Vec<u8>) -> Self {
+        if bytes.len() > N {
+            Self(Repr::Heap(bytes))
+        } else {
+            Self::from_slice(&bytes)
+        }
+    }
+
+    fn from_slice(bytes: &[u8]) -> Self {
+        let mut out = Self::default();
+        out.push_slice(bytes);
+        out
+    }
+
+    fn into_vec(self) -> Vec<u8> {
+        match self.0 {
+            Repr::Inline { len, buf } => buf[..len as usize].to_vec(),
+            Repr::Heap(heap) => heap,
+        }
+    }
+
+    // a spilled buffer keeps its capacity, like a Vec
+    fn clear(&mut self) {
+        match &mut self.0 {
+            Repr::Inline { len, .. } => *len = 0,
+            Repr::Heap(heap) => heap.clear(),
+        }
+    }
+
+    fn extend_from_slice(&mut self, bytes: &[u8]) {
+        self.push_slice(bytes);
+    }
+
+    fn read_from<R: Read>(reader: &mut R, len: usize) -> io::Result<Self> {
+        if len > N {
+            return Vec::read_from(reader, len).map(|heap| Self(Repr::Heap(heap)));
+        }
+
+        let mut buf = [0; N];
+        reader.read_exact(&mut buf[..len])?;
+        Ok(Self(Repr::Inline {
+            len: len as u8,
+            buf,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::hash_map::DefaultHasher;
+
+    use super::*;
+
+    type Small = InlineBytes<4>;
+
+    fn hash_of<T: Hash + ?Sized>(value: &T) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    #[test]
+    fn short_bytes_stay_inline_and_long_ones_spill() {
+        let inline = Small::from_slice(b"abcd");
+        assert!(!inline.spilled());
+        assert_eq!(&*inline, b"abcd");
+
+        let spilled = Small::from_slice(b"abcde");
+        assert!(spilled.spilled());
+        assert_eq!(&*spilled, b"abcde");
+
+        assert!(!Small::from_vec(b"ab".to_vec()).spilled());
+        assert!(Small::from_vec(b"abcdef".to_vec()).spilled());
+        assert_eq!(Small::from_vec(b"abcdef".to_vec()).into_vec(), b"abcdef");
+        assert_eq!(Small::from_slice(b"ab").into_vec(), b"ab");
+    }
+
+    #[test]
+    fn extending_past_the_buffer_keeps_the_bytes() {
+        let mut bytes = Small::from_slice(b"ab");
+        bytes.extend_from_slice(b"cd");
+        assert!(!bytes.spilled());
+        bytes.extend_from_slice(b"ef");
+        assert!(bytes.spilled());
+        assert_eq!(&*bytes, b"abcdef");
+
+        bytes.clear();
+        assert!(bytes.is_empty());
+        bytes.extend_from_slice(b"g");
+        assert_eq!(&*bytes, b"g");
+    }
+
+    #[test]
+    fn inline_and_spilled_copies_are_the_same_value() {
+        let mut spilled = Small::from_slice(b"abcdef");
+        spilled.clear();
+        spilled.extend_from_slice(b"ab");
+        let inline = Small::from_slice(b"ab");
+
+        assert!(spilled.spilled() && !inline.spilled());
+        assert_eq!(spilled, inline);
+        assert_eq!(hash_of(&spilled), hash_of(&inline));
+        assert_eq!(hash_of(&inline), hash_of(&b"ab".to_vec()));
+        assert_eq!(format!("{inline:?}"), format!("{:?}", b"ab".to_vec()));
+    }
+
+    #[test]
+    fn reads_exactly_len_bytes() {
+        let mut reader: &[u8] = b"abcdefgh";
+        let inline = Small::read_from(&mut reader, 3).unwrap();
+        assert!(!inline.spilled());
+        assert_eq!(&*inline, b"abc");
+
+        let spilled = Small::read_from(&mut reader, 5).unwrap();
+        assert!(spilled.spilled());
+        assert_eq!(&*spilled, b"defgh");
+        assert!(reader.is_empty());
+
+        let mut short: &[u8] = b"ab";
+        let err = Small::read_from(&mut short, 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+        let err = Vec::read_from(&mut &b"ab"[..], 3).unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::UnexpectedEof);
+    }
+
+    #[test]
+    fn serializes_like_a_vec() {
+        for bytes in [&b""[..], b"ab", b"abcdef"] {
+            let inline = Small::from_slice(bytes);
+            let json = serde_json::to_string(&inline).unwrap();
+            assert_eq!(json, serde_json::to_string(&bytes.to_vec()).unwrap());
+            assert_eq!(serde_json::from_str::<Small>(&json).unwrap(), inline);
+
+            let encoded = bincode::serialize(&inline).unwrap();
+            assert_eq!(encoded, bincode::serialize(&bytes.to_vec()).unwrap());
+            assert_eq!(bincode::deserialize::<Small>(&encoded).unwrap(), inline);
+        }
+    }
+
+    #[test]
+    fn spilled_bytes_move_out_without_a_copy() {
+        let heap = b"abcdef".to_vec();
+        let ptr = heap.as_ptr();
+        let spilled = Small::from_vec(heap);
+        assert_eq!(spilled.as_ptr(), ptr);
+        let moved = spilled.into_vec();
+        assert_eq!(moved.as_ptr(), ptr);
+
+        let vec = b"ab".to_vec();
+        let ptr = vec.as_ptr();
+        let moved = ByteStorage::into_vec(vec);
+        assert_eq!(moved.as_ptr(), ptr);
+    }
+}
@@ -0,0 +1,160 @@
+use std::error::Error;
+use std::fmt;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use anyhow::{anyhow, Result};
+use crossbeam_channel::{Sender, TrySendError};
+
+use super::storage::FileStore;
+use super::PlayerLog;
+
+/// The log that couldn't be queued is handed back, so the caller can retry or drop it.
+#[derive(Debug)]
+pub enum PushError {
+    QueueFull(Box<PlayerLog>),
+    /// The writer thread stopped after a failed write, see [`BoundedLogQueue::close`].
+    WriterStopped(Box<PlayerLog>),
+}
+
+impl PushError {
+    pub fn into_log(self) -> PlayerLog {
+        match self {
+            Self::QueueFull(log) | Self::WriterStopped(log) => *log,
+        }
+    }
+}
+
+impl fmt::Display for PushError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::QueueFull(_) => write!(f, "queue is full"),
+            Self::WriterStopped(_) => write!(f, "writer thread stopped"),
+        }
+    }
+}
+
+impl Error for PushError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct QueueStats {
+    /// Logs waiting for the writer.
+    pub queued: usize,
+    /// Logs appended to the file, they may still be buffered until the queue runs empty.
+    pub written: usize,
+    /// Logs a push gave up on, either because the queue was full or the writer had stopped.
+    pub dropped: usize,
+}
+
+/// Hands logs to a background thread appending them to a [`FileStore`], holding at most
+/// `capacity` of them in between.
+///
+/// A producer that can't wait, like a login handler, uses [`BoundedLogQueue::try_push`] and sheds
+/// logs once the disk falls behind. The file is flushed whenever the writer empties the queue.
+pub struct BoundedLogQueue {
+    sender: Option<Sender<PlayerLog>>,
+    writer: Option<JoinHandle<Result<()>>>,
+    written: Arc<AtomicUsize>,
+    dropped: AtomicUsize,
+}
+
+impl BoundedLogQueue {
+    /// Opens `path` the way [`FileStore::open`] does before starting the writer, so a file that
+    /// can't be opened fails here instead of on the first push.
+    pub fn new(capacity: usize, path: &Path) -> Result<Self> {
+        let mut store = FileStore::open(path)?;
+        let (sender, receiver) = crossbeam_channel::bounded::<PlayerLog>(capacity);
+        let written = Arc::new(AtomicUsize::new(0));
+
+        let writer = {
+            let written = Arc::clone(&written);
+            thread::Builder::new()
+                .name("log-queue-writer".to_string())
+                .spawn(move || -> Result<()> {
+                    // dropping the receiver on an error makes every later push fail
+                    for log in &receiver {
+                        store.append_one(&log)?;
+                        written.fetch_add(1, Ordering::Relaxed);
+
+                        if receiver.is_empty() {
+                            store.sync()?;
+                        }
+                    }
+
+                    store.sync()
+                })?
+        };
+
+        Ok(Self {
+            sender: Some(sender),
+            writer: Some(writer),
+            written,
+            dropped: AtomicUsize::new(0),
+        })
+    }
+
+    /// Queues `log` without blocking.
+    pub fn try_push(&self, log: PlayerLog) -> Result<(), PushError> {
+        let result = match self.sender().try_send(log) {
+            Ok(()) => return Ok(()),
+            Err(TrySendError::Full(log)) => PushError::QueueFull(Box::new(log)),
+            Err(TrySendError::Disconnected(log)) => PushError::WriterStopped(Box::new(log)),
+        };
+
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+        Err(result)
+    }
+
+    /// Waits until there's room for `log`. If the writer has stopped the log is dropped, the
+    /// error comes out of [`BoundedLogQueue::close`].
+    pub fn push_blocking(&self, log: PlayerLog) {
+        if self.sender().send(log).is_err() {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn stats(&self) -> QueueStats {
+        QueueStats {
+            queued: self.sender().len(),
+            written: self.written.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Waits for the writer to append every queued log and sync the file, returning the error
+    /// that stopped it if there was one.
+    pub fn close(mut self) -> Result<QueueStats> {
+        let stats = self.stats();
+        self.sender = None;
+
+        self.writer
+            .take()
+            .expect("the writer is only taken on close")
+            .join()
+            .map_err(|_| anyhow!("writer thread panicked"))??;
+
+        Ok(QueueStats {
+            queued: 0,
+            written: self.written.load(Ordering::Relaxed),
+            ..stats
+        })
+    }
+
+    const fn sender(&self) -> &Sender<PlayerLog> {
+        self.sender
+            .as_ref()
+            .expect("the sender is only dropped on close")
+    }
+}
+
+impl Drop for BoundedLogQueue {
+    // the same as close without the error, so queued logs aren't lost
+    fn drop(&mut self) {
+        self.sender = None;
+        if let Some(writer) = self.writer.take() {
+            let _ = writer.join();
+        }
+    }
+}
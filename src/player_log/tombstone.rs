@@ -0,0 +1,270 @@
+//! Records marking another record as deleted, so it can be taken down before the batch holding it
+//! is rewritten.
+//!
+//! The two [`LogEventType`](super::LogEventType) bits are all taken by real events, so a tombstone
+//! is an otherwise empty record carrying a [`TOMBSTONE_EXTENSION`] with the [`RecordRef`] of its
+//! target.
+//!
+//! A compacted segment keeps its batch id, and a [`GAP_EXTENSION`] record stands in for each run
+//! of records compaction dropped so the ordinals of the ones after it stay the same.
+//!
+//! Both extension kinds are reserved, [`PlayerLogBuilder`](super::PlayerLogBuilder) and
+//! [`PlayerLog::validate_consistency`] reject them on anything else. Every read of a batch that
+//! returns [`PlayerLog`]s leaves the markers out, only the [`SegmentStore`](super::segment::SegmentStore)
+//! sees them.
+
+use std::mem::size_of;
+
+use anyhow::Result;
+
+use super::ordinal::RecordRef;
+use super::{BatchId, DeserializerOptions, Extension, PlayerLog, PlayerLogSerializer};
+
+/// The extension kind of a tombstone, the data is the target's batch id followed by its ordinal
+/// as a big endian u32.
+pub const TOMBSTONE_EXTENSION: u8 = 0xC1;
+
+/// The extension kind of a gap, the data is how many ordinals it stands in for as a big endian
+/// u32.
+pub const GAP_EXTENSION: u8 = 0xC2;
+
+const TOMBSTONE_LEN: usize = size_of::<BatchId>() + size_of::<u32>();
+
+/// Whether `kind` is one only tombstones and gaps may carry.
+pub const fn is_reserved(kind: u8) -> bool {
+    matches!(kind, TOMBSTONE_EXTENSION | GAP_EXTENSION)
+}
+
+/// What a record of a segment is to the store, going by its extensions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Marker {
+    Record,
+    Tombstone(RecordRef),
+    Gap(u32),
+}
+
+impl Marker {
+    pub fn of(extensions: &[Extension]) -> Self {
+        extensions
+            .iter()
+            .find_map(|extension| match extension.kind {
+                TOMBSTONE_EXTENSION => tombstone_target(&extension.data).map(Self::Tombstone),
+                GAP_EXTENSION => gap_len(&extension.data).map(Self::Gap),
+                _ => None,
+            })
+            .unwrap_or(Self::Record)
+    }
+
+    /// How many ordinals the record takes up.
+    pub const fn ordinals(self) -> u32 {
+        match self {
+            Self::Record | Self::Tombstone(_) => 1,
+            Self::Gap(len) => len,
+        }
+    }
+}
+
+fn marker_log(kind: u8, data: Vec<u8>) -> PlayerLog {
+    let mut log = PlayerLog::default();
+    log.upgrade();
+    log.extensions.push(Extension { kind, data });
+    log
+}
+
+impl PlayerLog {
+    /// A tombstone for `target`, every field but the extension is left at its default.
+    pub fn tombstone(target: &RecordRef) -> Self {
+        let mut data = Vec::with_capacity(TOMBSTONE_LEN);
+        data.extend_from_slice(&target.batch_id);
+        data.extend_from_slice(&target.ordinal.to_be_bytes());
+
+        marker_log(TOMBSTONE_EXTENSION, data)
+    }
+
+    /// A gap standing in for `len` dropped records.
+    pub fn gap(len: u32) -> Self {
+        marker_log(GAP_EXTENSION, len.to_be_bytes().to_vec())
+    }
+
+    pub fn marker(&self) -> Marker {
+        Marker::of(&self.extensions)
+    }
+
+    /// The record this one deletes, `None` if it isn't a tombstone.
+    pub fn tombstone_target(&self) -> Option<RecordRef> {
+        match self.marker() {
+            Marker::Tombstone(target) => Some(target),
+            _ => None,
+        }
+    }
+
+    pub fn is_tombstone(&self) -> bool {
+        self.tombstone_target().is_some()
+    }
+
+    /// A tombstone or a gap rather than a log.
+    pub fn is_marker(&self) -> bool {
+        self.marker() != Marker::Record
+    }
+}
+
+impl PlayerLogSerializer {
+    /// [`PlayerLogSerializer::deserialize_many`] keeping the tombstones and gaps, so a record's
+    /// position is its ordinal.
+    pub(super) fn deserialize_many_with_markers(data: &[u8]) -> Result<Vec<PlayerLog>> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+        let mut reader = header.codec.decoder(&data[header_len..]);
+        let logs = Self::deserialize_helper(
+            &mut reader,
+            header.record_count,
+            header.endianness,
+            &DeserializerOptions::default(),
+            true,
+        )?;
+
+        super::check_payload_end(&mut reader)?;
+
+        Ok(logs)
+    }
+}
+
+/// The target in the data of a [`TOMBSTONE_EXTENSION`], `None` if it isn't 20 bytes long.
+pub fn tombstone_target(data: &[u8]) -> Option<RecordRef> {
+    let data: &[u8; TOMBSTONE_LEN] = data.try_into().ok()?;
+    let (batch_id, ordinal) = data.split_at(size_of::<BatchId>());
+
+    Some(RecordRef::new(
+        batch_id.try_into().ok()?,
+        u32::from_be_bytes(ordinal.try_into().ok()?),
+    ))
+}
+
+/// The length in the data of a [`GAP_EXTENSION`], `None` if it isn't 4 bytes long.
+pub fn gap_len(data: &[u8]) -> Option<u32> {
+    Some(u32::from_be_bytes(data.try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::consistency::ConsistencyError;
+    use super::super::ordinal::OffsetIndex;
+    use super::super::segment::{SegmentConfig, SegmentStore};
+    use super::super::Codec;
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn logs_cant_pass_for_markers() {
+        let target = RecordRef::new([7; 16], 3);
+        let mut data = target.batch_id.to_vec();
+        data.extend_from_slice(&target.ordinal.to_be_bytes());
+
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut builder = log_generator_seeded(&mut rng);
+        builder.extensions.push(Extension {
+            kind: TOMBSTONE_EXTENSION,
+            data: data.clone(),
+        });
+        let e = builder.build().unwrap_err();
+        assert!(e.to_string().contains("reserved"), "{e}");
+
+        // put together by hand, it would read back as a tombstone
+        let mut log = logs(1).remove(0);
+        log.upgrade();
+        log.extensions.push(Extension {
+            kind: TOMBSTONE_EXTENSION,
+            data,
+        });
+        assert_eq!(log.tombstone_target(), Some(target));
+        assert_eq!(
+            log.validate_consistency(),
+            Err(vec![ConsistencyError::ReservedExtension(
+                TOMBSTONE_EXTENSION
+            )])
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let config = SegmentConfig {
+            max_records: 8,
+            max_bytes: usize::MAX,
+            codec: Codec::None,
+            clock_skew: None,
+        };
+        let mut store = SegmentStore::open(&dir.path().join("logins"), config).unwrap();
+        assert!(store.append(log).is_err());
+        assert!(store.append(PlayerLog::gap(1)).is_err());
+        store.flush().unwrap();
+        assert!(store.segments().unwrap().is_empty());
+    }
+
+    #[test]
+    fn markers_are_left_out_of_reads() {
+        let logs = logs(3);
+        let mut batch = logs.clone();
+        batch.insert(1, PlayerLog::tombstone(&RecordRef::new([7; 16], 0)));
+        batch.insert(3, PlayerLog::gap(2));
+        let data = PlayerLogSerializer::serialize_many(&batch).unwrap();
+
+        assert_eq!(PlayerLogSerializer::deserialize_many(&data).unwrap(), logs);
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_with_markers(&data).unwrap(),
+            batch
+        );
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_indexed(&data).unwrap(),
+            [
+                (0, logs[0].clone()),
+                (2, logs[1].clone()),
+                (4, logs[2].clone())
+            ]
+        );
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_builders(&data)
+                .unwrap()
+                .len(),
+            3
+        );
+        assert_eq!(
+            PlayerLogSerializer::deserialize_many_owned(data.clone())
+                .unwrap()
+                .into_logs(),
+            logs
+        );
+
+        assert_eq!(PlayerLogSerializer::fetch(&data, 4).unwrap(), logs[2]);
+        assert!(PlayerLogSerializer::fetch(&data, 1).is_err());
+        let index = OffsetIndex::build(&data).unwrap();
+        assert_eq!(index.fetch(2).unwrap(), logs[1]);
+        assert!(index.fetch(3).is_err());
+    }
+
+    #[test]
+    fn marker_data_of_the_wrong_length_is_a_record() {
+        let extension = |kind, len| Extension {
+            kind,
+            data: vec![0; len],
+        };
+
+        assert_eq!(
+            Marker::of(&[extension(TOMBSTONE_EXTENSION, TOMBSTONE_LEN)]),
+            Marker::Tombstone(RecordRef::new([0; 16], 0))
+        );
+        assert_eq!(Marker::of(&[extension(GAP_EXTENSION, 4)]), Marker::Gap(0));
+        assert_eq!(
+            Marker::of(&[extension(TOMBSTONE_EXTENSION, 4)]),
+            Marker::Record
+        );
+        assert_eq!(Marker::of(&[extension(GAP_EXTENSION, 5)]), Marker::Record);
+        assert_eq!(Marker::Gap(5).ordinals(), 5);
+    }
+}
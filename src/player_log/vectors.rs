@@ -0,0 +1,201 @@
+//! Test vectors for readers written in other languages, generated from
+//! [`conformance::coverage_vectors`] so they can't drift from this implementation.
+//!
+//! Every vector is a `<slug>.bin` batch next to one `vectors.json` manifest. A vector is either
+//! `valid` with its header and records described field by field, or has to be rejected, in which
+//! case `error` is only what the reference decoder said and not part of the contract. Byte fields
+//! are lowercase hex, ips are dotted strings.
+
+use std::fs;
+use std::net::Ipv4Addr;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+use super::conformance::{self, coverage_vectors, reference_decode_batch};
+use super::{LogFlags, PlayerLog, PlayerLogSerializer};
+
+pub const MANIFEST: &str = "vectors.json";
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+fn ip(ip: [u8; 4]) -> String {
+    Ipv4Addr::from(ip).to_string()
+}
+
+/// `"v2 name too long"` is written to `v2-name-too-long.bin`.
+pub fn file_name(name: &str) -> String {
+    let slug = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() {
+                c.to_ascii_lowercase()
+            } else {
+                '-'
+            }
+        })
+        .collect::<String>();
+
+    format!("{slug}.bin")
+}
+
+fn describe_record(offset: usize, log: &PlayerLog) -> Value {
+    let flag_names = LogFlags::from_bits_retain(log.flags)
+        .iter_names()
+        .map(|(name, _)| name)
+        .collect::<Vec<_>>();
+
+    json!({
+        "payload_offset": offset,
+        "binary_version": log.binary_version,
+        "flags": log.flags,
+        "flag_names": flag_names,
+        "event_type": log.event_type().name(),
+        "player_uuid": log.player_uuid.map(|uuid| hex(&uuid)),
+        "player_name": hex(&log.player_name),
+        "player_ip": ip(log.player_ip),
+        "server_ip": ip(log.server_ip),
+        "server_port": log.server_port,
+        "server_domain": hex(&log.server_domain),
+        "server_version_id": log.server_version.id(),
+        "server_version_name": log.server_version.name(),
+        "timestamp": log.timestamp,
+        "session_id": log.session_id,
+        "outcome": log.outcome.to_byte(),
+        "geo": log.geo.map(|geo| json!({ "country": geo.country_code(), "asn": geo.asn })),
+        "proxy_ip": log.proxy_ip.map(ip),
+        "extensions": log
+            .extensions
+            .iter()
+            .map(|extension| json!({ "kind": extension.kind, "data": hex(&extension.data) }))
+            .collect::<Vec<_>>(),
+        "client_brand": hex(&log.client_brand),
+        "client_locale": hex(&log.client_locale),
+    })
+}
+
+/// The manifest entry for one batch, decoded with the reference decoder.
+pub fn describe(name: &str, data: &[u8]) -> Value {
+    let mut entry = json!({
+        "name": name,
+        "file": file_name(name),
+        "sha256": hex(&Sha256::digest(data)),
+    });
+
+    let decoded = reference_decode_batch(data)
+        .and_then(|records| PlayerLogSerializer::probe(data).map(|header| (header, records)));
+    match decoded {
+        Ok((header, records)) => {
+            entry["valid"] = true.into();
+            entry["header"] = json!({
+                "codec": header.codec.name(),
+                "batch_id": hex(&header.batch_id),
+                "record_count": header.record_count,
+                "producer": header.producer,
+            });
+            entry["records"] = records
+                .iter()
+                .map(|(offset, log)| describe_record(*offset, log))
+                .collect();
+        }
+        Err(e) => {
+            entry["valid"] = false.into();
+            entry["error"] = e.to_string().into();
+        }
+    }
+
+    entry
+}
+
+fn manifest() -> Value {
+    let vectors = coverage_vectors()
+        .iter()
+        .map(|(name, data)| describe(name, data))
+        .collect::<Vec<_>>();
+
+    json!({ "container_version": 1, "vectors": vectors })
+}
+
+/// Writes every coverage vector and the manifest describing them to `dir`.
+pub fn write_vectors(dir: &Path) -> Result<usize> {
+    fs::create_dir_all(dir)?;
+
+    let vectors = coverage_vectors();
+    for (name, data) in &vectors {
+        fs::write(dir.join(file_name(name)), data)?;
+    }
+
+    let mut manifest = serde_json::to_string_pretty(&manifest())?;
+    manifest.push('\n');
+    fs::write(dir.join(MANIFEST), manifest)?;
+
+    Ok(vectors.len())
+}
+
+/// Checks the vectors in `dir` are exactly what [`write_vectors`] writes now, and that both
+/// decoders read every committed file back the way its manifest entry describes.
+///
+/// Any change to the coverage vectors or to how they decode fails here until the vectors are
+/// regenerated, so changing the contract always shows up as a diff of the committed files.
+pub fn check_vectors(dir: &Path) -> Result<()> {
+    let path = dir.join(MANIFEST);
+    let committed: Value = serde_json::from_str(
+        &fs::read_to_string(&path).with_context(|| format!("reading {}", path.display()))?,
+    )?;
+    let entries = committed["vectors"]
+        .as_array()
+        .context("the manifest has no vectors")?;
+
+    let vectors = coverage_vectors();
+    if entries.len() != vectors.len() {
+        bail!(
+            "{} vectors are committed, the coverage vectors have {}",
+            entries.len(),
+            vectors.len()
+        );
+    }
+
+    for (entry, (name, data)) in entries.iter().zip(&vectors) {
+        let path = dir.join(file_name(name));
+        let file = fs::read(&path).with_context(|| format!("reading {}", path.display()))?;
+
+        if describe(name, &file) != *entry {
+            bail!(
+                "{name}: {} doesn't decode as its manifest entry",
+                path.display()
+            );
+        }
+        if let Some(divergence) = conformance::cross_check_batch(&file) {
+            bail!("{name}: {divergence}");
+        }
+        if file != *data {
+            bail!(
+                "{name}: {} differs from the coverage vector",
+                path.display()
+            );
+        }
+    }
+
+    if committed != manifest() {
+        bail!("{MANIFEST} differs from the coverage vectors");
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn committed_vectors_are_current() {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("test-vectors");
+        if let Err(e) = check_vectors(&dir) {
+            panic!("{e:#}, regenerate them with emit-test-vectors if the change is intended");
+        }
+    }
+}
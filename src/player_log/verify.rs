@@ -0,0 +1,179 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use anyhow::{bail, Context, Result};
+use sha2::{Digest, Sha256};
+
+use super::PlayerLog;
+
+/// How two batches have to match to count as the same.
+#[derive(Debug, Clone, Copy)]
+pub enum Equivalence {
+    /// The same logs in the same order.
+    Ordered,
+    /// The same logs the same number of times, in any order.
+    Unordered,
+    /// Logs are paired up by their key, every key has to be unique on both sides and map to equal
+    /// logs. [`content_hash`] works as a key for batches without duplicates.
+    KeyedBy(fn(&PlayerLog) -> [u8; 32]),
+}
+
+/// Sha256 of the serialized record, logs that can't be serialized hash their debug output.
+pub fn content_hash(log: &PlayerLog) -> [u8; 32] {
+    let mut data = Vec::with_capacity(log.serialized_size());
+    if log.serialize(&mut data).is_err() {
+        data = format!("{log:?}").into_bytes();
+    }
+
+    Sha256::digest(data).into()
+}
+
+fn push_diff<T: PartialEq + Debug>(diffs: &mut Vec<String>, field: &str, a: T, b: T) {
+    if a != b {
+        diffs.push(format!("  {field}: {a:?} != {b:?}"));
+    }
+}
+
+/// One line per field `a` and `b` differ in, byte strings are shown as text.
+pub fn field_diff(a: &PlayerLog, b: &PlayerLog) -> Vec<String> {
+    let text = |bytes: &[u8]| String::from_utf8_lossy(bytes).into_owned();
+
+    let mut diffs = Vec::new();
+    push_diff(
+        &mut diffs,
+        "binary_version",
+        a.binary_version,
+        b.binary_version,
+    );
+    push_diff(&mut diffs, "flags", a.flags, b.flags);
+    push_diff(&mut diffs, "player_uuid", a.player_uuid, b.player_uuid);
+    push_diff(
+        &mut diffs,
+        "player_name",
+        text(&a.player_name),
+        text(&b.player_name),
+    );
+    push_diff(&mut diffs, "player_ip", a.player_ip, b.player_ip);
+    push_diff(&mut diffs, "server_ip", a.server_ip, b.server_ip);
+    push_diff(&mut diffs, "server_port", a.server_port, b.server_port);
+    push_diff(
+        &mut diffs,
+        "server_domain",
+        text(&a.server_domain),
+        text(&b.server_domain),
+    );
+    push_diff(
+        &mut diffs,
+        "server_version",
+        &a.server_version,
+        &b.server_version,
+    );
+    push_diff(&mut diffs, "timestamp", a.timestamp, b.timestamp);
+    push_diff(&mut diffs, "session_id", a.session_id, b.session_id);
+    push_diff(&mut diffs, "outcome", a.outcome, b.outcome);
+    push_diff(&mut diffs, "geo", a.geo, b.geo);
+    push_diff(&mut diffs, "proxy_ip", a.proxy_ip, b.proxy_ip);
+    push_diff(&mut diffs, "extensions", &a.extensions, &b.extensions);
+    push_diff(
+        &mut diffs,
+        "client_brand",
+        text(&a.client_brand),
+        text(&b.client_brand),
+    );
+    push_diff(
+        &mut diffs,
+        "client_locale",
+        text(&a.client_locale),
+        text(&b.client_locale),
+    );
+    diffs
+}
+
+fn differs(context: String, a: &PlayerLog, b: &PlayerLog) -> anyhow::Error {
+    anyhow::anyhow!("{context}\n{}", field_diff(a, b).join("\n"))
+}
+
+/// Checks `a` and `b` hold the same logs under `mode`, the error names the first log that doesn't
+/// match and which of its fields differ.
+pub fn check_batches_equivalent(a: &[PlayerLog], b: &[PlayerLog], mode: Equivalence) -> Result<()> {
+    if a.len() != b.len() {
+        bail!("left has {} logs, right has {}", a.len(), b.len());
+    }
+
+    match mode {
+        Equivalence::Ordered => {
+            if let Some(i) = a.iter().zip(b).position(|(a, b)| a != b) {
+                return Err(differs(format!("log {i} differs"), &a[i], &b[i]));
+            }
+        }
+        Equivalence::Unordered => {
+            let mut counts = HashMap::<_, (Vec<usize>, Vec<usize>)>::new();
+            for (i, log) in a.iter().enumerate() {
+                counts.entry(content_hash(log)).or_default().0.push(i);
+            }
+            for (i, log) in b.iter().enumerate() {
+                counts.entry(content_hash(log)).or_default().1.push(i);
+            }
+
+            // every unmatched log on the left has one on the right since the lengths are equal
+            let mut only_left = Vec::<usize>::new();
+            let mut only_right = Vec::<usize>::new();
+            for (left, right) in counts.values() {
+                only_left.extend(left.iter().skip(right.len()));
+                only_right.extend(right.iter().skip(left.len()));
+            }
+            only_left.sort_unstable();
+            only_right.sort_unstable();
+
+            if let (Some(&left), Some(&right)) = (only_left.first(), only_right.first()) {
+                return Err(differs(
+                    format!(
+                        "{} logs only on the left and {} only on the right, left log {left} \
+                         compared to right log {right}",
+                        only_left.len(),
+                        only_right.len()
+                    ),
+                    &a[left],
+                    &b[right],
+                ));
+            }
+        }
+        Equivalence::KeyedBy(key) => {
+            let mut keyed = HashMap::with_capacity(b.len());
+            for (i, log) in b.iter().enumerate() {
+                if let Some(first) = keyed.insert(key(log), i) {
+                    bail!("right logs {first} and {i} have the same key");
+                }
+            }
+
+            let mut seen = HashMap::with_capacity(a.len());
+            for (i, log) in a.iter().enumerate() {
+                let k = key(log);
+                if let Some(first) = seen.insert(k, i) {
+                    bail!("left logs {first} and {i} have the same key");
+                }
+
+                let j = *keyed.get(&k).with_context(|| {
+                    format!("left log {i} has no log with its key on the right")
+                })?;
+                if *log != b[j] {
+                    return Err(differs(
+                        format!("left log {i} differs from right log {j} with the same key"),
+                        log,
+                        &b[j],
+                    ));
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// [`check_batches_equivalent`], panicking with the diff.
+#[track_caller]
+pub fn assert_batches_equivalent(a: &[PlayerLog], b: &[PlayerLog], mode: Equivalence) {
+    if let Err(e) = check_batches_equivalent(a, b, mode) {
+        panic!("batches aren't equivalent ({mode:?}): {e:#}");
+    }
+}
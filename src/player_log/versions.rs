@@ -0,0 +1,26 @@
+//! Which binary_version can record what, checked when a record is written.
+
+use super::PlayerLog;
+
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+pub enum Feature {
+    /// The player uuid of an online mode login.
+    OnlineModeUuid,
+    /// Players joining from Bedrock edition, no binary_version has a flag for them yet.
+    Bedrock,
+    /// Server versions missing from [`VERSIONS`](super::VERSIONS), written as their name.
+    SnapshotVersion,
+}
+
+/// `false` for every feature of a binary_version that doesn't exist.
+pub const fn version_supports(version_id: u8, feature: Feature) -> bool {
+    if version_id == 0 || version_id > PlayerLog::CURRENT_VERSION {
+        return false;
+    }
+
+    match feature {
+        Feature::OnlineModeUuid => true,
+        Feature::Bedrock => false,
+        Feature::SnapshotVersion => version_id >= 2,
+    }
+}
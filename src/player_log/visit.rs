@@ -0,0 +1,612 @@
+//! Walking a batch field by field without decoding its records into [`PlayerLog`]s.
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::io::{Cursor, Read, Write};
+use std::net::Ipv4Addr;
+
+use anyhow::{bail, Context, Result};
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+
+use super::{
+    validate_flags, Codec, Endianness, FieldPresenceMask, GeoInfo, LogFlags, LoginOutcome,
+    PlayerLog, PlayerLogSerializer, ServerVersion,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ServerVersionRef<'a> {
+    Known(u8),
+    Unknown(&'a str),
+}
+
+/// Callbacks for [`PlayerLogSerializer::visit`], every one of them does nothing by default.
+///
+/// They're called in this order for every record, and all but `visit_extension` exactly once.
+/// Fields a record doesn't store get what a full decode fills in, like a timestamp of 0 under
+/// binary_version 1 or the dotted server ip for a DOMAIN_IS_IP domain.
+pub trait RecordVisitor {
+    fn begin_record(&mut self, _binary_version: u8) {}
+    fn visit_flags(&mut self, _flags: u16) {}
+    fn visit_uuid(&mut self, _uuid: Option<&[u8; 16]>) {}
+    fn visit_name(&mut self, _name: &[u8]) {}
+    fn visit_player_ip(&mut self, _ip: [u8; 4]) {}
+    fn visit_server_ip(&mut self, _ip: [u8; 4]) {}
+    fn visit_port(&mut self, _port: u16) {}
+    fn visit_domain(&mut self, _domain: &[u8]) {}
+    fn visit_server_version(&mut self, _version: ServerVersionRef<'_>) {}
+    fn visit_timestamp(&mut self, _timestamp: u64) {}
+    fn visit_session_id(&mut self, _session_id: u32) {}
+    fn visit_outcome(&mut self, _outcome: LoginOutcome) {}
+    fn visit_geo(&mut self, _geo: Option<GeoInfo>) {}
+    fn visit_proxy_ip(&mut self, _proxy_ip: Option<[u8; 4]>) {}
+    fn visit_client_info(&mut self, _brand: &[u8], _locale: &[u8]) {}
+    fn visit_extension(&mut self, _kind: u8, _data: &[u8]) {}
+    fn end_record(&mut self) {}
+}
+
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8]> {
+    if data.len() < len {
+        bail!("record cut short");
+    }
+
+    let (field, rest) = data.split_at(len);
+    *data = rest;
+    Ok(field)
+}
+
+fn take_array<const N: usize>(data: &mut &[u8]) -> Result<[u8; N]> {
+    Ok(take(data, N)?.try_into().expect("took N bytes"))
+}
+
+// same bounds as the full decode, so the same records are rejected
+fn take_field<'a>(data: &mut &'a [u8], max_len: usize, field: &str) -> Result<&'a [u8]> {
+    let len = data.read_u8()? as usize;
+    if len > max_len {
+        bail!("invalid {field}: {len} bytes, at most {max_len} are allowed");
+    }
+
+    take(data, len)
+}
+
+fn visit_record<B: ByteOrder, V: RecordVisitor + ?Sized>(
+    data: &mut &[u8],
+    visitor: &mut V,
+) -> Result<()> {
+    let binary_version = data.read_u8()?;
+    if !(1..=PlayerLog::CURRENT_VERSION).contains(&binary_version) {
+        bail!("invalid binary version");
+    }
+    let v2 = binary_version >= 2;
+    visitor.begin_record(binary_version);
+
+    let flags = if v2 {
+        data.read_u16::<B>()?
+    } else {
+        u16::from(data.read_u8()?)
+    };
+    let parsed_flags = validate_flags(flags)?;
+    visitor.visit_flags(flags);
+
+    let uuid = if parsed_flags.contains(LogFlags::IS_ONLINE) {
+        Some(take_array::<16>(data)?)
+    } else {
+        None
+    };
+    visitor.visit_uuid(uuid.as_ref());
+    visitor.visit_name(take_field(
+        data,
+        PlayerLog::max_name_len(binary_version),
+        "player name",
+    )?);
+    visitor.visit_player_ip(take_array(data)?);
+    let server_ip = take_array(data)?;
+    visitor.visit_server_ip(server_ip);
+
+    let port = if !v2 || parsed_flags.contains(LogFlags::NONDEFAULT_PORT) {
+        data.read_u16::<B>()?
+    } else {
+        PlayerLog::DEFAULT_PORT
+    };
+    visitor.visit_port(port);
+
+    if v2 && parsed_flags.contains(LogFlags::DOMAIN_IS_IP) {
+        // the longest dotted ip is 15 bytes, formatting it on the stack saves the allocation
+        let mut buf = [0; 15];
+        let mut cursor = Cursor::new(&mut buf[..]);
+        write!(cursor, "{}", Ipv4Addr::from(server_ip)).expect("an ip fits 15 bytes");
+        let len = cursor.position() as usize;
+        visitor.visit_domain(&buf[..len]);
+    } else {
+        visitor.visit_domain(take_field(
+            data,
+            PlayerLog::MAX_DOMAIN_LEN,
+            "server domain",
+        )?);
+    }
+
+    match data.read_u8()? {
+        ServerVersion::UNKNOWN_ID if v2 => {
+            let name = take_field(
+                data,
+                ServerVersion::MAX_UNKNOWN_LEN,
+                "unknown server version",
+            )?;
+            let name = std::str::from_utf8(name).context("invalid unknown server version")?;
+            visitor.visit_server_version(ServerVersionRef::Unknown(name));
+        }
+        id => visitor.visit_server_version(ServerVersionRef::Known(id)),
+    }
+
+    if !v2 {
+        visitor.visit_timestamp(0);
+        visitor.visit_session_id(0);
+        visitor.visit_outcome(LoginOutcome::Success);
+        visitor.visit_geo(None);
+        visitor.visit_proxy_ip(None);
+        visitor.visit_client_info(&[], &[]);
+        visitor.end_record();
+        return Ok(());
+    }
+
+    visitor.visit_timestamp(data.read_u64::<B>()?);
+    visitor.visit_session_id(data.read_u32::<B>()?);
+    visitor.visit_outcome(if parsed_flags.contains(LogFlags::LOGIN_REFUSED) {
+        LoginOutcome::from_byte(data.read_u8()?)
+    } else {
+        LoginOutcome::Success
+    });
+
+    let geo = if parsed_flags.contains(LogFlags::HAS_GEO) {
+        let country = take_array(data)?;
+        let asn = data.read_u32::<B>()?;
+        Some(GeoInfo { country, asn })
+    } else {
+        None
+    };
+    visitor.visit_geo(geo);
+    visitor.visit_proxy_ip(if parsed_flags.contains(LogFlags::VIA_PROXY) {
+        Some(take_array(data)?)
+    } else {
+        None
+    });
+
+    if parsed_flags.contains(LogFlags::HAS_CLIENT_INFO) {
+        let brand = take_field(data, PlayerLog::MAX_CLIENT_BRAND_LEN, "client brand")?;
+        let locale = take_field(data, PlayerLog::MAX_CLIENT_LOCALE_LEN, "client locale")?;
+        visitor.visit_client_info(brand, locale);
+    } else {
+        visitor.visit_client_info(&[], &[]);
+    }
+
+    let extension_count = data.read_u8()? as usize;
+    if extension_count > PlayerLog::MAX_EXTENSIONS {
+        bail!("Too many extensions");
+    }
+    for _ in 0..extension_count {
+        let kind = data.read_u8()?;
+        let extension = take_field(data, PlayerLog::MAX_EXTENSION_LEN, "extension data")?;
+        visitor.visit_extension(kind, extension);
+    }
+
+    visitor.end_record();
+    Ok(())
+}
+
+impl PlayerLogSerializer {
+    /// Runs `visitor` over every record of the batch. The fields are handed over straight from
+    /// the payload, so nothing is allocated for an uncompressed batch and only the decompressed
+    /// payload for a compressed one.
+    ///
+    /// A record that fails to decode stops the walk, after the visitor saw its earlier fields.
+    /// Bytes after the last record fail it after every record was visited, like
+    /// [`PlayerLogSerializer::deserialize_many`] rejects them.
+    pub fn visit<V: RecordVisitor + ?Sized>(data: &[u8], visitor: &mut V) -> Result<()> {
+        let (header, header_len) = Self::probe_with_len(data)?;
+        header.check_no_dictionary()?;
+
+        let payload = match header.codec {
+            Codec::None => Cow::Borrowed(&data[header_len..]),
+            codec => {
+                let mut payload = Vec::new();
+                codec
+                    .decoder(&data[header_len..])
+                    .read_to_end(&mut payload)
+                    .context("corrupt payload")?;
+                Cow::Owned(payload)
+            }
+        };
+
+        let mut records = payload.as_ref();
+        for i in 0..header.record_count {
+            match header.endianness {
+                Endianness::Big => visit_record::<BigEndian, _>(&mut records, visitor),
+                Endianness::Little => visit_record::<LittleEndian, _>(&mut records, visitor),
+            }
+            .with_context(|| format!("record {i}"))?;
+        }
+        if !records.is_empty() {
+            bail!("{} bytes after the last record", records.len());
+        }
+
+        Ok(())
+    }
+}
+
+impl PlayerLog {
+    /// Runs `visitor` over the fields of the log, with the same calls
+    /// [`PlayerLogSerializer::visit`] makes for the record the log was decoded from.
+    pub fn visit<V: RecordVisitor + ?Sized>(&self, visitor: &mut V) {
+        visitor.begin_record(self.binary_version);
+        visitor.visit_flags(self.flags);
+        visitor.visit_uuid(self.player_uuid.as_ref());
+        visitor.visit_name(&self.player_name);
+        visitor.visit_player_ip(self.player_ip);
+        visitor.visit_server_ip(self.server_ip);
+        visitor.visit_port(self.server_port);
+        visitor.visit_domain(&self.server_domain);
+        visitor.visit_server_version(match &self.server_version {
+            ServerVersion::Known(id) => ServerVersionRef::Known(*id),
+            ServerVersion::Unknown(name) => ServerVersionRef::Unknown(name),
+        });
+        visitor.visit_timestamp(self.timestamp);
+        visitor.visit_session_id(self.session_id);
+        visitor.visit_outcome(self.outcome);
+        visitor.visit_geo(self.geo);
+        visitor.visit_proxy_ip(self.proxy_ip);
+        visitor.visit_client_info(&self.client_brand, &self.client_locale);
+        for extension in &self.extensions {
+            visitor.visit_extension(extension.kind, &extension.data);
+        }
+        visitor.end_record();
+    }
+}
+
+/// A few columns of a batch, one entry per record, without building a single [`PlayerLog`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct NetworkColumns {
+    pub player_ips: Vec<[u8; 4]>,
+    pub server_ips: Vec<[u8; 4]>,
+    pub server_ports: Vec<u16>,
+    pub timestamps: Vec<u64>,
+    /// `None` for unknown server versions.
+    pub server_versions: Vec<Option<u8>>,
+}
+
+impl RecordVisitor for NetworkColumns {
+    fn visit_player_ip(&mut self, ip: [u8; 4]) {
+        self.player_ips.push(ip);
+    }
+
+    fn visit_server_ip(&mut self, ip: [u8; 4]) {
+        self.server_ips.push(ip);
+    }
+
+    fn visit_port(&mut self, port: u16) {
+        self.server_ports.push(port);
+    }
+
+    fn visit_timestamp(&mut self, timestamp: u64) {
+        self.timestamps.push(timestamp);
+    }
+
+    fn visit_server_version(&mut self, version: ServerVersionRef<'_>) {
+        self.server_versions.push(match version {
+            ServerVersionRef::Known(id) => Some(id),
+            ServerVersionRef::Unknown(_) => None,
+        });
+    }
+}
+
+/// The per record counts of [`FileStats`](super::FileStats), named the same.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct StatsVisitor {
+    pub binary_versions: BTreeMap<u8, u64>,
+    pub present_fields: BTreeMap<&'static str, u64>,
+    pub outcomes: BTreeMap<LoginOutcome, u64>,
+    pub server_versions: BTreeMap<u8, u64>,
+    pub unknown_versions: BTreeMap<String, u64>,
+    pub countries: BTreeMap<String, u64>,
+    pub proxies: BTreeMap<Ipv4Addr, u64>,
+    // the present fields of the record being visited
+    present: FieldPresenceMask,
+}
+
+impl RecordVisitor for StatsVisitor {
+    fn begin_record(&mut self, binary_version: u8) {
+        *self.binary_versions.entry(binary_version).or_default() += 1;
+        self.present = FieldPresenceMask::empty();
+        self.present
+            .set(FieldPresenceMask::TIMESTAMP, binary_version >= 2);
+    }
+
+    fn visit_uuid(&mut self, uuid: Option<&[u8; 16]>) {
+        self.present.set(FieldPresenceMask::UUID, uuid.is_some());
+    }
+
+    fn visit_server_version(&mut self, version: ServerVersionRef<'_>) {
+        match version {
+            ServerVersionRef::Known(id) => *self.server_versions.entry(id).or_default() += 1,
+            // only allocates the first time a name shows up
+            ServerVersionRef::Unknown(name) => match self.unknown_versions.get_mut(name) {
+                Some(count) => *count += 1,
+                None => {
+                    self.unknown_versions.insert(name.to_string(), 1);
+                }
+            },
+        }
+    }
+
+    fn visit_session_id(&mut self, session_id: u32) {
+        // binary_version 1 records always visit a session id of 0
+        self.present
+            .set(FieldPresenceMask::SESSION_ID, session_id != 0);
+    }
+
+    fn visit_outcome(&mut self, outcome: LoginOutcome) {
+        *self.outcomes.entry(outcome).or_default() += 1;
+    }
+
+    fn visit_geo(&mut self, geo: Option<GeoInfo>) {
+        if let Some(geo) = geo {
+            self.present.insert(FieldPresenceMask::GEO);
+            match self.countries.get_mut(geo.country_code()) {
+                Some(count) => *count += 1,
+                None => {
+                    self.countries.insert(geo.country_code().to_string(), 1);
+                }
+            }
+        }
+    }
+
+    fn visit_proxy_ip(&mut self, proxy_ip: Option<[u8; 4]>) {
+        if let Some(proxy_ip) = proxy_ip {
+            self.present.insert(FieldPresenceMask::PROXY_IP);
+            *self.proxies.entry(Ipv4Addr::from(proxy_ip)).or_default() += 1;
+        }
+    }
+
+    fn visit_client_info(&mut self, brand: &[u8], locale: &[u8]) {
+        self.present.set(
+            FieldPresenceMask::CLIENT_INFO,
+            !brand.is_empty() || !locale.is_empty(),
+        );
+    }
+
+    fn visit_extension(&mut self, _kind: u8, _data: &[u8]) {
+        self.present.insert(FieldPresenceMask::EXTENSIONS);
+    }
+
+    fn end_record(&mut self) {
+        for (name, _) in self.present.iter_names() {
+            *self.present_fields.entry(name).or_default() += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use flate2::Compression;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::super::{conformance, SerializerOptions};
+    use super::*;
+    use crate::log_generator_seeded;
+
+    fn logs(count: usize) -> Vec<PlayerLog> {
+        let mut rng = StdRng::seed_from_u64(0);
+        (0..count)
+            .map(|_| log_generator_seeded(&mut rng).build().unwrap())
+            .collect()
+    }
+
+    fn batch(logs: &[PlayerLog], codec: Codec, endianness: Endianness) -> Vec<u8> {
+        let options = SerializerOptions {
+            codec,
+            endianness,
+            ..SerializerOptions::default()
+        };
+        PlayerLogSerializer::serialize_many_with_options(logs, &options).unwrap()
+    }
+
+    // every call, in the order it was made
+    #[derive(Default)]
+    struct Recorder(Vec<String>);
+
+    impl RecordVisitor for Recorder {
+        fn begin_record(&mut self, binary_version: u8) {
+            self.0.push(format!("begin {binary_version}"));
+        }
+        fn visit_flags(&mut self, flags: u16) {
+            self.0.push(format!("flags {flags:#x}"));
+        }
+        fn visit_uuid(&mut self, uuid: Option<&[u8; 16]>) {
+            self.0.push(format!("uuid {uuid:?}"));
+        }
+        fn visit_name(&mut self, name: &[u8]) {
+            self.0.push(format!("name {name:?}"));
+        }
+        fn visit_player_ip(&mut self, ip: [u8; 4]) {
+            self.0.push(format!("player ip {ip:?}"));
+        }
+        fn visit_server_ip(&mut self, ip: [u8; 4]) {
+            self.0.push(format!("server ip {ip:?}"));
+        }
+        fn visit_port(&mut self, port: u16) {
+            self.0.push(format!("port {port}"));
+        }
+        fn visit_domain(&mut self, domain: &[u8]) {
+            self.0.push(format!("domain {domain:?}"));
+        }
+        fn visit_server_version(&mut self, version: ServerVersionRef<'_>) {
+            self.0.push(format!("version {version:?}"));
+        }
+        fn visit_timestamp(&mut self, timestamp: u64) {
+            self.0.push(format!("timestamp {timestamp}"));
+        }
+        fn visit_session_id(&mut self, session_id: u32) {
+            self.0.push(format!("session {session_id}"));
+        }
+        fn visit_outcome(&mut self, outcome: LoginOutcome) {
+            self.0.push(format!("outcome {outcome:?}"));
+        }
+        fn visit_geo(&mut self, geo: Option<GeoInfo>) {
+            self.0.push(format!("geo {geo:?}"));
+        }
+        fn visit_proxy_ip(&mut self, proxy_ip: Option<[u8; 4]>) {
+            self.0.push(format!("proxy {proxy_ip:?}"));
+        }
+        fn visit_client_info(&mut self, brand: &[u8], locale: &[u8]) {
+            self.0.push(format!("client {brand:?} {locale:?}"));
+        }
+        fn visit_extension(&mut self, kind: u8, data: &[u8]) {
+            self.0.push(format!("extension {kind} {data:?}"));
+        }
+        fn end_record(&mut self) {
+            self.0.push("end".to_string());
+        }
+    }
+
+    fn visit_logs(logs: &[PlayerLog]) -> Vec<String> {
+        let mut recorder = Recorder::default();
+        for log in logs {
+            log.visit(&mut recorder);
+        }
+        recorder.0
+    }
+
+    #[test]
+    fn batches_visit_like_their_decoded_logs() {
+        let logs = logs(300);
+        for codec in [Codec::None, Codec::Zlib(Compression::default()), Codec::Lz4] {
+            for endianness in [Endianness::Big, Endianness::Little] {
+                let data = batch(&logs, codec, endianness);
+                let mut recorder = Recorder::default();
+                PlayerLogSerializer::visit(&data, &mut recorder).unwrap();
+                assert_eq!(recorder.0, visit_logs(&logs), "{codec:?} {endianness:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn coverage_vectors_visit_like_their_decoded_logs() {
+        // these hold the binary_version 1 records and DOMAIN_IS_IP domains the generator may not
+        for (name, data) in conformance::coverage_vectors() {
+            let Ok(logs) = PlayerLogSerializer::deserialize_many(&data) else {
+                continue;
+            };
+
+            let mut recorder = Recorder::default();
+            PlayerLogSerializer::visit(&data, &mut recorder).unwrap();
+            assert_eq!(recorder.0, visit_logs(&logs), "{name}");
+        }
+    }
+
+    #[test]
+    fn network_columns_hold_the_decoded_fields() {
+        let logs = logs(300);
+        let mut columns = NetworkColumns::default();
+        PlayerLogSerializer::visit(&batch(&logs, Codec::Lz4, Endianness::Little), &mut columns)
+            .unwrap();
+
+        assert_eq!(
+            columns.player_ips,
+            logs.iter().map(|log| log.player_ip).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            columns.server_ips,
+            logs.iter().map(|log| log.server_ip).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            columns.server_ports,
+            logs.iter().map(|log| log.server_port).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            columns.timestamps,
+            logs.iter().map(|log| log.timestamp).collect::<Vec<_>>()
+        );
+        assert_eq!(
+            columns.server_versions,
+            logs.iter()
+                .map(|log| match log.server_version {
+                    ServerVersion::Known(id) => Some(id),
+                    ServerVersion::Unknown(_) => None,
+                })
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn stats_count_what_the_logs_hold() {
+        let logs = logs(300);
+        let mut stats = StatsVisitor::default();
+        PlayerLogSerializer::visit(&batch(&logs, Codec::None, Endianness::Big), &mut stats)
+            .unwrap();
+
+        let mut expected = StatsVisitor::default();
+        for log in &logs {
+            *expected
+                .binary_versions
+                .entry(log.binary_version)
+                .or_default() += 1;
+            for (field, _) in log.present_fields().iter_names() {
+                *expected.present_fields.entry(field).or_default() += 1;
+            }
+            *expected.outcomes.entry(log.outcome).or_default() += 1;
+            match &log.server_version {
+                ServerVersion::Known(id) => *expected.server_versions.entry(*id).or_default() += 1,
+                ServerVersion::Unknown(name) => {
+                    *expected
+                        .unknown_versions
+                        .entry(name.to_string())
+                        .or_default() += 1;
+                }
+            }
+            if let Some(geo) = log.geo {
+                *expected
+                    .countries
+                    .entry(geo.country_code().to_string())
+                    .or_default() += 1;
+            }
+            if let Some(proxy_ip) = log.proxy_ip {
+                *expected
+                    .proxies
+                    .entry(Ipv4Addr::from(proxy_ip))
+                    .or_default() += 1;
+            }
+        }
+
+        assert_eq!(stats.binary_versions, expected.binary_versions);
+        assert_eq!(stats.present_fields, expected.present_fields);
+        assert_eq!(stats.outcomes, expected.outcomes);
+        assert_eq!(stats.server_versions, expected.server_versions);
+        assert_eq!(stats.unknown_versions, expected.unknown_versions);
+        assert_eq!(stats.countries, expected.countries);
+        assert_eq!(stats.proxies, expected.proxies);
+    }
+
+    #[test]
+    fn trailing_bytes_fail_after_every_record() {
+        let logs = logs(20);
+        let mut data = batch(&logs, Codec::None, Endianness::Big);
+        data.extend_from_slice(&[0; 3]);
+
+        let mut recorder = Recorder::default();
+        let err = PlayerLogSerializer::visit(&data, &mut recorder).unwrap_err();
+        assert_eq!(err.to_string(), "3 bytes after the last record");
+        assert_eq!(recorder.0, visit_logs(&logs));
+    }
+
+    #[test]
+    fn a_cut_record_names_itself_after_its_earlier_records() {
+        let logs = logs(20);
+        let mut data = batch(&logs, Codec::None, Endianness::Big);
+        data.pop();
+
+        let mut recorder = Recorder::default();
+        let err = PlayerLogSerializer::visit(&data, &mut recorder).unwrap_err();
+        assert_eq!(err.to_string(), "record 19");
+        let ends = recorder.0.iter().filter(|call| *call == "end").count();
+        assert_eq!(ends, 19);
+        assert!(recorder.0.starts_with(&visit_logs(&logs[..19])));
+    }
+}
@@ -0,0 +1,581 @@
+//! Allocation free encoding of single records, for collectors that can't use `PlayerLog`.
+//!
+//! The bytes are identical to `PlayerLog::serialize`, and everything here only needs `core`, so
+//! it builds without the `std` feature. The conformance run cross-checks both encoders.
+
+use core::fmt;
+use core::str;
+
+// kept in sync with LogFlags and the PlayerLog limits by the conformance run
+const IS_ONLINE: u16 = 1 << 1;
+const NONDEFAULT_PORT: u16 = 1 << 8;
+const DOMAIN_IS_IP: u16 = 1 << 9;
+const HAS_CLIENT_INFO: u16 = 1 << 10;
+const LOGIN_REFUSED: u16 = 1 << 11;
+const HAS_GEO: u16 = 1 << 12;
+const VIA_PROXY: u16 = 1 << 13;
+// every flag plus the event type bits
+const KNOWN_FLAGS: u16 = 0b11_1111_1111_1011;
+
+const CURRENT_VERSION: u8 = 2;
+const DEFAULT_PORT: u16 = 25565;
+const UNKNOWN_VERSION_ID: u8 = u8::MAX;
+const MAX_NAME_LEN: usize = 16;
+const MAX_V2_NAME_LEN: usize = 32;
+const MAX_UNKNOWN_VERSION_LEN: usize = 32;
+const MAX_CLIENT_BRAND_LEN: usize = 64;
+const MAX_CLIENT_LOCALE_LEN: usize = 16;
+const MAX_EXTENSIONS: usize = 8;
+const MAX_EXTENSION_LEN: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VersionField<'a> {
+    Known(u8),
+    /// A version name outside the version table, binary_version 2 only.
+    Unknown(&'a str),
+}
+
+impl Default for VersionField<'_> {
+    fn default() -> Self {
+        Self::Known(0)
+    }
+}
+
+/// The extensions of a record as they're laid out on the wire, a kind, length and data for each.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Extensions<'a> {
+    count: u8,
+    bytes: &'a [u8],
+}
+
+impl<'a> Extensions<'a> {
+    pub const EMPTY: Self = Self {
+        count: 0,
+        bytes: &[],
+    };
+
+    /// Checks `bytes` holds whole extensions within the record limits.
+    pub fn from_wire(bytes: &'a [u8]) -> Result<Self, DecodeError> {
+        let mut count = 0;
+        let mut rest = bytes;
+        while let [_, len, data @ ..] = rest {
+            let len = *len as usize;
+            if len > MAX_EXTENSION_LEN {
+                return Err(DecodeError::TooLong("extension data"));
+            }
+            if data.len() < len {
+                return Err(DecodeError::Truncated);
+            }
+
+            rest = &data[len..];
+            count += 1;
+        }
+
+        if !rest.is_empty() {
+            return Err(DecodeError::Truncated);
+        }
+        if count > MAX_EXTENSIONS {
+            return Err(DecodeError::TooLong("extensions"));
+        }
+
+        Ok(Self {
+            count: count as u8,
+            bytes,
+        })
+    }
+
+    pub const fn len(&self) -> usize {
+        self.count as usize
+    }
+
+    pub const fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub const fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+
+    /// `(kind, data)` of every extension.
+    pub fn iter(&self) -> impl Iterator<Item = (u8, &'a [u8])> {
+        let mut rest = self.bytes;
+        core::iter::from_fn(move || match rest {
+            [kind, len, data @ ..] => {
+                let (data, next) = data.split_at(*len as usize);
+                rest = next;
+                Some((*kind, data))
+            }
+            _ => None,
+        })
+    }
+}
+
+/// Every field of a record, borrowed. The flags decide which of the optional fields are written,
+/// like they do for `PlayerLog`, and a field whose flag is unset has to be left at its default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct RecordFields<'a> {
+    pub binary_version: u8,
+    pub flags: u16,
+    pub player_uuid: Option<&'a [u8; 16]>,
+    pub player_name: &'a [u8],
+    pub player_ip: [u8; 4],
+    pub server_ip: [u8; 4],
+    pub server_port: u16,
+    /// Not written under binary_version 2 with DOMAIN_IS_IP, it has to be empty then and decodes
+    /// as empty, the domain is the dotted server ip.
+    pub server_domain: &'a [u8],
+    pub server_version: VersionField<'a>,
+    // binary_version 2 and up
+    pub timestamp: u64,
+    pub session_id: u32,
+    pub outcome: u8,                 // only written with LOGIN_REFUSED
+    pub geo: Option<([u8; 2], u32)>, // country and asn, only written with HAS_GEO
+    pub proxy_ip: Option<[u8; 4]>,   // only written with VIA_PROXY
+    pub client_brand: &'a [u8],      // only written with HAS_CLIENT_INFO
+    pub client_locale: &'a [u8],
+    pub extensions: Extensions<'a>,
+}
+
+impl RecordFields<'_> {
+    const fn is_v2(&self) -> bool {
+        self.binary_version >= 2
+    }
+
+    const fn has(&self, flag: u16) -> bool {
+        self.is_v2() && self.flags & flag != 0
+    }
+
+    // multi-byte names can take up to twice the bytes under version 2
+    const fn max_name_len(&self) -> usize {
+        if self.is_v2() {
+            MAX_V2_NAME_LEN
+        } else {
+            MAX_NAME_LEN
+        }
+    }
+
+    const fn writes_port(&self) -> bool {
+        !self.is_v2() || self.flags & NONDEFAULT_PORT != 0
+    }
+
+    const fn writes_domain(&self) -> bool {
+        !self.is_v2() || self.flags & DOMAIN_IS_IP == 0
+    }
+
+    /// How many bytes [`encode_record`] writes.
+    pub const fn encoded_len(&self) -> usize {
+        let mut len = 1 + if self.is_v2() { 2 } else { 1 };
+        if self.flags & IS_ONLINE != 0 {
+            len += 16;
+        }
+        len += 1 + self.player_name.len() + 4 + 4;
+        if self.writes_port() {
+            len += 2;
+        }
+        if self.writes_domain() {
+            len += 1 + self.server_domain.len();
+        }
+        len += match self.server_version {
+            VersionField::Unknown(name) => 1 + 1 + name.len(),
+            VersionField::Known(_) => 1,
+        };
+
+        if self.is_v2() {
+            len += 8 + 4 + 1 + self.extensions.bytes.len();
+            if self.has(LOGIN_REFUSED) {
+                len += 1;
+            }
+            if self.has(HAS_GEO) {
+                len += 2 + 4;
+            }
+            if self.has(VIA_PROXY) {
+                len += 4;
+            }
+            if self.has(HAS_CLIENT_INFO) {
+                len += 1 + self.client_brand.len() + 1 + self.client_locale.len();
+            }
+        }
+
+        len
+    }
+
+    fn validate(&self) -> Result<(), EncodeError> {
+        use EncodeError::{Inconsistent, TooLong};
+
+        if !(1..=CURRENT_VERSION).contains(&self.binary_version) {
+            return Err(EncodeError::InvalidVersion(self.binary_version));
+        }
+        if !self.is_v2() && self.flags > u16::from(u8::MAX) {
+            return Err(Inconsistent(
+                "flags above the low byte need binary_version 2",
+            ));
+        }
+        if self.flags & IS_ONLINE != 0 && self.player_uuid.is_none() {
+            return Err(Inconsistent("missing player uuid"));
+        }
+        if self.player_name.len() > self.max_name_len() {
+            return Err(TooLong("player name"));
+        }
+        if self.server_domain.len() > u8::MAX as usize {
+            return Err(TooLong("server domain"));
+        }
+        if !self.writes_port() && self.server_port != DEFAULT_PORT {
+            return Err(Inconsistent(
+                "non-default port without the NONDEFAULT_PORT flag",
+            ));
+        }
+        if !self.writes_domain() && !self.server_domain.is_empty() {
+            return Err(Inconsistent("domain given with DOMAIN_IS_IP"));
+        }
+
+        match self.server_version {
+            VersionField::Known(UNKNOWN_VERSION_ID) if self.is_v2() => {
+                return Err(Inconsistent(
+                    "known server version with the unknown version id",
+                ))
+            }
+            VersionField::Unknown(_) if !self.is_v2() => {
+                return Err(Inconsistent(
+                    "unknown server versions need binary_version 2",
+                ))
+            }
+            VersionField::Unknown(name) if name.len() > MAX_UNKNOWN_VERSION_LEN => {
+                return Err(TooLong("unknown server version"))
+            }
+            _ => {}
+        }
+
+        if !self.is_v2()
+            && (self.timestamp != 0 || self.session_id != 0 || !self.extensions.is_empty())
+        {
+            return Err(Inconsistent(
+                "binary_version 2 fields in a binary_version 1 record",
+            ));
+        }
+        if !self.has(LOGIN_REFUSED) && self.outcome != 0 {
+            return Err(Inconsistent("refused login without the LOGIN_REFUSED flag"));
+        }
+        if self.has(HAS_GEO) != self.geo.is_some() {
+            return Err(Inconsistent("geo info has to match the HAS_GEO flag"));
+        }
+        if self.has(VIA_PROXY) != self.proxy_ip.is_some() {
+            return Err(Inconsistent("proxy ip has to match the VIA_PROXY flag"));
+        }
+        if !self.has(HAS_CLIENT_INFO)
+            && (!self.client_brand.is_empty() || !self.client_locale.is_empty())
+        {
+            return Err(Inconsistent(
+                "client brand or locale without the HAS_CLIENT_INFO flag",
+            ));
+        }
+        if self.client_brand.len() > MAX_CLIENT_BRAND_LEN {
+            return Err(TooLong("client brand"));
+        }
+        if self.client_locale.len() > MAX_CLIENT_LOCALE_LEN {
+            return Err(TooLong("client locale"));
+        }
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The record needs this many bytes.
+    BufferTooSmall(usize),
+    InvalidVersion(u8),
+    TooLong(&'static str),
+    Inconsistent(&'static str),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BufferTooSmall(needed) => write!(f, "record needs {needed} bytes"),
+            Self::InvalidVersion(version) => write!(f, "invalid binary version {version}"),
+            Self::TooLong(field) => write!(f, "{field} too long"),
+            Self::Inconsistent(reason) => f.write_str(reason),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for EncodeError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    Truncated,
+    InvalidVersion(u8),
+    InvalidFlags(u16),
+    TooLong(&'static str),
+    InvalidUtf8(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Truncated => f.write_str("truncated record"),
+            Self::InvalidVersion(version) => write!(f, "invalid binary version {version}"),
+            Self::InvalidFlags(flags) => write!(f, "invalid flags {flags:#06x}"),
+            Self::TooLong(field) => write!(f, "{field} too long"),
+            Self::InvalidUtf8(field) => write!(f, "invalid {field}"),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {}
+
+struct Writer<'o> {
+    out: &'o mut [u8],
+    pos: usize,
+}
+
+impl Writer<'_> {
+    fn put(&mut self, bytes: &[u8]) {
+        self.out[self.pos..self.pos + bytes.len()].copy_from_slice(bytes);
+        self.pos += bytes.len();
+    }
+
+    // every length is checked against a limit of at most 255 before it gets here
+    fn put_prefixed(&mut self, bytes: &[u8]) {
+        self.put(&[bytes.len() as u8]);
+        self.put(bytes);
+    }
+}
+
+/// Writes the record to the start of `out`, returning how many bytes it took.
+pub fn encode_record(fields: RecordFields<'_>, out: &mut [u8]) -> Result<usize, EncodeError> {
+    fields.validate()?;
+
+    let len = fields.encoded_len();
+    if out.len() < len {
+        return Err(EncodeError::BufferTooSmall(len));
+    }
+
+    let mut w = Writer { out, pos: 0 };
+    w.put(&[fields.binary_version]);
+    if fields.is_v2() {
+        w.put(&fields.flags.to_be_bytes());
+    } else {
+        w.put(&[fields.flags as u8]);
+    }
+
+    if let Some(uuid) = fields.player_uuid.filter(|_| fields.flags & IS_ONLINE != 0) {
+        w.put(uuid);
+    }
+    w.put_prefixed(fields.player_name);
+    w.put(&fields.player_ip);
+    w.put(&fields.server_ip);
+    if fields.writes_port() {
+        w.put(&fields.server_port.to_be_bytes());
+    }
+    if fields.writes_domain() {
+        w.put_prefixed(fields.server_domain);
+    }
+
+    match fields.server_version {
+        VersionField::Known(id) => w.put(&[id]),
+        VersionField::Unknown(name) => {
+            w.put(&[UNKNOWN_VERSION_ID]);
+            w.put_prefixed(name.as_bytes());
+        }
+    }
+
+    if fields.is_v2() {
+        w.put(&fields.timestamp.to_be_bytes());
+        w.put(&fields.session_id.to_be_bytes());
+
+        if fields.has(LOGIN_REFUSED) {
+            w.put(&[fields.outcome]);
+        }
+        if let Some((country, asn)) = fields.geo {
+            w.put(&country);
+            w.put(&asn.to_be_bytes());
+        }
+        if let Some(proxy_ip) = fields.proxy_ip {
+            w.put(&proxy_ip);
+        }
+        if fields.has(HAS_CLIENT_INFO) {
+            w.put_prefixed(fields.client_brand);
+            w.put_prefixed(fields.client_locale);
+        }
+
+        w.put(&[fields.extensions.count]);
+        w.put(fields.extensions.bytes);
+    }
+
+    Ok(w.pos)
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let bytes = self
+            .data
+            .get(self.pos..self.pos + len)
+            .ok_or(DecodeError::Truncated)?;
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N], DecodeError> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.take(N)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self) -> Result<u8, DecodeError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn prefixed(&mut self, max_len: usize, field: &'static str) -> Result<&'a [u8], DecodeError> {
+        let len = self.u8()? as usize;
+        if len > max_len {
+            return Err(DecodeError::TooLong(field));
+        }
+        self.take(len)
+    }
+}
+
+/// Reads the record at the start of `data`, returning its fields and how many bytes it took.
+pub fn decode_record(data: &[u8]) -> Result<(RecordFields<'_>, usize), DecodeError> {
+    let mut r = Reader { data, pos: 0 };
+
+    let binary_version = r.u8()?;
+    if !(1..=CURRENT_VERSION).contains(&binary_version) {
+        return Err(DecodeError::InvalidVersion(binary_version));
+    }
+
+    let flags = if binary_version >= 2 {
+        u16::from_be_bytes(r.array()?)
+    } else {
+        u16::from(r.u8()?)
+    };
+    if flags & !KNOWN_FLAGS != 0 {
+        return Err(DecodeError::InvalidFlags(flags));
+    }
+
+    let mut fields = RecordFields {
+        binary_version,
+        flags,
+        ..RecordFields::default()
+    };
+
+    if flags & IS_ONLINE != 0 {
+        fields.player_uuid = Some(r.take(16)?.try_into().map_err(|_| DecodeError::Truncated)?);
+    }
+    fields.player_name = r.prefixed(fields.max_name_len(), "player name")?;
+    fields.player_ip = r.array()?;
+    fields.server_ip = r.array()?;
+    fields.server_port = if fields.writes_port() {
+        u16::from_be_bytes(r.array()?)
+    } else {
+        DEFAULT_PORT
+    };
+    if fields.writes_domain() {
+        fields.server_domain = r.prefixed(u8::MAX as usize, "server domain")?;
+    }
+
+    fields.server_version = match r.u8()? {
+        UNKNOWN_VERSION_ID if fields.is_v2() => {
+            let name = r.prefixed(MAX_UNKNOWN_VERSION_LEN, "unknown server version")?;
+            VersionField::Unknown(
+                str::from_utf8(name)
+                    .map_err(|_| DecodeError::InvalidUtf8("unknown server version"))?,
+            )
+        }
+        id => VersionField::Known(id),
+    };
+
+    if fields.is_v2() {
+        fields.timestamp = u64::from_be_bytes(r.array()?);
+        fields.session_id = u32::from_be_bytes(r.array()?);
+
+        if fields.has(LOGIN_REFUSED) {
+            fields.outcome = r.u8()?;
+        }
+        if fields.has(HAS_GEO) {
+            fields.geo = Some((r.array()?, u32::from_be_bytes(r.array()?)));
+        }
+        if fields.has(VIA_PROXY) {
+            fields.proxy_ip = Some(r.array()?);
+        }
+        if fields.has(HAS_CLIENT_INFO) {
+            fields.client_brand = r.prefixed(MAX_CLIENT_BRAND_LEN, "client brand")?;
+            fields.client_locale = r.prefixed(MAX_CLIENT_LOCALE_LEN, "client locale")?;
+        }
+
+        let count = r.u8()? as usize;
+        if count > MAX_EXTENSIONS {
+            return Err(DecodeError::TooLong("extensions"));
+        }
+        let start = r.pos;
+        for _ in 0..count {
+            r.u8()?;
+            r.prefixed(MAX_EXTENSION_LEN, "extension data")?;
+        }
+        fields.extensions = Extensions {
+            count: count as u8,
+            bytes: &data[start..r.pos],
+        };
+    }
+
+    Ok((fields, r.pos))
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::player_log::conformance::{coverage_vectors, wire_fields};
+    use crate::player_log::{Codec, Endianness, PlayerLog, PlayerLogSerializer, ProbeResult};
+
+    #[test]
+    fn coverage_vectors_encode_the_same_as_std() {
+        let mut records = 0;
+        for (name, data) in coverage_vectors() {
+            let Ok(logs) = PlayerLogSerializer::deserialize_many(&data) else {
+                continue;
+            };
+
+            let mut payload = Vec::new();
+            let mut extensions = Vec::new();
+            for log in &logs {
+                let mut expected = Vec::new();
+                log.serialize(&mut expected).unwrap();
+
+                let mut out = [0; PlayerLog::MAX_ENCODED_SIZE];
+                let fields = wire_fields(log, &mut extensions).unwrap();
+                let len = encode_record(fields, &mut out).unwrap();
+                assert_eq!(out[..len], expected, "{name}");
+                assert_eq!(fields.encoded_len(), len, "{name}");
+
+                assert_eq!(
+                    encode_record(fields, &mut out[..len - 1]),
+                    Err(EncodeError::BufferTooSmall(len)),
+                    "{name}"
+                );
+                payload.extend_from_slice(&out[..len]);
+                records += 1;
+            }
+
+            // the records of a plain batch are its payload as is
+            let Ok(ProbeResult::Complete { header, header_len }) =
+                PlayerLogSerializer::probe_prefix(&data)
+            else {
+                panic!("{name}");
+            };
+            if header.codec == Codec::None
+                && header.endianness == Endianness::Big
+                && header.dictionary.is_none()
+            {
+                assert_eq!(data[header_len..], payload, "{name}");
+            }
+        }
+        assert!(records > 50, "{records}");
+    }
+}